@@ -1,7 +1,9 @@
 pub mod utils;
+pub mod errors;
 pub mod interfaces;
 pub mod objects;
 pub mod controllers;
+pub mod backends;
 pub mod config;
 pub mod embeddings;
 pub mod lsh;
@@ -0,0 +1,154 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use crate::core::interfaces::StorageBackend;
+
+// structs define
+
+/// Хранилище на локальной файловой системе. Пути отсчитываются от папки {path}/storage
+pub struct FsBackend {
+    root: PathBuf,
+}
+
+/// Хранилище в памяти: ключ — относительный путь файла. Данные живут, пока жив объект
+#[derive(Default)]
+pub struct InMemoryBackend {
+    files: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+// Impl block
+
+//  FsBackend impl
+
+impl FsBackend {
+    /// Создаёт файловое хранилище, инициализирует корневую папку, если её нет,
+    /// и сразу проверяет, что в неё можно писать
+    pub fn new(root: PathBuf) -> Result<FsBackend> {
+        let context = |e: Error| Error::new(e.kind(), format!("Папка storage '{}' недоступна для записи: {}", root.display(), e));
+        fs::create_dir_all(&root).map_err(context)?;
+        let probe = root.join(".write_test");
+        fs::write(&probe, b"").and_then(|_| fs::remove_file(&probe)).map_err(context)?;
+        Ok(FsBackend { root })
+    }
+
+    /// Имена элементов папки, отобранные по типу (файл или папка)
+    fn list(&self, dir: &str, want_dirs: bool) -> Result<Vec<String>> {
+        let entries = fs::read_dir(self.root.join(dir))?;
+        Ok(entries
+            .flatten()
+            .filter(|e| e.path().is_dir() == want_dirs)
+            .filter_map(|e| e.file_name().to_str().map(str::to_string))
+            .collect())
+    }
+}
+
+impl StorageBackend for FsBackend {
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        let file_path = self.root.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file_path, data)
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        fs::read(self.root.join(path))
+    }
+
+    fn list_files(&self, dir: &str) -> Result<Vec<String>> {
+        self.list(dir, false)
+    }
+
+    fn list_dirs(&self, dir: &str) -> Result<Vec<String>> {
+        self.list(dir, true)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.root.join(path).exists()
+    }
+
+    fn file_size(&self, path: &str) -> Result<u64> {
+        Ok(fs::metadata(self.root.join(path))?.len())
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        let full_path = self.root.join(path);
+        if full_path.is_dir() {
+            fs::remove_dir_all(full_path)
+        } else {
+            fs::remove_file(full_path)
+        }
+    }
+}
+
+//  InMemoryBackend impl
+
+impl InMemoryBackend {
+    pub fn new() -> InMemoryBackend {
+        InMemoryBackend::default()
+    }
+
+    /// Пути файлов, лежащих внутри папки dir (на любой глубине), относительно неё
+    fn children(&self, dir: &str) -> Vec<String> {
+        let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+        self.files
+            .read()
+            .expect("Хранилище в памяти повреждено")
+            .keys()
+            .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+            .collect()
+    }
+}
+
+impl StorageBackend for InMemoryBackend {
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.files
+            .write()
+            .expect("Хранилище в памяти повреждено")
+            .insert(path.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.files
+            .read()
+            .expect("Хранилище в памяти повреждено")
+            .get(path)
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Файл {} не найден", path)))
+    }
+
+    fn list_files(&self, dir: &str) -> Result<Vec<String>> {
+        Ok(self.children(dir).into_iter().filter(|child| !child.contains('/')).collect())
+    }
+
+    fn list_dirs(&self, dir: &str) -> Result<Vec<String>> {
+        let dirs: BTreeSet<String> = self.children(dir)
+            .iter()
+            .filter_map(|child| child.split_once('/').map(|(first, _)| first.to_string()))
+            .collect();
+        Ok(dirs.into_iter().collect())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files.read().expect("Хранилище в памяти повреждено").contains_key(path)
+            || !self.children(path).is_empty()
+    }
+
+    fn file_size(&self, path: &str) -> Result<u64> {
+        self.read(path).map(|data| data.len() as u64)
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        let prefix = format!("{}/", path);
+        let mut files = self.files.write().expect("Хранилище в памяти повреждено");
+        let before = files.len();
+        files.retain(|key, _| key != path && !key.starts_with(&prefix));
+        if files.len() == before {
+            return Err(Error::new(ErrorKind::NotFound, format!("Файл {} не найден", path)));
+        }
+        Ok(())
+    }
+}
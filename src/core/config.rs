@@ -1,18 +1,20 @@
 use std::{collections::HashMap};
 use std::fs;
 use serde_json::Value;
+use crate::core::lsh::{DEFAULT_BUCKET_WIDTH, DEFAULT_LSH_SEED, DEFAULT_NUM_HASHES};
 
 // structs define
 
 pub struct ConfigLoader {
     configs: Option<HashMap<String, String>>,
+    raw: Option<Value>,
 }
 
 // Impl block
 
 impl ConfigLoader {
     pub fn new() -> ConfigLoader {
-        ConfigLoader { configs: None }
+        ConfigLoader { configs: None, raw: None }
     }
 
     // Возвращает плоский хэшмап с ключами без префикса, соответствующими секции <names[0]>.
@@ -35,12 +37,91 @@ impl ConfigLoader {
         }
     }
 
+    // Возвращает настройки для StorageController: секцию "path", формат хранения из "storage.format"
+    // и бэкенд из "storage.backend"
+    pub fn storage_configs(&self) -> HashMap<String, String> {
+        let mut result = self.get("path");
+        let mut storage = self.get("storage");
+        for key in ["format", "backend", "compression", "load_concurrency", "io_retries", "io_retry_backoff_ms"] {
+            if let Some(value) = storage.remove(key) {
+                result.insert(key.to_string(), value);
+            }
+        }
+        result
+    }
+
+    // Возвращает настройки для ConnectionController: секцию "connection" и секцию "server"
+    // (ограничения запросов, например default_k и max_k)
+    pub fn connection_configs(&self) -> HashMap<String, String> {
+        let mut result = self.get("connection");
+        result.extend(self.get("server"));
+        result
+    }
+
+    // Возвращает seed проекций LSH из "sharding.lsh_seed": число — общий seed,
+    // null или "random" — случайный seed для каждой коллекции; без настройки — DEFAULT_LSH_SEED
+    pub fn lsh_seed(&self) -> Option<u64> {
+        match self.get("sharding").get("lsh_seed").map(String::as_str) {
+            None => Some(DEFAULT_LSH_SEED),
+            Some("null") | Some("random") => None,
+            Some(value) => Some(value.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid sharding.lsh_seed '{}', using default {}", value, DEFAULT_LSH_SEED);
+                DEFAULT_LSH_SEED
+            })),
+        }
+    }
+
+    // Возвращает количество хэш-функций и начальную ширину бакетов LSH из "lsh.num_hashes"
+    // и "lsh.bucket_width"; без настройки или при некорректном значении — значения по умолчанию
+    pub fn lsh_params(&self) -> (usize, f32) {
+        let lsh = self.get("lsh");
+        let num_hashes = match lsh.get("num_hashes") {
+            None => DEFAULT_NUM_HASHES,
+            Some(value) => value.parse::<usize>().ok().filter(|&n| n > 0).unwrap_or_else(|| {
+                eprintln!("Invalid lsh.num_hashes '{}', using default {}", value, DEFAULT_NUM_HASHES);
+                DEFAULT_NUM_HASHES
+            }),
+        };
+        let bucket_width = match lsh.get("bucket_width") {
+            None => DEFAULT_BUCKET_WIDTH,
+            Some(value) => value.parse::<f32>().ok().filter(|w| w.is_finite() && *w > 0.0).unwrap_or_else(|| {
+                eprintln!("Invalid lsh.bucket_width '{}', using default {}", value, DEFAULT_BUCKET_WIDTH);
+                DEFAULT_BUCKET_WIDTH
+            }),
+        };
+        (num_hashes, bucket_width)
+    }
+
+    // Возвращает порог слияния малых бакетов из "lsh.merge_small_buckets": число больше нуля включает
+    // слияние; без настройки, 0 или некорректное значение — слияние выключено
+    pub fn merge_small_buckets(&self) -> Option<usize> {
+        let value = self.get("lsh").remove("merge_small_buckets")?;
+        match value.parse::<usize>() {
+            Ok(threshold) => Some(threshold).filter(|&threshold| threshold > 0),
+            Err(_) => {
+                eprintln!("Invalid lsh.merge_small_buckets '{}', bucket merging disabled", value);
+                None
+            }
+        }
+    }
+
+    // Возвращает исходное JSON-значение по пути через точку (например, "connection" или "connection.port").
+    // В отличие от get, сохраняет массивы и вложенные объекты без приведения к строке.
+    pub fn get_value(&self, key: &str) -> Option<&Value> {
+        let mut current = self.raw.as_ref()?;
+        for part in key.split('.') {
+            current = current.as_object()?.get(part)?;
+        }
+        Some(current)
+    }
+
     pub fn load(&mut self, path: String) {
         let content = match fs::read_to_string(&path) {
             Ok(c) => c,
             Err(e) => {
                 eprintln!("Failed to read config file '{}': {:?}", path, e);
                 self.configs = None;
+                self.raw = None;
                 return;
             }
         };
@@ -49,25 +130,36 @@ impl ConfigLoader {
             Err(e) => {
                 eprintln!("Failed to parse config file '{}': {:?}", path, e);
                 self.configs = None;
+                self.raw = None;
                 return;
             }
         };
 
         let mut flat = HashMap::new();
-        if let Value::Object(map) = json {
+        if let Value::Object(map) = &json {
             for (k, v) in map.iter() {
-                if v.is_object() {
-                    // flatten one level
-                    if let Value::Object(inner) = v {
-                        for (ik, iv) in inner.iter() {
-                            flat.insert(format!("{}.{}", k, ik), iv.to_string().trim_matches('"').to_string());
-                        }
-                    }
-                } else {
-                    flat.insert(k.clone(), v.to_string().trim_matches('"').to_string());
-                }
+                Self::flatten(k, v, &mut flat);
             }
         }
         self.configs = Some(flat);
+        self.raw = Some(json);
     }
-}
\ No newline at end of file
+
+    // Рекурсивно раскладывает вложенные объекты в ключи вида "a.b.c";
+    // массивы и скаляры сохраняются как JSON-строки (строки — без кавычек)
+    fn flatten(prefix: &str, value: &Value, flat: &mut HashMap<String, String>) {
+        match value {
+            Value::Object(inner) => {
+                for (ik, iv) in inner.iter() {
+                    Self::flatten(&format!("{}.{}", prefix, ik), iv, flat);
+                }
+            }
+            Value::String(s) => {
+                flat.insert(prefix.to_string(), s.clone());
+            }
+            other => {
+                flat.insert(prefix.to_string(), other.to_string());
+            }
+        }
+    }
+}
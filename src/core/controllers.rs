@@ -1,23 +1,34 @@
-use std::{collections::HashMap, result::Result};
-use axum::{routing::post, Router};
+use std::{collections::{HashMap, HashSet}, result::Result};
+use axum::{routing::{get, post}, Router};
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tokio::sync::RwLock;
-use tokio::sync::broadcast;
-use crate::core::{objects::{Collection, Vector, Bucket}, interfaces::{CollectionObjectController, Object}, embeddings::{find_most_similar}, lsh::{LSH, LSHMetric}, config::ConfigLoader};
-use std::fs;
-use std::path::Path;
+use tokio::sync::Semaphore;
+use crate::core::{objects::{Collection, Vector, Bucket, EvictionPolicy}, interfaces::{CollectionObjectController, Object, StorageBackend}, backends::{FsBackend, InMemoryBackend}, embeddings::{find_most_similar, similarity_score, cosine_similarity_with_norms, l2_norm, normalize_in_place, validate_embedding, chunk_text, make_embeddings_batch}, lsh::{LSH, LSHMetric, LSHTable, DEFAULT_BUCKET_WIDTH, DEFAULT_LSH_SEED, DEFAULT_NUM_HASHES}, config::ConfigLoader, utils::{calculate_hash, verify_checksum, with_checksum, Metadata, MetadataBoosts, MetadataValue, MetadataFilter, MetadataMigration, StorageCompression, StorageFormat}};
 use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+use std::future::IntoFuture;
 use chrono::Utc;
 use utoipa_swagger_ui::SwaggerUi;
 use crate::core::openapi::load_openapi_spec;
-use crate::core::handlers::AppState;
+use crate::core::handlers::{AppState, ShutdownSignal};
+use crate::core::errors::VecDbError;
 
 // structs define
 
 pub struct StorageController {
-    configs: HashMap<String, String>,
+    pub format: StorageFormat,
+    /// Сжатие новых файлов; при чтении тип сжатия определяется по заголовку файла
+    pub compression: StorageCompression,
+    /// Сколько коллекций читается из storage одновременно при асинхронной загрузке
+    pub load_concurrency: usize,
+    /// Сколько раз выполняется операция ввода-вывода при временных ошибках (включая первую попытку)
+    pub io_attempts: usize,
+    /// Пауза перед первым повтором; перед каждым следующим удваивается
+    pub io_backoff: Duration,
+    backend: Box<dyn StorageBackend>,
 }
 
 pub struct ConnectionController {
@@ -27,6 +38,25 @@ pub struct ConnectionController {
 pub struct CollectionController {
     storage_controller: Arc<StorageController>,
     collections: Option<Vec<Collection>>,
+    /// Seed проекций LSH для новых коллекций (None — свой случайный seed у каждой коллекции)
+    lsh_seed: Option<u64>,
+    /// Количество хэш-функций LSH для новых коллекций
+    num_hashes: usize,
+    /// Начальная ширина бакетов LSH для новых коллекций
+    bucket_width: f32,
+    /// Порог слияния малых бакетов для всех коллекций (см. BucketController::merge_small_buckets)
+    merge_small_buckets: Option<usize>,
+}
+
+/// Запись векторов коллекции прямо в storage (режим добавления, см. CollectionController::begin_append).
+/// Держит в памяти только описание коллекции и её LSH, без списков векторов
+pub struct AppendWriter {
+    storage_controller: Arc<StorageController>,
+    /// Описание коллекции без бакетов: размерность, LSH, метаданные по умолчанию
+    collection: Collection,
+    /// Бакеты, файлы которых уже есть в storage
+    buckets: HashSet<u64>,
+    written: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -38,7 +68,329 @@ pub struct VectorController {
 pub struct BucketController {
     pub buckets: Option<Vec<Bucket>>,
     pub lsh: Option<LSH>,
+    /// Дополнительные хэш-таблицы LSH (всего таблиц: 1 + tables.len())
+    pub tables: Vec<LSHTable>,
     pub dimension: Option<usize>,
+    /// Порог заполненности для слияния бакетов: новый вектор без своего бакета попадает
+    /// в соседний бакет, если в том меньше merge_small_buckets векторов (None — слияние выключено)
+    pub merge_small_buckets: Option<usize>,
+    /// Инвертированный индекс метаданных для фильтров на равенство (None — индекс не строится)
+    pub metadata_index: Option<MetadataIndex>,
+}
+
+/// Строка сводки по бакету: (ID бакета, количество векторов, created_at, updated_at)
+pub type BucketSummaryRow = (u64, usize, i64, i64);
+
+/// Вектор пакетной вставки: (ID, если задан явно, эмбеддинг, метаданные, нормировать ли эмбеддинг)
+pub type BatchInsertItem = (Option<u64>, Vec<f32>, Metadata, Option<bool>);
+
+/// Результаты поиска по одному запросу: (bucket_id, индекс вектора, оценка)
+pub type SimilarResults = Vec<(u64, usize, f32)>;
+
+/// Параметры поиска похожих векторов
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Момент времени, после которого дополнительные бакеты не просматриваются
+    pub deadline: Option<Instant>,
+    /// Бусты score по метаданным, применяются до финальной сортировки
+    pub boosts: MetadataBoosts,
+    /// ID векторов, которые не попадают в результаты (отбрасываются до обрезки до k)
+    pub exclude_ids: HashSet<u64>,
+    /// Переранжирование: Some(candidate_factor) — собрать не меньше candidate_factor * k кандидатов
+    /// из бакета запроса и соседних бакетов и упорядочить их по точному score метрики коллекции
+    pub rerank: Option<usize>,
+    /// Ключи метаданных, по которым считаются фасеты среди всех просмотренных кандидатов
+    pub facets: Vec<String>,
+}
+
+/// Сколько раз выполняется операция storage при временных ошибках, если storage.io_retries не задан
+pub const DEFAULT_IO_ATTEMPTS: usize = 3;
+
+/// Пауза перед первым повтором операции storage, если storage.io_retry_backoff_ms не задан
+pub const DEFAULT_IO_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Множитель числа кандидатов для переранжирования по умолчанию
+pub const DEFAULT_RERANK_CANDIDATE_FACTOR: usize = 4;
+
+/// Допустимое косинусное расстояние для приближённой дедупликации, если server.dedup_tolerance не задан
+pub const DEFAULT_DEDUP_TOLERANCE: f32 = 1e-6;
+
+/// Занимаемое коллекцией место в хранилище, в байтах
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CollectionDiskUsage {
+    /// Файлы самой коллекции (описание коллекции и прочие файлы её верхнего уровня)
+    pub collection_file: u64,
+    /// Файлы бакетов без векторов
+    pub buckets: u64,
+    /// Файлы векторов
+    pub vectors: u64,
+}
+
+impl CollectionDiskUsage {
+    pub fn total(&self) -> u64 {
+        self.collection_file + self.buckets + self.vectors
+    }
+}
+
+/// Коллекция в общем списке: загруженная в память или прочитанная из storage
+/// только по описанию (без бакетов и векторов)
+#[derive(Debug)]
+pub enum ListedCollection<'a> {
+    Loaded(&'a Collection),
+    OnDisk(Box<Collection>),
+}
+
+impl ListedCollection<'_> {
+    /// Загружена ли коллекция в память (у незагруженных бакеты и векторы пусты)
+    pub fn is_loaded(&self) -> bool {
+        matches!(self, ListedCollection::Loaded(_))
+    }
+}
+
+impl std::ops::Deref for ListedCollection<'_> {
+    type Target = Collection;
+
+    fn deref(&self) -> &Collection {
+        match self {
+            ListedCollection::Loaded(collection) => collection,
+            ListedCollection::OnDisk(collection) => collection,
+        }
+    }
+}
+
+/// Результат поиска похожих векторов
+#[derive(Debug, Default, Clone)]
+pub struct SearchOutcome {
+    /// Найденные векторы: (ID бакета, индекс вектора в бакете, score)
+    pub results: Vec<(u64, usize, f32)>,
+    /// Поиск был прерван по дедлайну, результаты могут быть неполными
+    pub timed_out: bool,
+    /// Сколько бакетов просмотрено
+    pub buckets_probed: usize,
+    /// Для скольких векторов посчитан score
+    pub candidates_examined: usize,
+    /// Фасеты: ключ метаданных -> значение -> количество кандидатов с этим значением
+    /// (считаются до обрезки до k, исключённые ID не учитываются)
+    pub facets: HashMap<String, HashMap<String, usize>>,
+}
+
+impl SearchOutcome {
+    /// Учитывает вектор-кандидат в фасетах из options.facets
+    fn count_facets(&mut self, vector: &Vector, options: &SearchOptions) {
+        for key in &options.facets {
+            let counts = self.facets.entry(key.clone()).or_default();
+            if let Some(value) = vector.metadata.get(key) {
+                *counts.entry(value.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Учитывает в фасетах все векторы просмотренного бакета, кроме исключённых
+    fn count_bucket_facets(&mut self, bucket: &Bucket, options: &SearchOptions) {
+        if options.facets.is_empty() {
+            return;
+        }
+        for vector in bucket.vectors_controller.vectors.iter().flatten() {
+            if !options.exclude_ids.contains(&vector.hash_id()) {
+                self.count_facets(vector, options);
+            }
+        }
+    }
+}
+
+/// Шаг квантования запроса в ключе кэша поиска: запросы, совпадающие с точностью
+/// до шага, считаются одинаковыми
+pub const QUERY_CACHE_QUANTUM: f32 = 1e-6;
+
+/// Ключ кэша поиска: коллекция, её версия и отпечаток запроса (квантованный вектор, k и опции)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryCacheKey {
+    collection: String,
+    version: u64,
+    fingerprint: u64,
+}
+
+impl QueryCacheKey {
+    /// Версия коллекции входит в ключ, поэтому после любого изменения коллекции
+    /// старые записи перестают совпадать. Дедлайн в ключ не входит
+    pub fn new(collection: &Collection, query: &[f32], k: usize, options: &SearchOptions) -> Self {
+        let quantized: Vec<i64> = query.iter().map(|x| (x / QUERY_CACHE_QUANTUM).round() as i64).collect();
+        let mut exclude_ids: Vec<u64> = options.exclude_ids.iter().copied().collect();
+        exclude_ids.sort_unstable();
+        let fingerprint = calculate_hash(&(
+            quantized,
+            k,
+            format!("{:?}", options.boosts),
+            exclude_ids,
+            options.rerank,
+            &options.facets,
+        ));
+        QueryCacheKey { collection: collection.name.clone(), version: collection.version(), fingerprint }
+    }
+}
+
+/// LRU-кэш результатов find_similar для повторяющихся запросов.
+/// Ёмкость 0 отключает кэш
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    capacity: usize,
+    state: std::sync::Mutex<QueryCacheState>,
+}
+
+#[derive(Debug, Default)]
+struct QueryCacheState {
+    /// Ключ -> (результат, момент последнего обращения)
+    entries: HashMap<QueryCacheKey, (SearchOutcome, u64)>,
+    /// Логические часы обращений к кэшу
+    clock: u64,
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize) -> Self {
+        QueryCache { capacity, state: Default::default() }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Количество записей в кэше
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Закэшированный результат; обращение делает запись самой свежей
+    pub fn get(&self, key: &QueryCacheKey) -> Option<SearchOutcome> {
+        let mut state = self.lock();
+        state.clock += 1;
+        let clock = state.clock;
+        let (outcome, last_used) = state.entries.get_mut(key)?;
+        *last_used = clock;
+        Some(outcome.clone())
+    }
+
+    /// Сохраняет результат, вытесняя давно не использованную запись при переполнении.
+    /// Прерванные по дедлайну (неполные) результаты не кэшируются
+    pub fn insert(&self, key: QueryCacheKey, outcome: &SearchOutcome) {
+        if !self.is_enabled() || outcome.timed_out {
+            return;
+        }
+        let mut state = self.lock();
+        state.clock += 1;
+        if !state.entries.contains_key(&key) && state.entries.len() >= self.capacity {
+            let oldest = state.entries.iter().min_by_key(|(_, (_, last_used))| *last_used).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        let clock = state.clock;
+        state.entries.insert(key, (outcome.clone(), clock));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueryCacheState> {
+        // Кэш не хранит инвариантов между записями, поэтому после паники в другом потоке им можно пользоваться
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Инвертированный индекс метаданных: (ключ, значение) -> ID векторов. Строится только
+/// по выбранным ключам коллекции (indexed_metadata_keys) и обновляется при каждой вставке,
+/// изменении и удалении вектора
+#[derive(Debug, Default)]
+pub struct MetadataIndex {
+    keys: HashSet<String>,
+    entries: HashMap<(String, String), HashSet<u64>>,
+    /// ID вектора -> его проиндексированные пары (для удаления)
+    by_vector: HashMap<u64, Vec<(String, String)>>,
+    /// Сколько раз фильтр был выполнен по индексу
+    lookups: AtomicUsize,
+}
+
+impl MetadataIndex {
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        MetadataIndex { keys: keys.into_iter().collect(), ..Default::default() }
+    }
+
+    /// Проиндексированные ключи
+    pub fn keys(&self) -> &HashSet<String> {
+        &self.keys
+    }
+
+    /// Сколько фильтров выполнено по индексу, а не полным просмотром
+    pub fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::Relaxed)
+    }
+
+    /// Значение в индексе: числа приводятся к f64, как при сравнении в фильтрах
+    /// (см. MetadataValue::loosely_equals), поэтому 2020 и "2020" попадают в одну запись
+    fn entry_value(value: &MetadataValue) -> String {
+        match value.as_f64() {
+            Some(number) if matches!(value, MetadataValue::Int(_) | MetadataValue::Float(_)) => (number + 0.0).to_string(),
+            _ => value.to_string(),
+        }
+    }
+
+    /// Добавляет (или переиндексирует) метаданные вектора
+    pub fn insert(&mut self, vector_id: u64, metadata: &Metadata) {
+        self.remove(vector_id);
+        let pairs: Vec<(String, String)> = metadata
+            .iter()
+            .filter(|(key, _)| self.keys.contains(*key))
+            .map(|(key, value)| (key.clone(), Self::entry_value(value)))
+            .collect();
+        for pair in &pairs {
+            self.entries.entry(pair.clone()).or_default().insert(vector_id);
+        }
+        self.by_vector.insert(vector_id, pairs);
+    }
+
+    /// Удаляет вектор из индекса
+    pub fn remove(&mut self, vector_id: u64) {
+        for pair in self.by_vector.remove(&vector_id).unwrap_or_default() {
+            if let Some(ids) = self.entries.get_mut(&pair) {
+                ids.remove(&vector_id);
+                if ids.is_empty() {
+                    self.entries.remove(&pair);
+                }
+            }
+        }
+    }
+
+    /// Очищает индекс, сохраняя набор ключей
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.by_vector.clear();
+    }
+
+    /// ID векторов, подходящих под фильтр на равенство, как пересечение множеств индекса.
+    /// None, если фильтр пуст или в нём есть непроиндексированный ключ — тогда нужен полный просмотр
+    pub fn candidates(&self, filters: &Metadata) -> Option<Vec<u64>> {
+        if filters.is_empty() || !filters.keys().all(|key| self.keys.contains(key)) {
+            return None;
+        }
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+
+        let mut sets = Vec::with_capacity(filters.len());
+        for (key, value) in filters {
+            match self.entries.get(&(key.clone(), Self::entry_value(value))) {
+                Some(ids) => sets.push(ids),
+                None => return Some(Vec::new()),
+            }
+        }
+        // Перебираем самое маленькое множество и проверяем остальные
+        sets.sort_by_key(|ids| ids.len());
+        let (smallest, rest) = sets.split_first()?;
+        let mut ids: Vec<u64> = smallest.iter()
+            .filter(|id| rest.iter().all(|set| set.contains(id)))
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        Some(ids)
+    }
 }
 
 // Impl block
@@ -46,404 +398,581 @@ pub struct BucketController {
 //  StorageController impl
 
 impl StorageController {
-    /// Создаёт новый контроллер хранилища, инициализирует папку storage, если её нет
-    pub fn new(configs: HashMap<String, String>) -> StorageController {
-        fs::create_dir_all(format!("{}/storage", configs.get(&"path".to_string()).unwrap_or(&".".to_string())))
-            .expect("Не удалось создать папку storage");
-        StorageController { configs }
+    /// Создаёт новый контроллер хранилища. Бэкенд выбирается настройкой "backend":
+    /// "fs" (по умолчанию, папка {path}/storage) или "memory" (без обращения к диску).
+    /// Возвращает ошибку, если папку storage нельзя создать или в неё нельзя писать
+    pub fn new(configs: HashMap<String, String>) -> Result<StorageController, std::io::Error> {
+        let backend: Box<dyn StorageBackend> = match configs.get("backend").map(String::as_str) {
+            Some("memory") => Box::new(InMemoryBackend::new()),
+            other => {
+                if let Some(other) = other.filter(|b| *b != "fs") {
+                    eprintln!("Неизвестный storage.backend '{}', используется fs", other);
+                }
+                let root = format!("{}/storage", configs.get("path").map(String::as_str).unwrap_or("."));
+                Box::new(FsBackend::new(root.into())?)
+            }
+        };
+        Self::with_backend(backend, &configs)
+    }
+
+    /// Создаёт контроллер хранилища поверх готового бэкенда; остальные настройки берутся из configs
+    pub fn with_backend(backend: Box<dyn StorageBackend>, configs: &HashMap<String, String>) -> Result<StorageController, std::io::Error> {
+        let format = Self::resolve_format(backend.as_ref(), configs)?;
+        let compression = configs.get("compression")
+            .map(|value| StorageCompression::from_string(value).unwrap_or_else(|e| {
+                eprintln!("{}, сжатие отключено", e);
+                StorageCompression::None
+            }))
+            .unwrap_or_default();
+        // По умолчанию — по числу доступных ядер
+        let load_concurrency = configs.get("load_concurrency")
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()))
+            .max(1);
+        let io_attempts = configs.get("io_retries")
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_IO_ATTEMPTS)
+            .max(1);
+        let io_backoff = configs.get("io_retry_backoff_ms")
+            .and_then(|value| value.parse::<u64>().ok())
+            .map_or(DEFAULT_IO_BACKOFF, Duration::from_millis);
+        Ok(StorageController { format, compression, load_concurrency, io_attempts, io_backoff, backend })
+    }
+
+    /// Временная ошибка ввода-вывода, после которой операцию имеет смысл повторить:
+    /// прерванный системный вызов, занятый ресурс, а в Windows — файл, открытый другим процессом
+    fn is_transient(error: &std::io::Error) -> bool {
+        matches!(error.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock)
+            // ERROR_SHARING_VIOLATION и ERROR_LOCK_VIOLATION
+            || (cfg!(windows) && matches!(error.raw_os_error(), Some(32 | 33)))
+    }
+
+    /// Выполняет операцию бэкенда, повторяя её при временных ошибках не больше io_attempts раз
+    /// с растущей паузой. Постоянные ошибки и последняя временная возвращаются как есть.
+    /// Пауза блокирует поток, поэтому из async-кода операции storage вызываются через
+    /// spawn_blocking (см. CollectionController::read_blocking и write_blocking)
+    fn retry_io<T>(&self, mut operation: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+        let mut delay = self.io_backoff;
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Err(e) if attempt < self.io_attempts && Self::is_transient(&e) => {
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Определяет формат хранения. Формат записывается в файл storage/format при первом запуске,
+    /// и дальше используется именно он, чтобы данные читались в том же формате, в котором записаны
+    fn resolve_format(backend: &dyn StorageBackend, configs: &HashMap<String, String>) -> Result<StorageFormat, std::io::Error> {
+        let configured = match configs.get("format") {
+            Some(value) => StorageFormat::from_string(value).unwrap_or_else(|e| {
+                eprintln!("{}, используется bincode", e);
+                StorageFormat::Bincode
+            }),
+            None => StorageFormat::default(),
+        };
+
+        if let Ok(recorded) = backend.read("format") {
+            let recorded = StorageFormat::from_string(&String::from_utf8_lossy(&recorded))
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Повреждён файл формата storage: {}", e)))?;
+            if recorded != configured {
+                eprintln!(
+                    "Storage записан в формате '{}', настройка '{}' игнорируется",
+                    recorded, configured
+                );
+            }
+            return Ok(recorded);
+        }
+
+        // Хранилища, созданные до появления настройки, всегда записаны в bincode
+        let has_collections = backend.list_dirs("").map(|dirs| !dirs.is_empty()).unwrap_or(false);
+        let format = if has_collections { StorageFormat::Bincode } else { configured };
+        backend.write("format", format.to_string().as_bytes())?;
+        Ok(format)
     }
 
     /// Универсальный метод для сохранения данных в файл
-    fn save_to_file<P: AsRef<Path>>(&self, dir_path: P, file_name: u64, raw_data: Vec<u8>) -> Result<(), std::io::Error> {
-        fs::create_dir_all(&dir_path)?;
-        let file_path = dir_path.as_ref().join(format!("{}.bin", file_name));
-        fs::write(file_path, raw_data)
+    fn save_to_file(&self, dir_path: String, file_name: u64, raw_data: Vec<u8>) -> Result<(), std::io::Error> {
+        let data = self.compression.compress(raw_data)
+            .map_err(std::io::Error::other)?;
+        let data = with_checksum(data);
+        self.retry_io(|| self.backend.write(&format!("{}/{}.bin", dir_path, file_name), &data))
+    }
+
+    /// Читает файл, проверяет его контрольную сумму и распаковывает сжатые данные.
+    /// Повреждённый файл возвращает ошибку InvalidData
+    fn read_file(&self, file_path: &str) -> Result<Vec<u8>, std::io::Error> {
+        let raw_data = self.retry_io(|| self.backend.read(file_path))?;
+        verify_checksum(raw_data).and_then(StorageCompression::decompress).map_err(|e| {
+            eprintln!("Файл {:?} пропущен: {}", file_path, e);
+            std::io::Error::new(ErrorKind::InvalidData, e)
+        })
+    }
+
+    /// Читает один файл: отсутствующий или повреждённый файл даёт None,
+    /// прочие ошибки (после повторов) — VecDbError::Storage
+    fn read_single(&self, file_path: &str, what: &str) -> Result<Option<Vec<u8>>, VecDbError> {
+        match self.read_file(file_path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound || e.kind() == ErrorKind::InvalidData => Ok(None),
+            Err(e) => Err(VecDbError::Storage(format!("Ошибка чтения файла {}: {}", what, e))),
+        }
+    }
+
+    /// Список элементов папки: отсутствующая папка даёт пустой список,
+    /// прочие ошибки (после повторов) — VecDbError::Storage
+    fn list_or_empty(&self, listing: impl FnMut() -> std::io::Result<Vec<String>>, what: &str) -> Result<Vec<String>, VecDbError> {
+        match self.retry_io(listing) {
+            Ok(names) => Ok(names),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(VecDbError::Storage(format!("Ошибка чтения директории {}: {}", what, e))),
+        }
+    }
+
+    /// Хэши файлов вида <hash>.bin в папке
+    fn bin_file_names(&self, dir_path: &str, what: &str) -> Result<Vec<u64>, VecDbError> {
+        Ok(self.list_or_empty(|| self.backend.list_files(dir_path), what)?
+            .iter()
+            .filter_map(|name| name.strip_suffix(".bin").unwrap_or(name).parse::<u64>().ok())
+            .collect())
+    }
+
+    /// Читает все файлы вида <hash>.bin из папки в HashMap (hash -> данные); повреждённые файлы пропускаются
+    fn read_bin_files(&self, dir_path: &str, what: &str) -> Result<HashMap<u64, Vec<u8>>, VecDbError> {
+        self.bin_file_names(dir_path, what)?
+            .into_iter()
+            .filter_map(|hash| {
+                self.read_single(&format!("{}/{}.bin", dir_path, hash), what)
+                    .map(|data| data.map(|data| (hash, data)))
+                    .transpose()
+            })
+            .collect()
     }
 
     /// Сохраняет сырые данные коллекции по hash_id
     pub fn save_collection(&self, collection_name: String, raw_data: Vec<u8>, hash_id: u64) -> Result<(), std::io::Error> {
-        self.save_to_file(format!("{}/storage/{}", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name), hash_id, raw_data)
+        self.save_to_file(collection_name, hash_id, raw_data)
     }
 
     /// Сохраняет сырые данные вектора по hash_id
     pub fn save_vector(&self, collection_name: String, raw_data: Vec<u8>, hash_id: u64) -> Result<(), std::io::Error> {
-        self.save_to_file(format!("{}/storage/{}/vectors", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name), hash_id, raw_data)
+        self.save_to_file(format!("{}/vectors", collection_name), hash_id, raw_data)
     }
 
     /// Сохраняет сырые данные бакета в папку бакета по пути /storage/collection_name/bucket_name/bucket.bin
     pub fn save_bucket(&self, collection_name: String, bucket_name: String, raw_data: Vec<u8>) -> Result<(), std::io::Error> {
-        self.save_to_file(format!("{}/storage/{}/{}", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name, bucket_name), 0, raw_data) // Используем 0 как имя файла bucket.bin
+        self.save_to_file(format!("{}/{}", collection_name, bucket_name), 0, raw_data) // Используем 0 как имя файла bucket.bin
     }
 
     /// Сохраняет вектор в папку бакета по пути /storage/collection_name/bucket_name/vectors/vector_name.bin
     pub fn save_vector_to_bucket(&self, collection_name: String, bucket_name: String, vector_id: u64, raw_data: Vec<u8>) -> Result<(), std::io::Error> {
-        self.save_to_file(format!("{}/storage/{}/{}/vectors", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name, bucket_name), vector_id, raw_data)
+        self.save_to_file(format!("{}/{}/vectors", collection_name, bucket_name), vector_id, raw_data)
     }
 
     /// Загружает вектор из папки бакета
-    pub fn read_vector_from_bucket(&self, collection_name: String, bucket_name: String, vector_id: u64) -> Option<Vec<u8>> {
-        let vector_path_bin = format!("{}/storage/{}/{}/vectors/{}.bin", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name, bucket_name, vector_id);
-        match fs::read(&vector_path_bin) {
-            Ok(data) => Some(data),
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    None
-                } else {
-                    panic!("Ошибка чтения файла вектора из бакета: {:?}", e);
-                }
+    pub fn read_vector_from_bucket(&self, collection_name: String, bucket_name: String, vector_id: u64) -> Result<Option<Vec<u8>>, VecDbError> {
+        self.read_single(&format!("{}/{}/vectors/{}.bin", collection_name, bucket_name, vector_id), "вектора из бакета")
+    }
+
+    /// Читает все векторы из папки бакета и возвращает их в виде HashMap, где ключ — hash (u64), значение — Vec<u8>
+    pub fn read_all_vectors_from_bucket(&self, collection_name: String, bucket_name: String) -> Result<HashMap<u64, Vec<u8>>, VecDbError> {
+        self.read_bin_files(&format!("{}/{}/vectors", collection_name, bucket_name), "векторов бакета")
+    }
+
+    /// Читает векторы из папок всех бакетов коллекции, не глядя на файлы бакетов (0.bin).
+    /// Ключ — ID вектора; если вектор лежит в нескольких папках, остаётся одна копия
+    pub fn read_all_bucket_vectors(&self, collection_name: String) -> Result<HashMap<u64, Vec<u8>>, VecDbError> {
+        let mut vectors = HashMap::new();
+        for bucket_name in self.list_or_empty(|| self.backend.list_dirs(&collection_name), "коллекции")? {
+            if bucket_name.parse::<u64>().is_ok() {
+                vectors.extend(self.read_all_vectors_from_bucket(collection_name.clone(), bucket_name)?);
             }
         }
+        Ok(vectors)
     }
 
     /// Возвращает список имён всех коллекций (папок) в storage
     pub fn get_all_collections_name(&self) -> Vec<String> {
-        let storage_path = format!("{}/storage", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()));
-        let path = Path::new(&storage_path);
-        match fs::read_dir(path) {
-            Ok(entries) => entries.filter_map(|entry| {
-                if let Ok(e) = entry {
-                    let entry_path = e.path();
-                    if entry_path.is_dir() {
-                        if let Some(fname) = entry_path.file_name() {
-                            if let Some(name) = fname.to_str() {
-                                return Some(name.to_string());
-                            }
-                        }
-                    }
-                }
-                None
-            }).collect(),
-            Err(_) => Vec::new(),
-        }
+        self.retry_io(|| self.backend.list_dirs("")).unwrap_or_default()
     }
 
     /// Читает сырые данные коллекции (первый найденный файл в папке коллекции)
-    pub fn read_collection(&self, collection_name: String) -> Option<Vec<u8>> {
-        let col_path = format!("{}/storage/{}", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name);
-        let path = Path::new(&col_path);
-
-        match fs::read_dir(path) {
-            Ok(entries) => {
-                for entry in entries.flatten() {
-                    let entry_path = entry.path();
-                    if entry_path.is_file() {
-                        if let Ok(data) = fs::read(&entry_path) {
-                            return Some(data);
-                        }
-                    }
-                }
-                None
-            }
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    None
-                } else {
-                    panic!("Ошибка чтения директории: {:?}", e);
-                }
+    pub fn read_collection(&self, collection_name: String) -> Result<Option<Vec<u8>>, VecDbError> {
+        for file_name in self.list_or_empty(|| self.backend.list_files(&collection_name), "коллекции")? {
+            if let Some(data) = self.read_single(&format!("{}/{}", collection_name, file_name), "коллекции")? {
+                return Ok(Some(data));
             }
         }
+        Ok(None)
     }
 
     /// Читает все векторы (файлы) из папки vectors коллекции и возвращает их содержимое в виде HashMap, где ключ — hash (u64), значение — Vec<u8>
-    pub fn read_all_vector(&self, collection_name: String) -> HashMap<u64, Vec<u8>> {
-        let vector_path = format!("{}/storage/{}/vectors", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name);
-        let path = Path::new(&vector_path);
-        let mut result = HashMap::new();
-
-        match fs::read_dir(path) {
-            Ok(entries) => {
-                for entry in entries.flatten() {
-                    let entry_path = entry.path();
-                    if entry_path.is_file() {
-                        if let Some(file_name) = entry_path.file_name().and_then(|n| n.to_str()) {
-                            // Извлекаем hash из имени файла (например, "123456.bin" -> 123456)
-                            let hash_str = file_name.strip_suffix(".bin").unwrap_or(file_name);
-                            if let Ok(hash) = hash_str.parse::<u64>() {
-                                if let Ok(data) = fs::read(&entry_path) {
-                                    result.insert(hash, data);
-                                }
-                            }
-                        }
-                    }
-                }
-                result
-            }
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    result
-                } else {
-                    panic!("Ошибка чтения директории: {:?}", e);
-                }
-            }
-        }
+    pub fn read_all_vector(&self, collection_name: String) -> Result<HashMap<u64, Vec<u8>>, VecDbError> {
+        self.read_bin_files(&format!("{}/vectors", collection_name), "векторов")
     }
 
     /// Возвращает вектор хэшей (u64) файлов векторов по названию коллекции (имя файла соответствует хэшу)
-    pub fn get_all_vectors_names(&self, collection_name: String) -> Vec<u64> {
-        let vector_path = format!("{}/storage/{}/vectors", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name);
-        let path = Path::new(&vector_path);
-
-        match fs::read_dir(path) {
-            Ok(entries) => entries.filter_map(|entry| {
-                entry.ok().and_then(|e| {
-                    let entry_path = e.path();
-                    if entry_path.is_file() {
-                        entry_path.file_name()
-                            .and_then(|n| n.to_str())
-                            .and_then(|s| s.strip_suffix(".bin").or(Some(s)))
-                            .and_then(|s| s.parse::<u64>().ok())
-                    } else {
-                        None
-                    }
-                })
-            }).collect(),
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    Vec::new()
-                } else {
-                    panic!("Ошибка чтения директории: {:?}", e);
-                }
-            }
-        }
+    pub fn get_all_vectors_names(&self, collection_name: String) -> Result<Vec<u64>, VecDbError> {
+        self.bin_file_names(&format!("{}/vectors", collection_name), "векторов")
     }
 
     /// Читает конкретный вектор по имени коллекции и имени (или хэшу) вектора
-    pub fn read_vector(&self, collection_name: String, vector_hash: u64) -> Option<Vec<u8>> {
-        let vector_path_bin = format!("{}/storage/{}/vectors/{}.bin", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name, vector_hash);
-        match fs::read(&vector_path_bin) {
-            Ok(data) => Some(data),
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    None
-                } else {
-                    panic!("Ошибка чтения файла вектора: {:?}", e);
-                }
-            }
-        }
+    pub fn read_vector(&self, collection_name: String, vector_hash: u64) -> Result<Option<Vec<u8>>, VecDbError> {
+        self.read_single(&format!("{}/vectors/{}.bin", collection_name, vector_hash), "вектора")
     }
 
     /// Читает все файлы метадаты из папки metadata внутри коллекции и возвращает их содержимое в виде HashMap<u64, Vec<u8>>, где ключ - hash (имя файла без расширения)
-    pub fn read_all_metadata(&self, collection_name: String) -> HashMap<u64, Vec<u8>> {
-        let metadata_path = format!("{}/storage/{}/metadata", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name);
-        let path = Path::new(&metadata_path);
-
-        match fs::read_dir(path) {
-            Ok(entries) => {
-                let mut result = HashMap::new();
-                for entry in entries.flatten() {
-                    let entry_path = entry.path();
-                    if entry_path.is_file() {
-                        if let Some(file_name) = entry_path.file_name().and_then(|n| n.to_str()) {
-                            // Получаем hash из имени файла (без .bin)
-                            let hash_str = file_name.strip_suffix(".bin").unwrap_or(file_name);
-                            if let Ok(hash) = hash_str.parse::<u64>() {
-                                if let Ok(data) = fs::read(&entry_path) {
-                                    result.insert(hash, data);
-                                }
-                            }
-                        }
-                    }
-                }
-                result
-            },
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    HashMap::new()
-                } else {
-                    panic!("Ошибка чтения директории метадаты: {:?}", e);
-                }
-            }
-        }
+    pub fn read_all_metadata(&self, collection_name: String) -> Result<HashMap<u64, Vec<u8>>, VecDbError> {
+        self.read_bin_files(&format!("{}/metadata", collection_name), "метадаты")
     }
 
     /// Возвращает вектор имён файлов метадаты по названию коллекции (без расширения .bin) в виде Vec<u64>
-    pub fn get_all_metadata_names(&self, collection_name: String) -> Vec<u64> {
-        let metadata_path = format!("{}/storage/{}/metadata", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name);
-        let path = Path::new(&metadata_path);
-
-        match fs::read_dir(path) {
-            Ok(entries) => entries.filter_map(|entry| {
-                entry.ok().and_then(|e| {
-                    let entry_path = e.path();
-                    if entry_path.is_file() {
-                        entry_path.file_name()
-                            .and_then(|n| n.to_str())
-                            .and_then(|s| s.strip_suffix(".bin").or(Some(s)))
-                            .and_then(|s| s.parse::<u64>().ok())
-                    } else {
-                        None
-                    }
-                })
-            }).collect(),
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    Vec::new()
-                } else {
-                    panic!("Ошибка чтения директории метадаты: {:?}", e);
-                }
-            }
-        }
+    pub fn get_all_metadata_names(&self, collection_name: String) -> Result<Vec<u64>, VecDbError> {
+        self.bin_file_names(&format!("{}/metadata", collection_name), "метадаты")
     }
 
     /// Читает конкретный файл метадаты по имени коллекции и имени файла метадаты (без расширения)
-    pub fn read_metadata(&self, collection_name: String, metadata_hash: u64) -> Option<Vec<u8>> {
-        let metadata_path_bin = format!("{}/storage/{}/metadata/{}.bin", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name, metadata_hash);
-        match fs::read(&metadata_path_bin) {
-            Ok(data) => Some(data),
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    None
-                } else {
-                    panic!("Ошибка чтения файла метадаты: {:?}", e);
-                }
+    pub fn read_metadata(&self, collection_name: String, metadata_hash: u64) -> Result<Option<Vec<u8>>, VecDbError> {
+        self.read_single(&format!("{}/metadata/{}.bin", collection_name, metadata_hash), "метадаты")
+    }
+
+    /// Читает все бакеты (файлы) из папки buckets коллекции и возвращает их содержимое в виде HashMap, где ключ — hash (u64), значение — Vec<u8>
+    pub fn read_all_buckets(&self, collection_name: String) -> Result<HashMap<String, Vec<u8>>, VecDbError> {
+        let mut buckets = HashMap::new();
+        for bucket_name in self.list_or_empty(|| self.backend.list_dirs(&collection_name), "коллекции")? {
+            if bucket_name != "vectors"
+                && let Some(data) = self.read_single(&format!("{}/{}/0.bin", collection_name, bucket_name), "бакета")?
+            {
+                buckets.insert(bucket_name, data);
             }
         }
+        Ok(buckets)
     }
 
-    /// Читает все бакеты (файлы) из папки buckets коллекции и возвращает их содержимое в виде HashMap, где ключ — hash (u64), значение — Vec<u8>
-    pub fn read_all_buckets(&self, collection_name: String) -> HashMap<String, Vec<u8>> {
-        let collection_path = format!("{}/storage/{}", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name);
-        let path = Path::new(&collection_path);
-        let mut result = HashMap::new();
-
-        match fs::read_dir(path) {
-            Ok(entries) => {
-                for entry in entries.flatten() {
-                    let entry_path = entry.path();
-                    if entry_path.is_dir() {
-                        if let Some(bucket_name) = entry_path.file_name().and_then(|n| n.to_str()) {
-                            if bucket_name == "vectors" {
-                                continue;
-                            }
-                            
-                            let bucket_file_path = entry_path.join("0.bin");
-                            if let Ok(data) = fs::read(&bucket_file_path) {
-                                result.insert(bucket_name.to_string(), data);
-                            }
+    /// Возвращает вектор ID бакетов (String) по названию коллекции
+    pub fn get_all_buckets_names(&self, collection_name: String) -> Result<Vec<String>, VecDbError> {
+        Ok(self.list_or_empty(|| self.backend.list_dirs(&collection_name), "коллекции")?
+            .into_iter()
+            // Папка бакета названа числом (ID бакета) и содержит файл 0.bin (bucket.bin);
+            // папку vectors на верхнем уровне это условие отсекает
+            .filter(|bucket_name| bucket_name.parse::<u64>().is_ok())
+            .filter(|bucket_name| self.backend.exists(&format!("{}/{}/0.bin", collection_name, bucket_name)))
+            .collect())
+    }
+
+    /// Удаляет из storage бакеты и векторы коллекции, которых больше нет в памяти
+    /// (live: ID бакета -> ID его векторов). Файлы вне папок бакетов не трогаются
+    pub fn remove_stale(&self, collection_name: &str, live: &HashMap<u64, HashSet<u64>>) {
+        for bucket_name in self.retry_io(|| self.backend.list_dirs(collection_name)).unwrap_or_default() {
+            let Ok(bucket_id) = bucket_name.parse::<u64>() else { continue };
+            let bucket_path = format!("{}/{}", collection_name, bucket_name);
+            let stale: Vec<String> = match live.get(&bucket_id) {
+                None => vec![bucket_path],
+                Some(vector_ids) => {
+                    // Если папку не удалось прочитать, файлы бакета лучше оставить
+                    let stored = match self.bin_file_names(&format!("{}/vectors", bucket_path), "векторов бакета") {
+                        Ok(stored) => stored,
+                        Err(e) => {
+                            eprintln!("Устаревшие векторы бакета {} не удалены: {}", bucket_path, e);
+                            continue;
                         }
-                    }
+                    };
+                    stored.into_iter()
+                        .filter(|id| !vector_ids.contains(id))
+                        .map(|id| format!("{}/vectors/{}.bin", bucket_path, id))
+                        .collect()
                 }
-                result
-            }
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    result
-                } else {
-                    panic!("Ошибка чтения директории коллекции: {:?}", e);
+            };
+            for path in stale {
+                if let Err(e) = self.retry_io(|| self.backend.remove(&path)) {
+                    eprintln!("Не удалось удалить устаревший файл {}: {:?}", path, e);
                 }
             }
         }
     }
 
-    /// Возвращает вектор ID бакетов (String) по названию коллекции
-    pub fn get_all_buckets_names(&self, collection_name: String) -> Vec<String> {
-        let collection_path = format!("{}/storage/{}", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name);
-        let path = Path::new(&collection_path);
-
-        match fs::read_dir(path) {
-            Ok(entries) => entries.filter_map(|entry| {
-                entry.ok().and_then(|e| {
-                    let entry_path = e.path();
-                    if entry_path.is_dir() {
-                        if let Some(bucket_name) = entry_path.file_name().and_then(|n| n.to_str()) {
-                            // Пропускаем папку vectors, если она есть на верхнем уровне
-                            if bucket_name == "vectors" {
-                                None
-                            } else {
-                                // Проверяем, что в папке есть файл 0.bin (bucket.bin)
-                                let bucket_file_path = entry_path.join("0.bin");
-                                if bucket_file_path.exists() {
-                                    // Проверяем, что имя папки является числом (ID бакета)
-                                    if bucket_name.parse::<u64>().is_ok() {
-                                        Some(bucket_name.to_string())
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                }
-                            }
-                        } else {
-                            None
-                        }
+    /// Суммарный размер всех файлов в папке, включая вложенные
+    fn dir_size(&self, dir_path: &str) -> u64 {
+        let child = |name: &String| if dir_path.is_empty() { name.clone() } else { format!("{}/{}", dir_path, name) };
+        let files: u64 = self.backend.list_files(dir_path).unwrap_or_default()
+            .iter()
+            .filter_map(|name| self.backend.file_size(&child(name)).ok())
+            .sum();
+        let dirs: u64 = self.backend.list_dirs(dir_path).unwrap_or_default()
+            .iter()
+            .map(|name| self.dir_size(&child(name)))
+            .sum();
+        files + dirs
+    }
+
+    /// Проверяет, что в storage можно писать: записывает и сразу удаляет пустой служебный файл
+    pub fn check_writable(&self) -> Result<(), VecDbError> {
+        let probe = ".health_check";
+        self.retry_io(|| self.backend.write(probe, b""))
+            .and_then(|_| self.retry_io(|| self.backend.remove(probe)))
+            .map_err(|e| VecDbError::Storage(format!("Storage недоступен для записи: {}", e)))
+    }
+
+    /// Занимаемое место по коллекциям: имя коллекции -> размеры файлов коллекции, бакетов и векторов
+    pub fn disk_usage(&self) -> HashMap<String, CollectionDiskUsage> {
+        self.get_all_collections_name()
+            .into_iter()
+            .map(|collection_name| {
+                let mut usage = CollectionDiskUsage::default();
+                for file_name in self.backend.list_files(&collection_name).unwrap_or_default() {
+                    usage.collection_file += self.backend.file_size(&format!("{}/{}", collection_name, file_name)).unwrap_or(0);
+                }
+                for dir_name in self.backend.list_dirs(&collection_name).unwrap_or_default() {
+                    let dir_path = format!("{}/{}", collection_name, dir_name);
+                    if dir_name == "vectors" {
+                        usage.vectors += self.dir_size(&dir_path);
+                    } else if dir_name.parse::<u64>().is_ok() {
+                        let vectors = self.dir_size(&format!("{}/vectors", dir_path));
+                        usage.vectors += vectors;
+                        usage.buckets += self.dir_size(&dir_path) - vectors;
                     } else {
-                        None
+                        usage.collection_file += self.dir_size(&dir_path);
                     }
-                })
-            }).collect(),
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    Vec::new()
-                } else {
-                    panic!("Ошибка чтения директории коллекции: {:?}", e);
                 }
-            }
-        }
+                (collection_name, usage)
+            })
+            .collect()
+    }
+
+    /// Общий размер хранилища в байтах, включая служебные файлы
+    pub fn total_disk_usage(&self) -> u64 {
+        self.dir_size("")
     }
 
     /// Читает конкретный бакет по имени коллекции и имени (или хэшу) бакета
-    pub fn read_bucket(&self, collection_name: String, bucket_name: String) -> Option<Vec<u8>> {
-        let bucket_path_bin = format!("{}/storage/{}/{}/0.bin", self.configs.get(&"path".to_string()).unwrap_or(&".".to_string()), collection_name, bucket_name);
-        match fs::read(&bucket_path_bin) {
-            Ok(data) => Some(data),
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    None
-                } else {
-                    panic!("Ошибка чтения файла бакета: {:?}", e);
-                }
-            }
-        }
+    pub fn read_bucket(&self, collection_name: String, bucket_name: String) -> Result<Option<Vec<u8>>, VecDbError> {
+        self.read_single(&format!("{}/{}/0.bin", collection_name, bucket_name), "бакета")
     }
 }
 
 //  ConnectionController impl
 
+/// Маршруты, изменяющие коллекции, векторы или storage; в режиме server.read_only они отклоняются
+pub const MUTATING_ROUTES: &[&str] = &[
+    "/collection",
+    "/collection/delete",
+    "/collection/update",
+    "/vector",
+    "/vector/batch",
+    "/vector/text-chunks",
+    "/vector/update",
+    "/vector/upsert",
+    "/vector/delete",
+    "/admin/flush",
+    "/admin/reindex",
+    "/admin/prune",
+    "/admin/migrate-metadata",
+];
+
+/// Размер пула блокирующих потоков tokio, если server.max_blocking_threads не задан (как в tokio)
+pub const DEFAULT_MAX_BLOCKING_THREADS: usize = 512;
+
 impl ConnectionController {
+    /// Число рабочих и максимальное число блокирующих потоков рантайма из настроек
+    /// server.worker_threads и server.max_blocking_threads. Рабочих потоков по умолчанию
+    /// столько же, сколько ядер; некорректные и нулевые значения заменяются значениями по умолчанию
+    pub fn runtime_threads(configs: &HashMap<String, String>) -> (usize, usize) {
+        let setting = |key: &str| configs.get(key)
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|threads| *threads > 0);
+        let worker_threads = setting("worker_threads")
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |cores| cores.get()));
+        let max_blocking_threads = setting("max_blocking_threads").unwrap_or(DEFAULT_MAX_BLOCKING_THREADS);
+        (worker_threads, max_blocking_threads)
+    }
+
+    /// Строит многопоточный рантайм tokio с числом потоков из runtime_threads
+    pub fn build_runtime(configs: &HashMap<String, String>) -> std::io::Result<tokio::runtime::Runtime> {
+        let (worker_threads, max_blocking_threads) = Self::runtime_threads(configs);
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(worker_threads)
+            .max_blocking_threads(max_blocking_threads)
+            .enable_all()
+            .build()
+    }
+
     /// Создаёт новый ConnectionController с заданным StorageController и ConfigLoader
     pub fn new(config_loader: ConfigLoader) -> ConnectionController {
         ConnectionController { 
-            configs: config_loader.get("connection") 
+            configs: config_loader.connection_configs() 
         }
     }
 
     /// Запускает HTTP RPC-сервер на указанном адресе. Нужен общий доступ к CollectionController.
     /// Возвращает controller обратно для возможности dump после остановки.
+    /// После сигнала остановки сервер перестаёт принимать соединения и дожидается завершения
+    /// уже принятых запросов (не дольше server.shutdown_grace_secs, если задан);
+    /// финальный dump следует выполнять под блокировкой на запись.
     pub async fn connection_handler(&mut self, controller: Arc<RwLock<CollectionController>>, addr: SocketAddr) -> Result<Arc<RwLock<CollectionController>>, Box<dyn std::error::Error + Send + Sync>> {
-        // Создаём канал для сигнала остановки
-        let (shutdown_tx, mut shutdown_rx) = broadcast::channel::<()>(1);
+        // Однократный сигнал остановки: /stop может прийти сколько угодно раз
+        let shutdown = Arc::new(ShutdownSignal::new());
         
         let app_state = AppState { 
             controller: Arc::clone(&controller), 
             configs: self.configs.clone(),
-            shutdown_tx,
+            shutdown: Arc::clone(&shutdown),
+            query_cache: Arc::new(QueryCache::new(
+                self.configs.get("query_cache_size").and_then(|size| size.parse().ok()).unwrap_or(0)
+            )),
         };
 
         let app = Router::new()
             .route("/collection", post(crate::core::handlers::add_collection))
             .route("/collection/delete", post(crate::core::handlers::delete_collection))
+            .route("/collection/update", post(crate::core::handlers::update_collection))
             .route("/collection/all", post(crate::core::handlers::get_all_collections))
+            .route("/collection/get", post(crate::core::handlers::get_collection))
+            .route("/collection/exists", post(crate::core::handlers::collection_exists))
+            .route("/collection/buckets", post(crate::core::handlers::bucket_summaries))
+            .route("/collection/query-bucket", post(crate::core::handlers::query_bucket))
+            .route("/collection/bucket", post(crate::core::handlers::get_bucket))
+            .route("/collection/preload", post(crate::core::handlers::preload_collection))
             .route("/vector", post(crate::core::handlers::add_vector))
+            .route("/vector/batch", post(crate::core::handlers::add_vectors_batch))
+            .route("/vector/text-chunks", post(crate::core::handlers::add_text_chunks))
             .route("/vector/update", post(crate::core::handlers::update_vector))
+            .route("/vector/upsert", post(crate::core::handlers::upsert_vector))
             .route("/vector/get", post(crate::core::handlers::get_vector))
+            .route("/vector/get-many", post(crate::core::handlers::get_many_vectors))
+            .route("/vector/exists", post(crate::core::handlers::vector_exists))
             .route("/vector/delete", post(crate::core::handlers::delete_vector))
             .route("/vector/filter", post(crate::core::handlers::filter_by_metadata))
             .route("/vector/similar", post(crate::core::handlers::find_similar))
+            .route("/vector/similar-batch", post(crate::core::handlers::find_similar_batch))
+            .route("/vector/similar-scores", post(crate::core::handlers::similar_scores))
+            .route("/vector/similar-to", post(crate::core::handlers::similar_to))
+            .route("/vector/compare", post(crate::core::handlers::compare_vectors))
+            .route("/info", get(crate::core::handlers::info))
+            .route("/health", get(crate::core::handlers::health))
+            .route("/admin/disk-usage", get(crate::core::handlers::disk_usage))
+            .route("/admin/flush", post(crate::core::handlers::flush))
+            .route("/admin/vector-raw", post(crate::core::handlers::vector_raw))
+            .route("/admin/reindex", post(crate::core::handlers::reindex))
+            .route("/admin/prune", post(crate::core::handlers::prune))
+            .route("/admin/migrate-metadata", post(crate::core::handlers::migrate_metadata))
+            .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", load_openapi_spec()));
+
+        // Ограничение одновременных запросов (server.max_concurrent_requests); /stop под него
+        // не попадает, чтобы перегруженный сервер можно было остановить
+        let max_concurrent = self.configs.get("max_concurrent_requests")
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|max| *max > 0);
+        let app = match max_concurrent {
+            Some(max) => Self::with_concurrency_limit(app, max),
+            None => app,
+        };
+        // Режим только для чтения (server.read_only): изменяющие маршруты отвечают 403
+        let app = if self.configs.get("read_only").is_some_and(|value| value == "true") {
+            Self::with_read_only(app)
+        } else {
+            app
+        };
+        let app = app
             .route("/stop", post(crate::core::handlers::stop))
-            .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", load_openapi_spec()))
             .with_state(app_state);
 
         let listener = TcpListener::bind(addr).await?;
+
+        // Периодический dump без остановки сервера (server.auto_flush_interval_secs)
+        let auto_flush = self.configs.get("auto_flush_interval_secs")
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map(|secs| Self::spawn_auto_flush(Arc::clone(&controller), Duration::from_secs(secs)));
         
-        // Запускаем сервер с graceful shutdown
-        axum::serve(listener, app)
-            .with_graceful_shutdown(async move {
-                shutdown_rx.recv().await.ok();
-            })
-            .await?;
+        // Запускаем сервер с graceful shutdown; зависшие запросы ждём не дольше server.shutdown_grace_secs
+        let served = Self::serve_with_grace(listener, app, shutdown, self.shutdown_grace()).await;
+        if let Some(task) = auto_flush {
+            task.abort();
+        }
+        served?;
         
         Ok(controller)
     }
+
+    /// Сколько ждать зависшие запросы после сигнала остановки (server.shutdown_grace_secs);
+    /// None — ждать без ограничения
+    pub fn shutdown_grace(&self) -> Option<Duration> {
+        self.configs.get("shutdown_grace_secs")
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Duration::from_secs)
+    }
+
+    /// Обслуживает запросы до сигнала остановки, затем дожидается уже принятых запросов.
+    /// Если задан grace, через grace после сигнала оставшиеся соединения бросаются
+    /// (их задачи завершатся вместе с рантаймом), и управление возвращается вызывающему,
+    /// чтобы выполнить финальный dump. Без grace ожидание не ограничено
+    pub async fn serve_with_grace(
+        listener: TcpListener,
+        app: Router,
+        shutdown: Arc<ShutdownSignal>,
+        grace: Option<Duration>,
+    ) -> std::io::Result<()> {
+        let (signalled_tx, signalled_rx) = tokio::sync::oneshot::channel();
+        let served = axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                shutdown.wait().await;
+                let _ = signalled_tx.send(());
+            })
+            .into_future();
+        let Some(grace) = grace else {
+            return served.await;
+        };
+
+        tokio::pin!(served);
+        let grace_expired = async move {
+            match signalled_rx.await {
+                Ok(()) => tokio::time::sleep(grace).await,
+                // Сервер завершился без сигнала: срабатывает ветка served
+                Err(_) => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            result = &mut served => result,
+            _ = grace_expired => {
+                eprintln!("Не все запросы завершились за {:?} после остановки, соединения закрываются принудительно", grace);
+                Ok(())
+            }
+        }
+    }
+
+    /// Ограничивает число одновременно обрабатываемых запросов к маршрутам router:
+    /// запросы сверх max сразу получают 503
+    pub fn with_concurrency_limit<S: Clone + Send + Sync + 'static>(router: Router<S>, max: usize) -> Router<S> {
+        let semaphore = Arc::new(Semaphore::new(max));
+        router.layer(axum::middleware::from_fn_with_state(semaphore, crate::core::handlers::limit_concurrency))
+    }
+
+    /// Запрещает изменяющие маршруты router (MUTATING_ROUTES): они сразу получают 403,
+    /// остальные маршруты работают как обычно. Проверка идёт по сопоставленному маршруту,
+    /// поэтому middleware навешивается после маршрутизации
+    pub fn with_read_only<S: Clone + Send + Sync + 'static>(router: Router<S>) -> Router<S> {
+        router.route_layer(axum::middleware::from_fn(crate::core::handlers::reject_mutations))
+    }
+
+    /// Запускает фоновую задачу, которая раз в interval сохраняет все коллекции.
+    /// Первое сохранение происходит через interval после запуска
+    pub fn spawn_auto_flush(controller: Arc<RwLock<CollectionController>>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = CollectionController::dump_async(&controller).await {
+                    eprintln!("Ошибка фонового сохранения: {}", e);
+                }
+            }
+        })
+    }
 }
 
 
@@ -452,47 +981,178 @@ impl ConnectionController {
 impl CollectionController {
     /// Создаёт новый CollectionController с заданным StorageController
     pub fn new(storage_controller: Arc<StorageController>) -> CollectionController {
-        CollectionController { storage_controller, collections: None }
+        CollectionController {
+            storage_controller,
+            collections: None,
+            lsh_seed: Some(DEFAULT_LSH_SEED),
+            num_hashes: DEFAULT_NUM_HASHES,
+            bucket_width: DEFAULT_BUCKET_WIDTH,
+            merge_small_buckets: None,
+        }
+    }
+
+    /// Задаёт порог слияния малых бакетов и применяет его ко всем коллекциям в памяти
+    /// и к тем, что будут созданы или загружены позже
+    pub fn set_merge_small_buckets(&mut self, threshold: Option<usize>) {
+        self.merge_small_buckets = threshold.filter(|&threshold| threshold > 0);
+        for collection in self.collections.iter_mut().flatten() {
+            collection.buckets_controller.merge_small_buckets = self.merge_small_buckets;
+        }
+    }
+
+    /// Задаёт seed проекций LSH для создаваемых коллекций. Уже существующие
+    /// коллекции продолжают использовать сохранённый вместе с ними seed
+    pub fn set_lsh_seed(&mut self, seed: Option<u64>) {
+        self.lsh_seed = seed;
+    }
+
+    /// Задаёт количество хэш-функций и начальную ширину бакетов LSH для создаваемых коллекций.
+    /// Как и seed, параметры сохраняются вместе с коллекцией и на существующие коллекции не влияют
+    pub fn set_lsh_params(&mut self, num_hashes: usize, bucket_width: f32) {
+        self.num_hashes = num_hashes;
+        self.bucket_width = bucket_width;
     }
 
     /// Добавляет новую коллекцию с указанным именем
-    pub fn add_collection(&mut self, name: String, lsh_metric: LSHMetric, vector_dimension: usize) -> Result<(), &'static str> {
-        let collections = self.collections.get_or_insert_with(Vec::new);
-        collections.push(Collection::new(Some(name), lsh_metric, vector_dimension));
+    pub fn add_collection(&mut self, name: String, lsh_metric: LSHMetric, vector_dimension: usize) -> Result<(), VecDbError> {
+        self.add_collection_with_tables(name, lsh_metric, vector_dimension, 1)
+    }
+
+    /// Добавляет новую коллекцию с заданным количеством хэш-таблиц LSH
+    pub fn add_collection_with_tables(&mut self, name: String, lsh_metric: LSHMetric, vector_dimension: usize, num_tables: usize) -> Result<(), VecDbError> {
+        // Коллекция нулевой размерности строит вырожденный LSH, который ломается при первом поиске
+        if vector_dimension == 0 {
+            return Err(VecDbError::Validation("Размерность векторов должна быть больше нуля".to_string()));
+        }
+        if self.collection_exists(&name) {
+            return Err(VecDbError::AlreadyExists(format!("Коллекция '{}' уже существует", name)));
+        }
+        let mut collection = Collection::with_lsh(Some(name), lsh_metric, vector_dimension, num_tables, self.lsh_seed, self.num_hashes, self.bucket_width);
+        collection.buckets_controller.merge_small_buckets = self.merge_small_buckets;
+        self.collections.get_or_insert_with(Vec::new).push(collection);
+        Ok(())
+    }
+
+    /// Задаёт метаданные, которые получает каждый новый вектор коллекции
+    pub fn set_default_metadata(&mut self, name: &str, metadata: Metadata) -> Result<(), VecDbError> {
+        let collection = self.get_collection_mut(name)
+            .ok_or_else(|| VecDbError::collection_not_found(name))?;
+        collection.default_metadata = metadata;
+        Ok(())
+    }
+
+    /// Включает автонастройку ширины бакетов коллекции после sample_size векторов
+    pub fn enable_auto_tune(&mut self, name: &str, sample_size: usize) -> Result<(), VecDbError> {
+        let collection = self.get_collection_mut(name)
+            .ok_or_else(|| VecDbError::collection_not_found(name))?;
+        collection.enable_auto_tune(sample_size);
+        Ok(())
+    }
+
+    /// Ограничивает количество векторов коллекции. При вставке в заполненную коллекцию
+    /// она отклоняется (reject) или вытесняется вектор по политике fifo/lru.
+    /// Уменьшение лимита применяется при следующей вставке
+    pub fn set_max_vectors(&mut self, name: &str, max_vectors: Option<usize>, eviction: EvictionPolicy) -> Result<(), VecDbError> {
+        if max_vectors == Some(0) {
+            return Err(VecDbError::Validation("max_vectors должен быть больше нуля".to_string()));
+        }
+        let collection = self.get_collection_mut(name)
+            .ok_or_else(|| VecDbError::collection_not_found(name))?;
+        collection.max_vectors = max_vectors;
+        collection.eviction = eviction;
+        Ok(())
+    }
+
+    /// Включает инвертированный индекс метаданных коллекции по заданным ключам (пустой список — выключает)
+    pub fn set_indexed_metadata_keys(&mut self, name: &str, keys: Vec<String>) -> Result<(), VecDbError> {
+        let collection = self.get_collection_mut(name)
+            .ok_or_else(|| VecDbError::collection_not_found(name))?;
+        collection.set_indexed_metadata_keys(keys);
         Ok(())
     }
 
     /// Удаляет коллекцию по имени
-    pub fn delete_collection(&mut self, name: String) -> Result<(), &'static str> {
+    pub fn delete_collection(&mut self, name: String) -> Result<(), VecDbError> {
         match self.collections.as_mut() {
             Some(collections) => {
                 if let Some(pos) = collections.iter().position(|c| c.name == name) {
                     collections.remove(pos);
                     Ok(())
                 } else {
-                    Err("Коллекция с таким именем не найдена")
+                    Err(VecDbError::collection_not_found(&name))
                 }
             }
-            None => Err("Коллекции не инициализированы"),
+            None => Err(VecDbError::collection_not_found(&name)),
         }
     }
 
+    /// Меняет метрику коллекции и заново раскладывает её векторы по бакетам.
+    /// Возвращает количество перенесённых векторов; новая метрика попадёт в storage при следующем dump
+    pub fn update_collection_metric(&mut self, name: &str, lsh_metric: LSHMetric) -> Result<usize, VecDbError> {
+        let collection = self.get_collection_mut(name)
+            .ok_or_else(|| VecDbError::collection_not_found(name))?;
+        collection.set_metric(lsh_metric)
+    }
+
+    /// Формат, в котором записаны данные storage
+    pub fn storage_format(&self) -> StorageFormat {
+        self.storage_controller.format
+    }
+
+    /// Сохранённые в storage байты вектора из папки бакета, без десериализации
+    /// (контрольная сумма проверяется, сжатые данные распаковываются)
+    pub fn read_raw_vector(&self, collection_name: &str, bucket_id: u64, vector_id: u64) -> Result<Vec<u8>, VecDbError> {
+        self.storage_controller.read_vector_from_bucket(collection_name.to_string(), bucket_id.to_string(), vector_id)?
+            .ok_or_else(|| VecDbError::NotFound(format!(
+                "Вектор {} не найден в бакете {} коллекции '{}'", vector_id, bucket_id, collection_name
+            )))
+    }
+
     /// Получает ссылку на коллекцию по имени
     pub fn get_collection(&self, name: &str) -> Option<&Collection> {
         self.collections.as_ref()?.iter().find(|c| c.name == name)
     }
 
-    /// Получает мутабельную ссылку на коллекцию по имени
+    /// Проверяет существование коллекции без обращения к её данным
+    pub fn collection_exists(&self, name: &str) -> bool {
+        self.get_collection(name).is_some()
+    }
+
+    /// Получает мутабельную ссылку на коллекцию по имени. Коллекция считается изменённой
+    /// (получает новую версию), поэтому закэшированные результаты поиска по ней больше не используются
     pub fn get_collection_mut(&mut self, name: &str) -> Option<&mut Collection> {
-        self.collections.as_mut()?.iter_mut().find(|c| c.name == name)
+        let collection = self.collections.as_mut()?.iter_mut().find(|c| c.name == name)?;
+        collection.touch();
+        Some(collection)
     }
 
-    /// Получает список всех коллекций
+    /// Получает список всех коллекций в памяти, отсортированный по имени
     pub fn get_all_collections(&self) -> Vec<&Collection> {
-        match &self.collections {
+        let mut collections: Vec<&Collection> = match &self.collections {
             Some(collections) => collections.iter().collect(),
             None => Vec::new(),
+        };
+        collections.sort_by(|a, b| a.name.cmp(&b.name));
+        collections
+    }
+
+    /// Все коллекции: загруженные в память и те, что есть только в storage.
+    /// Для незагруженных читается только описание коллекции, в память они не добавляются.
+    /// Как и get_all_collections, список отсортирован по имени
+    pub fn get_all_collections_with_storage(&self) -> Vec<ListedCollection<'_>> {
+        let mut listed: Vec<ListedCollection> = self.get_all_collections()
+            .into_iter()
+            .map(ListedCollection::Loaded)
+            .collect();
+        for name in self.storage_controller.get_all_collections_name() {
+            if !self.collection_exists(&name)
+                && let Ok(Some(collection)) = Self::read_description_from_storage(&self.storage_controller, &name)
+            {
+                listed.push(ListedCollection::OnDisk(Box::new(collection)));
+            }
         }
+        listed.sort_by(|a, b| a.name.cmp(&b.name));
+        listed
     }
 
     /// Добавляет вектор в коллекцию по имени коллекции
@@ -500,128 +1160,600 @@ impl CollectionController {
         &mut self,
         collection_name: &str,
         embedding: Vec<f32>,
-        metadata: HashMap<String, String>,
-    ) -> Result<u64, &'static str> {
+        metadata: Metadata,
+    ) -> Result<u64, VecDbError> {
+        self.add_vector_with_options(collection_name, embedding, metadata, None)
+    }
+
+    /// Добавляет вектор, при normalize = Some(true) предварительно нормируя его по L2.
+    /// Нормирование выполняется до хэширования, а у сохранённого вектора выставляется флаг normalized
+    pub fn add_vector_with_options(
+        &mut self,
+        collection_name: &str,
+        mut embedding: Vec<f32>,
+        metadata: Metadata,
+        normalize: Option<bool>,
+    ) -> Result<u64, VecDbError> {
         // Проверяем, инициализированы ли коллекции
         let collections = match self.collections.as_mut() {
             Some(c) => c,
-            None => return Err("Коллекции не инициализированы"),
+            None => return Err(VecDbError::collection_not_found(collection_name)),
         };
 
         // Ищем коллекцию по имени
         let collection = match collections.iter_mut().find(|col| col.name == collection_name) {
             Some(col) => col,
-            None => return Err("Коллекция с указанным именем не найдена"),
+            None => return Err(VecDbError::collection_not_found(collection_name)),
         };
+        collection.touch();
+
+        validate_embedding(&embedding, collection.vector_dimension)?;
+
+        // По умолчанию векторы сохраняются как есть
+        let normalize = normalize.unwrap_or(false);
+        if normalize {
+            normalize_in_place(&mut embedding)?;
+        }
+
+        collection.check_capacity()?;
+        let metadata = collection.with_default_metadata(metadata);
+        let id = collection.buckets_controller.add_vector(embedding, metadata)?;
+        if normalize {
+            collection.buckets_controller.mark_normalized(id);
+        }
+        collection.admit_vector(id)?;
+        collection.maybe_auto_tune()?;
+        Ok(id)
+    }
 
-        // Проверяем размерность вектора
+    /// Приближённая дедупликация: ID вектора коллекции, почти совпадающего с embedding
+    /// (косинусное расстояние не больше tolerance). Проверяется только бакет, куда попал бы
+    /// новый вектор; при normalize = Some(true) бакет определяется по нормированному эмбеддингу
+    pub fn find_near_duplicate(
+        &self,
+        collection_name: &str,
+        embedding: &[f32],
+        normalize: Option<bool>,
+        tolerance: f32,
+    ) -> Result<Option<u64>, VecDbError> {
+        let collection = self.get_collection(collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
         if embedding.len() != collection.vector_dimension {
-            return Err("Размерность вектора не соответствует размерности коллекции");
+            return Err(VecDbError::DimensionMismatch { expected: collection.vector_dimension, actual: embedding.len() });
+        }
+
+        let norm = l2_norm(embedding);
+        if normalize.unwrap_or(false) && norm > 0.0 {
+            let normalized: Vec<f32> = embedding.iter().map(|x| x / norm).collect();
+            return Ok(collection.buckets_controller.find_near_duplicate(&normalized, tolerance));
+        }
+        Ok(collection.buckets_controller.find_near_duplicate(embedding, tolerance))
+    }
+
+    /// Добавляет пакет векторов в коллекцию. Ошибка одного вектора (размерность, NaN,
+    /// занятый ID) не прерывает вставку остальных: результат возвращается для каждого
+    /// вектора в порядке входа. Ошибка всего вызова — только если коллекция не найдена
+    pub fn add_vectors_batch(
+        &mut self,
+        collection_name: &str,
+        items: Vec<BatchInsertItem>,
+    ) -> Result<Vec<Result<u64, String>>, VecDbError> {
+        let collection = self.get_collection_mut(collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
+
+        let outcomes = items
+            .into_iter()
+            .map(|(id, mut embedding, metadata, normalize)| {
+                // Как и в add_vector_with_options: эмбеддинг проверяется до нормировки
+                validate_embedding(&embedding, collection.vector_dimension).map_err(|e| e.to_string())?;
+                let normalize = normalize.unwrap_or(false);
+                if normalize {
+                    normalize_in_place(&mut embedding).map_err(|e| e.to_string())?;
+                }
+                collection.check_capacity().map_err(|e| e.to_string())?;
+                let metadata = collection.with_default_metadata(metadata);
+                let controller = &mut collection.buckets_controller;
+                let id = match id {
+                    Some(id) => controller.add_vector_with_id(id, embedding, metadata),
+                    None => controller.add_vector(embedding, metadata),
+                }
+                .map_err(|e| e.to_string())?;
+                if normalize {
+                    controller.mark_normalized(id);
+                }
+                collection.admit_vector(id).map_err(|e| e.to_string())?;
+                Ok(id)
+            })
+            .collect();
+        collection.maybe_auto_tune()?;
+        Ok(outcomes)
+    }
+
+    /// Разбивает текст на перекрывающиеся чанки и строит эмбеддинги всех чанков одной загрузкой модели.
+    /// Коллекции не затрагиваются, а построение эмбеддингов долгое, поэтому из async-кода функция
+    /// вызывается через spawn_blocking до взятия блокировки контроллера
+    pub fn embed_text_chunks(text: &str, chunk_size: usize, overlap: usize) -> Result<Vec<Vec<f32>>, VecDbError> {
+        let chunks = chunk_text(text, chunk_size, overlap).map_err(|e| VecDbError::Validation(e.to_string()))?;
+        make_embeddings_batch(&chunks)
+            .map_err(|e| VecDbError::Validation(format!("Не удалось построить эмбеддинг: {}", e)))
+    }
+
+    /// Добавляет в коллекцию чанки текста с готовыми эмбеддингами (см. embed_text_chunks).
+    /// Каждый чанк получает метаданные doc_id (общий для документа) и chunk_index.
+    /// Документ добавляется целиком или не добавляется: если чанки не помещаются в max_vectors,
+    /// документ отклоняется до вставки при любой политике eviction (вытеснение не затрагивает
+    /// ни чужие векторы, ни ранние чанки), а если вставка чанка не удалась, уже добавленные чанки удаляются
+    pub fn add_text_chunks(
+        &mut self,
+        collection_name: &str,
+        text: &str,
+        embeddings: Vec<Vec<f32>>,
+        metadata: Metadata,
+    ) -> Result<(String, Vec<u64>), VecDbError> {
+        let collection = self.get_collection(collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
+        if let Some(max_vectors) = collection.max_vectors {
+            let remaining = max_vectors.saturating_sub(collection.buckets_controller.total_vectors());
+            if embeddings.len() > remaining {
+                return Err(VecDbError::Validation(format!(
+                    "Документ из {} чанков не помещается в коллекцию '{}': свободно {} из {} векторов",
+                    embeddings.len(), collection_name, remaining, max_vectors
+                )));
+            }
+        }
+
+        let doc_id = metadata.get("doc_id")
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| calculate_hash(&(text, Utc::now().timestamp_nanos_opt())).to_string());
+
+        let mut ids = Vec::with_capacity(embeddings.len());
+        for (chunk_index, embedding) in embeddings.into_iter().enumerate() {
+            let mut chunk_metadata = metadata.clone();
+            chunk_metadata.insert("doc_id".to_string(), MetadataValue::from(doc_id.clone()));
+            chunk_metadata.insert("chunk_index".to_string(), MetadataValue::Int(chunk_index as i64));
+            match self.add_vector(collection_name, embedding, chunk_metadata) {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    for id in ids {
+                        let _ = self.delete_vector(collection_name, id);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok((doc_id, ids))
+    }
+
+    /// Начинает добавление векторов в коллекцию без загрузки её в память: коллекция
+    /// сохраняется и выгружается, а векторы пишутся сразу в файлы своих бакетов.
+    /// Искать по коллекции можно после finalize_append. Для коллекций с max_vectors недоступно,
+    /// так как вытеснение требует всех векторов в памяти. Выполняет синхронный ввод-вывод
+    /// (как и AppendWriter), поэтому из async-кода вызывается через spawn_blocking
+    pub fn begin_append(&mut self, name: &str) -> Result<AppendWriter, VecDbError> {
+        if let Some(collection) = self.get_collection(name) {
+            if collection.max_vectors.is_some() {
+                return Err(VecDbError::Validation(format!("Коллекция '{}' ограничена max_vectors, режим добавления недоступен", name)));
+            }
+            self.dump_one(collection)?;
+            if let Some(collections) = self.collections.as_mut() {
+                collections.retain(|collection| collection.name != name);
+            }
+        }
+
+        let collection = Self::read_description_from_storage(&self.storage_controller, name)?
+            .ok_or_else(|| VecDbError::collection_not_found(name))?;
+        if collection.max_vectors.is_some() {
+            return Err(VecDbError::Validation(format!("Коллекция '{}' ограничена max_vectors, режим добавления недоступен", name)));
         }
+        Ok(AppendWriter {
+            storage_controller: Arc::clone(&self.storage_controller),
+            collection,
+            buckets: HashSet::new(),
+            written: 0,
+        })
+    }
 
-        match collection.buckets_controller.add_vector(embedding, metadata) {
-            Ok(id) => Ok(id),
-            Err(_) => Err("Ошибка при добавлении вектора в LSH бакет"),
+    /// Завершает режим добавления: загружает коллекцию из storage вместе с добавленными
+    /// векторами (копия в памяти, если её загрузили за это время, заменяется) и выполняет
+    /// ожидающую автонастройку. Возвращает (количество бакетов, количество векторов)
+    pub fn finalize_append(&mut self, writer: AppendWriter) -> Result<(usize, usize), VecDbError> {
+        let name = writer.collection.name;
+        let collection = Self::read_from_storage(&self.storage_controller, &name)?
+            .ok_or_else(|| VecDbError::NotFound(format!("Коллекция \'{}\' не найдена в storage", name)))?;
+        if let Some(collections) = self.collections.as_mut() {
+            collections.retain(|collection| collection.name != name);
         }
+        self.insert_loaded(collection);
+
+        let collection = self.get_collection_mut(&name)
+            .ok_or_else(|| VecDbError::collection_not_found(&name))?;
+        collection.maybe_auto_tune()?;
+        Ok((collection.buckets_controller.count(), collection.buckets_controller.total_vectors()))
     }
 
-    /// Сохраняет одну коллекцию и все её векторы и метаданные
-    pub fn dump_one(&self, collection: &Collection) {
+    /// Сохраняет одну коллекцию и все её векторы и метаданные. Бакеты и векторы, которые не
+    /// удалось записать, пропускаются с сообщением в лог; возвращается первая из таких ошибок
+    pub fn dump_one(&self, collection: &Collection) -> Result<(), VecDbError> {
         let collection_name = &collection.name;
-        match collection.dump() {
+        match collection.dump(self.storage_controller.format) {
             Ok((raw_data, hash_id)) => {
                 if let Err(e) = self.storage_controller.save_collection(collection_name.clone(), raw_data, hash_id) {
                     eprintln!("Ошибка сохранения коллекции '{}': {:?}", collection_name, e);
-                    return;
+                    return Err(e.into());
                 }
                 println!("Коллекция '{}' успешно сохранена (hash_id: {}).", collection_name, hash_id);
             }
             Err(_) => {
                 eprintln!("Ошибка сериализации коллекции '{}'.", collection_name);
-                return;
+                return Err(VecDbError::Serialization(format!("Ошибка сериализации коллекции '{}'", collection_name)));
             }
         }
 
-        // Сохраняем бакеты
+        let mut first_error: Option<VecDbError> = None;
+
+        // Сохраняем бакеты; пустые бакеты не записываются
         if let Some(ref buckets) = collection.buckets_controller.buckets {
-            for bucket in buckets {
-                match bucket.dump() {
+            for bucket in buckets.iter().filter(|bucket| !bucket.vectors_controller.is_empty()) {
+                match bucket.dump(self.storage_controller.format) {
                     Ok((bucket_raw_data, _hash_id)) => {
                         match self.storage_controller.save_bucket(collection_name.clone(), bucket.id.to_string(), bucket_raw_data) {
                             Ok(_) => println!("Бакет {} успешно сохранён в коллекции '{}'.", bucket.id, collection_name),
-                            Err(e) => eprintln!("Ошибка сохранения бакета {} в коллекции '{}': {:?}", bucket.id, collection_name, e),
+                            Err(e) => {
+                                eprintln!("Ошибка сохранения бакета {} в коллекции '{}': {:?}", bucket.id, collection_name, e);
+                                first_error.get_or_insert(e.into());
+                            }
                         }
                     }
                     Err(_) => {
                         eprintln!("Ошибка сериализации бакета {}.", bucket.id);
+                        first_error.get_or_insert(VecDbError::Serialization(format!("Ошибка сериализации бакета {}", bucket.id)));
                     }
                 }
             }
         }
 
         // Сохраняем векторы в соответствующие бакеты
-        for (bucket_id, vector_id, vector_raw_data) in collection.buckets_controller.dump_vectors() {
+        let mut live: HashMap<u64, HashSet<u64>> = HashMap::new();
+        for (bucket_id, vector_id, vector_raw_data) in collection.buckets_controller.dump_vectors(self.storage_controller.format) {
+            live.entry(bucket_id).or_default().insert(vector_id);
             match self.storage_controller.save_vector_to_bucket(collection_name.clone(), bucket_id.to_string(), vector_id, vector_raw_data) {
                 Ok(_) => println!("Вектор с ID {} успешно сохранён в бакете {} коллекции '{}'.", vector_id, bucket_id, collection_name),
-                Err(e) => eprintln!("Ошибка сохранения вектора с ID {} в бакете {} коллекции '{}': {:?}", vector_id, bucket_id, collection_name, e),
+                Err(e) => {
+                    eprintln!("Ошибка сохранения вектора с ID {} в бакете {} коллекции '{}': {:?}", vector_id, bucket_id, collection_name, e);
+                    first_error.get_or_insert(e.into());
+                }
             }
         }
+
+        // Удаляем бакеты и векторы, которые были перемещены или удалены в памяти,
+        // иначе при загрузке они появятся повторно
+        for bucket in collection.buckets_controller.buckets.iter().flatten().filter(|bucket| !bucket.vectors_controller.is_empty()) {
+            live.entry(bucket.id).or_default();
+        }
+        self.storage_controller.remove_stale(collection_name, &live);
+        first_error.map_or(Ok(()), Err)
     }
 
-    /// Сохраняет все коллекции
-    pub fn dump(&self) {
+    /// Сохраняет все коллекции. Возвращает результат сохранения каждой коллекции по имени
+    pub fn dump(&self) -> Vec<(String, Result<(), VecDbError>)> {
         match &self.collections {
-            Some(collections) if !collections.is_empty() => {
-                for collection in collections {
-                    self.dump_one(collection);
+            Some(collections) if !collections.is_empty() => collections
+                .iter()
+                .map(|collection| (collection.name.clone(), self.dump_one(collection)))
+                .collect(),
+            _ => {
+                println!("Нет коллекций для сохранения.");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Сохраняет все коллекции под блокировкой на чтение в пуле блокирующих задач tokio,
+    /// чтобы файловый ввод-вывод не занимал поток рантайма
+    pub async fn dump_async(controller: &Arc<RwLock<CollectionController>>) -> Result<Vec<(String, Result<(), VecDbError>)>, VecDbError> {
+        Self::read_blocking(controller, |ctrl| ctrl.dump()).await
+    }
+
+    /// Выполняет work под блокировкой контроллера на чтение в пуле блокирующих задач tokio.
+    /// Для обработчиков, обращающихся к storage: повторы ввода-вывода ждут через thread::sleep
+    /// и не должны занимать поток рантайма
+    pub async fn read_blocking<T: Send + 'static>(
+        controller: &Arc<RwLock<CollectionController>>,
+        work: impl FnOnce(&CollectionController) -> T + Send + 'static,
+    ) -> Result<T, VecDbError> {
+        let ctrl = Arc::clone(controller).read_owned().await;
+        tokio::task::spawn_blocking(move || work(&ctrl)).await
+            .map_err(|e| VecDbError::Storage(e.to_string()))
+    }
+
+    /// То же, что read_blocking, но под блокировкой на запись
+    pub async fn write_blocking<T: Send + 'static>(
+        controller: &Arc<RwLock<CollectionController>>,
+        work: impl FnOnce(&mut CollectionController) -> T + Send + 'static,
+    ) -> Result<T, VecDbError> {
+        let mut ctrl = Arc::clone(controller).write_owned().await;
+        tokio::task::spawn_blocking(move || work(&mut ctrl)).await
+            .map_err(|e| VecDbError::Storage(e.to_string()))
+    }
+
+    /// Применяет миграцию метаданных ко всем векторам коллекции за один проход под блокировкой
+    /// вызывающего. Эмбеддинги и раскладка по бакетам не меняются; файлы изменённых векторов
+    /// сразу перезаписываются в storage. Возвращает количество изменённых векторов или первую
+    /// ошибку сохранения: миграция в памяти применяется целиком, но часть файлов могла не записаться
+    pub fn migrate_metadata(&mut self, name: &str, migration: &MetadataMigration) -> Result<usize, VecDbError> {
+        let storage_controller = Arc::clone(&self.storage_controller);
+        let collection = self.get_collection_mut(name)
+            .ok_or_else(|| VecDbError::collection_not_found(name))?;
+
+        let mut updated = 0;
+        let mut first_error: Option<VecDbError> = None;
+        for bucket in collection.buckets_controller.buckets.iter_mut().flatten() {
+            for vector in bucket.vectors_controller.vectors.iter_mut().flatten() {
+                if !migration.apply(&mut vector.metadata) {
+                    continue;
                 }
+                updated += 1;
+                match vector.dump(storage_controller.format) {
+                    Ok((raw_data, vector_id)) => {
+                        if let Err(e) = storage_controller.save_vector_to_bucket(name.to_string(), bucket.id.to_string(), vector_id, raw_data) {
+                            eprintln!("Ошибка сохранения вектора с ID {} в бакете {} коллекции '{}': {:?}", vector_id, bucket.id, name, e);
+                            first_error.get_or_insert(e.into());
+                        }
+                    }
+                    Err(_) => {
+                        eprintln!("Ошибка сериализации вектора с ID {}.", vector.hash_id());
+                        first_error.get_or_insert(VecDbError::Serialization(format!("Ошибка сериализации вектора с ID {}", vector.hash_id())));
+                    }
+                }
+            }
+        }
+        collection.buckets_controller.rebuild_metadata_index();
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(updated),
+        }
+    }
+
+    /// Удаляет пустые бакеты во всех коллекциях. Возвращает количество удалённых бакетов
+    /// по коллекциям (только коллекции, где что-то удалено). Пустые бакеты не сохраняются
+    /// в storage, поэтому отдельный dump не нужен
+    pub fn prune_empty_buckets(&mut self) -> HashMap<String, usize> {
+        self.collections
+            .iter_mut()
+            .flatten()
+            .map(|collection| (collection.name.clone(), collection.buckets_controller.prune_empty_buckets()))
+            .filter(|(_, pruned)| *pruned > 0)
+            .collect()
+    }
+
+    /// Занимаемое место в хранилище: общий размер и разбивка по коллекциям.
+    /// Учитываются только сохранённые данные, несохранённые изменения в памяти не видны
+    pub fn disk_usage(&self) -> (u64, HashMap<String, CollectionDiskUsage>) {
+        (self.storage_controller.total_disk_usage(), self.storage_controller.disk_usage())
+    }
+
+    /// Проверка записи в storage для глубокой проверки здоровья (GET /health?deep=true)
+    pub fn check_storage(&self) -> Result<(), VecDbError> {
+        self.storage_controller.check_writable()
+    }
+
+    /// Читает коллекцию со всеми бакетами и векторами из storage, не трогая коллекции в памяти.
+    /// Выполняет синхронный файловый ввод-вывод, поэтому из async-кода вызывается через spawn_blocking.
+    /// None, если коллекции нет в storage; ошибка, если storage не удалось прочитать
+    pub fn read_from_storage(storage_controller: &StorageController, name: &str) -> Result<Option<Collection>, VecDbError> {
+        let Some(mut collection) = Self::read_description_from_storage(storage_controller, name)? else {
+            return Ok(None);
+        };
+
+        // Загружаем бакеты
+        let raw_buckets = storage_controller.read_all_buckets(name.to_string())?;
+        // Конвертируем HashMap<String, Vec<u8>> в HashMap<u64, Vec<u8>> для совместимости
+        let mut buckets_data: HashMap<u64, Vec<u8>> = HashMap::new();
+        for (bucket_name, data) in raw_buckets {
+            // Парсим ID бакета из имени
+            if let Ok(bucket_id) = bucket_name.parse::<u64>() {
+                buckets_data.insert(bucket_id, data);
             }
-            _ => println!("Нет коллекций для сохранения."),
         }
+        collection.buckets_controller.load(buckets_data, storage_controller.format)?;
+
+        // Загружаем векторы из бакетов
+        collection.buckets_controller.load_vectors_from_buckets(storage_controller, name.to_string())?;
+
+        Ok(Some(collection))
+    }
+
+    /// Читает из storage только описание коллекции (метрика, размерность, seed), без бакетов и векторов
+    fn read_description_from_storage(storage_controller: &StorageController, name: &str) -> Result<Option<Collection>, VecDbError> {
+        let Some(raw_collection) = storage_controller.read_collection(name.to_string())? else {
+            return Ok(None);
+        };
+        let mut collection = Collection::new(None, LSHMetric::Euclidean, 384); // Временные значения, будут загружены из файла
+        collection.load(raw_collection, storage_controller.format)?;
+        Ok(Some(collection))
+    }
+
+    /// Добавляет загруженную коллекцию в память, если коллекции с таким именем там ещё нет
+    fn insert_loaded(&mut self, mut collection: Collection) -> bool {
+        if self.collection_exists(&collection.name) {
+            return false;
+        }
+        collection.buckets_controller.merge_small_buckets = self.merge_small_buckets;
+        self.collections.get_or_insert_with(Vec::new).push(collection);
+        true
     }
 
-    /// Загружает одну коллекцию по имени из storage
+    /// Загружает одну коллекцию по имени из storage. Коллекция, которую не удалось прочитать,
+    /// пропускается с сообщением в лог
     pub fn load_one(&mut self, name: String) {
-        if let Some(raw_collection) = self.storage_controller.read_collection(name.clone()) {
-            let mut collection = Collection::new(None, LSHMetric::Euclidean, 384); // Временные значения, будут загружены из файла
-            collection.load(raw_collection);
-
-            // Загружаем бакеты
-            let raw_buckets = self.storage_controller.read_all_buckets(name.clone());
-            // Конвертируем HashMap<String, Vec<u8>> в HashMap<u64, Vec<u8>> для совместимости
-            let mut buckets_data: HashMap<u64, Vec<u8>> = HashMap::new();
-            for (bucket_name, data) in raw_buckets {
-                // Парсим ID бакета из имени
-                if let Ok(bucket_id) = bucket_name.parse::<u64>() {
-                    buckets_data.insert(bucket_id, data);
-                }
+        match Self::read_from_storage(&self.storage_controller, &name) {
+            Ok(Some(collection)) => {
+                self.insert_loaded(collection);
             }
-            collection.buckets_controller.load(buckets_data);
+            Ok(None) => {}
+            Err(e) => eprintln!("Коллекция '{}' не загружена: {}", name, e),
+        }
+    }
 
-            // Загружаем векторы из бакетов
-            collection.buckets_controller.load_vectors_from_buckets(&self.storage_controller, name.clone());
+    /// Перестраивает бакеты коллекции по векторам из storage: файлы бакетов игнорируются,
+    /// бакет каждого вектора заново вычисляется по LSH, после чего коллекция сохраняется
+    /// и заменяет версию в памяти (несохранённые изменения теряются).
+    /// Возвращает (количество бакетов, количество векторов)
+    pub fn reindex_collection(&mut self, name: &str) -> Result<(usize, usize), VecDbError> {
+        let mut collection = Self::read_description_from_storage(&self.storage_controller, name)?
+            .ok_or_else(|| VecDbError::NotFound(format!("Коллекция \'{}\' не найдена в storage", name)))?;
+        collection.buckets_controller.merge_small_buckets = self.merge_small_buckets;
 
-            match &mut self.collections {
-                Some(collections) => {
-                    collections.push(collection);
-                }
-                None => {
-                    self.collections = Some(vec![collection]);
-                }
+        let mut stored = VectorController::new();
+        stored.load(self.storage_controller.read_all_bucket_vectors(name.to_string())?, self.storage_controller.format)?;
+        for vector in stored.vectors.take().unwrap_or_default() {
+            collection.buckets_controller.insert_vector(vector)?;
+        }
+
+        self.dump_one(&collection)?;
+        let counts = (collection.buckets_controller.count(), collection.buckets_controller.total_vectors());
+        if let Some(collections) = self.collections.as_mut() {
+            collections.retain(|c| c.name != name);
+        }
+        self.insert_loaded(collection);
+        Ok(counts)
+    }
+
+    /// Финальный dump при остановке сервера. Блокировка на запись дожидается операций, ещё
+    /// удерживающих контроллер. С wait ожидание ограничено: если за wait блокировку на запись
+    /// получить не удалось, dump выполняется под блокировкой на чтение (зависшему поиску это
+    /// не мешает), а если контроллер удерживает зависшее изменение — пропускается.
+    /// Возвращает true, если dump выполнен и все коллекции сохранены
+    pub async fn dump_on_shutdown(controller: &Arc<RwLock<CollectionController>>, wait: Option<Duration>) -> bool {
+        let saved = |results: Vec<(String, Result<(), VecDbError>)>| results.iter().all(|(_, result)| result.is_ok());
+        let Some(wait) = wait else {
+            return saved(controller.write().await.dump());
+        };
+        if let Ok(ctrl) = tokio::time::timeout(wait, controller.write()).await {
+            return saved(ctrl.dump());
+        }
+        match controller.try_read() {
+            Ok(ctrl) => {
+                eprintln!("Контроллер занят дольше {:?}: dump выполняется под блокировкой на чтение, незавершённые операции в него не попадут", wait);
+                saved(ctrl.dump())
+            }
+            Err(_) => {
+                eprintln!("Контроллер удерживается зависшим изменением дольше {:?}: финальный dump пропущен", wait);
+                false
+            }
+        }
+    }
+
+    /// Асинхронно загружает одну коллекцию: чтение с диска идёт в пуле блокирующих задач tokio,
+    /// а блокировка на запись берётся только для вставки готовой коллекции.
+    /// Возвращает true, если коллекция была загружена сейчас
+    pub async fn load_one_async(
+        controller: &Arc<RwLock<CollectionController>>,
+        name: String,
+    ) -> Result<bool, VecDbError> {
+        let storage_controller = Arc::clone(&controller.read().await.storage_controller);
+        let collection = tokio::task::spawn_blocking(move || Self::read_from_storage(&storage_controller, &name)).await
+            .map_err(|e| VecDbError::Storage(e.to_string()))??;
+        match collection {
+            Some(collection) => Ok(controller.write().await.insert_loaded(collection)),
+            None => Ok(false),
+        }
+    }
+
+    /// Асинхронно загружает все коллекции из storage, не блокируя поток рантайма: чтение
+    /// (read_all_from_storage) идёт в пуле блокирующих задач tokio, а блокировка на запись
+    /// берётся только для вставки готовых коллекций. Возвращает количество загруженных коллекций
+    pub async fn load_async(controller: &Arc<RwLock<CollectionController>>) -> Result<usize, VecDbError> {
+        let storage_controller = Arc::clone(&controller.read().await.storage_controller);
+        let loaded = tokio::task::spawn_blocking(move || {
+            let names = storage_controller.get_all_collections_name();
+            Self::read_all_from_storage(&storage_controller, names)
+        }).await
+            .map_err(|e| VecDbError::Storage(e.to_string()))?;
+
+        let mut ctrl = controller.write().await;
+        let mut count = 0;
+        for collection in loaded {
+            if ctrl.insert_loaded(collection) {
+                count += 1;
             }
         }
+        Ok(count)
     }
 
-    /// Загружает все коллекции из storage
+    /// Читает коллекции из storage параллельно, не больше storage.load_concurrency одновременно.
+    /// Коллекция, которую не удалось прочитать, пропускается с сообщением в лог и не мешает
+    /// чтению остальных. Результат отсортирован по имени
+    fn read_all_from_storage(storage_controller: &StorageController, names: Vec<String>) -> Vec<Collection> {
+        let pending = std::sync::Mutex::new(names.into_iter());
+        let mut loaded: Vec<Collection> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..storage_controller.load_concurrency.max(1))
+                .map(|_| scope.spawn(|| {
+                    let mut read = Vec::new();
+                    loop {
+                        let Some(name) = pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).next() else {
+                            break;
+                        };
+                        match Self::read_from_storage(storage_controller, &name) {
+                            Ok(Some(collection)) => read.push(collection),
+                            Ok(None) => {}
+                            Err(e) => eprintln!("Коллекция '{}' не загружена: {}", name, e),
+                        }
+                    }
+                    read
+                }))
+                .collect();
+            workers.into_iter().flat_map(|worker| worker.join().unwrap_or_default()).collect()
+        });
+        loaded.sort_by(|a, b| a.name.cmp(&b.name));
+        loaded
+    }
+
+    /// Прогревает одну коллекцию: загружает её из storage, если она ещё не в памяти.
+    /// Возвращает (была ли загружена сейчас, количество бакетов, количество векторов)
+    pub fn preload_collection(&mut self, name: &str) -> Result<(bool, usize, usize), VecDbError> {
+        // Коллекцию, уже загруженную в память, с диска не читаем
+        let loaded = !self.collection_exists(name)
+            && match Self::read_from_storage(&self.storage_controller, name)? {
+                Some(collection) => self.insert_loaded(collection),
+                None => false,
+            };
+
+        let collection = self.get_collection(name)
+            .ok_or_else(|| VecDbError::NotFound(format!("Коллекция \'{}\' не найдена в storage", name)))?;
+        Ok((
+            loaded,
+            collection.buckets_controller.count(),
+            collection.buckets_controller.total_vectors(),
+        ))
+    }
+
+    /// Асинхронный вариант preload_collection для HTTP-обработчиков: чтение с диска не блокирует рантайм
+    pub async fn preload_collection_async(
+        controller: &Arc<RwLock<CollectionController>>,
+        name: &str,
+    ) -> Result<(bool, usize, usize), VecDbError> {
+        let exists = controller.read().await.collection_exists(name);
+        let loaded = !exists && Self::load_one_async(controller, name.to_string()).await?;
+
+        let ctrl = controller.read().await;
+        let collection = ctrl.get_collection(name)
+            .ok_or_else(|| VecDbError::NotFound(format!("Коллекция \'{}\' не найдена в storage", name)))?;
+        Ok((
+            loaded,
+            collection.buckets_controller.count(),
+            collection.buckets_controller.total_vectors(),
+        ))
+    }
+
+    /// Загружает все коллекции из storage (параллельно, как load_async)
     pub fn load(&mut self) {
         let collection_names = self.storage_controller.get_all_collections_name();
         let mut count = 0;
 
-        for name in collection_names {
-            let before = self.collections.as_ref().map(|c| c.len()).unwrap_or(0);
-            self.load_one(name);
-            let after = self.collections.as_ref().map(|c| c.len()).unwrap_or(0);
-            if after > before {
+        for collection in Self::read_all_from_storage(&self.storage_controller, collection_names) {
+            if self.insert_loaded(collection) {
                 count += 1;
             }
         }
@@ -645,17 +1777,51 @@ impl CollectionController {
         Some(collection.buckets_controller.get_all_buckets())
     }
 
+    /// Сводка по бакетам коллекции (самые заполненные первыми), опционально только top бакетов
+    pub fn bucket_summaries(
+        &self,
+        collection_name: &str,
+        top: Option<usize>,
+    ) -> Result<Vec<BucketSummaryRow>, VecDbError> {
+        let collection = self.get_collection(collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
+        let mut summaries = collection.buckets_controller.bucket_summaries();
+        if let Some(top) = top {
+            summaries.truncate(top);
+        }
+        Ok(summaries)
+    }
+
+    /// Вычисляет бакет, в который хэшируется запрос: (ID бакета, существует ли бакет, размер бакета)
+    pub fn query_bucket(
+        &self,
+        collection_name: &str,
+        query: &[f32],
+    ) -> Result<(u64, bool, usize), VecDbError> {
+        let collection = self.get_collection(collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
+        if query.len() != collection.vector_dimension {
+            return Err(VecDbError::DimensionMismatch { expected: collection.vector_dimension, actual: query.len() });
+        }
+        let lsh = collection.buckets_controller.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let bucket_id = collection.buckets_controller.resolve_query_bucket(lsh.hash(query));
+        let bucket = collection.buckets_controller.get_bucket(bucket_id);
+        Ok((bucket_id, bucket.is_some(), bucket.map(|b| b.size()).unwrap_or(0)))
+    }
+
     /// Получает вектор по ID из коллекции
     pub fn get_vector(
         &self,
         collection_name: &str,
         vector_id: u64,
-    ) -> Result<&Vector, Box<dyn std::error::Error>> {
+    ) -> Result<&Vector, VecDbError> {
         let collection = self.get_collection(collection_name)
-            .ok_or_else(|| format!("Коллекция '{}' не найдена", collection_name))?;
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
         
-        collection.buckets_controller.get_vector(vector_id)
-            .ok_or_else(|| format!("Вектор с ID {} не найден в коллекции '{}'", vector_id, collection_name).into())
+        let vector = collection.buckets_controller.get_vector(vector_id)
+            .ok_or_else(|| VecDbError::NotFound(format!("Вектор с ID {} не найден в коллекции '{}'", vector_id, collection_name)))?;
+        collection.record_access([vector_id]);
+        Ok(vector)
     }
 
     /// Обновляет вектор в коллекции, при необходимости перемещая его в другой бакет
@@ -664,20 +1830,44 @@ impl CollectionController {
         collection_name: &str,
         vector_id: u64,
         new_embedding: Option<Vec<f32>>,
-        new_metadata: Option<HashMap<String, String>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        new_metadata: Option<Metadata>,
+    ) -> Result<(), VecDbError> {
         let collection = self.get_collection_mut(collection_name)
-            .ok_or_else(|| format!("Коллекция '{}' не найдена", collection_name))?;
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
         
-        // Проверяем размерность нового вектора, если он предоставлен
+        // Проверяем новый вектор, если он предоставлен
         if let Some(ref embedding) = new_embedding {
-            if embedding.len() != collection.vector_dimension {
-                return Err(format!("Размерность вектора {} не соответствует размерности коллекции {}", 
-                    embedding.len(), collection.vector_dimension).into());
-            }
+            validate_embedding(embedding, collection.vector_dimension)?;
+        }
+        
+        collection.buckets_controller.update_vector(vector_id, new_embedding, new_metadata)
+    }
+
+    /// Вставляет вектор с заданным ID или заменяет существующий (при необходимости перемещая его в другой бакет).
+    /// Возвращает true, если вектор был вставлен, и false, если обновлён
+    pub fn upsert_vector(
+        &mut self,
+        collection_name: &str,
+        vector_id: u64,
+        embedding: Vec<f32>,
+        metadata: Option<Metadata>,
+    ) -> Result<bool, VecDbError> {
+        let collection = self.get_collection_mut(collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
+
+        validate_embedding(&embedding, collection.vector_dimension)?;
+
+        if collection.buckets_controller.get_vector(vector_id).is_some() {
+            collection.buckets_controller.update_vector(vector_id, Some(embedding), metadata)?;
+            Ok(false)
+        } else {
+            collection.check_capacity()?;
+            let metadata = collection.with_default_metadata(metadata.unwrap_or_default());
+            collection.buckets_controller.add_vector_with_id(vector_id, embedding, metadata)?;
+            collection.admit_vector(vector_id)?;
+            collection.maybe_auto_tune()?;
+            Ok(true)
         }
-        
-        collection.buckets_controller.update_vector(vector_id, new_embedding, new_metadata)
     }
 
     /// Удаляет вектор по ID из коллекции
@@ -685,12 +1875,13 @@ impl CollectionController {
         &mut self,
         collection_name: &str,
         vector_id: u64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<(), VecDbError> {
         let collection = self.get_collection_mut(collection_name)
-            .ok_or_else(|| format!("Коллекция '{}' не найдена", collection_name))?;
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
         
-        collection.buckets_controller.remove_vector(vector_id)
-            .map_err(|e| e.into())
+        collection.buckets_controller.remove_vector(vector_id)?;
+        collection.forget_vector(vector_id);
+        Ok(())
     }
 
     pub fn find_similar(
@@ -698,58 +1889,193 @@ impl CollectionController {
         collection_name: String, 
         query: &Vec<f32>, 
         k: usize
-    ) -> Result<Vec<(u64, usize, f32)>, Box<dyn std::error::Error>> {
-        let collection = self.get_collection(&collection_name);
-        match collection {
-            Some(current) => {
-                // Получаем LSH для вычисления хеша запроса
-                let lsh = current.buckets_controller.lsh.as_ref()
-                    .ok_or("LSH не инициализирован")?;
-                
-                // Вычисляем хеш для запроса
-                let query_hash = lsh.hash(query);
-                
-                // Ищем бакет с этим хешем
-                if let Some(ref buckets) = current.buckets_controller.buckets {
-                    if let Some(bucket) = buckets.iter().find(|b| b.hash_id() == query_hash) {
-                        // Проверяем размер бакета
-                        if bucket.size() >= k {
-                            // Если в бакете достаточно векторов, ищем напрямую в этом бакете
-                            return current.buckets_controller.find_similar(query, k);
-                        }
-                    }
+    ) -> Result<Vec<(u64, usize, f32)>, VecDbError> {
+        self.find_similar_with_options(collection_name, query, k, &SearchOptions::default())
+            .map(|outcome| outcome.results)
+    }
+
+    /// Ищет похожие векторы с дополнительными параметрами поиска (например, дедлайном).
+    /// Найденные векторы считаются использованными для политики вытеснения lru
+    pub fn find_similar_with_options(
+        &self, 
+        collection_name: String, 
+        query: &Vec<f32>, 
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<SearchOutcome, VecDbError> {
+        let current = self.get_collection(&collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(&collection_name))?;
+        let outcome = Self::search_collection(current, query, k, options)?;
+        current.record_search_hits(&outcome.results);
+        Ok(outcome)
+    }
+
+    /// Поиск в коллекции: в бакете запроса, в нескольких бакетах или с переранжированием
+    fn search_collection(
+        current: &Collection,
+        query: &Vec<f32>,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<SearchOutcome, VecDbError> {
+        // Проверяем размерность до хэширования: LSH::hash паникует на несовпадении
+        if query.len() != current.vector_dimension {
+            return Err(VecDbError::DimensionMismatch { expected: current.vector_dimension, actual: query.len() });
+        }
+
+        if let Some(candidate_factor) = options.rerank {
+            return current.buckets_controller.find_similar_reranked(query, k, candidate_factor, options);
+        }
+
+        // Получаем LSH для вычисления хеша запроса
+        let lsh = current.buckets_controller.lsh.as_ref()
+            .ok_or_else(VecDbError::lsh_not_initialized)?;
+        
+        // Вычисляем хеш для запроса
+        let query_hash = current.buckets_controller.resolve_query_bucket(lsh.hash(query));
+        
+        // Ищем бакет с этим хешем
+        if let Some(ref buckets) = current.buckets_controller.buckets {
+            if let Some(bucket) = buckets.iter().find(|b| b.hash_id() == query_hash) {
+                // Проверяем размер бакета (исключённые векторы не считаются)
+                if bucket.size() >= k + options.exclude_ids.len() {
+                    // Если в бакете достаточно векторов, ищем напрямую в этом бакете
+                    return current.buckets_controller.find_similar_with_options(query, k, options);
                 }
-                
-                // Если бакет не найден или в нем мало векторов, ищем в нескольких бакетах
-                current.buckets_controller.find_similar_multi_bucket(query, k)
             }
-            None => Err(format!("Коллекция '{}' не найдена", collection_name).into())
         }
+        
+        // Если бакет не найден или в нем мало векторов, ищем в нескольких бакетах
+        current.buckets_controller.find_similar_multi_bucket_with_options(query, k, options)
+    }
+
+    /// Ищет векторы, похожие на сохранённый вектор vector_id: его эмбеддинг берётся из коллекции
+    /// и используется как запрос. При exclude_self сам вектор в результаты не попадает
+    pub fn find_similar_to(
+        &self,
+        collection_name: &str,
+        vector_id: u64,
+        k: usize,
+        exclude_self: bool,
+    ) -> Result<SearchOutcome, VecDbError> {
+        let query = self.get_vector(collection_name, vector_id)?.data.clone();
+        let options = SearchOptions {
+            exclude_ids: exclude_self.then_some(vector_id).into_iter().collect(),
+            ..SearchOptions::default()
+        };
+        self.find_similar_with_options(collection_name.to_string(), &query, k, &options)
+    }
+
+    /// Ищет похожие векторы для нескольких запросов сразу. Размерность всех запросов
+    /// проверяется до начала поиска (первый неподходящий запрос даёт DimensionMismatch);
+    /// результаты идут в порядке запросов
+    pub fn find_similar_batch(
+        &self,
+        collection_name: &str,
+        queries: &[Vec<f32>],
+        k: usize,
+    ) -> Result<Vec<SimilarResults>, VecDbError> {
+        let collection = self.get_collection(collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
+
+        if let Some(query) = queries.iter().find(|q| q.len() != collection.vector_dimension) {
+            return Err(VecDbError::DimensionMismatch { expected: collection.vector_dimension, actual: query.len() });
+        }
+
+        // Коллекция уже найдена: ищем в ней напрямую, без повторного поиска по имени на каждый запрос
+        let options = SearchOptions::default();
+        queries
+            .iter()
+            .map(|query| {
+                let outcome = Self::search_collection(collection, query, k, &options)?;
+                collection.record_search_hits(&outcome.results);
+                Ok(outcome.results)
+            })
+            .collect()
     }
 
     /// Фильтрует векторы по метаданным в указанной коллекции
     pub fn filter_by_metadata(
         &self,
         collection_name: &str,
-        filters: &HashMap<String, String>,
-    ) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+        filters: &Metadata,
+    ) -> Result<Vec<u64>, VecDbError> {
         let collection = self.get_collection(collection_name);
         match collection {
             Some(current) => {
                 Ok(current.filter_by_metadata(filters))
             }
-            None => Err(format!("Коллекция '{}' не найдена", collection_name).into())
+            None => Err(VecDbError::collection_not_found(collection_name))
         }
     }
+
+    /// Фильтрует векторы по выражению над метаданными в указанной коллекции
+    pub fn filter_by_expression(
+        &self,
+        collection_name: &str,
+        expression: &MetadataFilter,
+    ) -> Result<Vec<u64>, VecDbError> {
+        let collection = self.get_collection(collection_name)
+            .ok_or_else(|| VecDbError::collection_not_found(collection_name))?;
+        Ok(collection.filter_by_expression(expression))
+    }
 }
 
 //  VectorController impl
 
+impl AppendWriter {
+    /// Записывает вектор в файл его бакета. Бакет вычисляется по LSH коллекции без учёта
+    /// слияния малых бакетов; метаданные по умолчанию добавляются как при обычной вставке
+    pub fn add_vector(&mut self, embedding: Vec<f32>, metadata: Metadata) -> Result<u64, VecDbError> {
+        validate_embedding(&embedding, self.collection.vector_dimension)?;
+
+        let lsh = self.collection.buckets_controller.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let bucket_id = lsh.hash(&embedding);
+        let metadata = self.collection.with_default_metadata(metadata);
+        let vector = Vector::new(Some(embedding), Some(Utc::now().timestamp()), Some(metadata));
+
+        let storage = &self.storage_controller;
+        let name = &self.collection.name;
+        // Файл бакета пишется один раз; существующий не перезаписывается, чтобы сохранить created_at
+        if self.buckets.insert(bucket_id) && storage.read_bucket(name.clone(), bucket_id.to_string())?.is_none() {
+            let (raw_bucket, _) = Bucket::new(bucket_id).dump(storage.format)
+                .map_err(|_| VecDbError::Serialization(format!("Ошибка сериализации бакета {}", bucket_id)))?;
+            storage.save_bucket(name.clone(), bucket_id.to_string(), raw_bucket)?;
+        }
+        let (raw_vector, vector_id) = vector.dump(storage.format)
+            .map_err(|_| VecDbError::Serialization("Ошибка сериализации вектора".to_string()))?;
+        storage.save_vector_to_bucket(name.clone(), bucket_id.to_string(), vector_id, raw_vector)?;
+        self.written += 1;
+        Ok(vector_id)
+    }
+
+    /// Имя коллекции, в которую идёт запись
+    pub fn collection_name(&self) -> &str {
+        &self.collection.name
+    }
+
+    /// Сколько векторов записано с начала режима добавления
+    pub fn written(&self) -> usize {
+        self.written
+    }
+}
+
 impl VectorController {
+    /// Создаёт пустой контроллер. Список векторов создаётся при первой вставке:
+    /// None и Some(пустой список) означают одно и то же — векторов нет
     pub fn new() -> Self {
         VectorController { vectors: None }
     }
 
+    /// Нет ни одного вектора (в том числе, если список ещё не создан)
+    pub fn is_empty(&self) -> bool {
+        self.vectors.as_ref().is_none_or(|vectors| vectors.is_empty())
+    }
+
+    /// Индекс вектора с заданным id в списке
+    fn position(&self, id: u64) -> Option<usize> {
+        self.vectors.as_ref()?.iter().position(|v| v.hash_id() == id)
+    }
+
     /// добавляет объект вектора к базе
     /// 
     /// Параметры:
@@ -765,10 +2091,10 @@ impl VectorController {
     pub fn add_vector(
         &mut self,
         embedding: Option<Vec<f32>>,
-        metadata: Option<HashMap<String, String>>,
+        metadata: Option<Metadata>,
         vector_id: Option<u64>,
         vector: Option<Vector>,
-    ) -> Result<u64, Box<dyn std::error::Error>> {
+    ) -> Result<u64, VecDbError> {
         let final_vector = if let Some(v) = vector {
             // Используем готовый объект вектора
             v
@@ -794,29 +2120,15 @@ impl VectorController {
     }
 
     /// Удаляет вектор по id
-    pub fn remove_vector(&mut self, id: u64) -> Result<(), String> {
-        if let Some(ref mut vectors) = self.vectors {
-            if let Some(pos) = vectors.iter().position(|v| v.hash_id() == id) {
-                vectors.remove(pos);
-                Ok(())
-            } else {
-                Err(format!("Вектор с id {} не найден.", id))
-            }
-        } else {
-            Err("Список векторов пуст.".to_string())
-        }
+    pub fn remove_vector(&mut self, id: u64) -> Result<(), VecDbError> {
+        self.remove_and_get_vector(id).map(|_| ())
     }
 
     /// Удаляет вектор по id и возвращает его
-    pub fn remove_and_get_vector(&mut self, id: u64) -> Result<Vector, String> {
-        if let Some(ref mut vectors) = self.vectors {
-            if let Some(pos) = vectors.iter().position(|v| v.hash_id() == id) {
-                Ok(vectors.remove(pos))
-            } else {
-                Err(format!("Вектор с id {} не найден.", id))
-            }
-        } else {
-            Err("Список векторов пуст.".to_string())
+    pub fn remove_and_get_vector(&mut self, id: u64) -> Result<Vector, VecDbError> {
+        match (self.position(id), self.vectors.as_mut()) {
+            (Some(pos), Some(vectors)) => Ok(vectors.remove(pos)),
+            _ => Err(VecDbError::vector_not_found(id)),
         }
     }
     
@@ -825,12 +2137,12 @@ impl VectorController {
         &mut self,
         id: u64,
         new_embedding: Option<Vec<f32>>,
-        new_metadata: Option<HashMap<String, String>>,
-    ) -> Result<(), String> {
+        new_metadata: Option<Metadata>,
+    ) -> Result<(), VecDbError> {
         if let Some(ref mut vectors) = self.vectors {
             if let Some(v) = vectors.iter_mut().find(|v| v.hash_id() == id) {
                 if let Some(embedding) = new_embedding {
-                    v.data = embedding;
+                    v.set_data(embedding);
                 }
                 if let Some(metadata) = new_metadata {
                     v.metadata = metadata;
@@ -838,42 +2150,47 @@ impl VectorController {
                 return Ok(());
             }
         }
-        Err(format!("Вектор с id {} не найден.", id))
+        Err(VecDbError::vector_not_found(id))
+    }
+
+    /// Помечает вектор как нормированный при вставке
+    pub fn mark_normalized(&mut self, id: u64) -> Result<(), VecDbError> {
+        match self.vectors.as_mut().and_then(|vectors| vectors.iter_mut().find(|v| v.hash_id() == id)) {
+            Some(v) => {
+                v.mark_normalized();
+                Ok(())
+            }
+            None => Err(VecDbError::vector_not_found(id)),
+        }
     }
 
     /// Добавляет метаданные к вектору по ID (объединяет с существующими)
-    pub fn add_metadata_to_vector(&mut self, id: u64, new_metadata: HashMap<String, String>) -> Result<(), String> {
-        if let Some(ref mut vectors) = self.vectors {
-            if let Some(v) = vectors.iter_mut().find(|v| v.hash_id() == id) {
+    pub fn add_metadata_to_vector(&mut self, id: u64, new_metadata: Metadata) -> Result<(), VecDbError> {
+        match self.vectors.as_mut().and_then(|vectors| vectors.iter_mut().find(|v| v.hash_id() == id)) {
+            Some(v) => {
                 v.metadata.extend(new_metadata);
                 Ok(())
-            } else {
-                Err(format!("Вектор с id {} не найден.", id))
             }
-        } else {
-            Err("Список векторов пуст.".to_string())
+            None => Err(VecDbError::vector_not_found(id)),
         }
     }
 
     /// Удаляет метаданные по ключу у вектора по ID
-    pub fn remove_metadata_from_vector(&mut self, id: u64, key: &str) -> Result<(), String> {
-        if let Some(ref mut vectors) = self.vectors {
-            if let Some(v) = vectors.iter_mut().find(|v| v.hash_id() == id) {
+    pub fn remove_metadata_from_vector(&mut self, id: u64, key: &str) -> Result<(), VecDbError> {
+        match self.vectors.as_mut().and_then(|vectors| vectors.iter_mut().find(|v| v.hash_id() == id)) {
+            Some(v) => {
                 v.metadata.remove(key);
                 Ok(())
-            } else {
-                Err(format!("Вектор с id {} не найден.", id))
             }
-        } else {
-            Err("Список векторов пуст.".to_string())
+            None => Err(VecDbError::vector_not_found(id)),
         }
     }
 
     /// поиск наиболее похожего вектора
-    pub fn find_most_similar(&self, query: &Vec<f32>, k: usize) -> Result<Vec<(usize, f32)>, Box<dyn std::error::Error>> {
+    pub fn find_most_similar(&self, query: &Vec<f32>, k: usize, metric: &LSHMetric) -> Result<Vec<(usize, f32)>, VecDbError> {
         match &self.vectors {
-            Some(vectors) => find_most_similar(query, vectors, k),
-            None => Err("Список векторов пуст.".into()),
+            Some(vectors) => find_most_similar(query, vectors, k, metric),
+            None => Ok(Vec::new()),
         }
     }
 
@@ -894,50 +2211,43 @@ impl VectorController {
     }
 
     // фильтрация по метаданным
-    pub fn filter_by_metadata(&self, filters: &HashMap<String, String>) -> Vec<u64> {
-        let mut result = Vec::new();
-        if let Some(ref vectors) = self.vectors {
-            for vector in vectors {
-                let mut matches = true;
-                for (key, value) in filters {
-                    if let Some(v) = vector.metadata.get(key) {
-                        if v != value {
-                            matches = false;
-                            break;
-                        }
-                    } else {
-                        matches = false;
-                        break;
-                    }
-                }
-                if matches {
-                    result.push(vector.hash_id());
-                }
-            }
+    pub fn filter_by_metadata(&self, filters: &Metadata) -> Vec<u64> {
+        self.filter_by_expression(&MetadataFilter::from_map(filters))
+    }
+
+    // фильтрация по выражению над метаданными (And / Or / Eq)
+    pub fn filter_by_expression(&self, expression: &MetadataFilter) -> Vec<u64> {
+        match &self.vectors {
+            Some(vectors) => vectors
+                .iter()
+                .filter(|vector| expression.matches(&vector.metadata))
+                .map(|vector| vector.hash_id())
+                .collect(),
+            None => Vec::new(),
         }
-        result
     }
 }
 
 impl CollectionObjectController for VectorController {
     /// Загружает векторы из HashMap<u64, Vec<u8>> (hash_id -> данные)
-    fn load(&mut self, raw_data: HashMap<u64, Vec<u8>>) {
+    fn load(&mut self, raw_data: HashMap<u64, Vec<u8>>, format: StorageFormat) -> Result<(), VecDbError> {
         let mut vectors = Vec::new();
         for (hash_id, data) in raw_data {
             let mut vector = Vector::new(None, None, None);
-            vector.load(data);
+            vector.load(data, format)?;
             vector.set_hash_id(hash_id);
             vectors.push(vector);
         }
         self.vectors = Some(vectors);
+        Ok(())
     }
 
     /// Сохраняет векторы в HashMap<u64, Vec<u8>> (hash_id -> данные)
-    fn dump(&self) -> HashMap<u64, Vec<u8>> {
+    fn dump(&self, format: StorageFormat) -> HashMap<u64, Vec<u8>> {
         let mut ready_storage_data: HashMap<u64, Vec<u8>> = HashMap::new();
         if let Some(ref vectors) = self.vectors {
             for vector in vectors {
-                match vector.dump() {
+                match vector.dump(format) {
                     Ok((raw_vector, hash_id)) => {
                         ready_storage_data.insert(hash_id, raw_vector);
                     }
@@ -958,14 +2268,106 @@ impl BucketController {
 
     /// Создаёт новый BucketController с LSH для автоматического создания бакетов
     pub fn new(dimension: usize, num_hashes: usize, bucket_width: f32, metric: LSHMetric, seed: Option<u64>) -> Self {
-        let lsh = LSH::new(dimension, num_hashes, bucket_width, metric, seed);
+        Self::with_tables(dimension, num_hashes, bucket_width, metric, seed, 1)
+    }
+
+    /// Создаёт BucketController с num_tables независимыми хэш-таблицами LSH.
+    /// Первая таблица раскладывает векторы по бакетам, остальные хранят только ID
+    /// и расширяют набор кандидатов при поиске
+    pub fn with_tables(dimension: usize, num_hashes: usize, bucket_width: f32, metric: LSHMetric, seed: Option<u64>, num_tables: usize) -> Self {
+        let lsh = LSH::new(dimension, num_hashes, bucket_width, metric.clone(), seed);
+        let tables = (1..num_tables.max(1))
+            .map(|table| {
+                let table_seed = seed.map(|s| s.wrapping_add(table as u64));
+                LSHTable::new(LSH::new(dimension, num_hashes, bucket_width, metric.clone(), table_seed))
+            })
+            .collect();
         BucketController {
             buckets: None,
             lsh: Some(lsh),
+            tables,
             dimension: Some(dimension),
+            merge_small_buckets: None,
+            metadata_index: None,
+        }
+    }
+
+    /// Количество хэш-таблиц LSH
+    pub fn num_tables(&self) -> usize {
+        1 + self.tables.len()
+    }
+
+    /// Добавляет вектор в дополнительные хэш-таблицы
+    fn index_vector(&mut self, vector_id: u64, embedding: &[f32]) {
+        for table in self.tables.iter_mut() {
+            table.insert(vector_id, embedding);
+        }
+    }
+
+    /// Удаляет вектор из дополнительных хэш-таблиц и индекса метаданных
+    fn unindex_vector(&mut self, vector_id: u64) {
+        for table in self.tables.iter_mut() {
+            table.remove(vector_id);
+        }
+        if let Some(index) = self.metadata_index.as_mut() {
+            index.remove(vector_id);
+        }
+    }
+
+    /// Добавляет метаданные вектора в индекс метаданных, если он включён
+    fn index_metadata(&mut self, vector_id: u64, metadata: &Metadata) {
+        if let Some(index) = self.metadata_index.as_mut() {
+            index.insert(vector_id, metadata);
+        }
+    }
+
+    /// Задаёт ключи метаданных, по которым строится инвертированный индекс, и строит его
+    /// по текущим векторам. Пустой список выключает индекс
+    pub fn set_indexed_metadata_keys(&mut self, keys: Vec<String>) {
+        self.metadata_index = (!keys.is_empty()).then(|| MetadataIndex::new(keys));
+        self.rebuild_metadata_index();
+    }
+
+    /// Перестраивает индекс метаданных по векторам из бакетов
+    pub fn rebuild_metadata_index(&mut self) {
+        let Some(index) = self.metadata_index.as_mut() else { return };
+        index.clear();
+        for vector in self.buckets.iter().flatten().flat_map(|bucket| bucket.vectors_controller.vectors.iter().flatten()) {
+            index.insert(vector.hash_id(), &vector.metadata);
+        }
+    }
+
+    /// Перестраивает дополнительные хэш-таблицы по векторам из бакетов
+    pub fn rebuild_tables(&mut self) {
+        if self.tables.is_empty() {
+            return;
+        }
+        for table in self.tables.iter_mut() {
+            table.clear();
+        }
+        if let Some(ref buckets) = self.buckets {
+            for bucket in buckets {
+                if let Some(ref vectors) = bucket.vectors_controller.vectors {
+                    for vector in vectors {
+                        for table in self.tables.iter_mut() {
+                            table.insert(vector.hash_id(), &vector.data);
+                        }
+                    }
+                }
+            }
         }
     }
 
+    /// Находит вектор по ID и возвращает его положение (ID бакета, индекс в бакете)
+    fn locate_vector(&self, vector_id: u64) -> Option<(u64, usize, &Vector)> {
+        self.buckets.as_ref()?.iter().find_map(|bucket| {
+            bucket.vectors_controller.vectors.as_ref()?
+                .iter()
+                .position(|v| v.hash_id() == vector_id)
+                .map(|index| (bucket.id, index, &bucket.vectors_controller.vectors.as_ref().unwrap()[index]))
+        })
+    }
+
     /// Получает бакет по ID
     pub fn get_bucket(&self, id: u64) -> Option<&Bucket> {
         match &self.buckets {
@@ -974,6 +2376,17 @@ impl BucketController {
         }
     }
 
+    /// Получает вектор по ID бакета и порядковому индексу внутри бакета (как в результатах поиска)
+    pub fn get_vector_at(&self, bucket_id: u64, vector_index: usize) -> Option<&Vector> {
+        self.get_bucket(bucket_id)?.vectors_controller.get_vector(vector_index)
+    }
+
+    /// ID векторов бакета в порядке хранения; None, если бакета нет
+    pub fn bucket_vector_ids(&self, bucket_id: u64) -> Option<Vec<u64>> {
+        let bucket = self.get_bucket(bucket_id)?;
+        Some(bucket.vectors_controller.vectors.iter().flatten().map(|v| v.hash_id()).collect())
+    }
+
     /// Получает мутабельную ссылку на бакет по ID
     pub fn get_bucket_mut(&mut self, id: u64) -> Option<&mut Bucket> {
         match &mut self.buckets {
@@ -986,27 +2399,111 @@ impl BucketController {
     pub fn add_vector(
         &mut self,
         embedding: Vec<f32>,
-        metadata: HashMap<String, String>,
-    ) -> Result<u64, Box<dyn std::error::Error>> {
-        let lsh = self.lsh.as_ref().ok_or("LSH не инициализирован. Используйте new для создания контроллера с LSH.")?;
-        let dimension = self.dimension.ok_or("Размерность не установлена")?;
+        metadata: Metadata,
+    ) -> Result<u64, VecDbError> {
+        let lsh = self.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let dimension = self.dimension.ok_or_else(|| VecDbError::Validation("Размерность не установлена".to_string()))?;
+
+        if embedding.is_empty() {
+            return Err(VecDbError::Validation("Эмбеддинг не может быть пустым".to_string()));
+        }
+        if embedding.len() != dimension {
+            return Err(VecDbError::DimensionMismatch { expected: dimension, actual: embedding.len() });
+        }
+
+        let bucket_hash = self.resolve_insert_bucket(lsh.hash(&embedding));
+        let indexed = (!self.tables.is_empty()).then(|| embedding.clone());
+        let indexed_metadata = self.metadata_index.is_some().then(|| metadata.clone());
+
+        let bucket = self.get_or_create_bucket(bucket_hash)?;
+
+        let vector_id = bucket.add_vector(embedding, metadata)?;
+        if let Some(embedding) = indexed {
+            self.index_vector(vector_id, &embedding);
+        }
+        if let Some(metadata) = indexed_metadata {
+            self.index_metadata(vector_id, &metadata);
+        }
+        Ok(vector_id)
+    }
+
+    /// Добавляет вектор с заданным ID с автоматическим созданием бакета на основе LSH
+    pub fn add_vector_with_id(
+        &mut self,
+        vector_id: u64,
+        embedding: Vec<f32>,
+        metadata: Metadata,
+    ) -> Result<u64, VecDbError> {
+        let lsh = self.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let dimension = self.dimension.ok_or_else(|| VecDbError::Validation("Размерность не установлена".to_string()))?;
 
+        if embedding.is_empty() {
+            return Err(VecDbError::Validation("Эмбеддинг не может быть пустым".to_string()));
+        }
         if embedding.len() != dimension {
-            return Err(format!("Размерность вектора {} не соответствует ожидаемой {}", embedding.len(), dimension).into());
+            return Err(VecDbError::DimensionMismatch { expected: dimension, actual: embedding.len() });
+        }
+
+        if self.get_vector(vector_id).is_some() {
+            return Err(VecDbError::AlreadyExists(format!("Вектор с id {} уже существует", vector_id)));
         }
 
-        let bucket_hash = lsh.hash(&embedding);
+        let bucket_hash = self.resolve_insert_bucket(lsh.hash(&embedding));
+        let indexed = (!self.tables.is_empty()).then(|| embedding.clone());
+        let indexed_metadata = self.metadata_index.is_some().then(|| metadata.clone());
 
         let bucket = self.get_or_create_bucket(bucket_hash)?;
 
-        bucket.add_vector(embedding, metadata)
+        bucket.add_vector_with_id(vector_id, embedding, metadata)?;
+        if let Some(embedding) = indexed {
+            self.index_vector(vector_id, &embedding);
+        }
+        if let Some(metadata) = indexed_metadata {
+            self.index_metadata(vector_id, &metadata);
+        }
+        Ok(vector_id)
+    }
+
+    /// Бакет для нового вектора с LSH-хэшем hash. Если своего бакета ещё нет и слияние включено,
+    /// вектор попадает в существующий соседний бакет, где меньше merge_small_buckets векторов
+    fn resolve_insert_bucket(&self, hash: u64) -> u64 {
+        let (Some(threshold), Some(lsh)) = (self.merge_small_buckets, self.lsh.as_ref()) else {
+            return hash;
+        };
+        if self.get_bucket(hash).is_some() {
+            return hash;
+        }
+        lsh.neighbor_hashes(hash)
+            .into_iter()
+            .filter_map(|id| self.get_bucket(id))
+            .filter(|bucket| bucket.size() < threshold)
+            .min_by_key(|bucket| bucket.size())
+            .map(|bucket| bucket.id)
+            .unwrap_or(hash)
+    }
+
+    /// Бакет, в котором ищется запрос с LSH-хэшем hash. Если своего бакета нет и слияние включено,
+    /// берётся самый заполненный соседний бакет (в него могли попасть векторы этого хэша)
+    pub fn resolve_query_bucket(&self, hash: u64) -> u64 {
+        let (Some(_), Some(lsh)) = (self.merge_small_buckets, self.lsh.as_ref()) else {
+            return hash;
+        };
+        if self.get_bucket(hash).is_some() {
+            return hash;
+        }
+        lsh.neighbor_hashes(hash)
+            .into_iter()
+            .filter_map(|id| self.get_bucket(id))
+            .max_by_key(|bucket| bucket.size())
+            .map(|bucket| bucket.id)
+            .unwrap_or(hash)
     }
 
     /// Получает или создает бакет
     fn get_or_create_bucket(
         &mut self,
         bucket_id: u64,
-    ) -> Result<&mut Bucket, Box<dyn std::error::Error>> {
+    ) -> Result<&mut Bucket, VecDbError> {
         // Проверяем, существует ли бакет
         let bucket_exists = if let Some(ref buckets) = self.buckets {
             buckets.iter().any(|b| b.id == bucket_id)
@@ -1043,6 +2540,17 @@ impl BucketController {
         }
     }
 
+    /// Сводка по бакетам: (ID бакета, количество векторов, created_at, updated_at),
+    /// отсортированная по убыванию размера
+    pub fn bucket_summaries(&self) -> Vec<BucketSummaryRow> {
+        let mut summaries: Vec<BucketSummaryRow> = self.get_all_buckets()
+            .iter()
+            .map(|b| (b.id, b.size(), b.created_at, b.updated_at))
+            .collect();
+        summaries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        summaries
+    }
+
     /// Получает количество бакетов
     pub fn count(&self) -> usize {
         match &self.buckets {
@@ -1056,24 +2564,64 @@ impl BucketController {
         &self,
         query: &Vec<f32>,
         k: usize,
-    ) -> Result<Vec<(u64, usize, f32)>, Box<dyn std::error::Error>> {
-        let lsh = self.lsh.as_ref().ok_or("LSH не инициализирован")?;
-        let dimension = self.dimension.ok_or("Размерность не установлена")?;
+    ) -> Result<Vec<(u64, usize, f32)>, VecDbError> {
+        self.find_similar_with_options(query, k, &SearchOptions::default())
+            .map(|outcome| outcome.results)
+    }
+
+    /// Поиск похожих векторов с использованием LSH с учётом бустов по метаданным
+    pub fn find_similar_with_options(
+        &self,
+        query: &Vec<f32>,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<SearchOutcome, VecDbError> {
+        let lsh = self.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let dimension = self.dimension.ok_or_else(|| VecDbError::Validation("Размерность не установлена".to_string()))?;
 
         if query.len() != dimension {
-            return Err(format!("Размерность вектора {} не соответствует ожидаемой {}", query.len(), dimension).into());
+            return Err(VecDbError::DimensionMismatch { expected: dimension, actual: query.len() });
         }
 
-        let query_hash = lsh.hash(query);
+        let query_hash = self.resolve_query_bucket(lsh.hash(query));
+        let mut outcome = SearchOutcome::default();
         
-        if let Some(ref buckets) = self.buckets {
-            if let Some(bucket) = buckets.iter().find(|b| b.hash_id() == query_hash) {
-                let results = bucket.find_similar(query, k)?;
-                return Ok(results.into_iter().map(|(idx, score)| (bucket.hash_id(), idx, score)).collect());
+        if let Some(ref buckets) = self.buckets
+            && let Some(bucket) = buckets.iter().find(|b| b.hash_id() == query_hash)
+        {
+            outcome.results = self.boosted_bucket_results(bucket, query, k, options)?;
+            outcome.buckets_probed = 1;
+            outcome.candidates_examined = bucket.size();
+            outcome.count_bucket_facets(bucket, options);
+        }
+
+        if self.tables.is_empty() {
+            return Ok(outcome);
+        }
+
+        // Объединяем кандидатов из дополнительных таблиц; бакет запроса уже просмотрен целиком
+        let query_norm = l2_norm(query);
+        let mut seen: HashSet<u64> = HashSet::new();
+        for table in &self.tables {
+            for vector_id in table.candidates(query) {
+                if options.exclude_ids.contains(&vector_id) || !seen.insert(vector_id) {
+                    continue;
+                }
+                if let Some((bucket_id, index, vector)) = self.locate_vector(vector_id)
+                    && bucket_id != query_hash
+                {
+                    let score = similarity_score(&lsh.metric, query, query_norm, &vector.data, vector.norm())
+                        + options.boosts.boost_for(&vector.metadata);
+                    outcome.results.push((bucket_id, index, score));
+                    outcome.candidates_examined += 1;
+                    outcome.count_facets(vector, options);
+                }
             }
         }
 
-        Ok(Vec::new())
+        outcome.results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        outcome.results.truncate(k);
+        Ok(outcome)
     }
 
     /// Поиск похожих векторов в нескольких бакетах
@@ -1081,30 +2629,136 @@ impl BucketController {
         &self,
         query: &Vec<f32>,
         k: usize,
-    ) -> Result<Vec<(u64, usize, f32)>, Box<dyn std::error::Error>> {
-        let dimension = self.dimension.ok_or("Размерность не установлена")?;
+    ) -> Result<Vec<(u64, usize, f32)>, VecDbError> {
+        self.find_similar_multi_bucket_with_options(query, k, &SearchOptions::default())
+            .map(|outcome| outcome.results)
+    }
+
+    /// Поиск похожих векторов в нескольких бакетах с учётом параметров поиска.
+    /// Бакет запроса просматривается первым; по истечении дедлайна остальные бакеты пропускаются
+    pub fn find_similar_multi_bucket_with_options(
+        &self,
+        query: &Vec<f32>,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<SearchOutcome, VecDbError> {
+        let dimension = self.dimension.ok_or_else(|| VecDbError::Validation("Размерность не установлена".to_string()))?;
 
         if query.len() != dimension {
-            return Err(format!("Размерность вектора {} не соответствует ожидаемой {}", query.len(), dimension).into());
+            return Err(VecDbError::DimensionMismatch { expected: dimension, actual: query.len() });
         }
 
-        let mut all_results = Vec::new();
+        let mut outcome = SearchOutcome::default();
 
         // Ищем во всех бакетах, так как векторы могут быть распределены по разным бакетам
         if let Some(ref buckets) = self.buckets {
-            for bucket in buckets.iter() {
-                let results = bucket.find_similar(query, k)?;
-                for (idx, score) in results {
-                    all_results.push((bucket.hash_id(), idx, score));
+            let query_hash = self.lsh.as_ref().map(|lsh| self.resolve_query_bucket(lsh.hash(query)));
+            let ordered = buckets.iter().filter(|b| Some(b.id) == query_hash)
+                .chain(buckets.iter().filter(|b| Some(b.id) != query_hash));
+
+            for bucket in ordered {
+                if outcome.buckets_probed > 0 && options.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    outcome.timed_out = true;
+                    break;
                 }
+
+                outcome.results.extend(self.boosted_bucket_results(bucket, query, k, options)?);
+                outcome.buckets_probed += 1;
+                outcome.candidates_examined += bucket.size();
+                outcome.count_bucket_facets(bucket, options);
             }
         }
 
         // Сортируем по убыванию схожести (score) и берем топ k
-        all_results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
-        all_results.truncate(k);
+        outcome.results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        outcome.results.truncate(k);
+
+        Ok(outcome)
+    }
+
+    /// Поиск с переранжированием: кандидаты собираются из бакета запроса и соседних бакетов,
+    /// пока их не наберётся max(k, candidate_factor * k), затем упорядочиваются по точному score.
+    /// Если бакеты рядом с запросом не дают даже k кандидатов, просматриваются все бакеты
+    pub fn find_similar_reranked(
+        &self,
+        query: &Vec<f32>,
+        k: usize,
+        candidate_factor: usize,
+        options: &SearchOptions,
+    ) -> Result<SearchOutcome, VecDbError> {
+        let lsh = self.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let dimension = self.dimension.ok_or_else(|| VecDbError::Validation("Размерность не установлена".to_string()))?;
+
+        if query.len() != dimension {
+            return Err(VecDbError::DimensionMismatch { expected: dimension, actual: query.len() });
+        }
+
+        let wanted = k.max(candidate_factor.saturating_mul(k));
+        let query_hash = self.resolve_query_bucket(lsh.hash(query));
+        let mut probed: Vec<&Bucket> = Vec::new();
+        let mut candidates = 0;
+        for bucket_id in std::iter::once(query_hash).chain(lsh.neighbor_hashes(query_hash)) {
+            if candidates >= wanted {
+                break;
+            }
+            if let Some(bucket) = self.get_bucket(bucket_id)
+                && !probed.iter().any(|b| b.id == bucket.id)
+            {
+                candidates += bucket.size();
+                probed.push(bucket);
+            }
+        }
 
-        Ok(all_results)
+        if candidates < k + options.exclude_ids.len() {
+            return self.find_similar_multi_bucket_with_options(query, k, options);
+        }
+
+        let mut outcome = SearchOutcome::default();
+        for bucket in probed {
+            if outcome.buckets_probed > 0 && options.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                outcome.timed_out = true;
+                break;
+            }
+            outcome.results.extend(self.boosted_bucket_results(bucket, query, wanted, options)?);
+            outcome.buckets_probed += 1;
+            outcome.candidates_examined += bucket.size();
+            outcome.count_bucket_facets(bucket, options);
+        }
+
+        outcome.results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        outcome.results.truncate(k);
+        Ok(outcome)
+    }
+
+    /// Метрика точного ранжирования: метрика LSH коллекции, без LSH — косинус
+    fn metric(&self) -> &LSHMetric {
+        self.lsh.as_ref().map_or(&LSHMetric::Cosine, |lsh| &lsh.metric)
+    }
+
+    /// Ищет похожие векторы в одном бакете, прибавляет бусты по метаданным и отбрасывает
+    /// исключённые ID. С бустами просматривается весь бакет: вектор за пределами топ-k может подняться выше
+    fn boosted_bucket_results(
+        &self,
+        bucket: &Bucket,
+        query: &Vec<f32>,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<SimilarResults, VecDbError> {
+        let limit = if options.boosts.is_empty() { k + options.exclude_ids.len() } else { bucket.size() };
+        let mut results: SimilarResults = bucket.find_similar(query, limit, self.metric())?
+            .into_iter()
+            .filter_map(|(idx, score)| {
+                let vector = bucket.vectors_controller.get_vector(idx);
+                if vector.is_some_and(|v| options.exclude_ids.contains(&v.hash_id())) {
+                    return None;
+                }
+                let boost = vector.map(|v| options.boosts.boost_for(&v.metadata)).unwrap_or(0.0);
+                Some((bucket.hash_id(), idx, score + boost))
+            })
+            .collect();
+        results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        results.truncate(k);
+        Ok(results)
     }
 
     /// Получает общее количество векторов во всех бакетах
@@ -1116,7 +2770,7 @@ impl BucketController {
     }
 
     /// Удаляет вектор из соответствующего бакета
-    pub fn remove_vector(&mut self, vector_id: u64) -> Result<(), String> {
+    pub fn remove_vector(&mut self, vector_id: u64) -> Result<(), VecDbError> {
         if let Some(ref mut buckets) = self.buckets {
             for (index, bucket) in buckets.iter_mut().enumerate() {
                 if bucket.contains_vector(vector_id) {
@@ -1126,12 +2780,27 @@ impl BucketController {
                     if result.is_ok() && bucket.size() == 0 {
                         buckets.remove(index);
                     }
+                    if result.is_ok() {
+                        self.unindex_vector(vector_id);
+                    }
                     
                     return result;
                 }
             }
         }
-        Err(format!("Вектор с id {} не найден ни в одном бакете", vector_id))
+        Err(VecDbError::vector_not_found(vector_id))
+    }
+
+    /// Помечает вектор как нормированный; возвращает false, если вектор не найден
+    pub fn mark_normalized(&mut self, vector_id: u64) -> bool {
+        self.buckets.as_mut().is_some_and(|buckets| {
+            buckets.iter_mut().any(|b| b.vectors_controller.mark_normalized(vector_id).is_ok())
+        })
+    }
+
+    /// Проверяет, есть ли вектор с таким ID в каком-либо бакете
+    pub fn contains_vector(&self, vector_id: u64) -> bool {
+        self.buckets.as_ref().is_some_and(|buckets| buckets.iter().any(|b| b.contains_vector(vector_id)))
     }
 
     /// Получает вектор по ID из любого бакета
@@ -1146,8 +2815,12 @@ impl BucketController {
         None
     }
 
-    /// Фильтрация векторов по метаданным во всех бакетах
-    pub fn filter_by_metadata(&self, filters: &HashMap<String, String>) -> Vec<u64> {
+    /// Фильтрация векторов по метаданным во всех бакетах. Если все ключи фильтра
+    /// проиндексированы, результат берётся из индекса (ID по возрастанию) без просмотра бакетов
+    pub fn filter_by_metadata(&self, filters: &Metadata) -> Vec<u64> {
+        if let Some(ids) = self.metadata_index.as_ref().and_then(|index| index.candidates(filters)) {
+            return ids;
+        }
         let mut result = Vec::new();
         if let Some(ref buckets) = self.buckets {
             for bucket in buckets {
@@ -1157,6 +2830,17 @@ impl BucketController {
         result
     }
 
+    /// Фильтрация векторов по выражению над метаданными во всех бакетах
+    pub fn filter_by_expression(&self, expression: &MetadataFilter) -> Vec<u64> {
+        let mut result = Vec::new();
+        if let Some(ref buckets) = self.buckets {
+            for bucket in buckets {
+                result.extend(bucket.filter_by_expression(expression));
+            }
+        }
+        result
+    }
+
     /// Получает статистику по бакетам
     pub fn get_statistics(&self) -> HashMap<String, String> {
         let mut stats = HashMap::new();
@@ -1185,13 +2869,22 @@ impl BucketController {
         &mut self,
         vector_id: u64,
         new_embedding: Option<Vec<f32>>,
-        new_metadata: Option<HashMap<String, String>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let lsh = self.lsh.as_ref().ok_or("LSH не инициализирован")?;
-        let dimension = self.dimension.ok_or("Размерность не установлена")?;
+        new_metadata: Option<Metadata>,
+    ) -> Result<(), VecDbError> {
+        let lsh = self.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let dimension = self.dimension.ok_or_else(|| VecDbError::Validation("Размерность не установлена".to_string()))?;
+
+        // Бакет для нового эмбеддинга выбираем заранее: без нового эмбеддинга вектор остаётся на месте
+        let target_bucket_id = match new_embedding.as_ref() {
+            Some(embedding) if embedding.len() != dimension => {
+                return Err(VecDbError::DimensionMismatch { expected: dimension, actual: embedding.len() });
+            }
+            Some(embedding) => Some(self.resolve_insert_bucket(lsh.hash(embedding))),
+            None => None,
+        };
 
         // Находим вектор в текущем бакете и извлекаем его
-        let mut vector_to_move: Option<Vector> = None;
+        let mut vector_to_move: Option<(Vector, u64)> = None;
         let mut source_bucket_id: Option<u64> = None;
 
         if let Some(ref mut buckets) = self.buckets {
@@ -1202,36 +2895,29 @@ impl BucketController {
                     
                     // Обновляем данные временного вектора
                     if let Some(embedding) = new_embedding.clone() {
-                        if embedding.len() != dimension {
-                            return Err(format!("Размерность вектора {} не соответствует ожидаемой {}", embedding.len(), dimension).into());
-                        }
-                        temp_vector.data = embedding;
+                        temp_vector.set_data(embedding);
                     }
                     if let Some(metadata) = new_metadata.clone() {
                         temp_vector.metadata = metadata;
                     }
 
-                    // Вычисляем новый LSH хэш
-                    let new_bucket_id = lsh.hash(&temp_vector.data);
+                    let new_bucket_id = target_bucket_id.unwrap_or(bucket.id);
                     
                     // Если хэш изменился, нужно переместить вектор
                     if bucket.id != new_bucket_id {
-                        // Извлекаем вектор из старого бакета
-                        vector_to_move = Some(bucket.remove_and_get_vector(vector_id)?);
+                        // Удаляем вектор из старого бакета. Переносим временную копию: изменения
+                        // к ней уже применены ровно один раз, а незаданные поля остались прежними
+                        bucket.remove_and_get_vector(vector_id)?;
+                        vector_to_move = Some((temp_vector, new_bucket_id));
                         source_bucket_id = Some(bucket.id);
-                        
-                        // Обновляем данные извлеченного вектора
-                        if let Some(ref mut vector) = vector_to_move {
-                            if let Some(embedding) = new_embedding {
-                                vector.data = embedding;
-                            }
-                            if let Some(metadata) = new_metadata {
-                                vector.metadata = metadata;
-                            }
-                        }
                     } else {
                         // Хэш не изменился, просто обновляем вектор в текущем бакете
-                        return bucket.update_vector(vector_id, new_embedding, new_metadata);
+                        bucket.update_vector(vector_id, new_embedding, new_metadata)?;
+                        if !self.tables.is_empty() {
+                            self.index_vector(vector_id, &temp_vector.data);
+                        }
+                        self.index_metadata(vector_id, &temp_vector.metadata);
+                        return Ok(());
                     }
                     break;
                 }
@@ -1239,21 +2925,84 @@ impl BucketController {
         }
 
         // Если нужно переместить вектор
-        if let (Some(vector), Some(source_id)) = (vector_to_move, source_bucket_id) {
+        if let (Some((vector, new_bucket_id)), Some(source_id)) = (vector_to_move, source_bucket_id) {
+            // Удаляем старый бакет, если он остался без векторов. Делаем это до вставки,
+            // чтобы ошибка при добавлении в новый бакет не оставила пустой бакет
+            self.remove_empty_bucket(source_id);
+
             // Добавляем вектор в новый бакет
-            let new_bucket_id = lsh.hash(&vector.data);
+            let indexed = (!self.tables.is_empty()).then(|| vector.data.clone());
+            let indexed_metadata = self.metadata_index.is_some().then(|| vector.metadata.clone());
             let target_bucket = self.get_or_create_bucket(new_bucket_id)?;
             
             // Добавляем вектор напрямую в новый бакет
             target_bucket.vectors_controller.add_vector(None, None, None, Some(vector))?;
-            
-            // Удаляем пустой бакет, если он остался без векторов
-            self.remove_empty_bucket(source_id);
+            if let Some(embedding) = indexed {
+                self.index_vector(vector_id, &embedding);
+            }
+            if let Some(metadata) = indexed_metadata {
+                self.index_metadata(vector_id, &metadata);
+            }
         }
 
         Ok(())
     }
 
+    /// Забирает все векторы из бакетов, оставляя контроллер без бакетов
+    pub fn take_vectors(&mut self) -> Vec<Vector> {
+        for table in self.tables.iter_mut() {
+            table.clear();
+        }
+        if let Some(index) = self.metadata_index.as_mut() {
+            index.clear();
+        }
+        self.buckets.take()
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|bucket| bucket.vectors_controller.vectors.unwrap_or_default())
+            .collect()
+    }
+
+    /// Добавляет готовый вектор (с его ID, метаданными и временной меткой) в бакет по его LSH-хэшу
+    pub fn insert_vector(&mut self, vector: Vector) -> Result<u64, VecDbError> {
+        let lsh = self.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let bucket_hash = self.resolve_insert_bucket(lsh.hash(&vector.data));
+        let vector_id = vector.hash_id();
+        let indexed = (!self.tables.is_empty()).then(|| vector.data.clone());
+        let indexed_metadata = self.metadata_index.is_some().then(|| vector.metadata.clone());
+
+        let bucket = self.get_or_create_bucket(bucket_hash)?;
+        bucket.vectors_controller.add_vector(None, None, None, Some(vector))?;
+        if let Some(embedding) = indexed {
+            self.index_vector(vector_id, &embedding);
+        }
+        if let Some(metadata) = indexed_metadata {
+            self.index_metadata(vector_id, &metadata);
+        }
+        Ok(vector_id)
+    }
+
+    /// Ищет в бакете, куда попал бы embedding, почти совпадающий вектор: косинусное
+    /// расстояние (1 - косинусное сходство) не больше tolerance. Возвращает ID первого найденного
+    pub fn find_near_duplicate(&self, embedding: &[f32], tolerance: f32) -> Option<u64> {
+        let lsh = self.lsh.as_ref()?;
+        let bucket = self.get_bucket(self.resolve_insert_bucket(lsh.hash(embedding)))?;
+        let norm = l2_norm(embedding);
+        bucket.vectors_controller.vectors
+            .iter()
+            .flatten()
+            .find(|vector| 1.0 - cosine_similarity_with_norms(embedding, norm, &vector.data, vector.norm()) <= tolerance)
+            .map(|vector| vector.hash_id())
+    }
+
+    /// Удаляет все пустые бакеты за один проход; возвращает количество удалённых
+    pub fn prune_empty_buckets(&mut self) -> usize {
+        let Some(buckets) = self.buckets.as_mut() else { return 0 };
+        let before = buckets.len();
+        buckets.retain(|bucket| bucket.size() > 0);
+        before - buckets.len()
+    }
+
     /// Удаляет пустой бакет по ID
     fn remove_empty_bucket(&mut self, bucket_id: u64) {
         if let Some(ref mut buckets) = self.buckets {
@@ -1267,13 +3016,13 @@ impl BucketController {
     }
 
     /// Возвращает все векторы из всех бакетов для сохранения в файловую систему
-    pub fn dump_vectors(&self) -> Vec<(u64, u64, Vec<u8>)> {
+    pub fn dump_vectors(&self, format: StorageFormat) -> Vec<(u64, u64, Vec<u8>)> {
         let mut vectors_data = Vec::new();
         if let Some(ref buckets) = self.buckets {
             for bucket in buckets {
                 if let Some(ref vectors) = bucket.vectors_controller.vectors {
                     for vector in vectors {
-                        match vector.dump() {
+                        match vector.dump(format) {
                             Ok((raw_data, vector_id)) => {
                                 vectors_data.push((bucket.id, vector_id, raw_data));
                             }
@@ -1289,40 +3038,44 @@ impl BucketController {
     }
 
     /// Загружает векторы из бакетов из файловой системы
-    pub fn load_vectors_from_buckets(&mut self, storage_controller: &StorageController, collection_name: String) {
+    pub fn load_vectors_from_buckets(&mut self, storage_controller: &StorageController, collection_name: String) -> Result<(), VecDbError> {
         if let Some(ref mut buckets) = self.buckets {
             for bucket in buckets.iter_mut() {
-                if let Some(ref vectors) = bucket.vectors_controller.vectors {
-                    for vector in vectors {
-                        let vector_id = vector.hash_id();
-                        if let Some(_raw_data) = storage_controller.read_vector_from_bucket(collection_name.clone(), bucket.id.to_string(), vector_id) {
-                            println!("Вектор с ID {} загружен из бакета {} коллекции '{}'.", vector_id, bucket.id, collection_name);
-                        }
-                    }
-                }
+                let raw_vectors = storage_controller.read_all_vectors_from_bucket(collection_name.clone(), bucket.id.to_string())?;
+                let count = raw_vectors.len();
+                // Загружаем векторы напрямую в контроллер, чтобы не сбрасывать updated_at бакета
+                bucket.vectors_controller.load(raw_vectors, storage_controller.format)?;
+                println!("Загружено {} векторов из бакета {} коллекции '{}'.", count, bucket.id, collection_name);
             }
         }
+        self.rebuild_tables();
+        self.rebuild_metadata_index();
+        Ok(())
     }
 }
 
 impl CollectionObjectController for BucketController {
     /// Загружает бакеты из HashMap<u64, Vec<u8>> (bucket_id -> данные)
-    fn load(&mut self, raw_data: HashMap<u64, Vec<u8>>) {
+    fn load(&mut self, raw_data: HashMap<u64, Vec<u8>>, format: StorageFormat) -> Result<(), VecDbError> {
         let mut buckets = Vec::new();
         for (bucket_id, data) in raw_data {
             let mut bucket = Bucket::new(bucket_id);
-            bucket.load(data);
+            bucket.load(data, format)?;
             buckets.push(bucket);
         }
+        // Порядок обхода HashMap случаен, поэтому сортируем бакеты по ID,
+        // чтобы раскладка в памяти совпадала между перезапусками
+        buckets.sort_by_key(|b| b.id);
         self.buckets = Some(buckets);
+        Ok(())
     }
 
     /// Сохраняет бакеты в HashMap<u64, Vec<u8>> (hash_id -> данные)
-    fn dump(&self) -> HashMap<u64, Vec<u8>> {
+    fn dump(&self, format: StorageFormat) -> HashMap<u64, Vec<u8>> {
         let mut ready_storage_data: HashMap<u64, Vec<u8>> = HashMap::new();
         if let Some(ref buckets) = self.buckets {
-            for bucket in buckets {
-                match bucket.dump() {
+            for bucket in buckets.iter().filter(|bucket| !bucket.vectors_controller.is_empty()) {
+                match bucket.dump(format) {
                     Ok((raw_bucket, hash_id)) => {
                         ready_storage_data.insert(hash_id, raw_bucket);
                     }
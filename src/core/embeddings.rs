@@ -1,11 +1,54 @@
 use std::error::Error;
 use std::cmp::Ordering::Equal;
 
+use crate::core::lsh::LSHMetric;
 use crate::core::objects::Vector;
+use crate::core::errors::VecDbError;
 
 #[cfg(not(test))]
 use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
 
+/// Известные модели эмбеддингов и размерность их выхода
+const EMBEDDING_MODEL_DIMENSIONS: &[(&str, usize)] = &[
+    ("AllMiniLML6V2", 384),
+];
+
+/// Размерность выхода модели эмбеддингов по её имени
+pub fn embedding_model_dimension(model: &str) -> Result<usize, String> {
+    EMBEDDING_MODEL_DIMENSIONS
+        .iter()
+        .find(|(name, _)| *name == model)
+        .map(|(_, dimension)| *dimension)
+        .ok_or_else(|| format!("Неизвестная модель эмбеддингов '{}'", model))
+}
+
+/// Проверяет, что размерность коллекции совпадает с размерностью выхода модели
+pub fn validate_embedding_dimension(model: &str, dimension: usize) -> Result<(), String> {
+    let expected = embedding_model_dimension(model)?;
+    if expected != dimension {
+        return Err(format!(
+            "Размерность коллекции {} не совпадает с размерностью модели {} ({})",
+            dimension, model, expected
+        ));
+    }
+    Ok(())
+}
+
+/// Проверка эмбеддинга перед записью в коллекцию: не пустой, нужной размерности,
+/// без NaN и бесконечностей
+pub fn validate_embedding(embedding: &[f32], dimension: usize) -> Result<(), VecDbError> {
+    if embedding.is_empty() {
+        return Err(VecDbError::Validation("Эмбеддинг не может быть пустым".to_string()));
+    }
+    if embedding.len() != dimension {
+        return Err(VecDbError::DimensionMismatch { expected: dimension, actual: embedding.len() });
+    }
+    if embedding.iter().any(|x| !x.is_finite()) {
+        return Err(VecDbError::Validation("Эмбеддинг содержит NaN или бесконечность".to_string()));
+    }
+    Ok(())
+}
+
 #[cfg(not(test))]
 pub fn make_embeddings(
     sentence: &str,
@@ -23,6 +66,25 @@ pub fn make_embeddings(
     Ok(embeddings[0].clone())
 }
 
+/// Эмбеддинги нескольких текстов: модель загружается один раз на весь набор
+#[cfg(not(test))]
+pub fn make_embeddings_batch(
+    sentences: &[String],
+) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+    let mut model = TextEmbedding::try_new(
+    InitOptions::new(EmbeddingModel::AllMiniLML6V2),
+    )?;
+
+    Ok(model.embed(sentences, None)?)
+}
+
+#[cfg(test)]
+pub fn make_embeddings_batch(
+    sentences: &[String],
+) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+    sentences.iter().map(|sentence| make_embeddings(sentence)).collect()
+}
+
 #[cfg(test)]
 pub fn make_embeddings(
     sentence: &str,
@@ -38,19 +100,59 @@ pub fn make_embeddings(
     Ok(embedding)
 }
 
+/// Разбивает текст на перекрывающиеся окна по chunk_size символов с перекрытием overlap
+pub fn chunk_text(
+    text: &str,
+    chunk_size: usize,
+    overlap: usize,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    if chunk_size == 0 {
+        return Err("Размер чанка должен быть больше нуля".into());
+    }
+    if overlap >= chunk_size {
+        return Err("Перекрытие должно быть меньше размера чанка".into());
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return Err("Текст пуст".into());
+    }
+
+    let step = chunk_size - overlap;
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    loop {
+        let end = (start + chunk_size).min(chars.len());
+        chunks.push(chars[start..end].iter().collect());
+        if end == chars.len() {
+            break;
+        }
+        start += step;
+    }
+
+    Ok(chunks)
+}
+
+/// Точный поиск k наиболее похожих векторов по метрике коллекции.
+/// Score всегда «чем больше, тем лучше» (см. similarity_score)
 pub fn find_most_similar(
     query: &Vec<f32>,
     vectors: &[Vector],
     k: usize,
-) -> Result<Vec<(usize, f32)>, Box<dyn Error>> {
+    metric: &LSHMetric,
+) -> Result<Vec<(usize, f32)>, VecDbError> {
+    // Пустой список — это не ошибка, а отсутствие результатов
     if vectors.is_empty() {
-        return Err("Список векторов пуст".into());
+        return Ok(Vec::new());
     }
 
+    // Норма запроса считается один раз, нормы векторов берутся из кэша
+    let query_norm = l2_norm(query);
     let mut scored: Vec<(usize, f32)> = vectors
         .iter()
         .enumerate()
-        .map(|(i, vector)| (i, cosine_similarity(query, &vector.data)))
+        .map(|(i, vector)| (i, similarity_score(metric, query, query_norm, &vector.data, vector.norm())))
         .collect();
 
     scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Equal));
@@ -60,12 +162,86 @@ pub fn find_most_similar(
     Ok(top_k)
 }
 
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+/// Оценка сходства по метрике: для косинуса — косинусное сходство,
+/// для расстояний — 1 / (1 + d), т.е. 1 для совпадающих векторов и стремится к 0 с ростом расстояния.
+/// Нормы используются только для косинуса
+pub fn similarity_score(metric: &LSHMetric, a: &[f32], norm_a: f32, b: &[f32], norm_b: f32) -> f32 {
+    match metric {
+        LSHMetric::Cosine => cosine_similarity_with_norms(a, norm_a, b, norm_b),
+        LSHMetric::Euclidean => 1.0 / (1.0 + euclidean_distance(a, b)),
+        LSHMetric::Manhattan => 1.0 / (1.0 + manhattan_distance(a, b)),
+    }
+}
+
+/// Евклидово расстояние между векторами
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
     assert_eq!(a.len(), b.len(), "Vectors must have the same dimension");
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+/// Манхэттенское расстояние между векторами
+pub fn manhattan_distance(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len(), "Vectors must have the same dimension");
+    a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum()
+}
+
+/// Скалярное произведение векторов
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len(), "Vectors must have the same dimension");
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Результат сравнения двух векторов по всем поддерживаемым мерам
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorComparison {
+    /// Косинусное сходство (0 для нулевого вектора)
+    pub cosine: f32,
+    /// Евклидово расстояние
+    pub euclidean: f32,
+    /// Манхэттенское расстояние
+    pub manhattan: f32,
+    /// Скалярное произведение
+    pub dot_product: f32,
+}
+
+/// Сравнивает два вектора одной размерности по всем мерам сразу
+pub fn compare_vectors(a: &[f32], b: &[f32]) -> Result<VectorComparison, VecDbError> {
+    if a.is_empty() {
+        return Err(VecDbError::Validation("Эмбеддинг не может быть пустым".to_string()));
+    }
+    if a.len() != b.len() {
+        return Err(VecDbError::DimensionMismatch { expected: a.len(), actual: b.len() });
+    }
+    Ok(VectorComparison {
+        cosine: cosine_similarity(a, b),
+        euclidean: euclidean_distance(a, b),
+        manhattan: manhattan_distance(a, b),
+        dot_product: dot_product(a, b),
+    })
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    cosine_similarity_with_norms(a, l2_norm(a), b, l2_norm(b))
+}
+
+/// L2-норма вектора
+pub fn l2_norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// Приводит вектор к единичной L2-норме на месте; нулевой вектор нормировать нельзя
+pub fn normalize_in_place(v: &mut [f32]) -> Result<(), VecDbError> {
+    let norm = l2_norm(v);
+    if norm == 0.0 {
+        return Err(VecDbError::Validation("Нулевой вектор нельзя нормировать".to_string()));
+    }
+    v.iter_mut().for_each(|x| *x /= norm);
+    Ok(())
+}
 
-    let dot_product: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
-    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+/// Косинусное сходство с уже посчитанными нормами векторов
+pub fn cosine_similarity_with_norms(a: &[f32], norm_a: f32, b: &[f32], norm_b: f32) -> f32 {
+    let dot_product = dot_product(a, b);
 
     if norm_a == 0.0 || norm_b == 0.0 {
         0.0
@@ -0,0 +1,94 @@
+use std::fmt;
+use axum::http::StatusCode;
+
+/// Ошибка операций базы: категория определяет HTTP-статус ответа,
+/// текст передаётся клиенту в поле message
+#[derive(Debug, Clone, PartialEq)]
+pub enum VecDbError {
+    /// Коллекция, бакет или вектор не найдены
+    NotFound(String),
+    /// Размерность вектора не совпадает с размерностью коллекции
+    DimensionMismatch { expected: usize, actual: usize },
+    /// Объект с таким именем или ID уже существует
+    AlreadyExists(String),
+    /// Ошибка чтения или записи хранилища
+    Storage(String),
+    /// Ошибка сериализации или десериализации данных
+    Serialization(String),
+    /// Некорректные входные данные
+    Validation(String),
+}
+
+impl VecDbError {
+    /// Коллекция с указанным именем не найдена
+    pub fn collection_not_found(name: &str) -> Self {
+        VecDbError::NotFound(format!("Коллекция '{}' не найдена", name))
+    }
+
+    /// Вектор с указанным ID не найден
+    pub fn vector_not_found(id: u64) -> Self {
+        VecDbError::NotFound(format!("Вектор с ID {} не найден", id))
+    }
+
+    /// Контроллер бакетов создан без LSH
+    pub fn lsh_not_initialized() -> Self {
+        VecDbError::Validation("LSH не инициализирован".to_string())
+    }
+
+    /// Машиночитаемый код категории ошибки (поле error_code в ответе API)
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            VecDbError::NotFound(_) => "not_found",
+            VecDbError::DimensionMismatch { .. } => "dimension_mismatch",
+            VecDbError::AlreadyExists(_) => "already_exists",
+            VecDbError::Storage(_) => "storage_error",
+            VecDbError::Serialization(_) => "serialization_error",
+            VecDbError::Validation(_) => "validation_error",
+        }
+    }
+
+    /// HTTP-статус, соответствующий категории ошибки
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            VecDbError::NotFound(_) => StatusCode::NOT_FOUND,
+            VecDbError::DimensionMismatch { .. } | VecDbError::Validation(_) => StatusCode::BAD_REQUEST,
+            VecDbError::AlreadyExists(_) => StatusCode::CONFLICT,
+            VecDbError::Storage(_) | VecDbError::Serialization(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for VecDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VecDbError::DimensionMismatch { expected, actual } => write!(
+                f, "Размерность вектора {} не соответствует размерности коллекции {}", actual, expected
+            ),
+            VecDbError::NotFound(message)
+            | VecDbError::AlreadyExists(message)
+            | VecDbError::Storage(message)
+            | VecDbError::Serialization(message)
+            | VecDbError::Validation(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for VecDbError {}
+
+impl From<std::io::Error> for VecDbError {
+    fn from(error: std::io::Error) -> Self {
+        VecDbError::Storage(error.to_string())
+    }
+}
+
+impl From<bincode::Error> for VecDbError {
+    fn from(error: bincode::Error) -> Self {
+        VecDbError::Serialization(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for VecDbError {
+    fn from(error: serde_json::Error) -> Self {
+        VecDbError::Serialization(error.to_string())
+    }
+}
@@ -1,17 +1,25 @@
-use axum::{extract::State, Json};
+use axum::{extract::{MatchedPath, Query, Request, State}, http::{header, HeaderMap, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Json};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio::sync::broadcast;
+use tokio::sync::Notify;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Semaphore;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use serde_json;
+use base64::prelude::{Engine, BASE64_STANDARD};
 
 use crate::core::{
-    lsh::LSHMetric,
+    embeddings::{self, validate_embedding_dimension},
+    errors::VecDbError,
+    lsh::{LSHMetric, DEFAULT_AUTO_TUNE_SAMPLE},
+    objects::{Collection, EvictionPolicy},
     interfaces::Object,
+    utils::{encode_embeddings, MetadataBoosts, MetadataFilter, MetadataMigration, EMBEDDINGS_CONTENT_TYPE},
     openapi::{
-        AddCollectionParams, DeleteCollectionParams, AddVectorParams, UpdateVectorParams,
-        GetVectorParams, DeleteVectorParams, FilterByMetadataParams, FindSimilarParams,
-        RpcResponse, SimilarVectorResult
+        AddCollectionParams, UpdateCollectionParams, DeleteCollectionParams, GetCollectionParams, CollectionExistsParams, BucketSummariesParams, QueryBucketParams, GetBucketParams, PreloadCollectionParams, AddVectorParams, AddVectorsBatchParams, AddTextChunksParams, UpdateVectorParams, UpsertVectorParams,
+        GetVectorParams, GetManyVectorsParams, VectorExistsParams, VectorRawParams, ReindexParams, MigrateMetadataParams, DeleteVectorParams, FilterByMetadataParams, FindSimilarParams, FindSimilarBatchParams, SimilarScoresParams, SimilarToParams, CompareVectorsParams, HealthParams,
+        RpcResponse, SimilarVectorResult, BatchInsertResult, BucketSummary
     }
 };
 
@@ -20,12 +28,72 @@ use crate::core::{
 pub struct AppState {
     pub controller: Arc<RwLock<CollectionController>>,
     pub configs: HashMap<String, String>,
-    pub shutdown_tx: broadcast::Sender<()>,
+    pub shutdown: Arc<ShutdownSignal>,
+    /// Кэш результатов /vector/similar (размер задаётся server.query_cache_size, 0 — выключен)
+    pub query_cache: Arc<QueryCache>,
+}
+
+/// Однократный сигнал остановки сервера. Срабатывает ровно один раз: повторные вызовы
+/// trigger ничего не делают, а сигнал, поданный до начала ожидания, не теряется
+#[derive(Debug, Default)]
+pub struct ShutdownSignal {
+    notify: Notify,
+    triggered: AtomicBool,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Подаёт сигнал остановки. Возвращает true только для первого вызова
+    pub fn trigger(&self) -> bool {
+        let first = !self.triggered.swap(true, Ordering::SeqCst);
+        if first {
+            // notify_one сохраняет разрешение, если ожидающего ещё нет
+            self.notify.notify_one();
+        }
+        first
+    }
+
+    /// Был ли уже подан сигнал остановки
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.load(Ordering::SeqCst)
+    }
+
+    /// Дожидается сигнала остановки (рассчитано на одного ожидающего — сам сервер)
+    pub async fn wait(&self) {
+        self.notify.notified().await;
+    }
 }
 
 // Временный импорт для CollectionController
 // TODO: Вынести в отдельный модуль или реорганизовать
-use crate::core::controllers::CollectionController;
+use crate::core::controllers::{BucketController, CollectionController, QueryCache, QueryCacheKey, SearchOptions, SearchOutcome, DEFAULT_DEDUP_TOLERANCE, DEFAULT_RERANK_CANDIDATE_FACTOR, MUTATING_ROUTES};
+
+/// Ответ с ошибкой базы: HTTP-статус определяется категорией ошибки, а data содержит
+/// error_code и параметры ошибки (expected и actual для несовпадения размерности)
+fn error_response(error: VecDbError) -> (StatusCode, Json<RpcResponse>) {
+    let mut data = serde_json::json!({"error_code": error.error_code()});
+    if let VecDbError::DimensionMismatch { expected, actual } = error {
+        data["expected"] = serde_json::json!(expected);
+        data["actual"] = serde_json::json!(actual);
+    }
+    (error.status_code(), Json(RpcResponse { 
+        status: "error".to_string(), 
+        data: Some(data), 
+        message: Some(error.to_string()) 
+    }))
+}
+
+/// Ответ с ошибкой операции над коллекцией: как error_response, с именем коллекции в data
+fn collection_error_response(collection: &str, error: VecDbError) -> (StatusCode, Json<RpcResponse>) {
+    let (status, mut response) = error_response(error);
+    if let Some(data) = response.0.data.as_mut() {
+        data["collection"] = serde_json::json!(collection);
+    }
+    (status, response)
+}
 
 /// Создание коллекции
 #[utoipa::path(
@@ -33,25 +101,106 @@ use crate::core::controllers::CollectionController;
     path = "/collection",
     request_body = AddCollectionParams,
     responses(
-        (status = 200, description = "Коллекция успешно создана", body = RpcResponse),
-        (status = 400, description = "Ошибка в запросе", body = RpcResponse)
+        (status = 200, description = "Коллекция создана; в data — её итоговые параметры (dimension, metric, num_hashes, bucket_width, seed)", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 409, description = "Коллекция с таким именем уже существует", body = RpcResponse)
     ),
     tag = "Collections"
 )]
-pub async fn add_collection(State(state): State<AppState>, Json(payload): Json<AddCollectionParams>) -> Json<RpcResponse> {
+pub async fn add_collection(State(state): State<AppState>, Json(payload): Json<AddCollectionParams>) -> (StatusCode, Json<RpcResponse>) {
     let metric = LSHMetric::from_string(&payload.metric).unwrap_or(LSHMetric::Euclidean);
+    if let Some(model) = payload.embedding_model.as_deref()
+        && let Err(e) = validate_embedding_dimension(model, payload.dimension)
+    {
+        return (StatusCode::BAD_REQUEST, Json(RpcResponse { 
+            status: "error".to_string(), 
+            data: None, 
+            message: Some(e) 
+        }));
+    }
+    let eviction = match payload.eviction.as_deref().map(EvictionPolicy::from_string).transpose() {
+        Ok(eviction) => eviction.unwrap_or_default(),
+        Err(e) => return error_response(VecDbError::Validation(e)),
+    };
+    if payload.max_vectors == Some(0) {
+        return error_response(VecDbError::Validation("max_vectors должен быть больше нуля".to_string()));
+    }
     let mut ctrl = state.controller.write().await;
-    match ctrl.add_collection(payload.name, metric, payload.dimension) {
-        Ok(_) => Json(RpcResponse { 
+    let result = ctrl.add_collection_with_tables(payload.name.clone(), metric, payload.dimension, payload.num_tables.unwrap_or(1))
+        .and_then(|_| match payload.default_metadata {
+            Some(metadata) => ctrl.set_default_metadata(&payload.name, metadata),
+            None => Ok(()),
+        })
+        .and_then(|_| match payload.auto_tune {
+            true => ctrl.enable_auto_tune(&payload.name, payload.auto_tune_sample.unwrap_or(DEFAULT_AUTO_TUNE_SAMPLE)),
+            false => Ok(()),
+        })
+        .and_then(|_| match payload.max_vectors {
+            Some(max_vectors) => ctrl.set_max_vectors(&payload.name, Some(max_vectors), eviction),
+            None => Ok(()),
+        })
+        .and_then(|_| match payload.indexed_metadata_keys.is_empty() {
+            true => Ok(()),
+            false => ctrl.set_indexed_metadata_keys(&payload.name, payload.indexed_metadata_keys),
+        });
+    let created = result.and_then(|_| ctrl.get_collection(&payload.name)
+        .map(created_collection_info)
+        .ok_or_else(|| VecDbError::collection_not_found(&payload.name)));
+    match created {
+        Ok(info) => (StatusCode::OK, Json(RpcResponse { 
             status: "ok".to_string(), 
-            data: Some(serde_json::json!({"added": true})), 
+            data: Some(info), 
             message: None 
-        }),
-        Err(e) => Json(RpcResponse { 
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Итоговые параметры созданной коллекции (с подставленными значениями по умолчанию),
+/// чтобы клиент мог проверить, с чем она построена
+fn created_collection_info(collection: &Collection) -> serde_json::Value {
+    let lsh = collection.buckets_controller.lsh.as_ref();
+    serde_json::json!({
+        "added": true,
+        "name": collection.name,
+        "dimension": collection.vector_dimension,
+        "metric": format!("{:?}", collection.lsh_metric),
+        "num_hashes": lsh.map(|l| l.num_hashes),
+        "bucket_width": lsh.map(|l| l.bucket_width),
+        "seed": lsh.and_then(|l| l.seed),
+        "num_tables": collection.num_tables
+    })
+}
+
+/// Изменение метрики коллекции с перераскладкой векторов по бакетам
+#[utoipa::path(
+    post,
+    path = "/collection/update",
+    request_body = UpdateCollectionParams,
+    responses(
+        (status = 200, description = "Метрика изменена, векторы разложены по новым бакетам", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "Collections"
+)]
+pub async fn update_collection(State(state): State<AppState>, Json(payload): Json<UpdateCollectionParams>) -> (StatusCode, Json<RpcResponse>) {
+    let metric = match LSHMetric::from_string(&payload.metric) {
+        Ok(metric) => metric,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(RpcResponse { 
             status: "error".to_string(), 
             data: None, 
-            message: Some(e.to_string()) 
-        }),
+            message: Some(e) 
+        })),
+    };
+    let mut ctrl = state.controller.write().await;
+    match ctrl.update_collection_metric(&payload.name, metric) {
+        Ok(reindexed) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({"updated": true, "metric": payload.metric, "reindexed_vectors": reindexed})), 
+            message: None 
+        })),
+        Err(e) => error_response(e),
     }
 }
 
@@ -62,49 +211,250 @@ pub async fn add_collection(State(state): State<AppState>, Json(payload): Json<A
     request_body = DeleteCollectionParams,
     responses(
         (status = 200, description = "Коллекция успешно удалена", body = RpcResponse),
-        (status = 400, description = "Ошибка в запросе", body = RpcResponse)
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
     ),
     tag = "Collections"
 )]
-pub async fn delete_collection(State(state): State<AppState>, Json(payload): Json<DeleteCollectionParams>) -> Json<RpcResponse> {
+pub async fn delete_collection(State(state): State<AppState>, Json(payload): Json<DeleteCollectionParams>) -> (StatusCode, Json<RpcResponse>) {
     let mut ctrl = state.controller.write().await;
     match ctrl.delete_collection(payload.name) {
-        Ok(_) => Json(RpcResponse { 
+        Ok(_) => (StatusCode::OK, Json(RpcResponse { 
             status: "ok".to_string(), 
             data: Some(serde_json::json!({"deleted": true})), 
             message: None 
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Проверка существования коллекции
+#[utoipa::path(
+    post,
+    path = "/collection/exists",
+    request_body = CollectionExistsParams,
+    responses(
+        (status = 200, description = "Результат проверки существования коллекции", body = RpcResponse)
+    ),
+    tag = "Collections"
+)]
+pub async fn collection_exists(State(state): State<AppState>, Json(payload): Json<CollectionExistsParams>) -> Json<RpcResponse> {
+    let ctrl = state.controller.read().await;
+    Json(RpcResponse { 
+        status: "ok".to_string(), 
+        data: Some(serde_json::json!({"exists": ctrl.collection_exists(&payload.name)})), 
+        message: None 
+    })
+}
+
+/// Сводка по бакетам коллекции для поиска «горячих» бакетов
+#[utoipa::path(
+    post,
+    path = "/collection/buckets",
+    request_body = BucketSummariesParams,
+    responses(
+        (status = 200, description = "Бакеты коллекции по убыванию размера", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "Collections"
+)]
+pub async fn bucket_summaries(State(state): State<AppState>, Json(payload): Json<BucketSummariesParams>) -> (StatusCode, Json<RpcResponse>) {
+    let ctrl = state.controller.read().await;
+    match ctrl.bucket_summaries(&payload.name, payload.top) {
+        Ok(summaries) => {
+            let buckets: Vec<BucketSummary> = summaries
+                .into_iter()
+                .map(|(bucket_id, size, created_at, updated_at)| BucketSummary { bucket_id, size, created_at, updated_at })
+                .collect();
+            (StatusCode::OK, Json(RpcResponse { 
+                status: "ok".to_string(), 
+                data: Some(serde_json::json!({"buckets": buckets})), 
+                message: None 
+            }))
+        },
+        Err(e) => error_response(e),
+    }
+}
+
+/// Диагностика: в какой бакет хэшируется запрос. Ничего не изменяет
+#[utoipa::path(
+    post,
+    path = "/collection/query-bucket",
+    request_body = QueryBucketParams,
+    responses(
+        (status = 200, description = "ID бакета запроса, его наличие и размер", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "Collections"
+)]
+pub async fn query_bucket(State(state): State<AppState>, Json(payload): Json<QueryBucketParams>) -> (StatusCode, Json<RpcResponse>) {
+    let ctrl = state.controller.read().await;
+    match ctrl.query_bucket(&payload.name, &payload.query) {
+        Ok((bucket_id, bucket_exists, bucket_size)) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({
+                "bucket_id": bucket_id,
+                "bucket_exists": bucket_exists,
+                "bucket_size": bucket_size
+            })), 
+            message: None 
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Диагностика: список векторов одного бакета
+#[utoipa::path(
+    post,
+    path = "/collection/bucket",
+    request_body = GetBucketParams,
+    responses(
+        (status = 200, description = "ID векторов бакета, его размер и временные метки", body = RpcResponse),
+        (status = 404, description = "Коллекция или бакет не найдены", body = RpcResponse)
+    ),
+    tag = "Collections"
+)]
+pub async fn get_bucket(State(state): State<AppState>, Json(payload): Json<GetBucketParams>) -> (StatusCode, Json<RpcResponse>) {
+    let ctrl = state.controller.read().await;
+    let not_found = |message: String| (StatusCode::NOT_FOUND, Json(RpcResponse { 
+        status: "error".to_string(), 
+        data: None, 
+        message: Some(message) 
+    }));
+    let Some(collection) = ctrl.get_collection(&payload.name) else {
+        return not_found(format!("Коллекция '{}' не найдена", payload.name));
+    };
+    let buckets_controller = &collection.buckets_controller;
+    match (buckets_controller.get_bucket(payload.bucket_id), buckets_controller.bucket_vector_ids(payload.bucket_id)) {
+        (Some(bucket), Some(vector_ids)) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({
+                "bucket_id": bucket.id,
+                "size": vector_ids.len(),
+                "created_at": bucket.created_at,
+                "updated_at": bucket.updated_at,
+                "vector_ids": vector_ids
+            })), 
+            message: None 
+        })),
+        _ => not_found(format!("Бакет {} не найден", payload.bucket_id)),
+    }
+}
+
+/// Прогрев коллекции: загрузка из storage в память, если она ещё не загружена
+#[utoipa::path(
+    post,
+    path = "/collection/preload",
+    request_body = PreloadCollectionParams,
+    responses(
+        (status = 200, description = "Коллекция загружена в память", body = RpcResponse),
+        (status = 404, description = "Коллекции нет в storage", body = RpcResponse)
+    ),
+    tag = "Collections"
+)]
+pub async fn preload_collection(State(state): State<AppState>, Json(payload): Json<PreloadCollectionParams>) -> (StatusCode, Json<RpcResponse>) {
+    match CollectionController::preload_collection_async(&state.controller, &payload.name).await {
+        Ok((loaded, buckets, vectors)) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({
+                "name": payload.name,
+                "loaded": loaded,
+                "buckets": buckets,
+                "vectors": vectors
+            })), 
+            message: None 
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Описание коллекции для ответов API, включая параметры LSH, с которыми она построена
+fn collection_info(collection: &Collection) -> serde_json::Value {
+    let lsh = collection.buckets_controller.lsh.as_ref();
+    serde_json::json!({
+        "name": collection.name,
+        "vector_dimension": collection.vector_dimension,
+        "metric": format!("{:?}", collection.lsh_metric),
+        "total_vectors": collection.buckets_controller.total_vectors(),
+        "total_buckets": collection.buckets_controller.count(),
+        "num_hashes": lsh.map(|l| l.num_hashes),
+        "bucket_width": lsh.map(|l| l.bucket_width),
+        "seed": lsh.and_then(|l| l.seed),
+        "num_tables": collection.num_tables,
+        "default_metadata": collection.default_metadata,
+        "auto_tune_after": collection.auto_tune_after,
+        "max_vectors": collection.max_vectors,
+        "eviction": collection.eviction.to_string(),
+        "indexed_metadata_keys": collection.indexed_metadata_keys
+    })
+}
+
+/// Получение информации о коллекции
+#[utoipa::path(
+    post,
+    path = "/collection/get",
+    request_body = GetCollectionParams,
+    responses(
+        (status = 200, description = "Информация о коллекции", body = RpcResponse)
+    ),
+    tag = "Collections"
+)]
+pub async fn get_collection(State(state): State<AppState>, Json(payload): Json<GetCollectionParams>) -> Json<RpcResponse> {
+    let ctrl = state.controller.read().await;
+    match ctrl.get_collection(&payload.name) {
+        Some(collection) => Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(collection_info(collection)), 
+            message: None 
         }),
-        Err(e) => Json(RpcResponse { 
+        None => Json(RpcResponse { 
             status: "error".to_string(), 
             data: None, 
-            message: Some(e.to_string()) 
+            message: Some(format!("Коллекция '{}' не найдена", payload.name)) 
         }),
     }
 }
 
 /// Получение всех коллекций
-pub async fn get_all_collections(State(state): State<AppState>, Json(_payload): Json<serde_json::Value>) -> Json<RpcResponse> {
-    let ctrl = state.controller.read().await;
-    let collections = ctrl.get_all_collections();
-    
-    let collections_info: Vec<serde_json::Value> = collections.iter().map(|c| {
-        serde_json::json!({
-            "name": c.name,
-            "vector_dimension": c.vector_dimension,
-            "metric": format!("{:?}", c.lsh_metric),
-            "total_vectors": c.buckets_controller.total_vectors(),
-            "total_buckets": c.buckets_controller.count()
-        })
-    }).collect();
+#[utoipa::path(
+    post,
+    path = "/collection/all",
+    responses(
+        (status = 200, description = "Все коллекции: загруженные в память и лежащие только в storage (loaded = false)", body = RpcResponse)
+    ),
+    tag = "Collections"
+)]
+pub async fn get_all_collections(State(state): State<AppState>, Json(_payload): Json<serde_json::Value>) -> (StatusCode, Json<RpcResponse>) {
+    // Список коллекций в storage читается с повторами ввода-вывода, поэтому вне потока рантайма
+    let listed = CollectionController::read_blocking(&state.controller, |ctrl| {
+        // У незагруженных коллекций счётчики бакетов и векторов неизвестны
+        ctrl.get_all_collections_with_storage()
+            .iter()
+            .map(|c| {
+                let mut info = collection_info(c);
+                info["loaded"] = serde_json::json!(c.is_loaded());
+                if !c.is_loaded() {
+                    info["total_vectors"] = serde_json::Value::Null;
+                    info["total_buckets"] = serde_json::Value::Null;
+                }
+                info
+            })
+            .collect::<Vec<serde_json::Value>>()
+    }).await;
+    let collections_info = match listed {
+        Ok(collections_info) => collections_info,
+        Err(e) => return error_response(e),
+    };
     
-    Json(RpcResponse { 
+    (StatusCode::OK, Json(RpcResponse { 
         status: "ok".to_string(), 
         data: Some(serde_json::json!({
             "collections": collections_info,
             "total": collections_info.len()
         })), 
         message: None 
-    })
+    }))
 }
 
 /// Добавление вектора
@@ -113,122 +463,327 @@ pub async fn get_all_collections(State(state): State<AppState>, Json(_payload):
     path = "/vector",
     request_body = AddVectorParams,
     responses(
-        (status = 200, description = "Вектор успешно добавлен", body = RpcResponse),
-        (status = 400, description = "Ошибка в запросе", body = RpcResponse)
+        (status = 200, description = "Вектор добавлен; при dedup = true может вернуться ID уже существующего почти такого же вектора", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
     ),
     tag = "Vectors"
 )]
-pub async fn add_vector(State(state): State<AppState>, Json(payload): Json<AddVectorParams>) -> Json<RpcResponse> {
+pub async fn add_vector(State(state): State<AppState>, Json(payload): Json<AddVectorParams>) -> (StatusCode, Json<RpcResponse>) {
     let mut ctrl = state.controller.write().await;
-    match ctrl.add_vector(&payload.collection, payload.embedding, payload.metadata.unwrap_or_default()) {
-        Ok(id) => Json(RpcResponse { 
+    let normalized = payload.normalize.unwrap_or(false);
+    if payload.dedup {
+        let tolerance = payload.dedup_tolerance
+            .or_else(|| state.configs.get("dedup_tolerance").and_then(|value| value.parse().ok()))
+            .unwrap_or(DEFAULT_DEDUP_TOLERANCE);
+        match ctrl.find_near_duplicate(&payload.collection, &payload.embedding, payload.normalize, tolerance) {
+            Ok(Some(id)) => return (StatusCode::OK, Json(RpcResponse { 
+                status: "ok".to_string(), 
+                data: Some(serde_json::json!({"id": id, "deduplicated": true})), 
+                message: None 
+            })),
+            Ok(None) => {}
+            Err(e) => return collection_error_response(&payload.collection, e),
+        }
+    }
+    match ctrl.add_vector_with_options(&payload.collection, payload.embedding, payload.metadata.unwrap_or_default(), payload.normalize) {
+        Ok(id) => (StatusCode::OK, Json(RpcResponse { 
             status: "ok".to_string(), 
-            data: Some(serde_json::json!({"id": id})), 
+            data: Some(serde_json::json!({"id": id, "normalized": normalized, "deduplicated": false})), 
             message: None 
-        }),
-        Err(e) => Json(RpcResponse { 
-            status: "error".to_string(), 
-            data: None, 
-            message: Some(e.to_string()) 
-        }),
+        })),
+        Err(e) => collection_error_response(&payload.collection, e),
+    }
+}
+
+/// Пакетное добавление векторов с результатом по каждому вектору
+#[utoipa::path(
+    post,
+    path = "/vector/batch",
+    request_body = AddVectorsBatchParams,
+    responses(
+        (status = 200, description = "Пакет обработан; результаты в порядке входных векторов", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn add_vectors_batch(State(state): State<AppState>, Json(payload): Json<AddVectorsBatchParams>) -> (StatusCode, Json<RpcResponse>) {
+    let items = payload.vectors
+        .into_iter()
+        .map(|item| (item.id, item.embedding, item.metadata.unwrap_or_default(), item.normalize.or(payload.normalize)))
+        .collect();
+    let mut ctrl = state.controller.write().await;
+    match ctrl.add_vectors_batch(&payload.collection, items) {
+        Ok(outcomes) => {
+            let results: Vec<BatchInsertResult> = outcomes
+                .into_iter()
+                .enumerate()
+                .map(|(index, outcome)| match outcome {
+                    Ok(id) => BatchInsertResult { index, status: "ok".to_string(), id: Some(id), error: None },
+                    Err(error) => BatchInsertResult { index, status: "error".to_string(), id: None, error: Some(error) },
+                })
+                .collect();
+            let inserted = results.iter().filter(|r| r.id.is_some()).count();
+            (StatusCode::OK, Json(RpcResponse { 
+                status: "ok".to_string(), 
+                data: Some(serde_json::json!({
+                    "results": results,
+                    "inserted": inserted,
+                    "failed": results.len() - inserted
+                })), 
+                message: None 
+            }))
+        },
+        Err(e) => collection_error_response(&payload.collection, e),
+    }
+}
+
+/// Добавление текста, разбитого на перекрывающиеся чанки
+#[utoipa::path(
+    post,
+    path = "/vector/text-chunks",
+    request_body = AddTextChunksParams,
+    responses(
+        (status = 200, description = "Чанки текста успешно добавлены", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn add_text_chunks(State(state): State<AppState>, Json(payload): Json<AddTextChunksParams>) -> (StatusCode, Json<RpcResponse>) {
+    if !state.controller.read().await.collection_exists(&payload.collection) {
+        return error_response(VecDbError::collection_not_found(&payload.collection));
+    }
+
+    // Эмбеддинги строятся в пуле блокирующих задач и без блокировки контроллера:
+    // загрузка модели и инференс не задерживают другие запросы
+    let text = payload.text.clone();
+    let (chunk_size, overlap) = (payload.chunk_size, payload.overlap);
+    let embeddings = match tokio::task::spawn_blocking(move || CollectionController::embed_text_chunks(&text, chunk_size, overlap)).await {
+        Ok(Ok(embeddings)) => embeddings,
+        Ok(Err(e)) => return error_response(e),
+        Err(e) => return error_response(VecDbError::Validation(format!("Не удалось построить эмбеддинг: {}", e))),
+    };
+
+    let mut ctrl = state.controller.write().await;
+    match ctrl.add_text_chunks(&payload.collection, &payload.text, embeddings, payload.metadata.unwrap_or_default()) {
+        Ok((doc_id, ids)) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({"doc_id": doc_id, "ids": ids})), 
+            message: None 
+        })),
+        Err(e) => error_response(e),
     }
 }
 
 /// Обновление вектора
 #[utoipa::path(
-    put,
+    post,
     path = "/vector/update",
     request_body = UpdateVectorParams,
     responses(
         (status = 200, description = "Вектор успешно обновлен", body = RpcResponse),
-        (status = 400, description = "Ошибка в запросе", body = RpcResponse)
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция или вектор не найдены", body = RpcResponse)
     ),
     tag = "Vectors"
 )]
-pub async fn update_vector(State(state): State<AppState>, Json(payload): Json<UpdateVectorParams>) -> Json<RpcResponse> {
+pub async fn update_vector(State(state): State<AppState>, Json(payload): Json<UpdateVectorParams>) -> (StatusCode, Json<RpcResponse>) {
     let mut ctrl = state.controller.write().await;
     match ctrl.update_vector(&payload.collection, payload.vector_id, payload.embedding, payload.metadata) {
-        Ok(_) => Json(RpcResponse { 
+        Ok(_) => (StatusCode::OK, Json(RpcResponse { 
             status: "ok".to_string(), 
             data: Some(serde_json::json!({"updated": true})), 
             message: None 
-        }),
-        Err(e) => Json(RpcResponse { 
-            status: "error".to_string(), 
-            data: None, 
-            message: Some(e.to_string()) 
-        }),
+        })),
+        Err(e) => collection_error_response(&payload.collection, e),
     }
 }
 
-/// Получение вектора
+/// Вставка или замена вектора
+#[utoipa::path(
+    post,
+    path = "/vector/upsert",
+    request_body = UpsertVectorParams,
+    responses(
+        (status = 200, description = "Вектор успешно вставлен или обновлен", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn upsert_vector(State(state): State<AppState>, Json(payload): Json<UpsertVectorParams>) -> (StatusCode, Json<RpcResponse>) {
+    let mut ctrl = state.controller.write().await;
+    match ctrl.upsert_vector(&payload.collection, payload.id, payload.embedding, payload.metadata) {
+        Ok(inserted) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({
+                "id": payload.id,
+                "operation": if inserted { "inserted" } else { "updated" }
+            })), 
+            message: None 
+        })),
+        Err(e) => collection_error_response(&payload.collection, e),
+    }
+}
+
+/// Клиент запросил эмбеддинги в компактном бинарном виде (Accept: application/octet-stream)
+fn wants_binary_embeddings(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.split(';').next().unwrap_or("").trim() == EMBEDDINGS_CONTENT_TYPE)
+}
+
+/// Бинарный ответ с эмбеддингами в формате encode_embeddings
+fn binary_embeddings_response(dimension: usize, embeddings: &[&[f32]]) -> Response {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, EMBEDDINGS_CONTENT_TYPE)],
+        encode_embeddings(dimension, embeddings),
+    ).into_response()
+}
+
+/// Получение вектора. С заголовком Accept: application/octet-stream возвращается только
+/// эмбеддинг: заголовок (количество = 1, размерность) в u32 LE и значения f32 LE
 #[utoipa::path(
     post,
     path = "/vector/get",
     request_body = GetVectorParams,
+    params(
+        ("Accept" = Option<String>, Header, description = "application/octet-stream — вернуть эмбеддинг в бинарном виде")
+    ),
     responses(
-        (status = 200, description = "Вектор успешно получен", body = RpcResponse),
+        (status = 200, description = "Вектор успешно получен (JSON или бинарный эмбеддинг)", body = RpcResponse),
         (status = 400, description = "Ошибка в запросе", body = RpcResponse)
     ),
     tag = "Vectors"
 )]
-pub async fn get_vector(State(state): State<AppState>, Json(payload): Json<GetVectorParams>) -> Json<RpcResponse> {
+pub async fn get_vector(State(state): State<AppState>, headers: HeaderMap, Json(payload): Json<GetVectorParams>) -> Response {
     let ctrl = state.controller.read().await;
     match ctrl.get_collection(&payload.collection) {
         Some(collection) => {
-            match collection.buckets_controller.get_vector(payload.vector_id) {
+            let vector = collection.buckets_controller.get_vector(payload.vector_id);
+            if vector.is_some() {
+                collection.record_access([payload.vector_id]);
+            }
+            match vector {
+                Some(vector) if wants_binary_embeddings(&headers) => {
+                    binary_embeddings_response(collection.vector_dimension, &[&vector.data])
+                }
                 Some(vector) => Json(RpcResponse { 
                     status: "ok".to_string(), 
                     data: Some(serde_json::json!({
                         "id": vector.hash_id(),
                         "embedding": vector.data,
-                        "metadata": vector.metadata
+                        "metadata": vector.metadata,
+                        "normalized": vector.is_normalized()
                     })), 
                     message: None 
-                }),
+                }).into_response(),
                 None => Json(RpcResponse { 
                     status: "error".to_string(), 
                     data: None, 
                     message: Some("Вектор не найден".to_string()) 
-                }),
+                }).into_response(),
             }
         }
         None => Json(RpcResponse { 
             status: "error".to_string(), 
             data: None, 
             message: Some("Коллекция не найдена".to_string()) 
-        }),
+        }).into_response(),
     }
 }
 
-/// Удаление вектора
+/// Получение нескольких векторов по ID. С заголовком Accept: application/octet-stream
+/// возвращаются только эмбеддинги в порядке запроса: заголовок (количество, размерность)
+/// в u32 LE и значения f32 LE. В бинарном виде все векторы должны существовать
 #[utoipa::path(
     post,
-    path = "/vector/delete",
-    request_body = DeleteVectorParams,
+    path = "/vector/get-many",
+    request_body = GetManyVectorsParams,
+    params(
+        ("Accept" = Option<String>, Header, description = "application/octet-stream — вернуть эмбеддинги в бинарном виде")
+    ),
     responses(
-        (status = 200, description = "Вектор успешно удален", body = RpcResponse),
-        (status = 400, description = "Ошибка в запросе", body = RpcResponse)
+        (status = 200, description = "Найденные векторы и ID ненайденных (JSON) или бинарные эмбеддинги", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена или (в бинарном виде) часть векторов отсутствует", body = RpcResponse)
     ),
     tag = "Vectors"
 )]
-pub async fn delete_vector(State(state): State<AppState>, Json(payload): Json<DeleteVectorParams>) -> Json<RpcResponse> {
-    let mut ctrl = state.controller.write().await;
-    match ctrl.get_collection_mut(&payload.collection) {
-        Some(collection) => {
-            match collection.buckets_controller.remove_vector(payload.vector_id) {
-                Ok(_) => Json(RpcResponse { 
-                    status: "ok".to_string(), 
-                    data: Some(serde_json::json!({"deleted": true})), 
-                    message: None 
-                }),
-                Err(e) => Json(RpcResponse { 
-                    status: "error".to_string(), 
-                    data: None, 
-                    message: Some(e) 
-                }),
-            }
+pub async fn get_many_vectors(State(state): State<AppState>, headers: HeaderMap, Json(payload): Json<GetManyVectorsParams>) -> Response {
+    let ctrl = state.controller.read().await;
+    let Some(collection) = ctrl.get_collection(&payload.collection) else {
+        return (StatusCode::NOT_FOUND, Json(RpcResponse { 
+            status: "error".to_string(), 
+            data: None, 
+            message: Some(format!("Коллекция '{}' не найдена", payload.collection)) 
+        })).into_response();
+    };
+
+    let mut vectors = Vec::with_capacity(payload.vector_ids.len());
+    let mut missing = Vec::new();
+    for &vector_id in &payload.vector_ids {
+        match collection.buckets_controller.get_vector(vector_id) {
+            Some(vector) => vectors.push(vector),
+            None => missing.push(vector_id),
         }
+    }
+    collection.record_access(vectors.iter().map(|vector| vector.hash_id()));
+
+    if wants_binary_embeddings(&headers) {
+        // В бинарном ответе нет ID, поэтому пропуски сделали бы порядок неоднозначным
+        if !missing.is_empty() {
+            return (StatusCode::NOT_FOUND, Json(RpcResponse { 
+                status: "error".to_string(), 
+                data: Some(serde_json::json!({"missing": missing})), 
+                message: Some("Не все векторы найдены".to_string()) 
+            })).into_response();
+        }
+        let embeddings: Vec<&[f32]> = vectors.iter().map(|vector| vector.data.as_slice()).collect();
+        return binary_embeddings_response(collection.vector_dimension, &embeddings);
+    }
+
+    let vectors: Vec<serde_json::Value> = vectors
+        .iter()
+        .map(|vector| serde_json::json!({
+            "id": vector.hash_id(),
+            "embedding": vector.data,
+            "metadata": vector.metadata,
+            "normalized": vector.is_normalized()
+        }))
+        .collect();
+    Json(RpcResponse { 
+        status: "ok".to_string(), 
+        data: Some(serde_json::json!({"vectors": vectors, "missing": missing})), 
+        message: None 
+    }).into_response()
+}
+
+/// Проверка существования вектора без передачи эмбеддинга и метаданных
+#[utoipa::path(
+    post,
+    path = "/vector/exists",
+    request_body = VectorExistsParams,
+    responses(
+        (status = 200, description = "Признак существования вектора", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn vector_exists(State(state): State<AppState>, Json(payload): Json<VectorExistsParams>) -> Json<RpcResponse> {
+    let ctrl = state.controller.read().await;
+    match ctrl.get_collection(&payload.collection) {
+        Some(collection) => Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({
+                "exists": collection.buckets_controller.contains_vector(payload.vector_id)
+            })), 
+            message: None 
+        }),
         None => Json(RpcResponse { 
             status: "error".to_string(), 
             data: None, 
@@ -237,6 +792,30 @@ pub async fn delete_vector(State(state): State<AppState>, Json(payload): Json<De
     }
 }
 
+/// Удаление вектора
+#[utoipa::path(
+    post,
+    path = "/vector/delete",
+    request_body = DeleteVectorParams,
+    responses(
+        (status = 200, description = "Вектор успешно удален", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция или вектор не найдены", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn delete_vector(State(state): State<AppState>, Json(payload): Json<DeleteVectorParams>) -> (StatusCode, Json<RpcResponse>) {
+    let mut ctrl = state.controller.write().await;
+    match ctrl.delete_vector(&payload.collection, payload.vector_id) {
+        Ok(_) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({"deleted": true})), 
+            message: None 
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
 /// Фильтрация векторов по метаданным
 #[utoipa::path(
     post,
@@ -244,65 +823,627 @@ pub async fn delete_vector(State(state): State<AppState>, Json(payload): Json<De
     request_body = FilterByMetadataParams,
     responses(
         (status = 200, description = "Векторы успешно отфильтрованы", body = RpcResponse),
-        (status = 400, description = "Ошибка в запросе", body = RpcResponse)
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
     ),
     tag = "Vectors"
 )]
-pub async fn filter_by_metadata(State(state): State<AppState>, Json(payload): Json<FilterByMetadataParams>) -> Json<RpcResponse> {
+pub async fn filter_by_metadata(State(state): State<AppState>, Json(payload): Json<FilterByMetadataParams>) -> (StatusCode, Json<RpcResponse>) {
+    // Плоские фильтры трактуются как неявное AND и объединяются с выражением
+    let mut conditions = vec![MetadataFilter::from_map(&payload.filters)];
+    conditions.extend(payload.expression);
+    let expression = MetadataFilter::And(conditions);
+
     let ctrl = state.controller.read().await;
-    match ctrl.filter_by_metadata(&payload.collection, &payload.filters) {
-        Ok(vector_ids) => Json(RpcResponse { 
+    match ctrl.filter_by_expression(&payload.collection, &expression) {
+        Ok(vector_ids) => (StatusCode::OK, Json(RpcResponse { 
             status: "ok".to_string(), 
             data: Some(serde_json::json!({"vector_ids": vector_ids})), 
             message: None 
-        }),
-        Err(e) => Json(RpcResponse { 
-            status: "error".to_string(), 
-            data: None, 
-            message: Some(e.to_string()) 
-        }),
+        })),
+        Err(e) => error_response(e),
     }
 }
 
+/// Количество результатов поиска, если k не указан и server.default_k не задан
+const DEFAULT_K: usize = 10;
+/// Верхняя граница k, если server.max_k не задан
+const DEFAULT_MAX_K: usize = 1000;
+
+/// Определяет k для поиска: значение из запроса или server.default_k, ограниченное server.max_k.
+/// Возвращает итоговое k и признак того, что запрошенное значение было урезано
+fn resolve_k(configs: &HashMap<String, String>, requested: Option<usize>) -> (usize, bool) {
+    let config_value = |key: &str, default: usize| {
+        configs.get(key).and_then(|v| v.parse::<usize>().ok()).unwrap_or(default)
+    };
+    let max_k = config_value("max_k", DEFAULT_MAX_K);
+    let k = requested.unwrap_or_else(|| config_value("default_k", DEFAULT_K));
+    (k.min(max_k), k > max_k)
+}
+
+/// Преобразует кортежи результатов поиска в структуры для красивого JSON,
+/// при необходимости сразу добавляя метаданные и эмбеддинг вектора
+fn format_similar_results(
+    buckets_controller: &BucketController,
+    results: Vec<(u64, usize, f32)>,
+    include_metadata: bool,
+    include_embedding: bool,
+) -> Vec<SimilarVectorResult> {
+    results
+        .into_iter()
+        .map(|(bucket_id, vector_index, score)| {
+            let vector = buckets_controller.get_vector_at(bucket_id, vector_index);
+            SimilarVectorResult {
+                bucket_id,
+                vector_index,
+                vector_id: vector.map(|v| v.hash_id()).unwrap_or_default(),
+                score,
+                metadata: vector.filter(|_| include_metadata).map(|v| v.metadata.clone()),
+                embedding: vector.filter(|_| include_embedding).map(|v| v.data.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Поля результата поиска, которые можно запросить в FindSimilarParams::fields
+const SIMILAR_RESULT_FIELDS: &[&str] = &["vector_id", "score", "metadata", "embedding", "bucket_id", "vector_index"];
+
+/// Оставляет в каждом результате только запрошенные поля, в порядке fields
+fn project_similar_results(results: Vec<SimilarVectorResult>, fields: &[String]) -> Vec<serde_json::Value> {
+    results
+        .into_iter()
+        .map(|result| {
+            let projected: serde_json::Map<String, serde_json::Value> = fields
+                .iter()
+                .filter_map(|field| {
+                    let value = match field.as_str() {
+                        "vector_id" => serde_json::json!(result.vector_id),
+                        "score" => serde_json::json!(result.score),
+                        "metadata" => serde_json::json!(result.metadata),
+                        "embedding" => serde_json::json!(result.embedding),
+                        "bucket_id" => serde_json::json!(result.bucket_id),
+                        "vector_index" => serde_json::json!(result.vector_index),
+                        _ => return None,
+                    };
+                    Some((field.clone(), value))
+                })
+                .collect();
+            serde_json::Value::Object(projected)
+        })
+        .collect()
+}
+
 /// Поиск похожих векторов
 #[utoipa::path(
     post,
     path = "/vector/similar",
     request_body = FindSimilarParams,
     responses(
-        (status = 200, description = "Похожие векторы найдены", body = RpcResponse),
-        (status = 400, description = "Ошибка в запросе", body = RpcResponse)
+        (status = 200, description = "Похожие векторы найдены (collection_empty = true, если коллекция пуста)", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
     ),
     tag = "Vectors"
 )]
-pub async fn find_similar(State(state): State<AppState>, Json(payload): Json<FindSimilarParams>) -> Json<RpcResponse> {
+pub async fn find_similar(State(state): State<AppState>, Json(payload): Json<FindSimilarParams>) -> (StatusCode, Json<RpcResponse>) {
+    let fields = payload.fields.as_deref();
+    if let Some(unknown) = fields.into_iter().flatten().find(|field| !SIMILAR_RESULT_FIELDS.contains(&field.as_str())) {
+        return error_response(VecDbError::Validation(format!(
+            "Неизвестное поле результата '{}', допустимы: {}", unknown, SIMILAR_RESULT_FIELDS.join(", ")
+        )));
+    }
+    let requested = |field: &str| fields.is_some_and(|fields| fields.iter().any(|f| f == field));
+    let boosts = match payload.boosts.as_ref().map(MetadataBoosts::from_map).transpose() {
+        Ok(boosts) => boosts.unwrap_or_default(),
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(RpcResponse { 
+            status: "error".to_string(), 
+            data: None, 
+            message: Some(e) 
+        })),
+    };
+    let options = SearchOptions {
+        deadline: payload.timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms)),
+        boosts,
+        exclude_ids: payload.exclude_ids.iter().copied().collect(),
+        rerank: payload.rerank.then(|| payload.candidate_factor.unwrap_or(DEFAULT_RERANK_CANDIDATE_FACTOR)),
+        facets: payload.facets.clone(),
+    };
     let ctrl = state.controller.read().await;
-    match ctrl.find_similar(payload.collection, &payload.query, payload.k) {
-        Ok(results) => {
-            // Преобразуем кортежи в структуры для красивого JSON
-            let formatted_results: Vec<SimilarVectorResult> = results
+    let Some(collection) = ctrl.get_collection(&payload.collection) else {
+        return collection_error_response(&payload.collection, VecDbError::collection_not_found(&payload.collection));
+    };
+    let buckets_controller = &collection.buckets_controller;
+
+    let (k, k_clamped) = resolve_k(&state.configs, payload.k);
+
+    let cache_key = state.query_cache.is_enabled()
+        .then(|| QueryCacheKey::new(collection, &payload.query, k, &options));
+    let cached = cache_key.as_ref().and_then(|key| state.query_cache.get(key));
+    let is_cached = cached.is_some();
+    let outcome = match cached {
+        Some(outcome) => {
+            collection.record_search_hits(&outcome.results);
+            Ok(outcome)
+        }
+        None => ctrl.find_similar_with_options(payload.collection.clone(), &payload.query, k, &options)
+            .inspect(|outcome| {
+                if let Some(key) = cache_key {
+                    state.query_cache.insert(key, outcome);
+                }
+            }),
+    };
+
+    match outcome {
+        Ok(SearchOutcome { results, timed_out, buckets_probed, candidates_examined, mut facets }) => {
+            let formatted_results = format_similar_results(
+                buckets_controller,
+                results,
+                payload.include_metadata || requested("metadata"),
+                payload.include_embedding || requested("embedding"),
+            );
+            let formatted_results = match fields {
+                Some(fields) => serde_json::json!(project_similar_results(formatted_results, fields)),
+                None => serde_json::json!(formatted_results),
+            };
+            // Запрошенный ключ есть в ответе, даже если ни у одного кандидата его нет
+            for key in &payload.facets {
+                facets.entry(key.clone()).or_default();
+            }
+            
+            (StatusCode::OK, Json(RpcResponse { 
+                status: "ok".to_string(), 
+                data: Some(serde_json::json!({
+                    "results": formatted_results,
+                    "timed_out": timed_out,
+                    "buckets_probed": buckets_probed,
+                    "candidates_examined": candidates_examined,
+                    "facets": facets,
+                    "collection_empty": buckets_controller.total_vectors() == 0,
+                    "cached": is_cached,
+                    "k": k,
+                    "k_clamped": k_clamped
+                })), 
+                message: None 
+            }))
+        },
+        Err(e) => collection_error_response(&payload.collection, e),
+    }
+}
+
+/// Пакетный поиск похожих векторов: несколько запросов под одной блокировкой на чтение
+#[utoipa::path(
+    post,
+    path = "/vector/similar-batch",
+    request_body = FindSimilarBatchParams,
+    responses(
+        (status = 200, description = "Списки похожих векторов в порядке запросов", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn find_similar_batch(State(state): State<AppState>, Json(payload): Json<FindSimilarBatchParams>) -> (StatusCode, Json<RpcResponse>) {
+    let ctrl = state.controller.read().await;
+    let Some(collection) = ctrl.get_collection(&payload.collection) else {
+        return collection_error_response(&payload.collection, VecDbError::collection_not_found(&payload.collection));
+    };
+
+    let (k, k_clamped) = resolve_k(&state.configs, payload.k);
+
+    match ctrl.find_similar_batch(&payload.collection, &payload.queries, k) {
+        Ok(batch) => {
+            let formatted: Vec<Vec<SimilarVectorResult>> = batch
                 .into_iter()
-                .map(|(bucket_id, vector_index, score)| SimilarVectorResult {
-                    bucket_id,
-                    vector_index,
-                    score,
-                })
+                .map(|results| format_similar_results(
+                    &collection.buckets_controller, results, payload.include_metadata, payload.include_embedding
+                ))
                 .collect();
-            
-            Json(RpcResponse { 
+            (StatusCode::OK, Json(RpcResponse { 
                 status: "ok".to_string(), 
-                data: Some(serde_json::json!({"results": formatted_results})), 
+                data: Some(serde_json::json!({"results": formatted, "k": k, "k_clamped": k_clamped})), 
                 message: None 
-            })
+            }))
+        },
+        Err(e @ VecDbError::DimensionMismatch { .. }) => {
+            // Номер первого запроса неверной размерности, чтобы клиент знал, какой исправить
+            let query_index = payload.queries.iter().position(|q| q.len() != collection.vector_dimension);
+            let (status, mut response) = collection_error_response(&payload.collection, e);
+            if let Some(data) = response.0.data.as_mut() {
+                data["query_index"] = serde_json::json!(query_index);
+            }
+            (status, response)
         },
-        Err(e) => Json(RpcResponse { 
+        Err(e) => collection_error_response(&payload.collection, e),
+    }
+}
+
+/// Распределение score top-k соседей запроса: для подбора порога отсечения по score
+#[utoipa::path(
+    post,
+    path = "/vector/similar-scores",
+    request_body = SimilarScoresParams,
+    responses(
+        (status = 200, description = "Score соседей по убыванию, их минимум, максимум и среднее", body = RpcResponse),
+        (status = 400, description = "Ошибка в запросе", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn similar_scores(State(state): State<AppState>, Json(payload): Json<SimilarScoresParams>) -> (StatusCode, Json<RpcResponse>) {
+    let ctrl = state.controller.read().await;
+    if !ctrl.collection_exists(&payload.collection) {
+        return collection_error_response(&payload.collection, VecDbError::collection_not_found(&payload.collection));
+    }
+
+    let (k, k_clamped) = resolve_k(&state.configs, payload.k);
+
+    match ctrl.find_similar(payload.collection.clone(), &payload.query, k) {
+        Ok(results) => {
+            let mut scores: Vec<f32> = results.into_iter().map(|(_, _, score)| score).collect();
+            scores.sort_by(|a, b| b.total_cmp(a));
+            let mean = (!scores.is_empty()).then(|| scores.iter().sum::<f32>() / scores.len() as f32);
+            (StatusCode::OK, Json(RpcResponse { 
+                status: "ok".to_string(), 
+                data: Some(serde_json::json!({
+                    "scores": scores,
+                    "min": scores.last(),
+                    "max": scores.first(),
+                    "mean": mean,
+                    "k": k,
+                    "k_clamped": k_clamped
+                })), 
+                message: None 
+            }))
+        },
+        Err(e) => collection_error_response(&payload.collection, e),
+    }
+}
+
+/// Поиск векторов, похожих на сохранённый вектор («more like this»): эмбеддинг
+/// берётся на сервере, клиенту не нужно его получать и отправлять обратно
+#[utoipa::path(
+    post,
+    path = "/vector/similar-to",
+    request_body = SimilarToParams,
+    responses(
+        (status = 200, description = "Похожие векторы найдены", body = RpcResponse),
+        (status = 404, description = "Коллекция или вектор не найдены", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn similar_to(State(state): State<AppState>, Json(payload): Json<SimilarToParams>) -> (StatusCode, Json<RpcResponse>) {
+    let ctrl = state.controller.read().await;
+    let (k, k_clamped) = resolve_k(&state.configs, payload.k);
+
+    match ctrl.find_similar_to(&payload.collection, payload.id, k, payload.exclude_self) {
+        Ok(outcome) => {
+            let Some(collection) = ctrl.get_collection(&payload.collection) else {
+                return collection_error_response(&payload.collection, VecDbError::collection_not_found(&payload.collection));
+            };
+            let formatted_results = format_similar_results(
+                &collection.buckets_controller, outcome.results, payload.include_metadata, payload.include_embedding
+            );
+            (StatusCode::OK, Json(RpcResponse { 
+                status: "ok".to_string(), 
+                data: Some(serde_json::json!({
+                    "id": payload.id,
+                    "results": formatted_results,
+                    "buckets_probed": outcome.buckets_probed,
+                    "candidates_examined": outcome.candidates_examined,
+                    "k": k,
+                    "k_clamped": k_clamped
+                })), 
+                message: None 
+            }))
+        },
+        Err(e) => collection_error_response(&payload.collection, e),
+    }
+}
+
+/// Сравнение двух векторов без вставки: переданных явно (a и b) или сохранённых
+/// в коллекции (collection, id_a и id_b). Возвращает косинусное сходство, евклидово
+/// и манхэттенское расстояния, скалярное произведение и score поиска для каждой метрики
+#[utoipa::path(
+    post,
+    path = "/vector/compare",
+    request_body = CompareVectorsParams,
+    responses(
+        (status = 200, description = "Меры сходства и расстояния между векторами", body = RpcResponse),
+        (status = 400, description = "Неполный запрос или разная размерность векторов", body = RpcResponse),
+        (status = 404, description = "Коллекция или вектор не найдены", body = RpcResponse)
+    ),
+    tag = "Vectors"
+)]
+pub async fn compare_vectors(State(state): State<AppState>, Json(payload): Json<CompareVectorsParams>) -> (StatusCode, Json<RpcResponse>) {
+    let compared = match payload {
+        CompareVectorsParams { a: Some(a), b: Some(b), collection: None, id_a: None, id_b: None } => {
+            embeddings::compare_vectors(&a, &b)
+                .map(|comparison| (a, b, comparison))
+                .map_err(error_response)
+        }
+        CompareVectorsParams { a: None, b: None, collection: Some(collection), id_a: Some(id_a), id_b: Some(id_b) } => {
+            let ctrl = state.controller.read().await;
+            ctrl.get_vector(&collection, id_a)
+                .and_then(|a| ctrl.get_vector(&collection, id_b).map(|b| (a.data.clone(), b.data.clone())))
+                .and_then(|(a, b)| embeddings::compare_vectors(&a, &b).map(|comparison| (a, b, comparison)))
+                .map_err(|e| collection_error_response(&collection, e))
+        }
+        _ => Err(error_response(VecDbError::Validation(
+            "Передайте либо a и b, либо collection, id_a и id_b".to_string()
+        ))),
+    };
+
+    match compared {
+        Ok((a, b, comparison)) => {
+            // Score считается той же функцией, что и в результатах поиска («чем больше, тем лучше»)
+            let (norm_a, norm_b) = (embeddings::l2_norm(&a), embeddings::l2_norm(&b));
+            let score = |metric: LSHMetric| embeddings::similarity_score(&metric, &a, norm_a, &b, norm_b);
+            (StatusCode::OK, Json(RpcResponse { 
+                status: "ok".to_string(), 
+                data: Some(serde_json::json!({
+                    "cosine": comparison.cosine,
+                    "euclidean": comparison.euclidean,
+                    "manhattan": comparison.manhattan,
+                    "dot_product": comparison.dot_product,
+                    "scores": {
+                        "Cosine": score(LSHMetric::Cosine),
+                        "Euclidean": score(LSHMetric::Euclidean),
+                        "Manhattan": score(LSHMetric::Manhattan)
+                    }
+                })), 
+                message: None 
+            }))
+        }
+        Err(response) => response,
+    }
+}
+
+/// Информация о сервере
+#[utoipa::path(
+    get,
+    path = "/info",
+    responses(
+        (status = 200, description = "Информация о сервере", body = RpcResponse)
+    ),
+    tag = "System"
+)]
+pub async fn info(State(state): State<AppState>) -> Json<RpcResponse> {
+    let ctrl = state.controller.read().await;
+    let host = state.configs.get("host").map(|s| s.as_str()).unwrap_or("0.0.0.0");
+    let port = state.configs.get("port").map(|s| s.as_str()).unwrap_or("8080");
+
+    // Узел работает без шардирования, поэтому список шардов всегда пуст
+    Json(RpcResponse { 
+        status: "ok".to_string(), 
+        data: Some(serde_json::json!({
+            "role": "standalone",
+            "version": env!("CARGO_PKG_VERSION"),
+            "sharded": false,
+            "shards": [],
+            "routing_strategy": null,
+            "address": format!("{}:{}", host, port),
+            "swagger_ui": "/swagger-ui",
+            "openapi": "/api-docs/openapi.json",
+            "collections_count": ctrl.get_all_collections().len()
+        })), 
+        message: None 
+    })
+}
+
+/// Проверка здоровья узла. По умолчанию дешёвая: сервер отвечает — узел здоров.
+/// С deep=true дополнительно пробует записать и удалить служебный файл в storage
+#[utoipa::path(
+    get,
+    path = "/health",
+    params(HealthParams),
+    responses(
+        (status = 200, description = "Узел здоров", body = RpcResponse),
+        (status = 503, description = "Storage недоступен для записи (только при deep=true)", body = RpcResponse)
+    ),
+    tag = "System"
+)]
+pub async fn health(State(state): State<AppState>, Query(params): Query<HealthParams>) -> (StatusCode, Json<RpcResponse>) {
+    if !params.deep {
+        return (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({"healthy": true, "deep": false})), 
+            message: None 
+        }));
+    }
+
+    let checked = CollectionController::read_blocking(&state.controller, |ctrl| ctrl.check_storage()).await;
+    match checked.and_then(|checked| checked) {
+        Ok(()) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({"healthy": true, "deep": true, "storage": "writable"})), 
+            message: None 
+        })),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, Json(RpcResponse { 
             status: "error".to_string(), 
-            data: None, 
+            data: Some(serde_json::json!({"healthy": false, "deep": true, "storage": "unwritable", "error_code": e.error_code()})), 
             message: Some(e.to_string()) 
-        }),
+        })),
     }
 }
 
+/// Занимаемое место в хранилище
+#[utoipa::path(
+    get,
+    path = "/admin/disk-usage",
+    responses(
+        (status = 200, description = "Общий размер хранилища и разбивка по коллекциям в байтах", body = RpcResponse)
+    ),
+    tag = "System"
+)]
+pub async fn disk_usage(State(state): State<AppState>) -> (StatusCode, Json<RpcResponse>) {
+    let (total_bytes, usage) = match CollectionController::read_blocking(&state.controller, |ctrl| ctrl.disk_usage()).await {
+        Ok(usage) => usage,
+        Err(e) => return error_response(e),
+    };
+    let collections: serde_json::Map<String, serde_json::Value> = usage
+        .into_iter()
+        .map(|(name, usage)| (name, serde_json::json!({
+            "collection_file": usage.collection_file,
+            "buckets": usage.buckets,
+            "vectors": usage.vectors,
+            "total": usage.total()
+        })))
+        .collect();
+
+    (StatusCode::OK, Json(RpcResponse { 
+        status: "ok".to_string(), 
+        data: Some(serde_json::json!({"total_bytes": total_bytes, "collections": collections})), 
+        message: None 
+    }))
+}
+
+/// Сохранение всех коллекций без остановки сервера
+#[utoipa::path(
+    post,
+    path = "/admin/flush",
+    responses(
+        (status = 200, description = "Все коллекции сохранены в storage", body = RpcResponse),
+        (status = 500, description = "Часть коллекций не сохранена: ошибки по коллекциям в data.failed", body = RpcResponse)
+    ),
+    tag = "System"
+)]
+pub async fn flush(State(state): State<AppState>) -> (StatusCode, Json<RpcResponse>) {
+    let results = match CollectionController::dump_async(&state.controller).await {
+        Ok(results) => results,
+        Err(e) => return error_response(e),
+    };
+    let failed: serde_json::Map<String, serde_json::Value> = results
+        .iter()
+        .filter_map(|(name, result)| result.as_ref().err().map(|e| (name.clone(), serde_json::json!(e.to_string()))))
+        .collect();
+
+    if !failed.is_empty() {
+        let names: Vec<&str> = failed.keys().map(String::as_str).collect();
+        let message = format!("Не удалось сохранить коллекции: {}", names.join(", "));
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(RpcResponse { 
+            status: "error".to_string(), 
+            data: Some(serde_json::json!({"flushed": false, "collections": results.len(), "failed": failed})), 
+            message: Some(message) 
+        }));
+    }
+    (StatusCode::OK, Json(RpcResponse { 
+        status: "ok".to_string(), 
+        data: Some(serde_json::json!({"flushed": true, "collections": results.len()})), 
+        message: None 
+    }))
+}
+
+/// Диагностика: сохранённые байты вектора в base64, без десериализации
+#[utoipa::path(
+    post,
+    path = "/admin/vector-raw",
+    request_body = VectorRawParams,
+    responses(
+        (status = 200, description = "Байты вектора в base64 и их длина", body = RpcResponse),
+        (status = 404, description = "Файл вектора не найден или повреждён", body = RpcResponse)
+    ),
+    tag = "System"
+)]
+pub async fn vector_raw(State(state): State<AppState>, Json(payload): Json<VectorRawParams>) -> (StatusCode, Json<RpcResponse>) {
+    let raw = CollectionController::read_blocking(&state.controller, move |ctrl| {
+        ctrl.read_raw_vector(&payload.collection, payload.bucket_id, payload.id)
+            .map(|raw| (raw, ctrl.storage_format()))
+    }).await;
+    match raw.and_then(|raw| raw) {
+        Ok((raw, format)) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({
+                "length": raw.len(),
+                "format": format.to_string(),
+                "raw_base64": BASE64_STANDARD.encode(&raw)
+            })), 
+            message: None 
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Перестроение бакетов коллекции по векторам из storage (после потери или порчи файлов бакетов)
+#[utoipa::path(
+    post,
+    path = "/admin/reindex",
+    request_body = ReindexParams,
+    responses(
+        (status = 200, description = "Бакеты перестроены и сохранены", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена в storage", body = RpcResponse)
+    ),
+    tag = "System"
+)]
+pub async fn reindex(State(state): State<AppState>, Json(payload): Json<ReindexParams>) -> (StatusCode, Json<RpcResponse>) {
+    let name = payload.name.clone();
+    let reindexed = CollectionController::write_blocking(&state.controller, move |ctrl| ctrl.reindex_collection(&name)).await;
+    match reindexed.and_then(|reindexed| reindexed) {
+        Ok((buckets, vectors)) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({
+                "name": payload.name,
+                "buckets": buckets,
+                "vectors": vectors
+            })), 
+            message: None 
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Массовая миграция метаданных коллекции без перестроения бакетов
+#[utoipa::path(
+    post,
+    path = "/admin/migrate-metadata",
+    request_body = MigrateMetadataParams,
+    responses(
+        (status = 200, description = "Метаданные обновлены, возвращается количество изменённых векторов", body = RpcResponse),
+        (status = 400, description = "Не задано ни одного правила или пустой ключ", body = RpcResponse),
+        (status = 404, description = "Коллекция не найдена", body = RpcResponse)
+    ),
+    tag = "System"
+)]
+pub async fn migrate_metadata(State(state): State<AppState>, Json(payload): Json<MigrateMetadataParams>) -> (StatusCode, Json<RpcResponse>) {
+    let migration = match MetadataMigration::new(payload.rename, payload.remap) {
+        Ok(migration) => migration,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(RpcResponse { 
+            status: "error".to_string(), 
+            data: None, 
+            message: Some(e) 
+        })),
+    };
+    let name = payload.collection.clone();
+    let migrated = CollectionController::write_blocking(&state.controller, move |ctrl| ctrl.migrate_metadata(&name, &migration)).await;
+    match migrated.and_then(|updated| updated) {
+        Ok(updated) => (StatusCode::OK, Json(RpcResponse { 
+            status: "ok".to_string(), 
+            data: Some(serde_json::json!({"collection": payload.collection, "updated": updated})), 
+            message: None 
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Удаление пустых бакетов во всех коллекциях
+#[utoipa::path(
+    post,
+    path = "/admin/prune",
+    responses(
+        (status = 200, description = "Количество удалённых пустых бакетов, всего и по коллекциям", body = RpcResponse)
+    ),
+    tag = "System"
+)]
+pub async fn prune(State(state): State<AppState>) -> Json<RpcResponse> {
+    let mut ctrl = state.controller.write().await;
+    let pruned = ctrl.prune_empty_buckets();
+
+    Json(RpcResponse { 
+        status: "ok".to_string(), 
+        data: Some(serde_json::json!({"pruned": pruned.values().sum::<usize>(), "collections": pruned})), 
+        message: None 
+    })
+}
+
 /// Остановка сервера
 #[utoipa::path(
     post,
@@ -313,12 +1454,40 @@ pub async fn find_similar(State(state): State<AppState>, Json(payload): Json<Fin
     tag = "System"
 )]
 pub async fn stop(State(state): State<AppState>) -> Json<RpcResponse> {
-    // Отправляем сигнал остановки
-    let _ = state.shutdown_tx.send(());
+    // Сигнал срабатывает один раз; повторный /stop только сообщает, что остановка уже идёт
+    let message = if state.shutdown.trigger() { "Server stopping..." } else { "Server is already stopping" };
     
     Json(RpcResponse { 
         status: "ok".to_string(), 
-        data: Some(serde_json::json!("Server stopping...")), 
+        data: Some(serde_json::json!(message)), 
         message: None 
     })
 }
+
+/// Middleware режима только для чтения: запросы к изменяющим маршрутам получают 403
+pub async fn reject_mutations(request: Request, next: Next) -> Response {
+    let mutating = request.extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|path| MUTATING_ROUTES.contains(&path.as_str()));
+    if !mutating {
+        return next.run(request).await;
+    }
+    (StatusCode::FORBIDDEN, Json(RpcResponse { 
+        status: "error".to_string(), 
+        data: Some(serde_json::json!({"error_code": "read_only"})), 
+        message: Some("Сервер работает в режиме только для чтения (server.read_only): изменения запрещены".to_string()) 
+    })).into_response()
+}
+
+/// Middleware ограничения одновременных запросов: без свободного разрешения запрос
+/// сразу получает 503, а не ждёт в очереди за глобальной блокировкой
+pub async fn limit_concurrency(State(semaphore): State<Arc<Semaphore>>, request: Request, next: Next) -> Response {
+    match semaphore.try_acquire_owned() {
+        Ok(_permit) => next.run(request).await,
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, Json(RpcResponse { 
+            status: "error".to_string(), 
+            data: None, 
+            message: Some("Сервер перегружен: превышен server.max_concurrent_requests".to_string()) 
+        })).into_response(),
+    }
+}
@@ -1,26 +1,52 @@
 use std::collections::HashMap;
+use crate::core::{errors::VecDbError, utils::StorageFormat};
 
 /// Трейт для контроллеров объектов коллекции (например, векторов или метаданных)
 pub trait CollectionObjectController {
-    /// Загружает объекты из HashMap<u64, Vec<u8>> (hash_id -> данные)
-    fn load(&mut self, raw_data: HashMap<u64, Vec<u8>>);
+    /// Загружает объекты из HashMap<u64, Vec<u8>> (hash_id -> данные) в заданном формате
+    fn load(&mut self, raw_data: HashMap<u64, Vec<u8>>, format: StorageFormat) -> Result<(), VecDbError>;
 
-    /// Сохраняет объекты в HashMap<u64, Vec<u8>> (hash_id -> данные)
-    fn dump(&self) -> HashMap<u64, Vec<u8>>;
+    /// Сохраняет объекты в HashMap<u64, Vec<u8>> (hash_id -> данные) в заданном формате
+    fn dump(&self, format: StorageFormat) -> HashMap<u64, Vec<u8>>;
 }
 
 #[allow(dead_code)]
 /// Трейт для объектов, которые могут быть сериализованы и десериализованы
 pub trait Object {
-    /// Загружает объект из вектора байт
-    fn load(&mut self, raw_data: Vec<u8>);
+    /// Загружает объект из вектора байт в заданном формате
+    fn load(&mut self, raw_data: Vec<u8>, format: StorageFormat) -> Result<(), VecDbError>;
 
-    /// Сохраняет объект в вектор байт, возвращает также hash_id
-    fn dump(&self) -> Result<(Vec<u8>, u64), ()>;
+    /// Сохраняет объект в вектор байт в заданном формате, возвращает также hash_id
+    fn dump(&self, format: StorageFormat) -> Result<(Vec<u8>, u64), ()>;
 
     /// Возвращает hash_id объекта
     fn hash_id(&self) -> u64;
 
     /// Устанавливает hash_id объекта
     fn set_hash_id(&mut self, id: u64);
-}
\ No newline at end of file
+}
+
+/// Трейт для хранилища сырых данных. Пути относительные (например, "collection/bucket/0.bin"),
+/// папки создаются при записи автоматически
+pub trait StorageBackend: Send + Sync {
+    /// Записывает файл целиком
+    fn write(&self, path: &str, data: &[u8]) -> std::io::Result<()>;
+
+    /// Читает файл целиком; ErrorKind::NotFound, если файла нет
+    fn read(&self, path: &str) -> std::io::Result<Vec<u8>>;
+
+    /// Имена файлов непосредственно в папке
+    fn list_files(&self, dir: &str) -> std::io::Result<Vec<String>>;
+
+    /// Имена вложенных папок непосредственно в папке
+    fn list_dirs(&self, dir: &str) -> std::io::Result<Vec<String>>;
+
+    /// Проверяет, существует ли файл или папка
+    fn exists(&self, path: &str) -> bool;
+
+    /// Размер файла в байтах
+    fn file_size(&self, path: &str) -> std::io::Result<u64>;
+
+    /// Удаляет файл или папку со всем содержимым
+    fn remove(&self, path: &str) -> std::io::Result<()>;
+}
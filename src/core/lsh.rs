@@ -2,6 +2,24 @@ use std::collections::HashMap;
 use rand::{Rng, SeedableRng};
 use rand::rngs::StdRng;
 
+/// Seed проекций LSH по умолчанию, если в конфиге не задан sharding.lsh_seed
+pub const DEFAULT_LSH_SEED: u64 = 42;
+
+/// Количество хэш-функций LSH по умолчанию, если в конфиге не задан lsh.num_hashes
+pub const DEFAULT_NUM_HASHES: usize = 3;
+
+/// Ширина бакетов по умолчанию (и временная ширина до автонастройки)
+pub const DEFAULT_BUCKET_WIDTH: f32 = 10.0;
+
+/// Номер соседа, расстояние до которого задаёт масштаб данных при автонастройке
+pub const AUTO_TUNE_NEIGHBORS: usize = 5;
+
+/// Во сколько раз подобранная ширина бакета больше расстояния до AUTO_TUNE_NEIGHBORS-го соседа
+pub const AUTO_TUNE_WIDTH_FACTOR: f32 = 4.0;
+
+/// Количество векторов, после которого выполняется автонастройка, если оно не задано
+pub const DEFAULT_AUTO_TUNE_SAMPLE: usize = 100;
+
 // structs define
 
 /// Метрики расстояния для LSH
@@ -29,6 +47,20 @@ pub struct LSH {
     pub bucket_width: f32,
     /// Метрика расстояния
     pub metric: LSHMetric,
+    /// Seed генератора проекций (None — случайный)
+    pub seed: Option<u64>,
+}
+
+/// Дополнительная хэш-таблица LSH. Хранит только ID векторов по хэшам,
+/// сами векторы лежат в бакетах основной таблицы
+#[derive(Debug)]
+pub struct LSHTable {
+    /// Хэш-функция таблицы
+    pub lsh: LSH,
+    /// Хэш -> ID векторов
+    entries: HashMap<u64, Vec<u64>>,
+    /// ID вектора -> хэш (для удаления и переиндексации)
+    hashes: HashMap<u64, u64>,
 }
 
 // Impl block
@@ -88,20 +120,23 @@ impl LSH {
             offsets,
             bucket_width,
             metric,
+            seed,
         }
     }
 
-    /// Вычисляет расстояние между двумя векторами в зависимости от метрики
-    fn compute_distance(&self, vector: &[f32], projection: &[f32]) -> f32 {
+    /// Проекция вектора на случайное направление в зависимости от метрики.
+    /// Это значение хэш-функции, а не расстояние между векторами
+    pub(crate) fn projected(&self, vector: &[f32], projection: &[f32]) -> f32 {
         match self.metric {
-            LSHMetric::Euclidean => self.euclidean_distance(vector, projection),
-            LSHMetric::Cosine => self.cosine_distance(vector, projection),
-            LSHMetric::Manhattan => self.manhattan_distance(vector, projection),
+            LSHMetric::Euclidean => self.projection_value(vector, projection),
+            LSHMetric::Cosine => self.normalized_projection_value(vector, projection),
+            LSHMetric::Manhattan => self.manhattan_projection_value(vector, projection),
         }
     }
 
-    /// Евклидово расстояние (скалярное произведение)
-    fn euclidean_distance(&self, vector: &[f32], projection: &[f32]) -> f32 {
+    /// Скалярное произведение с проекцией (семейство random projection для евклидовой метрики).
+    /// Для совпадающих векторов равно квадрату нормы, а не нулю
+    pub(crate) fn projection_value(&self, vector: &[f32], projection: &[f32]) -> f32 {
         let mut dot_product = 0.0;
         for i in 0..self.dimension {
             dot_product += vector[i] * projection[i];
@@ -109,8 +144,8 @@ impl LSH {
         dot_product
     }
 
-    /// Косинусное расстояние
-    fn cosine_distance(&self, vector: &[f32], projection: &[f32]) -> f32 {
+    /// Скалярное произведение с проекцией, нормированное на длины обоих векторов
+    fn normalized_projection_value(&self, vector: &[f32], projection: &[f32]) -> f32 {
         let mut dot_product = 0.0;
         let mut norm_a = 0.0;
         let mut norm_b = 0.0;
@@ -128,8 +163,8 @@ impl LSH {
         }
     }
 
-    /// Манхэттенское расстояние
-    fn manhattan_distance(&self, vector: &[f32], projection: &[f32]) -> f32 {
+    /// L1-расстояние до вектора проекции
+    fn manhattan_projection_value(&self, vector: &[f32], projection: &[f32]) -> f32 {
         let mut distance = 0.0;
         for i in 0..self.dimension {
             distance += (vector[i] - projection[i]).abs();
@@ -147,11 +182,11 @@ impl LSH {
         let mut multiplier = 1u64;
 
         for i in 0..self.num_hashes {
-            // Вычисляем расстояние в зависимости от метрики
-            let distance = self.compute_distance(vector, &self.projections[i]);
+            // Вычисляем проекцию в зависимости от метрики
+            let projected = self.projected(vector, &self.projections[i]);
 
             // Добавляем смещение и делим на ширину бакета
-            let hash_bucket = ((distance + self.offsets[i]) / self.bucket_width).floor() as i64;
+            let hash_bucket = ((projected + self.offsets[i]) / self.bucket_width).floor() as i64;
             
             // Преобразуем в положительное число и добавляем к общему хэшу
             hash_value = hash_value.wrapping_add((hash_bucket as u64).wrapping_mul(multiplier));
@@ -170,14 +205,58 @@ impl LSH {
                 break;
             }
 
-            let distance = self.compute_distance(vector, &self.projections[i]);
-            let hash_bucket = ((distance + self.offsets[i]) / self.bucket_width).floor() as i64;
+            let projected = self.projected(vector, &self.projections[i]);
+            let hash_bucket = ((projected + self.offsets[i]) / self.bucket_width).floor() as i64;
             hashes.push(hash_bucket as u64);
         }
 
         hashes
     }
 
+    /// Хэши соседних бакетов: отличаются от hash на ±1 ровно в одной хэш-функции
+    pub fn neighbor_hashes(&self, hash: u64) -> Vec<u64> {
+        let mut neighbors = Vec::with_capacity(self.num_hashes * 2);
+        let mut multiplier = 1u64;
+        for _ in 0..self.num_hashes {
+            neighbors.push(hash.wrapping_sub(multiplier));
+            neighbors.push(hash.wrapping_add(multiplier));
+            multiplier = multiplier.wrapping_mul(31);
+        }
+        neighbors
+    }
+
+    /// Подбирает ширину бакета по выборке векторов: медианное по выборке расстояние
+    /// до AUTO_TUNE_NEIGHBORS-го ближайшего соседа в пространстве проекций (максимум
+    /// по хэш-функциям), умноженное на AUTO_TUNE_WIDTH_FACTOR. Расстояние меряется там же,
+    /// где применяется ширина, поэтому подбор одинаково работает для всех метрик.
+    /// None, если в выборке меньше двух различных векторов
+    pub fn suggest_bucket_width(&self, sample: &[&[f32]]) -> Option<f32> {
+        let projected: Vec<Vec<f32>> = sample
+            .iter()
+            .map(|vector| self.projections.iter().map(|projection| self.projected(vector, projection)).collect())
+            .collect();
+
+        let mut neighbor_distances: Vec<f32> = projected
+            .iter()
+            .filter_map(|a| {
+                // Совпадающие векторы не учитываются, иначе дубликаты свели бы ширину к нулю
+                let mut distances: Vec<f32> = projected
+                    .iter()
+                    .map(|b| a.iter().zip(b).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max))
+                    .filter(|distance| *distance > 0.0)
+                    .collect();
+                distances.sort_by(|x, y| x.total_cmp(y));
+                distances.get(AUTO_TUNE_NEIGHBORS.min(distances.len()).checked_sub(1)?).copied()
+            })
+            .collect();
+        if neighbor_distances.is_empty() {
+            return None;
+        }
+
+        neighbor_distances.sort_by(|x, y| x.total_cmp(y));
+        Some(neighbor_distances[neighbor_distances.len() / 2] * AUTO_TUNE_WIDTH_FACTOR)
+    }
+
     /// Находит похожие векторы в LSH бакетах
     pub fn find_similar_buckets(&self, query_vector: &[f32], all_buckets: &HashMap<u64, Vec<u64>>) -> Vec<u64> {
         let query_hash = self.hash(query_vector);
@@ -190,4 +269,43 @@ impl LSH {
 
         similar_vectors
     }
-}
\ No newline at end of file
+}
+//  LSHTable impl
+
+impl LSHTable {
+    /// Создаёт пустую таблицу с заданной хэш-функцией
+    pub fn new(lsh: LSH) -> Self {
+        LSHTable { lsh, entries: HashMap::new(), hashes: HashMap::new() }
+    }
+
+    /// Добавляет (или переиндексирует) вектор в таблицу
+    pub fn insert(&mut self, vector_id: u64, vector: &[f32]) {
+        self.remove(vector_id);
+        let hash = self.lsh.hash(vector);
+        self.entries.entry(hash).or_default().push(vector_id);
+        self.hashes.insert(vector_id, hash);
+    }
+
+    /// Удаляет вектор из таблицы
+    pub fn remove(&mut self, vector_id: u64) {
+        if let Some(hash) = self.hashes.remove(&vector_id)
+            && let Some(ids) = self.entries.get_mut(&hash)
+        {
+            ids.retain(|id| *id != vector_id);
+            if ids.is_empty() {
+                self.entries.remove(&hash);
+            }
+        }
+    }
+
+    /// Очищает таблицу
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.hashes.clear();
+    }
+
+    /// Возвращает ID векторов, попавших в тот же бакет таблицы, что и запрос
+    pub fn candidates(&self, query: &[f32]) -> Vec<u64> {
+        self.lsh.find_similar_buckets(query, &self.entries)
+    }
+}
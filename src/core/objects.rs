@@ -1,18 +1,25 @@
-use std::{collections::HashMap};
-use crate::core::{interfaces::Object, utils::{calculate_hash, StorageCollection, StorageVector, StorageBucket}};
+use crate::core::{interfaces::Object, utils::{calculate_hash, Metadata, MetadataFilter, StorageCollection, StorageVector, StorageBucket, StorageFormat}};
 use std::fmt;
 use crate::core::controllers::{VectorController, BucketController};
-use crate::core::lsh::LSHMetric;
-use std::collections::BTreeMap;
+use crate::core::lsh::{LSHMetric, DEFAULT_BUCKET_WIDTH, DEFAULT_LSH_SEED, DEFAULT_NUM_HASHES};
+use crate::core::embeddings::l2_norm;
+use crate::core::errors::VecDbError;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 // structs define
 
 #[derive(Debug, Clone)]
 pub struct Vector {
+    /// Эмбеддинг. Для изменения используйте set_data, чтобы обновилась кэшированная норма
     pub data: Vec<f32>,
     pub timestamp: i64,
-    pub metadata: HashMap<String, String>,
+    pub metadata: Metadata,
     hash_id: u64,
+    norm: f32,
+    /// Эмбеддинг нормирован сервером при вставке (норма равна 1)
+    normalized: bool,
 }
 
 #[derive(Debug)]
@@ -21,9 +28,58 @@ pub struct Collection {
     pub buckets_controller: BucketController,
     pub lsh_metric: LSHMetric,
     pub vector_dimension: usize,
+    /// Количество хэш-таблиц LSH
+    pub num_tables: usize,
+    /// Количество хэш-функций LSH в каждой таблице
+    pub num_hashes: usize,
+    /// Seed проекций LSH. Сохраняется вместе с коллекцией, чтобы после загрузки
+    /// векторы попадали в те же бакеты
+    pub lsh_seed: u64,
+    /// Метаданные, которые получает каждый добавляемый вектор (ключи клиента имеют приоритет)
+    pub default_metadata: Metadata,
+    /// Ширина бакетов LSH
+    pub bucket_width: f32,
+    /// Количество векторов, после которого ширина бакетов подбирается по данным.
+    /// None — автонастройка выключена или уже выполнена
+    pub auto_tune_after: Option<usize>,
+    /// Максимальное количество векторов; None — без ограничения
+    pub max_vectors: Option<usize>,
+    /// Что делать со вставкой в заполненную коллекцию
+    pub eviction: EvictionPolicy,
+    /// Ключи метаданных, по которым строится инвертированный индекс (см. set_indexed_metadata_keys)
+    pub indexed_metadata_keys: Vec<String>,
     id: u64,
+    /// Версия данных коллекции, меняется при каждом изменении (см. touch)
+    version: u64,
+    /// Порядок вставок и обращений к векторам для вытеснения. Хранится только в памяти
+    usage: Mutex<VectorUsage>,
 }
 
+/// Политика вытеснения при достижении max_vectors
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EvictionPolicy {
+    /// Отклонять вставку
+    #[default]
+    Reject,
+    /// Удалять самый старый вектор (по timestamp)
+    Fifo,
+    /// Удалять вектор, к которому дольше всего не обращались (получение по ID и попадание в выдачу поиска)
+    Lru,
+}
+
+/// Логические часы вставок и обращений к векторам коллекции. Векторы, загруженные
+/// из storage, в них отсутствуют и вытесняются раньше остальных, старые — первыми
+#[derive(Debug, Default)]
+struct VectorUsage {
+    clock: u64,
+    inserted: HashMap<u64, u64>,
+    accessed: HashMap<u64, u64>,
+}
+
+/// Источник версий коллекций. Счётчик общий, чтобы пересозданная коллекция
+/// с тем же именем не получила версию удалённой
+static NEXT_COLLECTION_VERSION: AtomicU64 = AtomicU64::new(1);
+
 #[derive(Debug, Clone)]
 pub struct Bucket {
     pub id: u64,
@@ -38,25 +94,33 @@ pub struct Bucket {
 
 impl Object for Vector {
     /// Загружает объект Vector из вектора байт (десериализация)
-    fn load(&mut self, raw_data: Vec<u8>) {
-        let decoded: StorageVector = bincode::deserialize(&raw_data[..])
-            .expect("Ошибка");
+    fn load(&mut self, raw_data: Vec<u8>, format: StorageFormat) -> Result<(), VecDbError> {
+        let decoded: StorageVector = format.decode_versioned(&raw_data[..])
+            .map_err(|e| VecDbError::Serialization(format!("Ошибка десериализации Vector: {}", e)))?;
 
+        self.norm = decoded.norm.unwrap_or_else(|| l2_norm(&decoded.data));
+        self.metadata = decoded.merged_metadata();
         self.data = decoded.data;
         self.hash_id = decoded.hash_id;
         self.timestamp = decoded.timestamp;
+        self.normalized = decoded.normalized;
+        Ok(())
     }
 
     /// Сохраняет объект Vector в вектор байт (сериализация)
-    fn dump(&self) -> Result<(Vec<u8>, u64), ()> {
+    fn dump(&self, format: StorageFormat) -> Result<(Vec<u8>, u64), ()> {
+        let (metadata, typed_metadata) = StorageVector::split_metadata(&self.metadata);
         let storage_data = StorageVector { 
             data: self.data.to_vec(),
             timestamp: self.timestamp,
-            metadata: self.metadata.clone(),
+            metadata,
             hash_id: self.hash_id,
+            norm: Some(self.norm),
+            typed_metadata,
+            normalized: self.normalized,
         };
 
-        let encoded = bincode::serialize(&storage_data)
+        let encoded = format.encode_versioned(&storage_data)
             .expect("Ошибка сериализации Vector");
 
         Ok((encoded, self.hash_id))
@@ -75,24 +139,50 @@ impl Object for Vector {
 
 impl Vector {
     /// Создаёт новый объект Vector с опциональными данными и временной меткой
-    pub fn new(data: Option<Vec<f32>>, timestamp: Option<i64>, metadata: Option<HashMap<String, String>>) -> Vector {
+    pub fn new(data: Option<Vec<f32>>, timestamp: Option<i64>, metadata: Option<Metadata>) -> Vector {
         let data_val = data.unwrap_or_default();
         let timestamp_val = timestamp.unwrap_or(0);
         let metadata_val = metadata.unwrap_or_default();
 
         let hash_id = Vector::calculate_hash(&data_val, timestamp_val, &metadata_val);
+        let norm = l2_norm(&data_val);
 
         Vector { 
             data: data_val, 
             timestamp: timestamp_val, 
             metadata: metadata_val, 
-            hash_id
+            hash_id,
+            norm,
+            normalized: false,
         }
     }
 
-    fn calculate_hash(data: &Vec<f32>, timestamp: i64, metadata: &HashMap<String, String>) -> u64 {
+    /// Заменяет эмбеддинг и пересчитывает кэшированную норму
+    pub fn set_data(&mut self, data: Vec<f32>) {
+        self.norm = l2_norm(&data);
+        self.data = data;
+        self.normalized = false;
+    }
+
+    /// Был ли эмбеддинг нормирован сервером при вставке
+    pub fn is_normalized(&self) -> bool {
+        self.normalized
+    }
+
+    /// Помечает эмбеддинг как нормированный; сами данные не меняются
+    pub fn mark_normalized(&mut self) {
+        self.normalized = true;
+    }
+
+    /// Кэшированная L2-норма эмбеддинга
+    pub fn norm(&self) -> f32 {
+        self.norm
+    }
+
+    fn calculate_hash(data: &Vec<f32>, timestamp: i64, metadata: &Metadata) -> u64 {
         let data_bits: Vec<u32> = data.iter().map(|f| f.to_bits()).collect();
-        let metadata_btree: BTreeMap<String, String> = metadata.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        // Значения хэшируются в строковом виде, чтобы ID векторов со строковыми метаданными не изменились
+        let metadata_btree: BTreeMap<String, String> = metadata.iter().map(|(k, v)| (k.clone(), v.to_string())).collect();
         calculate_hash(&(data_bits, timestamp, metadata_btree))
     }
 }
@@ -111,32 +201,79 @@ impl fmt::Display for Vector {
     }
 }
 
+//  EvictionPolicy impl
+
+impl EvictionPolicy {
+    pub fn from_string(s: &str) -> Result<Self, String> {
+        match s {
+            "reject" => Ok(EvictionPolicy::Reject),
+            "fifo" => Ok(EvictionPolicy::Fifo),
+            "lru" => Ok(EvictionPolicy::Lru),
+            _ => Err(format!("Неизвестная политика вытеснения: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvictionPolicy::Reject => write!(f, "reject"),
+            EvictionPolicy::Fifo => write!(f, "fifo"),
+            EvictionPolicy::Lru => write!(f, "lru"),
+        }
+    }
+}
+
 //  Collection impl
 
 impl Object for Collection {
     /// Загружает объект Collection из вектора байт (десериализация StorageCollection)
-    fn load(&mut self, raw_data: Vec<u8>) {
-        // Десериализуем не саму Collection, а StorageCollection
-        let decoded: StorageCollection = bincode::deserialize(&raw_data[..])
-            .expect("Ошибка десериализации StorageCollection");
+    fn load(&mut self, raw_data: Vec<u8>, format: StorageFormat) -> Result<(), VecDbError> {
+        // Десериализуем не саму Collection, а StorageCollection (с миграцией старых версий схемы)
+        let decoded: StorageCollection = format.decode_versioned(&raw_data[..])
+            .map_err(|e| VecDbError::Serialization(format!("Ошибка десериализации StorageCollection: {}", e)))?;
 
         self.name = decoded.name;
         self.id = decoded.id;
         self.lsh_metric = LSHMetric::from_string(&decoded.lsh_metric)
             .unwrap_or(LSHMetric::Euclidean); // По умолчанию Euclidean для старых коллекций
         self.vector_dimension = decoded.vector_dimension;
+        self.num_tables = decoded.num_tables;
+        self.num_hashes = decoded.num_hashes;
+        self.lsh_seed = decoded.lsh_seed;
+        self.default_metadata = StorageVector::join_metadata(&decoded.default_metadata, &decoded.default_typed_metadata);
+        self.bucket_width = decoded.bucket_width;
+        self.auto_tune_after = decoded.auto_tune_after;
+        self.max_vectors = decoded.max_vectors;
+        self.eviction = EvictionPolicy::from_string(&decoded.eviction).unwrap_or_default();
+        self.indexed_metadata_keys = decoded.indexed_metadata_keys;
+        // Пересоздаём LSH под загруженные размерность, метрику, ширину бакетов, число таблиц и хэш-функций и seed
+        self.buckets_controller = BucketController::with_tables(self.vector_dimension, self.num_hashes, self.bucket_width, self.lsh_metric.clone(), Some(self.lsh_seed), self.num_tables);
+        self.buckets_controller.set_indexed_metadata_keys(self.indexed_metadata_keys.clone());
+        Ok(())
     }
 
     /// Сохраняет объект Collection в вектор байт (сериализация StorageCollection)
-    fn dump(&self) -> Result<(Vec<u8>, u64), ()> {
+    fn dump(&self, format: StorageFormat) -> Result<(Vec<u8>, u64), ()> {
+        let (default_metadata, default_typed_metadata) = StorageVector::split_metadata(&self.default_metadata);
         let storage_data = StorageCollection{ 
             name: self.name.clone(),
             id: self.id,
             lsh_metric: self.lsh_metric.to_string(),
             vector_dimension: self.vector_dimension,
+            num_tables: self.num_tables,
+            lsh_seed: self.lsh_seed,
+            default_metadata,
+            default_typed_metadata,
+            bucket_width: self.bucket_width,
+            auto_tune_after: self.auto_tune_after,
+            max_vectors: self.max_vectors,
+            eviction: self.eviction.to_string(),
+            indexed_metadata_keys: self.indexed_metadata_keys.clone(),
+            num_hashes: self.num_hashes,
         };
 
-        let encoded = bincode::serialize(&storage_data)
+        let encoded = format.encode_versioned(&storage_data)
             .expect("Ошибка сериализации Collection");
         
         Ok((encoded, self.id))
@@ -156,6 +293,33 @@ impl Object for Collection {
 impl Collection {
     /// Создаёт новый объект Collection с опциональным именем, метрикой LSH и размерностью векторов
     pub fn new(name: Option<String>, lsh_metric: LSHMetric, vector_dimension: usize) -> Collection {
+        Collection::with_tables(name, lsh_metric, vector_dimension, 1)
+    }
+
+    /// Создаёт коллекцию с заданным количеством хэш-таблиц LSH
+    pub fn with_tables(name: Option<String>, lsh_metric: LSHMetric, vector_dimension: usize, num_tables: usize) -> Collection {
+        Collection::with_seed(name, lsh_metric, vector_dimension, num_tables, Some(DEFAULT_LSH_SEED))
+    }
+
+    /// Создаёт коллекцию с заданным seed проекций LSH. None — случайный seed,
+    /// который выбирается один раз и сохраняется вместе с коллекцией
+    pub fn with_seed(name: Option<String>, lsh_metric: LSHMetric, vector_dimension: usize, num_tables: usize, seed: Option<u64>) -> Collection {
+        Collection::with_lsh(name, lsh_metric, vector_dimension, num_tables, seed, DEFAULT_NUM_HASHES, DEFAULT_BUCKET_WIDTH)
+    }
+
+    /// Создаёт коллекцию с заданными seed, количеством хэш-функций и начальной шириной бакетов LSH
+    pub fn with_lsh(
+        name: Option<String>,
+        lsh_metric: LSHMetric,
+        vector_dimension: usize,
+        num_tables: usize,
+        seed: Option<u64>,
+        num_hashes: usize,
+        bucket_width: f32,
+    ) -> Collection {
+        let num_tables = num_tables.max(1);
+        let num_hashes = num_hashes.max(1);
+        let lsh_seed = seed.unwrap_or_else(rand::random);
         let (name, id) = match name {
             Some(n) => {
                 let hash = calculate_hash(&n);
@@ -163,47 +327,243 @@ impl Collection {
             },
             None => ("".to_string(), 0),
         };
-        let buckets_controller = BucketController::new(vector_dimension, 3, 10.0, lsh_metric.clone(), Some(42));
+        let buckets_controller = BucketController::with_tables(vector_dimension, num_hashes, bucket_width, lsh_metric.clone(), Some(lsh_seed), num_tables);
         Collection { 
             name, 
             id, 
             buckets_controller: buckets_controller,
             lsh_metric,
-            vector_dimension
+            vector_dimension,
+            num_tables,
+            num_hashes,
+            lsh_seed,
+            default_metadata: Metadata::new(),
+            bucket_width,
+            auto_tune_after: None,
+            max_vectors: None,
+            eviction: EvictionPolicy::Reject,
+            indexed_metadata_keys: Vec::new(),
+            version: NEXT_COLLECTION_VERSION.fetch_add(1, Ordering::Relaxed),
+            usage: Mutex::new(VectorUsage::default()),
+        }
+    }
+
+    /// Текущая версия данных коллекции. Результаты поиска, полученные при одной версии,
+    /// остаются верными, пока версия не изменилась
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Отмечает коллекцию изменённой: выдаёт ей новую версию
+    pub fn touch(&mut self) {
+        self.version = NEXT_COLLECTION_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Проверяет, можно ли добавить вектор: ошибка, если коллекция заполнена
+    /// и политика вытеснения — reject
+    pub fn check_capacity(&self) -> Result<(), VecDbError> {
+        match self.max_vectors {
+            Some(max_vectors) if self.eviction == EvictionPolicy::Reject
+                && self.buckets_controller.total_vectors() >= max_vectors =>
+            {
+                Err(VecDbError::Validation(format!("Коллекция '{}' заполнена: максимум {} векторов", self.name, max_vectors)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Учитывает только что добавленный вектор и, если коллекция переполнена, вытесняет
+    /// векторы по политике eviction (сам добавленный вектор не вытесняется).
+    /// Возвращает ID вытесненных векторов
+    pub fn admit_vector(&mut self, vector_id: u64) -> Result<Vec<u64>, VecDbError> {
+        let Some(max_vectors) = self.max_vectors else { return Ok(Vec::new()) };
+        {
+            let mut usage = self.lock_usage();
+            usage.clock += 1;
+            let tick = usage.clock;
+            usage.inserted.insert(vector_id, tick);
+        }
+
+        let mut evicted = Vec::new();
+        while self.buckets_controller.total_vectors() > max_vectors {
+            let Some(victim) = self.eviction_candidate(vector_id) else { break };
+            self.buckets_controller.remove_vector(victim)?;
+            self.forget_vector(victim);
+            evicted.push(victim);
+        }
+        Ok(evicted)
+    }
+
+    /// Отмечает обращение к векторам (учитывается политикой lru)
+    pub fn record_access(&self, vector_ids: impl IntoIterator<Item = u64>) {
+        if self.max_vectors.is_none() || self.eviction != EvictionPolicy::Lru {
+            return;
+        }
+        let mut usage = self.lock_usage();
+        for vector_id in vector_ids {
+            usage.clock += 1;
+            let tick = usage.clock;
+            usage.accessed.insert(vector_id, tick);
+        }
+    }
+
+    /// Отмечает обращение к векторам из результатов поиска (bucket_id, vector_index, score)
+    pub fn record_search_hits(&self, results: &[(u64, usize, f32)]) {
+        self.record_access(results.iter().filter_map(|&(bucket_id, vector_index, _)| {
+            self.buckets_controller.get_vector_at(bucket_id, vector_index).map(|vector| vector.hash_id())
+        }));
+    }
+
+    /// Забывает порядок вставки и обращений удалённого вектора
+    pub fn forget_vector(&self, vector_id: u64) {
+        let mut usage = self.lock_usage();
+        usage.inserted.remove(&vector_id);
+        usage.accessed.remove(&vector_id);
+    }
+
+    /// Вектор, который следует вытеснить первым: для fifo — самый старый по timestamp
+    /// (при равных — вставленный раньше), для lru — с самым давним обращением или вставкой
+    fn eviction_candidate(&self, keep: u64) -> Option<u64> {
+        let usage = self.lock_usage();
+        let inserted = |id: u64| usage.inserted.get(&id).copied().unwrap_or(0);
+        let vectors = self.buckets_controller.get_all_buckets()
+            .into_iter()
+            .flat_map(|bucket| bucket.vectors_controller.vectors.iter().flatten())
+            .filter(|vector| vector.hash_id() != keep);
+        match self.eviction {
+            EvictionPolicy::Reject => None,
+            EvictionPolicy::Fifo => vectors
+                .min_by_key(|vector| (vector.timestamp, inserted(vector.hash_id())))
+                .map(|vector| vector.hash_id()),
+            EvictionPolicy::Lru => vectors
+                .min_by_key(|vector| {
+                    let id = vector.hash_id();
+                    (usage.accessed.get(&id).copied().unwrap_or(0).max(inserted(id)), vector.timestamp)
+                })
+                .map(|vector| vector.hash_id()),
         }
     }
 
-    pub fn find_similar(&self, query: &Vec<f32> , k: usize) -> Result<Vec<(u64, usize, f32)>, Box<dyn std::error::Error>> {
+    fn lock_usage(&self) -> std::sync::MutexGuard<'_, VectorUsage> {
+        self.usage.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Метаданные вектора с добавленными метаданными коллекции по умолчанию.
+    /// Ключи, переданные клиентом, перекрывают значения по умолчанию
+    pub fn with_default_metadata(&self, metadata: Metadata) -> Metadata {
+        let mut merged = self.default_metadata.clone();
+        merged.extend(metadata);
+        merged
+    }
+
+    pub fn find_similar(&self, query: &Vec<f32> , k: usize) -> Result<Vec<(u64, usize, f32)>, VecDbError> {
         self.buckets_controller.find_similar(query, k)
     }
 
-    pub fn filter_by_metadata(&self, filters: &HashMap<String, String>) -> Vec<u64> {
+    /// Меняет метрику коллекции: пересоздаёт LSH с тем же seed и заново раскладывает
+    /// все векторы по бакетам. ID, метаданные и временные метки векторов сохраняются.
+    /// Возвращает количество перенесённых векторов
+    pub fn set_metric(&mut self, lsh_metric: LSHMetric) -> Result<usize, VecDbError> {
+        self.rebucket(lsh_metric, self.bucket_width)
+    }
+
+    /// Меняет ширину бакетов и заново раскладывает векторы, как set_metric
+    pub fn set_bucket_width(&mut self, bucket_width: f32) -> Result<usize, VecDbError> {
+        self.rebucket(self.lsh_metric.clone(), bucket_width)
+    }
+
+    /// Задаёт ключи метаданных с инвертированным индексом и строит индекс по текущим векторам.
+    /// Фильтры на равенство только по этим ключам выполняются без просмотра бакетов
+    pub fn set_indexed_metadata_keys(&mut self, keys: Vec<String>) {
+        self.buckets_controller.set_indexed_metadata_keys(keys.clone());
+        self.indexed_metadata_keys = keys;
+    }
+
+    /// Включает автонастройку: после sample_size векторов ширина бакетов будет подобрана по данным
+    pub fn enable_auto_tune(&mut self, sample_size: usize) {
+        self.auto_tune_after = Some(sample_size.max(2));
+    }
+
+    /// Подбирает ширину бакетов, если автонастройка ожидает и векторов уже достаточно.
+    /// В выборку берётся auto_tune_after векторов, равномерно по всей коллекции.
+    /// Возвращает новую ширину, если настройка выполнена сейчас
+    pub fn maybe_auto_tune(&mut self) -> Result<Option<f32>, VecDbError> {
+        let Some(sample_size) = self.auto_tune_after else { return Ok(None) };
+        let total = self.buckets_controller.total_vectors();
+        if total < sample_size {
+            return Ok(None);
+        }
+
+        let lsh = self.buckets_controller.lsh.as_ref().ok_or_else(VecDbError::lsh_not_initialized)?;
+        let sample: Vec<&[f32]> = self.buckets_controller.get_all_buckets()
+            .into_iter()
+            .flat_map(|bucket| bucket.vectors_controller.vectors.iter().flatten())
+            .step_by((total / sample_size).max(1))
+            .take(sample_size)
+            .map(|vector| vector.data.as_slice())
+            .collect();
+        let tuned = lsh.suggest_bucket_width(&sample);
+
+        // Если все векторы совпадают, подбирать не из чего: остаётся текущая ширина
+        self.auto_tune_after = None;
+        match tuned {
+            Some(bucket_width) => {
+                self.set_bucket_width(bucket_width)?;
+                Ok(Some(bucket_width))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Пересоздаёт LSH с тем же seed и заданными метрикой и шириной бакетов,
+    /// затем заново раскладывает все векторы по бакетам
+    fn rebucket(&mut self, lsh_metric: LSHMetric, bucket_width: f32) -> Result<usize, VecDbError> {
+        let vectors = self.buckets_controller.take_vectors();
+        let count = vectors.len();
+        let mut buckets_controller = BucketController::with_tables(self.vector_dimension, self.num_hashes, bucket_width, lsh_metric.clone(), Some(self.lsh_seed), self.num_tables);
+        buckets_controller.merge_small_buckets = self.buckets_controller.merge_small_buckets;
+        buckets_controller.set_indexed_metadata_keys(self.indexed_metadata_keys.clone());
+        for vector in vectors {
+            buckets_controller.insert_vector(vector)?;
+        }
+        self.buckets_controller = buckets_controller;
+        self.lsh_metric = lsh_metric;
+        self.bucket_width = bucket_width;
+        Ok(count)
+    }
+
+    pub fn filter_by_metadata(&self, filters: &Metadata) -> Vec<u64> {
         self.buckets_controller.filter_by_metadata(filters)
     }
+
+    pub fn filter_by_expression(&self, expression: &MetadataFilter) -> Vec<u64> {
+        self.buckets_controller.filter_by_expression(expression)
+    }
 }
 
 //  Bucket impl
 
 impl Object for Bucket {
     /// Загружает объект Bucket из вектора байт (десериализация)
-    fn load(&mut self, raw_data: Vec<u8>) {
-        let decoded: StorageBucket = bincode::deserialize(&raw_data[..])
-            .expect("Ошибка десериализации Bucket");
+    fn load(&mut self, raw_data: Vec<u8>, format: StorageFormat) -> Result<(), VecDbError> {
+        let decoded: StorageBucket = format.decode_versioned(&raw_data[..])
+            .map_err(|e| VecDbError::Serialization(format!("Ошибка десериализации Bucket: {}", e)))?;
 
         self.id = decoded.id;
         self.created_at = decoded.created_at;
         self.updated_at = decoded.updated_at;
+        Ok(())
     }
 
     /// Сохраняет объект Bucket в вектор байт (сериализация)
-    fn dump(&self) -> Result<(Vec<u8>, u64), ()> {
+    fn dump(&self, format: StorageFormat) -> Result<(Vec<u8>, u64), ()> {
         let storage_data = StorageBucket {
             id: self.id,
             created_at: self.created_at,
             updated_at: self.updated_at,
         };
 
-        let encoded = bincode::serialize(&storage_data)
+        let encoded = format.encode_versioned(&storage_data)
             .expect("Ошибка сериализации Bucket");
 
         Ok((encoded, self.id))
@@ -235,14 +595,21 @@ impl Bucket {
     }
 
     /// Добавляет вектор в бакет через VectorController
-    pub fn add_vector(&mut self, embedding: Vec<f32>, metadata: HashMap<String, String>) -> Result<u64, Box<dyn std::error::Error>> {
+    pub fn add_vector(&mut self, embedding: Vec<f32>, metadata: Metadata) -> Result<u64, VecDbError> {
         let vector_id = self.vectors_controller.add_vector(Some(embedding), Some(metadata), None, None)?;
         self.updated_at = chrono::Utc::now().timestamp();
         Ok(vector_id)
     }
 
+    /// Добавляет вектор с заданным ID в бакет
+    pub fn add_vector_with_id(&mut self, vector_id: u64, embedding: Vec<f32>, metadata: Metadata) -> Result<u64, VecDbError> {
+        let vector_id = self.vectors_controller.add_vector(Some(embedding), Some(metadata), Some(vector_id), None)?;
+        self.updated_at = chrono::Utc::now().timestamp();
+        Ok(vector_id)
+    }
+
     /// Удаляет вектор из бакета
-    pub fn remove_vector(&mut self, vector_id: u64) -> Result<(), String> {
+    pub fn remove_vector(&mut self, vector_id: u64) -> Result<(), VecDbError> {
         match self.vectors_controller.remove_vector(vector_id) {
             Ok(_) => {
                 self.updated_at = chrono::Utc::now().timestamp();
@@ -259,10 +626,7 @@ impl Bucket {
 
     /// Возвращает количество векторов в бакете
     pub fn size(&self) -> usize {
-        match &self.vectors_controller.vectors {
-            Some(vectors) => vectors.len(),
-            None => 0,
-        }
+        self.vectors_controller.vectors.as_ref().map_or(0, Vec::len)
     }
 
     /// Получает вектор по ID
@@ -271,29 +635,34 @@ impl Bucket {
     }
 
     /// Поиск похожих векторов в бакете
-    pub fn find_similar(&self, query: &Vec<f32>, k: usize) -> Result<Vec<(usize, f32)>, Box<dyn std::error::Error>> {
-        self.vectors_controller.find_most_similar(query, k)
+    pub fn find_similar(&self, query: &Vec<f32>, k: usize, metric: &LSHMetric) -> Result<Vec<(usize, f32)>, VecDbError> {
+        self.vectors_controller.find_most_similar(query, k, metric)
     }
 
     /// Фильтрация векторов по метаданным
-    pub fn filter_by_metadata(&self, filters: &HashMap<String, String>) -> Vec<u64> {
+    pub fn filter_by_metadata(&self, filters: &Metadata) -> Vec<u64> {
         self.vectors_controller.filter_by_metadata(filters)
     }
 
+    /// Фильтрация векторов по выражению над метаданными
+    pub fn filter_by_expression(&self, expression: &MetadataFilter) -> Vec<u64> {
+        self.vectors_controller.filter_by_expression(expression)
+    }
+
     /// Обновляет вектор в бакете
     pub fn update_vector(
         &mut self,
         vector_id: u64,
         new_embedding: Option<Vec<f32>>,
-        new_metadata: Option<HashMap<String, String>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        new_metadata: Option<Metadata>,
+    ) -> Result<(), VecDbError> {
         self.vectors_controller.update_vector(vector_id, new_embedding, new_metadata)?;
         self.updated_at = chrono::Utc::now().timestamp();
         Ok(())
     }
 
     /// Удаляет вектор из бакета и возвращает его
-    pub fn remove_and_get_vector(&mut self, vector_id: u64) -> Result<Vector, String> {
+    pub fn remove_and_get_vector(&mut self, vector_id: u64) -> Result<Vector, VecDbError> {
         match self.vectors_controller.remove_and_get_vector(vector_id) {
             Ok(vector) => {
                 self.updated_at = chrono::Utc::now().timestamp();
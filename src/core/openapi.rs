@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use utoipa::{ToSchema, OpenApi};
+use utoipa::{IntoParams, ToSchema, OpenApi};
+use crate::core::utils::{Metadata, MetadataFilter, MetadataValue};
 
 /// Параметры для создания коллекции
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -10,6 +11,44 @@ pub struct AddCollectionParams {
     pub metric: String,
     /// Размерность векторов
     pub dimension: usize,
+    /// Количество хэш-таблиц LSH (больше таблиц — выше полнота поиска, по умолчанию 1)
+    #[serde(default)]
+    pub num_tables: Option<usize>,
+    /// Модель серверных эмбеддингов (например, AllMiniLML6V2); если задана,
+    /// размерность коллекции должна совпадать с размерностью её выхода
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+    /// Метаданные, которые получает каждый добавляемый вектор (например, {"tenant": "acme"});
+    /// ключи, переданные при вставке, имеют приоритет
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_metadata: Option<Metadata>,
+    /// Подобрать ширину бакетов по данным: после auto_tune_sample векторов ширина
+    /// вычисляется по расстояниям между ними, и векторы раскладываются заново
+    #[serde(default)]
+    pub auto_tune: bool,
+    /// Сколько векторов собрать перед автонастройкой (по умолчанию 100)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_tune_sample: Option<usize>,
+    /// Максимальное количество векторов в коллекции (по умолчанию без ограничения)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_vectors: Option<usize>,
+    /// Что делать при вставке в заполненную коллекцию: reject (по умолчанию) — отклонить,
+    /// fifo — удалить самый старый вектор, lru — удалить вектор, к которому дольше всего не обращались
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eviction: Option<String>,
+    /// Ключи метаданных с инвертированным индексом: фильтры на равенство только по ним
+    /// выполняются без просмотра всех векторов
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexed_metadata_keys: Vec<String>,
+}
+
+/// Параметры для изменения метрики коллекции
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpdateCollectionParams {
+    /// Название коллекции
+    pub name: String,
+    /// Новая метрика для LSH (Euclidean, Cosine или Manhattan)
+    pub metric: String,
 }
 
 /// Параметры для удаления коллекции
@@ -19,6 +58,55 @@ pub struct DeleteCollectionParams {
     pub name: String,
 }
 
+/// Параметры для проверки существования коллекции
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CollectionExistsParams {
+    /// Название коллекции
+    pub name: String,
+}
+
+/// Параметры для получения информации о коллекции
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GetCollectionParams {
+    /// Название коллекции
+    pub name: String,
+}
+
+/// Параметры для получения сводки по бакетам коллекции
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BucketSummariesParams {
+    /// Название коллекции
+    pub name: String,
+    /// Вернуть только top самых заполненных бакетов
+    #[serde(default)]
+    pub top: Option<usize>,
+}
+
+/// Параметры для просмотра содержимого бакета
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GetBucketParams {
+    /// Название коллекции
+    pub name: String,
+    /// ID бакета
+    pub bucket_id: u64,
+}
+
+/// Параметры для определения бакета запроса
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct QueryBucketParams {
+    /// Название коллекции
+    pub name: String,
+    /// Запросный вектор
+    pub query: Vec<f32>,
+}
+
+/// Параметры для прогрева коллекции
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PreloadCollectionParams {
+    /// Название коллекции для загрузки в память
+    pub name: String,
+}
+
 /// Параметры для добавления вектора
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct AddVectorParams {
@@ -28,7 +116,62 @@ pub struct AddVectorParams {
     pub embedding: Vec<f32>,
     /// Метаданные вектора
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub metadata: Option<std::collections::HashMap<String, String>>,
+    pub metadata: Option<Metadata>,
+    /// Нормировать ли эмбеддинг по L2 перед сохранением (по умолчанию нет)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<bool>,
+    /// Приближённая дедупликация: если в бакете нового вектора уже есть почти такой же,
+    /// вставка не выполняется и возвращается ID существующего вектора
+    #[serde(default)]
+    pub dedup: bool,
+    /// Допустимое косинусное расстояние для дедупликации; по умолчанию server.dedup_tolerance (1e-6)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup_tolerance: Option<f32>,
+}
+
+/// Один вектор пакетной вставки
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchVectorItem {
+    /// Вектор эмбеддинга
+    pub embedding: Vec<f32>,
+    /// Метаданные вектора
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    /// ID вектора; без него ID вычисляется автоматически
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// Нормировать ли эмбеддинг по L2 перед сохранением; без поля действует normalize пакета
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<bool>,
+}
+
+/// Параметры для пакетного добавления векторов
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AddVectorsBatchParams {
+    /// Название коллекции
+    pub collection: String,
+    /// Добавляемые векторы; ошибка в одном из них не прерывает вставку остальных
+    pub vectors: Vec<BatchVectorItem>,
+    /// Нормировать ли эмбеддинги по L2 перед сохранением (по умолчанию нет)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<bool>,
+}
+
+/// Параметры для добавления текста, разбитого на чанки
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AddTextChunksParams {
+    /// Название коллекции
+    pub collection: String,
+    /// Исходный текст
+    pub text: String,
+    /// Размер чанка в символах
+    pub chunk_size: usize,
+    /// Перекрытие соседних чанков в символах
+    #[serde(default)]
+    pub overlap: usize,
+    /// Метаданные, добавляемые к каждому чанку
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
 }
 
 /// Параметры для обновления вектора
@@ -43,7 +186,21 @@ pub struct UpdateVectorParams {
     pub embedding: Option<Vec<f32>>,
     /// Новые метаданные
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub metadata: Option<std::collections::HashMap<String, String>>,
+    pub metadata: Option<Metadata>,
+}
+
+/// Параметры для вставки или замены вектора
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpsertVectorParams {
+    /// Название коллекции
+    pub collection: String,
+    /// ID вектора
+    pub id: u64,
+    /// Вектор эмбеддинга
+    pub embedding: Vec<f32>,
+    /// Метаданные вектора
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
 }
 
 /// Параметры для получения вектора
@@ -55,6 +212,57 @@ pub struct GetVectorParams {
     pub vector_id: u64,
 }
 
+/// Параметры для получения нескольких векторов по ID
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GetManyVectorsParams {
+    /// Название коллекции
+    pub collection: String,
+    /// ID векторов; результаты возвращаются в том же порядке
+    pub vector_ids: Vec<u64>,
+}
+
+/// Параметры для проверки существования вектора
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct VectorExistsParams {
+    /// Название коллекции
+    pub collection: String,
+    /// ID вектора
+    #[serde(alias = "id")]
+    pub vector_id: u64,
+}
+
+/// Параметры для чтения сохранённых байт вектора
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct VectorRawParams {
+    /// Название коллекции
+    pub collection: String,
+    /// ID бакета, в папке которого лежит вектор
+    pub bucket_id: u64,
+    /// ID вектора
+    pub id: u64,
+}
+
+/// Параметры для перестроения бакетов коллекции
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReindexParams {
+    /// Название коллекции
+    pub name: String,
+}
+
+/// Параметры для массовой миграции метаданных коллекции
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MigrateMetadataParams {
+    /// Название коллекции
+    pub collection: String,
+    /// Переименование ключей: {"старый_ключ": "новый_ключ"}
+    #[serde(default)]
+    pub rename: std::collections::HashMap<String, String>,
+    /// Замена значений по ключу (после переименования): {"ключ": {"старое значение": новое значение}}.
+    /// Старое значение сравнивается в строковом виде
+    #[serde(default)]
+    pub remap: std::collections::HashMap<String, std::collections::HashMap<String, MetadataValue>>,
+}
+
 /// Параметры для удаления вектора
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct DeleteVectorParams {
@@ -69,8 +277,12 @@ pub struct DeleteVectorParams {
 pub struct FilterByMetadataParams {
     /// Название коллекции
     pub collection: String,
-    /// Фильтры метаданных
-    pub filters: std::collections::HashMap<String, String>,
+    /// Фильтры метаданных (все пары должны совпадать)
+    #[serde(default)]
+    pub filters: Metadata,
+    /// Дополнительное выражение фильтрации с And / Or, применяется вместе с filters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expression: Option<MetadataFilter>,
 }
 
 /// Параметры для поиска похожих векторов
@@ -80,8 +292,120 @@ pub struct FindSimilarParams {
     pub collection: String,
     /// Запросный вектор
     pub query: Vec<f32>,
-    /// Количество похожих векторов
-    pub k: usize,
+    /// Количество похожих векторов; по умолчанию server.default_k, не больше server.max_k
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<usize>,
+    /// Добавлять ли метаданные найденных векторов в результаты
+    #[serde(default)]
+    pub include_metadata: bool,
+    /// Добавлять ли эмбеддинги найденных векторов в результаты
+    #[serde(default)]
+    pub include_embedding: bool,
+    /// Ограничение времени поиска в миллисекундах; по истечении возвращаются лучшие найденные результаты
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Бусты вида {"premium=true": 0.1}: значение прибавляется к score, если метаданные результата совпадают
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boosts: Option<std::collections::HashMap<String, f32>>,
+    /// ID векторов, которые не должны попасть в результаты (например, сам вектор при поиске «похожих на него»)
+    #[serde(default)]
+    pub exclude_ids: Vec<u64>,
+    /// Переранжировать ли результаты: собрать больше кандидатов из соседних бакетов
+    /// и упорядочить их по точному score
+    #[serde(default)]
+    pub rerank: bool,
+    /// Во сколько раз больше k кандидатов собирать при переранжировании (по умолчанию 4)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate_factor: Option<usize>,
+    /// Ключи метаданных для фасетов: для каждого в ответе возвращается число кандидатов
+    /// (до обрезки до k) с каждым значением ключа
+    #[serde(default)]
+    pub facets: Vec<String>,
+    /// Поля каждого результата: vector_id, score, metadata, embedding, bucket_id, vector_index
+    /// (например, ["vector_id"]), с теми же ключами, что и в полном результате.
+    /// Без параметра результаты возвращаются целиком, как раньше
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<String>>,
+}
+
+/// Параметры для пакетного поиска похожих векторов
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FindSimilarBatchParams {
+    /// Название коллекции
+    pub collection: String,
+    /// Запросные векторы; результаты возвращаются в том же порядке
+    pub queries: Vec<Vec<f32>>,
+    /// Количество похожих векторов для каждого запроса; по умолчанию server.default_k, не больше server.max_k
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<usize>,
+    /// Добавлять ли метаданные найденных векторов в результаты
+    #[serde(default)]
+    pub include_metadata: bool,
+    /// Добавлять ли эмбеддинги найденных векторов в результаты
+    #[serde(default)]
+    pub include_embedding: bool,
+}
+
+/// Параметры для поиска векторов, похожих на сохранённый вектор
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SimilarToParams {
+    /// Название коллекции
+    pub collection: String,
+    /// ID сохранённого вектора, эмбеддинг которого используется как запрос
+    pub id: u64,
+    /// Количество похожих векторов; по умолчанию server.default_k, не больше server.max_k
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<usize>,
+    /// Не включать в результаты сам вектор-запрос
+    #[serde(default)]
+    pub exclude_self: bool,
+    /// Добавлять ли метаданные найденных векторов в результаты
+    #[serde(default)]
+    pub include_metadata: bool,
+    /// Добавлять ли эмбеддинги найденных векторов в результаты
+    #[serde(default)]
+    pub include_embedding: bool,
+}
+
+/// Параметры проверки здоровья узла
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HealthParams {
+    /// Дополнительно проверить запись в storage (по умолчанию только проверка, что сервер отвечает)
+    #[serde(default)]
+    pub deep: bool,
+}
+
+/// Параметры для сравнения двух векторов: либо a и b, либо collection, id_a и id_b
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CompareVectorsParams {
+    /// Первый вектор
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub a: Option<Vec<f32>>,
+    /// Второй вектор (той же размерности)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub b: Option<Vec<f32>>,
+    /// Коллекция, из которой берутся сохранённые векторы
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    /// ID первого сохранённого вектора
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_a: Option<u64>,
+    /// ID второго сохранённого вектора
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_b: Option<u64>,
+}
+
+/// Параметры для получения распределения score соседей запроса
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SimilarScoresParams {
+    /// Название коллекции
+    pub collection: String,
+    /// Запросный вектор
+    pub query: Vec<f32>,
+    /// Количество соседей; по умолчанию server.default_k, не больше server.max_k
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<usize>,
 }
 
 /// Стандартный RPC ответ
@@ -102,7 +426,39 @@ pub struct RpcResponse {
 pub struct SimilarVectorResult {
     pub bucket_id: u64,
     pub vector_index: usize,
+    pub vector_id: u64,
     pub score: f32,
+    /// Метаданные вектора (если запрошены)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    /// Эмбеддинг вектора (если запрошен)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// Результат вставки одного вектора пакета
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchInsertResult {
+    /// Индекс вектора во входном пакете
+    pub index: usize,
+    /// "ok" или "error"
+    pub status: String,
+    /// ID добавленного вектора (при успехе)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// Причина ошибки (при неудаче)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Сводка по одному бакету
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BucketSummary {
+    pub bucket_id: u64,
+    /// Количество векторов в бакете
+    pub size: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
 }
 
 /// OpenAPI спецификация для VectorDB API
@@ -111,26 +467,75 @@ pub struct SimilarVectorResult {
     paths(
         crate::core::handlers::add_collection,
         crate::core::handlers::delete_collection,
+        crate::core::handlers::update_collection,
+        crate::core::handlers::get_all_collections,
+        crate::core::handlers::get_collection,
+        crate::core::handlers::collection_exists,
+        crate::core::handlers::bucket_summaries,
+        crate::core::handlers::query_bucket,
+        crate::core::handlers::get_bucket,
+        crate::core::handlers::preload_collection,
         crate::core::handlers::add_vector,
+        crate::core::handlers::add_vectors_batch,
+        crate::core::handlers::add_text_chunks,
         crate::core::handlers::update_vector,
+        crate::core::handlers::upsert_vector,
         crate::core::handlers::get_vector,
+        crate::core::handlers::get_many_vectors,
+        crate::core::handlers::vector_exists,
         crate::core::handlers::delete_vector,
         crate::core::handlers::filter_by_metadata,
         crate::core::handlers::find_similar,
+        crate::core::handlers::find_similar_batch,
+        crate::core::handlers::similar_scores,
+        crate::core::handlers::similar_to,
+        crate::core::handlers::compare_vectors,
+        crate::core::handlers::info,
+        crate::core::handlers::health,
+        crate::core::handlers::disk_usage,
+        crate::core::handlers::flush,
+        crate::core::handlers::vector_raw,
+        crate::core::handlers::reindex,
+        crate::core::handlers::prune,
+        crate::core::handlers::migrate_metadata,
         crate::core::handlers::stop
     ),
     components(
         schemas(
             AddCollectionParams,
             DeleteCollectionParams,
+            UpdateCollectionParams,
+            GetCollectionParams,
+            CollectionExistsParams,
+            BucketSummariesParams,
+            QueryBucketParams,
+            GetBucketParams,
+            PreloadCollectionParams,
             AddVectorParams,
+            BatchVectorItem,
+            AddVectorsBatchParams,
+            AddTextChunksParams,
             UpdateVectorParams,
+            UpsertVectorParams,
             GetVectorParams,
+            GetManyVectorsParams,
+            VectorExistsParams,
+            VectorRawParams,
+            ReindexParams,
+            MigrateMetadataParams,
             DeleteVectorParams,
             FilterByMetadataParams,
+            MetadataFilter,
+            MetadataValue,
             FindSimilarParams,
+            FindSimilarBatchParams,
+            SimilarScoresParams,
+            SimilarToParams,
+            CompareVectorsParams,
             RpcResponse,
-            SimilarVectorResult
+            SimilarVectorResult,
+            BatchInsertResult,
+            BucketSummary
         )
     ),
     tags(
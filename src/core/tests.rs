@@ -1,14 +1,17 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use super::{controllers::{VectorController, BucketController}, embeddings::make_embeddings, lsh::{LSH, LSHMetric}, objects::Collection};
+use axum::{extract::State, Json};
+
+use super::{controllers::{VectorController, BucketController, CollectionController, QueryCache, StorageController}, embeddings::make_embeddings, handlers::{AppState, ShutdownSignal}, lsh::{LSH, LSHMetric}, objects::Collection, utils::{Metadata, MetadataValue}};
 
 fn embedding_for(text: &str) -> Vec<f32> {
     make_embeddings(text).expect("Не удалось создать эмбеддинг в тесте")
 }
 
-fn metadata_with_category(category: &str) -> HashMap<String, String> {
+fn metadata_with_category(category: &str) -> Metadata {
     let mut metadata = HashMap::new();
-    metadata.insert("category".to_string(), category.to_string());
+    metadata.insert("category".to_string(), MetadataValue::from(category));
     metadata
 }
 
@@ -27,7 +30,7 @@ fn add_and_get_vector() {
         .expect("Добавленный вектор должен существовать");
 
     assert_eq!(stored.data, embedding);
-    assert_eq!(stored.metadata.get("category"), Some(&"greeting".to_string()));
+    assert_eq!(stored.metadata.get("category"), Some(&MetadataValue::from("greeting")));
     assert!(stored.timestamp > 0);
 }
 
@@ -41,7 +44,7 @@ fn add_and_remove_metadata() {
         .expect("Не удалось добавить вектор");
 
     let mut extra = HashMap::new();
-    extra.insert("lang".to_string(), "en".to_string());
+    extra.insert("lang".to_string(), MetadataValue::from("en"));
 
     controller
         .add_metadata_to_vector(id, extra)
@@ -50,7 +53,7 @@ fn add_and_remove_metadata() {
     let vector = controller
         .get_vector_by_id(id)
         .expect("Вектор должен существовать после добавления метаданных");
-    assert_eq!(vector.metadata.get("lang"), Some(&"en".to_string()));
+    assert_eq!(vector.metadata.get("lang"), Some(&MetadataValue::from("en")));
 
     controller
         .remove_metadata_from_vector(id, "lang")
@@ -94,7 +97,7 @@ fn update_vector_replaces_metadata() {
         .expect("Не удалось добавить вектор");
 
     let mut new_metadata = metadata_with_category("updated_greeting");
-    new_metadata.insert("lang".to_string(), "en".to_string());
+    new_metadata.insert("lang".to_string(), MetadataValue::from("en"));
 
     // Оставляем старый embedding
     controller
@@ -141,7 +144,7 @@ fn filter_by_metadata_returns_only_matching_ids() {
         .expect("Не удалось добавить третий вектор");
 
     let mut filters = HashMap::new();
-    filters.insert("category".to_string(), "greeting".to_string());
+    filters.insert("category".to_string(), MetadataValue::from("greeting"));
 
     let filtered = controller.filter_by_metadata(&filters);
 
@@ -168,7 +171,7 @@ fn find_most_similar_returns_k_vectors() {
         .expect("Не удалось добавить третий вектор");
 
     let results = controller
-        .find_most_similar(&embedding_for("hello"), 2)
+        .find_most_similar(&embedding_for("hello"), 2, &LSHMetric::Cosine)
         .expect("Поиск похожих векторов должен завершиться успешно");
 
     assert_eq!(results.len(), 2);
@@ -192,7 +195,7 @@ fn operations_fail_for_missing_vector() {
     let missing_id = 999_u64;
 
     let mut extra_meta = HashMap::new();
-    extra_meta.insert("key".to_string(), "value".to_string());
+    extra_meta.insert("key".to_string(), MetadataValue::from("value"));
 
     assert!(controller.add_metadata_to_vector(missing_id, extra_meta.clone()).is_err());
     assert!(controller.remove_metadata_from_vector(missing_id, "key").is_err());
@@ -242,13 +245,13 @@ fn test_bucket_controller_add_vectors() {
     let vector3 = vec![10.0, 20.0, 30.0, 40.0];
     
     let mut metadata1 = HashMap::new();
-    metadata1.insert("category".to_string(), "test1".to_string());
+    metadata1.insert("category".to_string(), MetadataValue::from("test1"));
     
     let mut metadata2 = HashMap::new();
-    metadata2.insert("category".to_string(), "test2".to_string());
+    metadata2.insert("category".to_string(), MetadataValue::from("test2"));
     
     let mut metadata3 = HashMap::new();
-    metadata3.insert("category".to_string(), "test3".to_string());
+    metadata3.insert("category".to_string(), MetadataValue::from("test3"));
     
     // Добавляем векторы
     let id1 = lsh_controller.add_vector(vector1, metadata1).expect("Не удалось добавить первый вектор");
@@ -274,13 +277,13 @@ fn test_bucket_controller_similarity_search() {
     let vector3 = vec![10.0, 20.0, 30.0, 40.0];
     
     let mut metadata1 = HashMap::new();
-    metadata1.insert("category".to_string(), "similar".to_string());
+    metadata1.insert("category".to_string(), MetadataValue::from("similar"));
     
     let mut metadata2 = HashMap::new();
-    metadata2.insert("category".to_string(), "similar".to_string());
+    metadata2.insert("category".to_string(), MetadataValue::from("similar"));
     
     let mut metadata3 = HashMap::new();
-    metadata3.insert("category".to_string(), "different".to_string());
+    metadata3.insert("category".to_string(), MetadataValue::from("different"));
     
     // Добавляем векторы
     let _id1 = lsh_controller.add_vector(vector1.clone(), metadata1).expect("Не удалось добавить первый вектор");
@@ -312,7 +315,7 @@ fn test_bucket_controller_multi_bucket_search() {
     let vector3 = vec![10.0, 20.0, 30.0, 40.0];
     
     let mut metadata1 = HashMap::new();
-    metadata1.insert("category".to_string(), "test".to_string());
+    metadata1.insert("category".to_string(), MetadataValue::from("test"));
     
     // Добавляем векторы
     let _id1 = lsh_controller.add_vector(vector1.clone(), metadata1.clone()).expect("Не удалось добавить первый вектор");
@@ -338,16 +341,16 @@ fn test_bucket_controller_metadata_filtering() {
     let vector3 = vec![10.0, 20.0, 30.0, 40.0];
     
     let mut metadata1 = HashMap::new();
-    metadata1.insert("category".to_string(), "documents".to_string());
-    metadata1.insert("language".to_string(), "ru".to_string());
+    metadata1.insert("category".to_string(), MetadataValue::from("documents"));
+    metadata1.insert("language".to_string(), MetadataValue::from("ru"));
     
     let mut metadata2 = HashMap::new();
-    metadata2.insert("category".to_string(), "images".to_string());
-    metadata2.insert("language".to_string(), "en".to_string());
+    metadata2.insert("category".to_string(), MetadataValue::from("images"));
+    metadata2.insert("language".to_string(), MetadataValue::from("en"));
     
     let mut metadata3 = HashMap::new();
-    metadata3.insert("category".to_string(), "documents".to_string());
-    metadata3.insert("language".to_string(), "en".to_string());
+    metadata3.insert("category".to_string(), MetadataValue::from("documents"));
+    metadata3.insert("language".to_string(), MetadataValue::from("en"));
     
     // Добавляем векторы
     let _id1 = lsh_controller.add_vector(vector1, metadata1).expect("Не удалось добавить первый вектор");
@@ -356,7 +359,7 @@ fn test_bucket_controller_metadata_filtering() {
     
     // Фильтрация по метаданным
     let mut filters = HashMap::new();
-    filters.insert("category".to_string(), "documents".to_string());
+    filters.insert("category".to_string(), MetadataValue::from("documents"));
     
     let filtered_ids = lsh_controller.filter_by_metadata(&filters);
     
@@ -372,7 +375,7 @@ fn test_bucket_controller_statistics() {
     let vector2 = vec![1.1, 2.1, 3.1, 4.1];
     
     let mut metadata = HashMap::new();
-    metadata.insert("category".to_string(), "test".to_string());
+    metadata.insert("category".to_string(), MetadataValue::from("test"));
     
     // Добавляем векторы
     let _id1 = lsh_controller.add_vector(vector1, metadata.clone()).expect("Не удалось добавить первый вектор");
@@ -397,7 +400,7 @@ fn test_bucket_controller_remove_vector() {
     let vector2 = vec![1.1, 2.1, 3.1, 4.1];
     
     let mut metadata = HashMap::new();
-    metadata.insert("category".to_string(), "test".to_string());
+    metadata.insert("category".to_string(), MetadataValue::from("test"));
     
     // Добавляем векторы
     let id1 = lsh_controller.add_vector(vector1, metadata.clone()).expect("Не удалось добавить первый вектор");
@@ -457,7 +460,7 @@ fn test_vector_storage_in_buckets() {
     use crate::core::controllers::StorageController;
     
     // Создаем StorageController
-    let storage_controller = StorageController::new(HashMap::new());
+    let storage_controller = StorageController::new(HashMap::new()).unwrap();
     
     // Создаем тестовые данные
     let collection_name = "test_collection".to_string();
@@ -479,7 +482,7 @@ fn test_vector_storage_in_buckets() {
         collection_name.clone(), 
         bucket_id.to_string(), 
         vector_id
-    );
+    ).unwrap();
     
     assert!(loaded_data.is_some(), "Вектор не найден в бакете");
     assert_eq!(loaded_data.unwrap(), test_data, "Загруженные данные не совпадают с сохраненными");
@@ -489,7 +492,7 @@ fn test_vector_storage_in_buckets() {
         collection_name,
         bucket_id.to_string(),
         99999u64
-    );
+    ).unwrap();
     assert!(non_existent.is_none(), "Несуществующий вектор не должен быть найден");
 }
 
@@ -498,7 +501,7 @@ fn test_bucket_storage_in_own_folder() {
     use crate::core::controllers::StorageController;
     
     // Создаем StorageController
-    let storage_controller = StorageController::new(HashMap::new());
+    let storage_controller = StorageController::new(HashMap::new()).unwrap();
     
     // Создаем тестовые данные
     let collection_name = "test_collection_bucket".to_string();
@@ -517,7 +520,7 @@ fn test_bucket_storage_in_own_folder() {
     let loaded_data = storage_controller.read_bucket(
         collection_name.clone(), 
         bucket_id.to_string()
-    );
+    ).unwrap();
     
     assert!(loaded_data.is_some(), "Данные бакета не найдены");
     assert_eq!(loaded_data.unwrap(), test_data, "Загруженные данные бакета не совпадают с сохраненными");
@@ -526,11 +529,11 @@ fn test_bucket_storage_in_own_folder() {
     let non_existent = storage_controller.read_bucket(
         collection_name, 
         "non_existent_bucket".to_string()
-    );
+    ).unwrap();
     assert!(non_existent.is_none(), "Несуществующий бакет не должен быть найден");
     
     // Тестируем получение списка бакетов
-    let bucket_names = storage_controller.get_all_buckets_names("test_collection_bucket".to_string());
+    let bucket_names = storage_controller.get_all_buckets_names("test_collection_bucket".to_string()).unwrap();
     assert!(bucket_names.contains(&bucket_id.to_string()), "Бакет должен быть найден в списке");
 }
 
@@ -545,7 +548,7 @@ fn test_vector_moves_between_buckets_on_update() {
     // Добавляем вектор
     let original_vector = vec![1.0, 2.0, 3.0, 4.0];
     let mut metadata = HashMap::new();
-    metadata.insert("type".to_string(), "test".to_string());
+    metadata.insert("type".to_string(), MetadataValue::from("test"));
     
     let vector_id = bucket_controller.add_vector(original_vector.clone(), metadata).unwrap();
     
@@ -586,7 +589,7 @@ fn test_vector_dimension_validation() {
     use std::sync::Arc;
 
     // Создаем контроллеры с Arc
-    let storage_controller = Arc::new(StorageController::new(HashMap::new()));
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
     let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
 
     // Создаем коллекцию с размерностью 384
@@ -608,10 +611,10 @@ fn test_vector_dimension_validation() {
     let result = collection_controller.add_vector(&collection_name, incorrect_vector, metadata);
     assert!(result.is_err(), "Неправильный вектор должен быть отклонен");
 
-    // Проверяем сообщение об ошибке
-    if let Err(error_msg) = result {
-        assert!(error_msg.to_string().contains("Размерность вектора не соответствует размерности коллекции"));
-    }
+    // Проверяем категорию и сообщение об ошибке
+    let error = result.unwrap_err();
+    assert_eq!(error, crate::core::errors::VecDbError::DimensionMismatch { expected: 384, actual: 100 });
+    assert!(error.to_string().contains("не соответствует размерности коллекции"));
 
     println!("Тест валидации размерности векторов завершен успешно!");
 }
@@ -695,3 +698,3529 @@ fn test_empty_bucket_removal_on_vector_deletion() {
     println!("Тест удаления пустых бакетов при удалении векторов завершен успешно!");
 }
 
+#[test]
+fn test_config_loader_keeps_arrays_and_nested_objects() {
+    use crate::core::config::ConfigLoader;
+
+    let config_path = std::env::temp_dir().join("vecdb_test_config_nested.json");
+    std::fs::write(&config_path, r#"{
+        "storage": {"path": "./storage"},
+        "sharding": {
+            "shards": [{"id": "s1", "port": 9001}, {"id": "s2", "port": 9002}],
+            "lsh": {"num_hashes": 4}
+        }
+    }"#).unwrap();
+
+    let mut config_loader = ConfigLoader::new();
+    config_loader.load(config_path.to_string_lossy().to_string());
+    // Удаляем файл, чтобы убедиться, что дальнейшие чтения идут из памяти
+    std::fs::remove_file(&config_path).unwrap();
+
+    // Массив доступен как структурированное значение
+    let shards = config_loader.get_value("sharding.shards").expect("Массив шардов должен быть доступен");
+    let shards = shards.as_array().expect("Значение должно остаться массивом");
+    assert_eq!(shards.len(), 2);
+    assert_eq!(shards[1]["port"], 9002);
+
+    // Вложенные объекты раскладываются в плоские ключи на любую глубину
+    let sharding = config_loader.get("sharding");
+    assert_eq!(sharding.get("lsh.num_hashes"), Some(&"4".to_string()));
+    assert_eq!(config_loader.get("storage").get("path"), Some(&"./storage".to_string()));
+
+    assert!(config_loader.get_value("sharding.missing").is_none());
+}
+
+#[test]
+fn test_collection_exists() {
+    use crate::core::controllers::{CollectionController, StorageController};
+    use std::sync::Arc;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+
+    // До инициализации коллекций проверка не должна падать
+    assert!(!collection_controller.collection_exists("exists_collection"));
+
+    collection_controller.add_collection("exists_collection".to_string(), LSHMetric::Euclidean, 4).unwrap();
+
+    assert!(collection_controller.collection_exists("exists_collection"));
+    assert!(!collection_controller.collection_exists("missing_collection"));
+}
+
+#[test]
+fn test_upsert_vector_inserts_missing_id() {
+    use crate::core::controllers::{CollectionController, StorageController};
+    use std::sync::Arc;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("upsert_insert".to_string(), LSHMetric::Euclidean, 4).unwrap();
+
+    let inserted = collection_controller
+        .upsert_vector("upsert_insert", 777, vec![1.0, 2.0, 3.0, 4.0], Some(metadata_with_category("new")))
+        .expect("Upsert должен завершиться успешно");
+    assert!(inserted, "Отсутствующий вектор должен быть вставлен");
+
+    let vector = collection_controller.get_vector("upsert_insert", 777).expect("Вектор должен быть доступен по заданному ID");
+    assert_eq!(vector.data, vec![1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(vector.metadata.get("category"), Some(&MetadataValue::from("new")));
+
+    // Неверная размерность отклоняется и для вставки
+    assert!(collection_controller.upsert_vector("upsert_insert", 778, vec![1.0; 3], None).is_err());
+}
+
+#[test]
+fn test_upsert_vector_updates_existing_id_and_moves_bucket() {
+    use crate::core::controllers::{CollectionController, StorageController};
+    use std::sync::Arc;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("upsert_update".to_string(), LSHMetric::Euclidean, 4).unwrap();
+
+    let original = vec![1.0, 2.0, 3.0, 4.0];
+    let moved = vec![100.0, 200.0, 300.0, 400.0];
+    collection_controller
+        .upsert_vector("upsert_update", 42, original.clone(), Some(metadata_with_category("old")))
+        .unwrap();
+
+    let inserted = collection_controller
+        .upsert_vector("upsert_update", 42, moved.clone(), None)
+        .expect("Upsert существующего вектора должен завершиться успешно");
+    assert!(!inserted, "Существующий вектор должен быть обновлен");
+
+    let collection = collection_controller.get_collection("upsert_update").unwrap();
+    let lsh = collection.buckets_controller.lsh.as_ref().unwrap();
+    let old_bucket_id = lsh.hash(&original);
+    let new_bucket_id = lsh.hash(&moved);
+    assert_ne!(old_bucket_id, new_bucket_id, "Новый эмбеддинг должен попасть в другой бакет");
+
+    assert!(collection.buckets_controller.get_bucket(new_bucket_id).unwrap().contains_vector(42));
+    assert!(collection.buckets_controller.get_bucket(old_bucket_id).is_none(), "Пустой исходный бакет должен быть удален");
+    assert_eq!(collection.buckets_controller.total_vectors(), 1);
+
+    let vector = collection.buckets_controller.get_vector(42).unwrap();
+    assert_eq!(vector.data, moved);
+    assert_eq!(vector.metadata.get("category"), Some(&MetadataValue::from("old")), "Метаданные без замены должны сохраниться");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_shutdown_dump_waits_for_in_flight_insert() {
+    use crate::core::config::ConfigLoader;
+    use crate::core::controllers::{CollectionController, ConnectionController, StorageController};
+    use crate::core::interfaces::Object;
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("shutdown_collection".to_string(), LSHMetric::Euclidean, 4).unwrap();
+    let controller = Arc::new(RwLock::new(collection_controller));
+
+    // Выбираем свободный порт
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let server_controller = Arc::clone(&controller);
+    let server = tokio::spawn(async move {
+        let mut connection_controller = ConnectionController::new(ConfigLoader::new());
+        connection_controller.connection_handler(server_controller, addr).await
+    });
+
+    // Долгая вставка, удерживающая контроллер на запись во время остановки
+    let insert_controller = Arc::clone(&controller);
+    let (started_tx, started_rx) = std::sync::mpsc::channel();
+    let insert = tokio::task::spawn_blocking(move || {
+        let mut ctrl = insert_controller.blocking_write();
+        started_tx.send(()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        ctrl.add_vector("shutdown_collection", vec![1.0, 2.0, 3.0, 4.0], HashMap::new()).unwrap()
+    });
+    started_rx.recv().unwrap();
+
+    // Отправляем /stop, пока вставка ещё выполняется
+    tokio::task::spawn_blocking(move || {
+        let mut stream = loop {
+            match std::net::TcpStream::connect(addr) {
+                Ok(stream) => break stream,
+                Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        };
+        stream.write_all(b"POST /stop HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+    }).await.unwrap();
+
+    let returned_controller = server.await.unwrap().expect("Сервер должен корректно остановиться");
+
+    // Финальный dump под блокировкой на запись дожидается завершения вставки
+    let ctrl = returned_controller.write().await;
+    ctrl.dump();
+
+    let vector_id = insert.await.unwrap();
+    let collection = ctrl.get_collection("shutdown_collection").unwrap();
+    let vector = collection.buckets_controller.get_vector(vector_id).expect("Вставленный вектор должен попасть в dump");
+    let bucket_id = collection.buckets_controller.get_all_buckets()
+        .into_iter()
+        .find(|b| b.contains_vector(vector.hash_id()))
+        .unwrap()
+        .id;
+    assert!(storage_controller
+        .read_vector_from_bucket("shutdown_collection".to_string(), bucket_id.to_string(), vector_id).unwrap()
+        .is_some(), "Файл вектора должен быть сохранён финальным dump");
+}
+
+#[test]
+fn test_chunk_text_boundaries() {
+    use crate::core::embeddings::chunk_text;
+
+    // Текст короче размера чанка даёт один чанк
+    assert_eq!(chunk_text("abc", 10, 2).unwrap(), vec!["abc".to_string()]);
+
+    // Длина текста кратна размеру чанка без перекрытия
+    assert_eq!(chunk_text("abcdef", 3, 0).unwrap(), vec!["abc".to_string(), "def".to_string()]);
+
+    // Перекрывающиеся окна, последний чанк заканчивается на конце текста
+    assert_eq!(
+        chunk_text("abcdefg", 4, 2).unwrap(),
+        vec!["abcd".to_string(), "cdef".to_string(), "efg".to_string()]
+    );
+
+    // Многобайтовые символы не разрываются
+    assert_eq!(chunk_text("привет", 3, 0).unwrap(), vec!["при".to_string(), "вет".to_string()]);
+
+    assert!(chunk_text("", 3, 0).is_err());
+    assert!(chunk_text("abc", 0, 0).is_err());
+    assert!(chunk_text("abc", 3, 3).is_err());
+}
+
+#[test]
+fn test_add_text_chunks_links_chunks_by_doc_id() {
+    use crate::core::controllers::{CollectionController, StorageController};
+    use crate::core::objects::EvictionPolicy;
+    use crate::core::errors::VecDbError;
+    use std::sync::Arc;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("chunks_collection".to_string(), LSHMetric::Euclidean, 4).unwrap();
+
+    // Окна: "hello", "o wor", "rld!"
+    let embeddings = CollectionController::embed_text_chunks("hello world!", 5, 1).unwrap();
+    assert_eq!(embeddings.len(), 3);
+
+    let (doc_id, ids) = collection_controller
+        .add_text_chunks("chunks_collection", "hello world!", embeddings.clone(), metadata_with_category("doc"))
+        .expect("Чанки должны добавляться");
+    assert_eq!(ids.len(), 3);
+
+    for (index, id) in ids.iter().enumerate() {
+        let vector = collection_controller.get_vector("chunks_collection", *id).unwrap();
+        assert_eq!(vector.metadata.get("doc_id"), Some(&MetadataValue::from(doc_id.clone())));
+        assert_eq!(vector.metadata.get("chunk_index"), Some(&MetadataValue::Int(index as i64)));
+        assert_eq!(vector.metadata.get("category"), Some(&MetadataValue::from("doc")));
+    }
+
+    let first = collection_controller.get_vector("chunks_collection", ids[0]).unwrap();
+    assert_eq!(first.data, embedding_for("hello"));
+
+    assert!(collection_controller.add_text_chunks("missing", "text", embeddings.clone(), HashMap::new()).is_err());
+
+    // Третий чанк не помещается в коллекцию: документ отклоняется целиком
+    collection_controller.add_collection("bounded_chunks".to_string(), LSHMetric::Euclidean, 4).unwrap();
+    collection_controller.set_max_vectors("bounded_chunks", Some(2), EvictionPolicy::Reject).unwrap();
+    assert!(matches!(
+        collection_controller.add_text_chunks("bounded_chunks", "hello world!", embeddings.clone(), HashMap::new()),
+        Err(VecDbError::Validation(_))
+    ));
+    assert_eq!(collection_controller.get_collection("bounded_chunks").unwrap().buckets_controller.total_vectors(), 0);
+
+    // При fifo документ, не помещающийся в свободное место, не вытесняет уже сохранённые векторы
+    collection_controller.add_collection("fifo_chunks".to_string(), LSHMetric::Euclidean, 4).unwrap();
+    collection_controller.set_max_vectors("fifo_chunks", Some(4), EvictionPolicy::Fifo).unwrap();
+    let existing: Vec<u64> = (0..2)
+        .map(|i| collection_controller.add_vector("fifo_chunks", vec![i as f32, 1.0, 0.0, 0.0], HashMap::new()).unwrap())
+        .collect();
+    assert!(matches!(
+        collection_controller.add_text_chunks("fifo_chunks", "hello world!", embeddings.clone(), HashMap::new()),
+        Err(VecDbError::Validation(_))
+    ));
+    assert_eq!(collection_controller.get_collection("fifo_chunks").unwrap().buckets_controller.total_vectors(), 2);
+    for id in &existing {
+        assert!(collection_controller.get_vector("fifo_chunks", *id).is_ok());
+    }
+
+    // Документ, помещающийся в свободное место, добавляется без вытеснения
+    collection_controller.delete_vector("fifo_chunks", existing[1]).unwrap();
+    let (_, ids) = collection_controller.add_text_chunks("fifo_chunks", "hello world!", embeddings, HashMap::new()).unwrap();
+    assert_eq!(ids.len(), 3);
+    assert!(collection_controller.get_vector("fifo_chunks", existing[0]).is_ok());
+    assert_eq!(collection_controller.get_collection("fifo_chunks").unwrap().buckets_controller.total_vectors(), 4);
+}
+
+fn app_state_for(collection_controller: CollectionController, configs: HashMap<String, String>) -> AppState {
+    AppState {
+        controller: Arc::new(tokio::sync::RwLock::new(collection_controller)),
+        query_cache: Arc::new(QueryCache::new(
+            configs.get("query_cache_size").and_then(|size| size.parse().ok()).unwrap_or(0)
+        )),
+        configs,
+        shutdown: Arc::new(ShutdownSignal::new()),
+    }
+}
+
+async fn response_body(response: axum::response::Response) -> Vec<u8> {
+    axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    serde_json::from_slice(&response_body(response).await).unwrap()
+}
+
+fn collection_controller_with(name: &str, dimension: usize) -> CollectionController {
+    collection_controller_with_metric(name, dimension, LSHMetric::Euclidean)
+}
+
+fn collection_controller_with_metric(name: &str, dimension: usize, metric: LSHMetric) -> CollectionController {
+    let configs = HashMap::from([("backend".to_string(), "memory".to_string())]);
+    let storage_controller = Arc::new(StorageController::new(configs).unwrap());
+    let mut collection_controller = CollectionController::new(storage_controller);
+    collection_controller.add_collection(name.to_string(), metric, dimension).unwrap();
+    collection_controller
+}
+
+#[tokio::test]
+async fn test_info_reports_standalone_node() {
+    use crate::core::handlers::info;
+
+    let mut configs = HashMap::new();
+    configs.insert("host".to_string(), "127.0.0.1".to_string());
+    configs.insert("port".to_string(), "9000".to_string());
+
+    let state = app_state_for(collection_controller_with("info_collection", 4), configs);
+
+    let response = info(State(state)).await.0;
+    let data = response.data.expect("Ответ должен содержать данные");
+
+    assert_eq!(response.status, "ok");
+    assert_eq!(data["role"], "standalone");
+    assert_eq!(data["sharded"], false);
+    assert_eq!(data["shards"].as_array().unwrap().len(), 0);
+    assert_eq!(data["address"], "127.0.0.1:9000");
+    assert_eq!(data["collections_count"], 1);
+}
+
+#[tokio::test]
+async fn test_find_similar_includes_metadata_inline() {
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+
+    let mut collection_controller = collection_controller_with("inline_collection", 4);
+    let id = collection_controller
+        .add_vector("inline_collection", vec![1.0, 2.0, 3.0, 4.0], metadata_with_category("inline"))
+        .unwrap();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params = |include_metadata: bool, include_embedding: bool| -> FindSimilarParams {
+        serde_json::from_value(serde_json::json!({
+            "collection": "inline_collection",
+            "query": [1.0, 2.0, 3.0, 4.0],
+            "k": 1,
+            "include_metadata": include_metadata,
+            "include_embedding": include_embedding
+        })).unwrap()
+    };
+
+    let response = find_similar(State(state.clone()), Json(params(true, true))).await.1.0;
+    let results = &response.data.unwrap()["results"];
+    assert_eq!(results[0]["vector_id"], id);
+    assert_eq!(results[0]["metadata"]["category"], "inline");
+    assert_eq!(results[0]["embedding"], serde_json::json!([1.0, 2.0, 3.0, 4.0]));
+
+    // Без флагов метаданные и эмбеддинг не возвращаются
+    let response = find_similar(State(state), Json(params(false, false))).await.1.0;
+    let results = &response.data.unwrap()["results"];
+    assert_eq!(results[0]["vector_id"], id);
+    assert!(results[0].get("metadata").is_none());
+    assert!(results[0].get("embedding").is_none());
+}
+
+#[test]
+fn test_bucket_load_order_is_deterministic() {
+    use crate::core::interfaces::{CollectionObjectController, Object};
+    use crate::core::objects::Bucket;
+    use crate::core::utils::StorageFormat;
+
+    let raw_buckets = || -> HashMap<u64, Vec<u8>> {
+        [42u64, 7, 1000, 3, 512, 99]
+            .iter()
+            .map(|id| (*id, Bucket::new(*id).dump(StorageFormat::Bincode).unwrap().0))
+            .collect()
+    };
+
+    let mut first = BucketController::new(4, 3, 1.0, LSHMetric::Euclidean, Some(42));
+    first.load(raw_buckets(), StorageFormat::Bincode).unwrap();
+    let mut second = BucketController::new(4, 3, 1.0, LSHMetric::Euclidean, Some(42));
+    second.load(raw_buckets(), StorageFormat::Bincode).unwrap();
+
+    let first_ids: Vec<u64> = first.get_all_buckets().iter().map(|b| b.id).collect();
+    let second_ids: Vec<u64> = second.get_all_buckets().iter().map(|b| b.id).collect();
+
+    assert_eq!(first_ids, second_ids);
+    assert_eq!(first_ids, vec![3, 7, 42, 99, 512, 1000]);
+}
+
+#[test]
+fn test_multi_bucket_search_stops_at_deadline() {
+    use crate::core::controllers::SearchOptions;
+    use std::time::Instant;
+
+    let mut bucket_controller = BucketController::new(4, 3, 1.0, LSHMetric::Euclidean, Some(42));
+    let query = vec![1.0, 2.0, 3.0, 4.0];
+    bucket_controller.add_vector(query.clone(), HashMap::new()).unwrap();
+    for i in 1..20 {
+        let scale = (i * 10) as f32;
+        bucket_controller.add_vector(vec![scale, scale * 2.0, scale * 3.0, scale * 4.0], HashMap::new()).unwrap();
+    }
+    assert!(bucket_controller.count() > 1, "Векторы должны попасть в разные бакеты");
+
+    // Без дедлайна просматриваются все бакеты
+    let full = bucket_controller
+        .find_similar_multi_bucket_with_options(&query, 20, &SearchOptions::default())
+        .unwrap();
+    assert!(!full.timed_out);
+    assert_eq!(full.results.len(), 20);
+
+    // Уже истёкший дедлайн: просматривается только бакет запроса
+    let options = SearchOptions { deadline: Some(Instant::now()), ..Default::default() };
+    let partial = bucket_controller
+        .find_similar_multi_bucket_with_options(&query, 20, &options)
+        .unwrap();
+    assert!(partial.timed_out);
+    assert!(!partial.results.is_empty(), "Лучшие найденные результаты должны вернуться");
+    assert!(partial.results.len() < full.results.len());
+
+    let query_bucket = bucket_controller.lsh.as_ref().unwrap().hash(&query);
+    assert!(partial.results.iter().all(|(bucket_id, _, _)| *bucket_id == query_bucket));
+}
+
+#[test]
+fn test_bucket_timestamps_and_vectors_survive_dump_load() {
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    let collection_name = "reload_timestamps_collection";
+    collection_controller.add_collection(collection_name.to_string(), LSHMetric::Cosine, 4).unwrap();
+
+    let id = collection_controller
+        .add_vector(collection_name, vec![1.0, 2.0, 3.0, 4.0], metadata_with_category("reload"))
+        .unwrap();
+
+    // Делаем временные метки заведомо отличными от момента загрузки
+    let bucket_id = {
+        let collection = collection_controller.get_collection_mut(collection_name).unwrap();
+        let bucket = collection.buckets_controller.buckets.as_mut().unwrap().first_mut().unwrap();
+        bucket.created_at = 1_000;
+        bucket.updated_at = 2_000;
+        bucket.id
+    };
+    collection_controller.dump();
+
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load_one(collection_name.to_string());
+
+    let collection = reloaded.get_collection(collection_name).expect("Коллекция должна загрузиться");
+    assert_eq!(collection.lsh_metric, LSHMetric::Cosine);
+    assert_eq!(collection.buckets_controller.dimension, Some(4));
+
+    let bucket = collection.buckets_controller.get_bucket(bucket_id).expect("Бакет должен загрузиться");
+    assert_eq!(bucket.created_at, 1_000);
+    assert_eq!(bucket.updated_at, 2_000);
+
+    let vector = bucket.get_vector(id).expect("Векторы бакета должны загрузиться");
+    assert_eq!(vector.data, vec![1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(vector.metadata.get("category"), Some(&MetadataValue::from("reload")));
+
+    // После загрузки поиск снова работает
+    let results = reloaded.find_similar(collection_name.to_string(), &vec![1.0, 2.0, 3.0, 4.0], 1).unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn test_filter_by_expression_and_or() {
+    use crate::core::utils::MetadataFilter;
+
+    let mut controller = VectorController::new();
+    let mut add = |category: &str, lang: &str| {
+        let mut metadata = metadata_with_category(category);
+        metadata.insert("lang".to_string(), MetadataValue::from(lang));
+        controller
+            .add_vector(Some(embedding_for(&format!("{}{}", category, lang))), Some(metadata), None, None)
+            .unwrap()
+    };
+    let news_ru = add("news", "ru");
+    let blog_en = add("blog", "en");
+    let news_en = add("news", "en");
+    let docs_ru = add("docs", "ru");
+
+    let eq = |key: &str, value: &str| MetadataFilter::Eq { key: key.to_string(), value: MetadataValue::from(value) };
+
+    // category == news OR category == blog
+    let news_or_blog = MetadataFilter::Or(vec![eq("category", "news"), eq("category", "blog")]);
+    let mut ids = controller.filter_by_expression(&news_or_blog);
+    ids.sort();
+    let mut expected = vec![news_ru, blog_en, news_en];
+    expected.sort();
+    assert_eq!(ids, expected);
+
+    // (category == news OR category == blog) AND lang == en
+    let nested = MetadataFilter::And(vec![news_or_blog.clone(), eq("lang", "en")]);
+    let mut ids = controller.filter_by_expression(&nested);
+    ids.sort();
+    let mut expected = vec![blog_en, news_en];
+    expected.sort();
+    assert_eq!(ids, expected);
+
+    // lang == ru AND (category == docs OR category == blog)
+    let nested = MetadataFilter::And(vec![
+        eq("lang", "ru"),
+        MetadataFilter::Or(vec![eq("category", "docs"), eq("category", "blog")]),
+    ]);
+    assert_eq!(controller.filter_by_expression(&nested), vec![docs_ru]);
+
+    // Пустое выражение подходит для всех векторов
+    assert_eq!(controller.filter_by_expression(&MetadataFilter::And(vec![])).len(), 4);
+    assert_eq!(controller.filter_by_expression(&MetadataFilter::Or(vec![])).len(), 4);
+
+    // Выражение приходит в запросе в виде JSON
+    let parsed: MetadataFilter = serde_json::from_value(serde_json::json!({
+        "or": [
+            {"eq": {"key": "category", "value": "news"}},
+            {"eq": {"key": "category", "value": "blog"}}
+        ]
+    })).unwrap();
+    assert_eq!(parsed, news_or_blog);
+}
+
+#[test]
+fn test_preload_collection_loads_only_requested() {
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    let hot = "preload_hot_collection";
+    let cold = "preload_cold_collection";
+    collection_controller.add_collection(hot.to_string(), LSHMetric::Euclidean, 3).unwrap();
+    collection_controller.add_collection(cold.to_string(), LSHMetric::Euclidean, 3).unwrap();
+    collection_controller.add_vector(hot, vec![1.0, 0.0, 0.0], HashMap::new()).unwrap();
+    collection_controller.add_vector(hot, vec![0.0, 5.0, 0.0], HashMap::new()).unwrap();
+    collection_controller.add_vector(cold, vec![0.0, 0.0, 1.0], HashMap::new()).unwrap();
+    collection_controller.dump();
+
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    let (loaded, buckets, vectors) = reloaded.preload_collection(hot).unwrap();
+    assert!(loaded);
+    assert!(buckets >= 1);
+    assert_eq!(vectors, 2);
+    assert!(reloaded.collection_exists(hot));
+    assert!(!reloaded.collection_exists(cold));
+
+    // Повторный прогрев не загружает коллекцию второй раз
+    let (loaded, _, vectors) = reloaded.preload_collection(hot).unwrap();
+    assert!(!loaded);
+    assert_eq!(vectors, 2);
+    assert_eq!(reloaded.get_all_collections().len(), 1);
+
+    assert!(reloaded.preload_collection("preload_missing_collection").is_err());
+}
+
+#[test]
+fn test_json_storage_format_round_trip() {
+    use crate::core::utils::StorageFormat;
+
+    let root = std::env::temp_dir().join(format!("vecdb_json_format_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let configs: HashMap<String, String> = [
+        ("path".to_string(), root.to_string_lossy().to_string()),
+        ("format".to_string(), "json".to_string()),
+    ].into_iter().collect();
+
+    let storage_controller = Arc::new(StorageController::new(configs).unwrap());
+    assert_eq!(storage_controller.format, StorageFormat::Json);
+
+    let collection_name = "json_format_collection";
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection(collection_name.to_string(), LSHMetric::Euclidean, 3).unwrap();
+    let id = collection_controller
+        .add_vector(collection_name, vec![0.5, 1.5, 2.5], metadata_with_category("debug"))
+        .unwrap();
+    collection_controller.dump();
+
+    // Файлы читаются как обычный JSON
+    let collection_dir = root.join("storage").join(collection_name);
+    let bucket_dir = std::fs::read_dir(&collection_dir).unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| p.is_dir())
+        .expect("Должна быть папка бакета");
+    let vector_file = bucket_dir.join("vectors").join(format!("{}.bin", id));
+    let text = std::fs::read_to_string(&vector_file).expect("Файл вектора должен быть текстовым");
+    // Первые строки — заголовки с контрольной суммой и версией схемы, дальше обычный JSON
+    let (header, body) = text.split_once('\n').unwrap();
+    assert!(header.starts_with("vdb1:"));
+    let (schema_header, body) = body.split_once('\n').unwrap();
+    assert!(schema_header.starts_with("vdbs:"));
+    let json: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(json["metadata"]["category"], "debug");
+    assert_eq!(json["hash_id"], id);
+    let bucket_text = std::fs::read_to_string(bucket_dir.join("0.bin")).unwrap();
+    let bucket_json: serde_json::Value = serde_json::from_str(bucket_text.splitn(3, '\n').nth(2).unwrap()).unwrap();
+    assert!(bucket_json["created_at"].is_i64());
+
+    // Формат записан в storage: даже без настройки хранилище читается как JSON
+    let reopened = Arc::new(StorageController::new(
+        [("path".to_string(), root.to_string_lossy().to_string())].into_iter().collect(),
+    ).unwrap());
+    assert_eq!(reopened.format, StorageFormat::Json);
+
+    let mut reloaded = CollectionController::new(reopened);
+    reloaded.load_one(collection_name.to_string());
+    let vector = reloaded.get_vector(collection_name, id).unwrap();
+    assert_eq!(vector.data, vec![0.5, 1.5, 2.5]);
+    assert_eq!(vector.metadata.get("category"), Some(&MetadataValue::from("debug")));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_get_collection_reports_lsh_params() {
+    use crate::core::handlers::get_collection;
+
+    let state = app_state_for(collection_controller_with("lsh_params_collection", 4), HashMap::new());
+
+    let response = get_collection(
+        State(state.clone()),
+        Json(serde_json::from_value(serde_json::json!({"name": "lsh_params_collection"})).unwrap()),
+    ).await.0;
+    assert_eq!(response.status, "ok");
+    let data = response.data.unwrap();
+    assert_eq!(data["vector_dimension"], 4);
+    assert_eq!(data["num_hashes"], 3);
+    assert_eq!(data["bucket_width"], 10.0);
+    assert_eq!(data["seed"], 42);
+
+    let response = get_collection(
+        State(state),
+        Json(serde_json::from_value(serde_json::json!({"name": "missing_collection"})).unwrap()),
+    ).await.0;
+    assert_eq!(response.status, "error");
+}
+
+#[tokio::test]
+async fn test_find_similar_on_empty_and_missing_collection() {
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+    use axum::http::StatusCode;
+
+    let state = app_state_for(collection_controller_with("empty_search_collection", 3), HashMap::new());
+    let params = |collection: &str| -> FindSimilarParams {
+        serde_json::from_value(serde_json::json!({
+            "collection": collection,
+            "query": [1.0, 0.0, 0.0],
+            "k": 3
+        })).unwrap()
+    };
+
+    // Существующая, но пустая коллекция — пустой результат без ошибки
+    let (status, response) = find_similar(State(state.clone()), Json(params("empty_search_collection"))).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+    assert_eq!(data["results"], serde_json::json!([]));
+    assert_eq!(data["collection_empty"], true);
+
+    // Несуществующая коллекция — 404
+    let (status, response) = find_similar(State(state), Json(params("missing_search_collection"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(response.0.status, "error");
+
+    // Пустой список векторов — это пустой результат, а не ошибка
+    let controller = VectorController::new();
+    assert!(controller.find_most_similar(&vec![1.0, 0.0, 0.0], 3, &LSHMetric::Cosine).unwrap().is_empty());
+    assert!(crate::core::embeddings::find_most_similar(&vec![1.0], &[], 3, &LSHMetric::Cosine).unwrap().is_empty());
+}
+
+#[test]
+fn test_more_lsh_tables_improve_recall() {
+    use crate::core::interfaces::Object;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    let dimension = 8;
+    let k = 5;
+    let mut rng = StdRng::seed_from_u64(2024);
+    let dataset: Vec<Vec<f32>> = (0..300)
+        .map(|_| (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+    let queries: Vec<Vec<f32>> = dataset.iter().take(40)
+        .map(|v| v.iter().map(|x| x + rng.gen_range(-0.05..0.05)).collect())
+        .collect();
+
+    let recall = |num_tables: usize| -> f32 {
+        let mut controller = BucketController::with_tables(dimension, 4, 0.5, LSHMetric::Euclidean, Some(42), num_tables);
+        let ids: Vec<u64> = dataset.iter()
+            .map(|v| controller.add_vector(v.clone(), HashMap::new()).unwrap())
+            .collect();
+
+        let mut found = 0;
+        for query in &queries {
+            // Точный ответ полным перебором в той же евклидовой метрике, что и у контроллера
+            let mut exact: Vec<(u64, f32)> = dataset.iter().zip(&ids)
+                .map(|(v, id)| (*id, crate::core::embeddings::euclidean_distance(query, v)))
+                .collect();
+            exact.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+            let exact: Vec<u64> = exact.into_iter().take(k).map(|(id, _)| id).collect();
+
+            let results = controller.find_similar(query, k).unwrap();
+            found += results.iter()
+                .filter_map(|(bucket_id, index, _)| controller.get_vector_at(*bucket_id, *index))
+                .filter(|v| exact.contains(&v.hash_id()))
+                .count();
+        }
+        found as f32 / (queries.len() * k) as f32
+    };
+
+    let single = recall(1);
+    let multi = recall(8);
+    assert!(multi > single, "recall с 8 таблицами ({:.3}) должен быть выше, чем с одной ({:.3})", multi, single);
+}
+
+#[test]
+fn test_num_tables_survive_dump_load() {
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    let collection_name = "num_tables_collection";
+    collection_controller
+        .add_collection_with_tables(collection_name.to_string(), LSHMetric::Euclidean, 3, 4)
+        .unwrap();
+    collection_controller.add_vector(collection_name, vec![1.0, 2.0, 3.0], HashMap::new()).unwrap();
+    collection_controller.dump();
+
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load_one(collection_name.to_string());
+    let collection = reloaded.get_collection(collection_name).unwrap();
+    assert_eq!(collection.num_tables, 4);
+    assert_eq!(collection.buckets_controller.num_tables(), 4);
+
+    let results = collection.buckets_controller.find_similar(&vec![1.0, 2.0, 3.0], 1).unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[tokio::test]
+async fn test_bucket_summaries_match_insert_distribution() {
+    use crate::core::handlers::bucket_summaries;
+
+    let mut collection_controller = collection_controller_with("bucket_summary_collection", 2);
+    // Точки далеко друг от друга, чтобы гарантированно попасть в разные бакеты
+    let groups = [(vec![0.0, 0.0], 5), (vec![500.0, 500.0], 3), (vec![-900.0, 300.0], 1)];
+    for (point, count) in &groups {
+        for i in 0..*count {
+            let embedding = vec![point[0] + i as f32 * 0.01, point[1]];
+            collection_controller.add_vector("bucket_summary_collection", embedding, HashMap::new()).unwrap();
+        }
+    }
+
+    let summaries = collection_controller.bucket_summaries("bucket_summary_collection", None).unwrap();
+    let sizes: Vec<usize> = summaries.iter().map(|s| s.1).collect();
+    assert_eq!(sizes, vec![5, 3, 1]);
+    for (bucket_id, size, _, _) in &summaries {
+        let bucket = collection_controller.get_bucket("bucket_summary_collection", *bucket_id).unwrap();
+        assert_eq!(bucket.size(), *size);
+    }
+
+    let state = app_state_for(collection_controller, HashMap::new());
+    let response = bucket_summaries(
+        State(state),
+        Json(serde_json::from_value(serde_json::json!({"name": "bucket_summary_collection", "top": 2})).unwrap()),
+    ).await.1.0;
+    let buckets = response.data.unwrap()["buckets"].as_array().unwrap().clone();
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0]["size"], 5);
+    assert_eq!(buckets[1]["size"], 3);
+    assert!(buckets[0]["created_at"].is_i64());
+}
+
+#[test]
+fn test_embedding_only_update_preserves_metadata_on_bucket_move() {
+    let mut controller = BucketController::new(2, 3, 1.0, LSHMetric::Euclidean, Some(42));
+    let mut metadata = metadata_with_category("moved");
+    metadata.insert("source".to_string(), MetadataValue::from("test"));
+    let id = controller.add_vector(vec![0.0, 0.0], metadata.clone()).unwrap();
+    let old_bucket_id = controller.get_all_buckets()[0].id;
+
+    // Только новый эмбеддинг, далеко от исходного — вектор должен переехать в другой бакет
+    controller.update_vector(id, Some(vec![400.0, -300.0]), None).unwrap();
+
+    let buckets = controller.get_all_buckets();
+    assert_eq!(buckets.len(), 1, "Старый пустой бакет должен быть удалён");
+    assert_ne!(buckets[0].id, old_bucket_id);
+    assert!(controller.get_bucket(old_bucket_id).is_none());
+
+    let vector = controller.get_vector(id).expect("Вектор должен сохранить свой ID");
+    assert_eq!(vector.data, vec![400.0, -300.0]);
+    assert_eq!(vector.metadata, metadata, "Метаданные не должны потеряться или измениться");
+    assert_eq!(controller.total_vectors(), 1, "Вектор не должен задублироваться");
+}
+
+#[test]
+fn test_corrupted_file_is_detected_and_skipped() {
+    use crate::core::interfaces::Object;
+    use crate::core::objects::Vector;
+    use crate::core::utils::{verify_checksum, with_checksum, StorageFormat};
+
+    let root = std::env::temp_dir().join(format!("vecdb_checksum_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let configs: HashMap<String, String> =
+        [("path".to_string(), root.to_string_lossy().to_string())].into_iter().collect();
+    let storage_controller = Arc::new(StorageController::new(configs).unwrap());
+
+    let collection_name = "checksum_collection";
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection(collection_name.to_string(), LSHMetric::Euclidean, 2).unwrap();
+    let healthy = collection_controller.add_vector(collection_name, vec![1.0, 1.0], HashMap::new()).unwrap();
+    let corrupted = collection_controller.add_vector(collection_name, vec![1.0, 1.01], HashMap::new()).unwrap();
+    let bucket_id = collection_controller.get_all_buckets(collection_name).unwrap()[0].id;
+    collection_controller.dump();
+
+    // Портим один байт в данных вектора (после заголовка)
+    let vectors_dir = root.join("storage").join(collection_name).join(bucket_id.to_string()).join("vectors");
+    let corrupted_path = vectors_dir.join(format!("{}.bin", corrupted));
+    let mut bytes = std::fs::read(&corrupted_path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xFF;
+    std::fs::write(&corrupted_path, &bytes).unwrap();
+    assert!(verify_checksum(bytes).unwrap_err().contains("checksum mismatch"));
+
+    // Файл без заголовка (старый формат) считается корректным
+    let legacy = Vector::new(Some(vec![0.9, 1.0]), Some(1), None);
+    let (legacy_raw, legacy_id) = legacy.dump(StorageFormat::Bincode).unwrap();
+    std::fs::write(vectors_dir.join(format!("{}.bin", legacy_id)), &legacy_raw).unwrap();
+    assert_eq!(verify_checksum(legacy_raw.clone()).unwrap(), legacy_raw);
+    assert_eq!(verify_checksum(with_checksum(legacy_raw.clone())).unwrap(), legacy_raw);
+
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load_one(collection_name.to_string());
+    assert!(reloaded.get_vector(collection_name, healthy).is_ok());
+    assert!(reloaded.get_vector(collection_name, legacy_id).is_ok());
+    assert!(reloaded.get_vector(collection_name, corrupted).is_err(), "Повреждённый файл должен быть пропущен");
+    assert!(storage_controller.read_vector_from_bucket(collection_name.to_string(), bucket_id.to_string(), corrupted).unwrap().is_none());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_cached_norm_scores_match_naive_cosine() {
+    use crate::core::embeddings::{cosine_similarity, find_most_similar};
+    use crate::core::interfaces::Object;
+    use crate::core::objects::Vector;
+    use crate::core::utils::StorageFormat;
+
+    let vectors: Vec<Vector> = [vec![3.0, 4.0, 0.0], vec![-1.0, 2.0, 0.5], vec![0.1, 0.0, 9.0], vec![0.0, 0.0, 0.0]]
+        .into_iter()
+        .map(|data| Vector::new(Some(data), Some(1), None))
+        .collect();
+    let query = vec![1.0, 1.5, -0.5];
+
+    for (index, score) in find_most_similar(&query, &vectors, vectors.len(), &LSHMetric::Cosine).unwrap() {
+        let naive = cosine_similarity(&query, &vectors[index].data);
+        assert!((score - naive).abs() < 1e-6, "score {} != naive {}", score, naive);
+    }
+
+    // Норма сохраняется и загружается вместе с вектором, set_data её пересчитывает
+    let (raw, _) = vectors[0].dump(StorageFormat::Bincode).unwrap();
+    let mut loaded = Vector::new(None, None, None);
+    loaded.load(raw, StorageFormat::Bincode).unwrap();
+    assert_eq!(loaded.norm(), 5.0);
+    loaded.set_data(vec![0.0, 6.0, 8.0]);
+    assert_eq!(loaded.norm(), 10.0);
+
+    // При поиске норма кандидата не пересчитывается: если изменить data в обход set_data,
+    // score считается со старой (кэшированной) нормой
+    let mut stale = vec![Vector::new(Some(vec![3.0, 4.0, 0.0]), Some(1), None)];
+    stale[0].data = vec![6.0, 8.0, 0.0];
+    let score = find_most_similar(&vec![3.0, 4.0, 0.0], &stale, 1, &LSHMetric::Cosine).unwrap()[0].1;
+    assert!((score - 2.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_zero_dimension_and_empty_embedding_are_rejected() {
+    let storage_controller = Arc::new(StorageController::new(HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(storage_controller);
+
+    let err = collection_controller
+        .add_collection("zero_dim_collection".to_string(), LSHMetric::Euclidean, 0)
+        .unwrap_err();
+    assert!(err.to_string().contains("больше нуля"));
+    assert!(!collection_controller.collection_exists("zero_dim_collection"));
+
+    collection_controller.add_collection("non_empty_collection".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    let err = collection_controller
+        .add_vector("non_empty_collection", Vec::new(), HashMap::new())
+        .unwrap_err();
+    assert!(err.to_string().contains("пустым"));
+    assert!(collection_controller.upsert_vector("non_empty_collection", 7, Vec::new(), None).is_err());
+
+    // Состояние не испорчено: коллекция пуста и принимает корректные векторы
+    let collection = collection_controller.get_collection("non_empty_collection").unwrap();
+    assert_eq!(collection.buckets_controller.total_vectors(), 0);
+    collection_controller.add_vector("non_empty_collection", vec![1.0, 2.0, 3.0], HashMap::new()).unwrap();
+    assert_eq!(collection_controller.find_similar("non_empty_collection".to_string(), &vec![1.0, 2.0, 3.0], 1).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_async_load_does_not_block_runtime() {
+    use crate::core::handlers::info;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let root = std::env::temp_dir().join(format!("vecdb_async_load_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let configs: HashMap<String, String> =
+        [("path".to_string(), root.to_string_lossy().to_string())].into_iter().collect();
+    let storage_controller = Arc::new(StorageController::new(configs).unwrap());
+
+    let collection_name = "async_load_collection";
+    let mut writer = CollectionController::new(Arc::clone(&storage_controller));
+    writer.add_collection(collection_name.to_string(), LSHMetric::Euclidean, 4).unwrap();
+    for i in 0..500 {
+        let x = i as f32;
+        writer.add_vector(collection_name, vec![x, x * 0.5, -x, 1.0], HashMap::new()).unwrap();
+    }
+    writer.dump();
+
+    let state = app_state_for(CollectionController::new(Arc::clone(&storage_controller)), HashMap::new());
+    let load_done = Arc::new(AtomicBool::new(false));
+
+    // Тест идёт на однопоточном рантайме: если загрузка блокирует поток,
+    // запрос /info не выполнится, пока она не закончится
+    let load = {
+        let controller = Arc::clone(&state.controller);
+        let load_done = Arc::clone(&load_done);
+        tokio::spawn(async move {
+            let count = CollectionController::load_async(&controller).await.unwrap();
+            load_done.store(true, Ordering::SeqCst);
+            count
+        })
+    };
+    tokio::task::yield_now().await;
+
+    let response = info(State(state.clone())).await.0;
+    assert_eq!(response.status, "ok");
+    assert!(!load_done.load(Ordering::SeqCst), "/info должен ответить, не дожидаясь окончания загрузки");
+
+    assert_eq!(load.await.unwrap(), 1);
+    let ctrl = state.controller.read().await;
+    assert_eq!(ctrl.get_collection(collection_name).unwrap().buckets_controller.total_vectors(), 500);
+    drop(ctrl);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_find_similar_batch_preserves_query_order() {
+    use crate::core::handlers::find_similar_batch;
+    use crate::core::openapi::FindSimilarBatchParams;
+    use axum::http::StatusCode;
+
+    let mut collection_controller = collection_controller_with("batch_collection", 3);
+    let ids: Vec<u64> = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+        .iter()
+        .map(|data| collection_controller.add_vector("batch_collection", data.to_vec(), HashMap::new()).unwrap())
+        .collect();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params = |queries: serde_json::Value| -> FindSimilarBatchParams {
+        serde_json::from_value(serde_json::json!({
+            "collection": "batch_collection",
+            "queries": queries,
+            "k": 1
+        })).unwrap()
+    };
+
+    // Запросы в обратном порядке — ответы должны идти в том же порядке
+    let queries = serde_json::json!([[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]);
+    let (status, response) = find_similar_batch(State(state.clone()), Json(params(queries))).await;
+    assert_eq!(status, StatusCode::OK);
+    let results = response.0.data.unwrap()["results"].clone();
+    assert_eq!(results.as_array().unwrap().len(), 3);
+    assert_eq!(results[0][0]["vector_id"], ids[2]);
+    assert_eq!(results[1][0]["vector_id"], ids[1]);
+    assert_eq!(results[2][0]["vector_id"], ids[0]);
+
+    // Один запрос неверной размерности отклоняет весь пакет
+    let queries = serde_json::json!([[1.0, 0.0, 0.0], [1.0, 0.0]]);
+    let (status, response) = find_similar_batch(State(state), Json(params(queries))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let data = response.0.data.unwrap();
+    assert_eq!(data["error_code"], "dimension_mismatch");
+    assert_eq!((data["expected"].as_u64(), data["actual"].as_u64()), (Some(3), Some(2)));
+    assert_eq!(data["query_index"], 1);
+}
+
+#[test]
+fn test_lsh_seed_controls_bucket_assignment() {
+    use crate::core::config::ConfigLoader;
+    use crate::core::lsh::DEFAULT_LSH_SEED;
+
+    let vectors: Vec<Vec<f32>> = (0..20)
+        .map(|i| (0..8).map(|j| ((i * 7 + j * 3) % 11) as f32 - 5.0).collect())
+        .collect();
+    let assignments = |collection: &Collection| -> Vec<u64> {
+        let lsh = collection.buckets_controller.lsh.as_ref().unwrap();
+        vectors.iter().map(|v| lsh.hash(v)).collect()
+    };
+
+    let first = Collection::with_seed(Some("seed_a".to_string()), LSHMetric::Euclidean, 8, 1, Some(7));
+    let same = Collection::with_seed(Some("seed_b".to_string()), LSHMetric::Euclidean, 8, 1, Some(7));
+    let other = Collection::with_seed(Some("seed_c".to_string()), LSHMetric::Euclidean, 8, 1, Some(8));
+    assert_eq!(assignments(&first), assignments(&same));
+    assert_ne!(assignments(&first), assignments(&other));
+
+    // Без seed выбирается случайный, и он сохраняется в коллекции
+    let random = Collection::with_seed(Some("seed_d".to_string()), LSHMetric::Euclidean, 8, 1, None);
+    assert_eq!(random.buckets_controller.lsh.as_ref().unwrap().seed, Some(random.lsh_seed));
+
+    // Seed из конфига: число, null и отсутствие настройки
+    let dir = std::env::temp_dir().join(format!("vecdb_seed_config_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config_for = |body: &str| -> ConfigLoader {
+        let path = dir.join("config.json");
+        std::fs::write(&path, body).unwrap();
+        let mut loader = ConfigLoader::new();
+        loader.load(path.to_string_lossy().to_string());
+        loader
+    };
+    assert_eq!(config_for(r#"{"sharding": {"lsh_seed": 123}}"#).lsh_seed(), Some(123));
+    assert_eq!(config_for(r#"{"sharding": {"lsh_seed": null}}"#).lsh_seed(), None);
+    assert_eq!(config_for(r#"{"storage": {"path": "./storage"}}"#).lsh_seed(), Some(DEFAULT_LSH_SEED));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_find_similar_rejects_wrong_dimension_before_hashing() {
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+    use axum::http::StatusCode;
+
+    let mut collection_controller = collection_controller_with("dimension_check_collection", 3);
+    collection_controller
+        .add_vector("dimension_check_collection", vec![1.0, 0.0, 0.0], HashMap::new())
+        .unwrap();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params: FindSimilarParams = serde_json::from_value(serde_json::json!({
+        "collection": "dimension_check_collection",
+        "query": [1.0, 0.0],
+        "k": 1
+    })).unwrap();
+
+    let (status, response) = find_similar(State(state), Json(params)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(response.0.message.unwrap().contains("Размерность вектора 2"));
+}
+
+#[tokio::test]
+async fn test_metadata_boost_outranks_equally_similar_result() {
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+    use axum::http::StatusCode;
+
+    let mut collection_controller = collection_controller_with_metric("boost_collection", 3, LSHMetric::Cosine);
+    let mut premium = metadata_with_category("doc");
+    premium.insert("premium".to_string(), MetadataValue::from("true"));
+    let regular_id = collection_controller
+        .add_vector("boost_collection", vec![1.0, 0.0, 0.0], metadata_with_category("doc"))
+        .unwrap();
+    let premium_id = collection_controller
+        .add_vector("boost_collection", vec![2.0, 0.0, 0.0], premium)
+        .unwrap();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params = |boosts: serde_json::Value| -> FindSimilarParams {
+        serde_json::from_value(serde_json::json!({
+            "collection": "boost_collection",
+            "query": [1.0, 0.0, 0.0],
+            "k": 2,
+            "boosts": boosts
+        })).unwrap()
+    };
+
+    // Оба вектора одинаково похожи (косинус = 1), буст поднимает premium на первое место
+    let (status, response) = find_similar(State(state.clone()), Json(params(serde_json::json!({"premium=true": 0.5})))).await;
+    assert_eq!(status, StatusCode::OK);
+    let results = response.0.data.unwrap()["results"].clone();
+    assert_eq!(results[0]["vector_id"], premium_id);
+    assert_eq!(results[1]["vector_id"], regular_id);
+    assert!(results[0]["score"].as_f64().unwrap() > results[1]["score"].as_f64().unwrap());
+
+    // Буст без знака '=' — ошибка запроса
+    let (status, _) = find_similar(State(state), Json(params(serde_json::json!({"premium": 0.5})))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_find_similar_default_and_clamped_k() {
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+
+    let mut collection_controller = collection_controller_with("k_collection", 3);
+    for i in 0..5 {
+        collection_controller
+            .add_vector("k_collection", vec![1.0, i as f32 * 0.1, 0.0], HashMap::new())
+            .unwrap();
+    }
+    let mut configs = HashMap::new();
+    configs.insert("default_k".to_string(), "2".to_string());
+    configs.insert("max_k".to_string(), "3".to_string());
+    let state = app_state_for(collection_controller, configs);
+
+    let params = |k: Option<usize>| -> FindSimilarParams {
+        serde_json::from_value(serde_json::json!({
+            "collection": "k_collection",
+            "query": [1.0, 0.0, 0.0],
+            "k": k
+        })).unwrap()
+    };
+
+    // Без k используется server.default_k
+    let data = find_similar(State(state.clone()), Json(params(None))).await.1.0.data.unwrap();
+    assert_eq!(data["k"], 2);
+    assert_eq!(data["k_clamped"], false);
+    assert_eq!(data["results"].as_array().unwrap().len(), 2);
+
+    // Обычный k в пределах max_k
+    let data = find_similar(State(state.clone()), Json(params(Some(1)))).await.1.0.data.unwrap();
+    assert_eq!(data["k"], 1);
+    assert_eq!(data["k_clamped"], false);
+    assert_eq!(data["results"].as_array().unwrap().len(), 1);
+
+    // Слишком большой k урезается до server.max_k
+    let data = find_similar(State(state), Json(params(Some(1_000_000)))).await.1.0.data.unwrap();
+    assert_eq!(data["k"], 3);
+    assert_eq!(data["k_clamped"], true);
+    assert_eq!(data["results"].as_array().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_query_bucket_matches_insertion_bucket() {
+    use crate::core::handlers::query_bucket;
+    use crate::core::openapi::QueryBucketParams;
+
+    let mut collection_controller = collection_controller_with("query_bucket_collection", 3);
+    collection_controller
+        .add_vector("query_bucket_collection", vec![0.5, -1.5, 2.0], HashMap::new())
+        .unwrap();
+    // В коллекции один вектор, значит и бакет ровно один
+    let inserted_bucket = collection_controller.bucket_summaries("query_bucket_collection", None).unwrap()[0].0;
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params: QueryBucketParams = serde_json::from_value(serde_json::json!({
+        "name": "query_bucket_collection",
+        "query": [0.5, -1.5, 2.0]
+    })).unwrap();
+    let data = query_bucket(State(state), Json(params)).await.1.0.data.unwrap();
+    assert_eq!(data["bucket_id"], inserted_bucket);
+    assert_eq!(data["bucket_exists"], true);
+    assert_eq!(data["bucket_size"], 1);
+}
+
+#[tokio::test]
+async fn test_vector_exists_without_payload() {
+    use crate::core::handlers::vector_exists;
+    use crate::core::openapi::VectorExistsParams;
+
+    let mut collection_controller = collection_controller_with("exists_collection", 3);
+    let id = collection_controller
+        .add_vector("exists_collection", vec![1.0, 2.0, 3.0], metadata_with_category("exists"))
+        .unwrap();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params = |id: u64| -> VectorExistsParams {
+        serde_json::from_value(serde_json::json!({"collection": "exists_collection", "id": id})).unwrap()
+    };
+
+    let data = vector_exists(State(state.clone()), Json(params(id))).await.0.data.unwrap();
+    assert_eq!(data, serde_json::json!({"exists": true}));
+
+    let data = vector_exists(State(state), Json(params(id.wrapping_add(1)))).await.0.data.unwrap();
+    assert_eq!(data, serde_json::json!({"exists": false}));
+}
+
+#[tokio::test]
+async fn test_typed_metadata_insert_retrieve_and_range_filter() {
+    use crate::core::handlers::{add_vector, filter_by_metadata, get_vector};
+    use crate::core::interfaces::Object;
+    use crate::core::objects::Vector;
+    use crate::core::openapi::{AddVectorParams, FilterByMetadataParams, GetVectorParams};
+    use crate::core::utils::StorageFormat;
+
+    let state = app_state_for(collection_controller_with("typed_collection", 3), HashMap::new());
+    let mut ids = Vec::new();
+    for (i, metadata) in [
+        serde_json::json!({"year": 2019, "price": 9.5, "premium": true, "title": "old"}),
+        serde_json::json!({"year": 2021, "price": 19.9, "premium": false, "title": "new"}),
+        serde_json::json!({"year": "2023", "title": "legacy string"}),
+    ].into_iter().enumerate() {
+        let params: AddVectorParams = serde_json::from_value(serde_json::json!({
+            "collection": "typed_collection",
+            "embedding": [1.0, i as f32, 0.0],
+            "metadata": metadata
+        })).unwrap();
+        let data = add_vector(State(state.clone()), Json(params)).await.1.0.data.unwrap();
+        ids.push(data["id"].as_u64().unwrap());
+    }
+
+    // Типы сохраняются: число остаётся числом, bool — bool, строка — строкой
+    let params: GetVectorParams = serde_json::from_value(serde_json::json!({
+        "collection": "typed_collection",
+        "vector_id": ids[0]
+    })).unwrap();
+    let response = get_vector(State(state.clone()), axum::http::HeaderMap::new(), Json(params)).await;
+    let data = response_json(response).await["data"].clone();
+    assert_eq!(data["metadata"]["year"], 2019);
+    assert_eq!(data["metadata"]["price"], 9.5);
+    assert_eq!(data["metadata"]["premium"], true);
+    assert_eq!(data["metadata"]["title"], "old");
+
+    // Диапазон по числам; строковое "2023" из старых данных тоже разбирается как число
+    let filter = |body: serde_json::Value| -> FilterByMetadataParams { serde_json::from_value(body).unwrap() };
+    let data = filter_by_metadata(State(state.clone()), Json(filter(serde_json::json!({
+        "collection": "typed_collection",
+        "expression": {"range": {"key": "year", "min": 2020}}
+    })))).await.1.0.data.unwrap();
+    let mut found: Vec<u64> = serde_json::from_value(data["vector_ids"].clone()).unwrap();
+    found.sort();
+    let mut expected = vec![ids[1], ids[2]];
+    expected.sort();
+    assert_eq!(found, expected);
+
+    // Равенство с приведением типов: строковый фильтр находит числовое значение
+    let data = filter_by_metadata(State(state), Json(filter(serde_json::json!({
+        "collection": "typed_collection",
+        "filters": {"year": "2019", "premium": true}
+    })))).await.1.0.data.unwrap();
+    assert_eq!(data["vector_ids"], serde_json::json!([ids[0]]));
+
+    // Типизированные значения переживают сохранение на диск
+    let mut metadata = metadata_with_category("typed");
+    metadata.insert("count".to_string(), MetadataValue::Int(3));
+    let original = Vector::new(Some(vec![1.0, 2.0]), Some(1), Some(metadata.clone()));
+    let (raw, _) = original.dump(StorageFormat::Bincode).unwrap();
+    let mut restored = Vector::new(None, None, None);
+    restored.load(raw, StorageFormat::Bincode).unwrap();
+    assert_eq!(restored.metadata, metadata);
+
+    // Миграция: файлы со строковыми метаданными (data, timestamp, metadata, hash_id, norm) читаются как MetadataValue::Str
+    let legacy_metadata: HashMap<String, String> = [("count".to_string(), "3".to_string())].into_iter().collect();
+    let legacy = (vec![1.0f32, 2.0], 1i64, legacy_metadata, 42u64, None::<f32>);
+    let mut migrated = Vector::new(None, None, None);
+    migrated.load(StorageFormat::Bincode.encode(&legacy).unwrap(), StorageFormat::Bincode).unwrap();
+    assert_eq!(migrated.metadata.get("count"), Some(&MetadataValue::from("3")));
+    assert_eq!(migrated.hash_id(), 42);
+}
+
+#[tokio::test]
+async fn test_get_bucket_lists_inserted_ids() {
+    use crate::core::handlers::get_bucket;
+    use crate::core::openapi::GetBucketParams;
+    use axum::http::StatusCode;
+
+    let mut collection_controller = collection_controller_with("bucket_listing_collection", 3);
+    let mut inserted = Vec::new();
+    for i in 0..4 {
+        inserted.push(collection_controller
+            .add_vector("bucket_listing_collection", vec![1.0, 1.0 + i as f32 * 0.01, 1.0], HashMap::new())
+            .unwrap());
+    }
+    // Ожидаемое содержимое каждого бакета берём из сводки и поиска по ID
+    let summaries = collection_controller.bucket_summaries("bucket_listing_collection", None).unwrap();
+    let (bucket_id, size, _, _) = summaries[0];
+    let collection = collection_controller.get_collection("bucket_listing_collection").unwrap();
+    let bucket = collection.buckets_controller.get_bucket(bucket_id).unwrap();
+    let mut expected: Vec<u64> = inserted.iter().copied().filter(|id| bucket.contains_vector(*id)).collect();
+    expected.sort();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params = |bucket_id: u64| -> GetBucketParams {
+        serde_json::from_value(serde_json::json!({"name": "bucket_listing_collection", "bucket_id": bucket_id})).unwrap()
+    };
+
+    let (status, response) = get_bucket(State(state.clone()), Json(params(bucket_id))).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+    let mut ids: Vec<u64> = serde_json::from_value(data["vector_ids"].clone()).unwrap();
+    ids.sort();
+    assert_eq!(ids, expected);
+    assert_eq!(data["size"], size);
+
+    let (status, _) = get_bucket(State(state), Json(params(bucket_id.wrapping_add(1)))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_in_memory_backend_dump_load_cycle() {
+    let root = std::env::temp_dir().join(format!("vecdb_memory_backend_{}", std::process::id()));
+    let configs = HashMap::from([
+        ("backend".to_string(), "memory".to_string()),
+        ("path".to_string(), root.to_string_lossy().to_string()),
+    ]);
+    let storage_controller = Arc::new(StorageController::new(configs).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("memory_collection".to_string(), LSHMetric::Euclidean, 3).unwrap();
+
+    let mut metadata = metadata_with_category("memory");
+    metadata.insert("rank".to_string(), MetadataValue::Int(7));
+    let ids: Vec<u64> = (0..5)
+        .map(|i| collection_controller.add_vector("memory_collection", vec![i as f32, 1.0, -1.0], metadata.clone()).unwrap())
+        .collect();
+    collection_controller.dump();
+
+    // Повторная загрузка из того же хранилища в памяти
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load();
+    let collection = reloaded.get_collection("memory_collection").expect("Коллекция должна загрузиться");
+    assert_eq!(collection.buckets_controller.total_vectors(), ids.len());
+    for id in &ids {
+        let vector = reloaded.get_vector("memory_collection", *id).unwrap();
+        assert_eq!(vector.metadata, metadata);
+    }
+    let results = reloaded.find_similar("memory_collection".to_string(), &vec![0.0, 1.0, -1.0], 1).unwrap();
+    assert_eq!(results.len(), 1);
+
+    // На диск ничего не записано
+    assert!(!root.exists());
+}
+
+#[test]
+fn test_disk_usage_grows_and_matches_file_sizes() {
+    fn walk(path: &std::path::Path) -> u64 {
+        std::fs::read_dir(path).unwrap()
+            .flatten()
+            .map(|entry| if entry.path().is_dir() { walk(&entry.path()) } else { entry.metadata().unwrap().len() })
+            .sum()
+    }
+
+    let root = std::env::temp_dir().join(format!("vecdb_disk_usage_{}", std::process::id()));
+    let configs = HashMap::from([("path".to_string(), root.to_string_lossy().to_string())]);
+    let storage_controller = Arc::new(StorageController::new(configs).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("usage_collection".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    collection_controller.add_vector("usage_collection", vec![1.0, 0.0, 0.0], metadata_with_category("a")).unwrap();
+    collection_controller.dump();
+    let (total_before, usage_before) = collection_controller.disk_usage();
+
+    for i in 0..10 {
+        collection_controller.add_vector("usage_collection", vec![i as f32, 1.0, -1.0], metadata_with_category("b")).unwrap();
+    }
+    collection_controller.dump();
+    let (total_after, usage_after) = collection_controller.disk_usage();
+
+    let before = &usage_before["usage_collection"];
+    let after = &usage_after["usage_collection"];
+    assert!(total_after > total_before);
+    assert!(after.vectors > before.vectors);
+    assert!(after.collection_file > 0 && after.buckets > 0);
+    // Сумма по коллекции совпадает с реальными размерами файлов в её папке
+    assert_eq!(after.total(), walk(&root.join("storage").join("usage_collection")));
+    assert_eq!(total_after, walk(&root.join("storage")));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_find_similar_excludes_ids_before_truncation() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+
+    let mut collection_controller = collection_controller_with("exclude_collection", 3);
+    let ids: Vec<u64> = (0..6)
+        .map(|i| collection_controller
+            .add_vector("exclude_collection", vec![1.0, i as f32 * 0.1, 0.0], HashMap::new())
+            .unwrap())
+        .collect();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params = |exclude_ids: Vec<u64>| -> FindSimilarParams {
+        serde_json::from_value(serde_json::json!({
+            "collection": "exclude_collection",
+            "query": [1.0, 0.0, 0.0],
+            "k": 3,
+            "exclude_ids": exclude_ids
+        })).unwrap()
+    };
+
+    // Без исключений первым находится сам вектор
+    let (_, response) = find_similar(State(state.clone()), Json(params(vec![]))).await;
+    let results = response.0.data.unwrap()["results"].clone();
+    assert_eq!(results[0]["vector_id"], ids[0]);
+
+    let (status, response) = find_similar(State(state), Json(params(vec![ids[0]]))).await;
+    assert_eq!(status, StatusCode::OK);
+    let results = response.0.data.unwrap()["results"].as_array().unwrap().clone();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|r| r["vector_id"] != ids[0]));
+}
+
+#[test]
+fn test_exact_distances_and_metric_scores() {
+    use crate::core::embeddings::{euclidean_distance, manhattan_distance, similarity_score, find_most_similar};
+    use crate::core::objects::Vector;
+
+    let a = [1.0, 2.0, 3.0];
+    let b = [4.0, 6.0, 3.0];
+    let zero = [0.0, 0.0, 0.0];
+    assert_eq!(euclidean_distance(&a, &b), 5.0);
+    assert_eq!(manhattan_distance(&a, &b), 7.0);
+    assert_eq!(euclidean_distance(&a, &a), 0.0);
+    assert_eq!(manhattan_distance(&a, &a), 0.0);
+    assert_eq!(euclidean_distance(&zero, &zero), 0.0);
+    assert_eq!(euclidean_distance(&zero, &[3.0, 4.0, 0.0]), 5.0);
+    assert_eq!(manhattan_distance(&zero, &[-3.0, 4.0, 1.0]), 8.0);
+
+    // Расстояние переводится в score «чем больше, тем лучше»: совпадающие векторы дают 1
+    assert_eq!(similarity_score(&LSHMetric::Euclidean, &a, 0.0, &a, 0.0), 1.0);
+    assert_eq!(similarity_score(&LSHMetric::Euclidean, &a, 0.0, &b, 0.0), 1.0 / 6.0);
+    assert_eq!(similarity_score(&LSHMetric::Manhattan, &a, 0.0, &b, 0.0), 1.0 / 8.0);
+    assert_eq!(similarity_score(&LSHMetric::Manhattan, &zero, 0.0, &zero, 0.0), 1.0);
+
+    // Косинус считает [2, 0, 0] и [1, 0, 0] одинаково похожими, евклидова метрика — нет
+    let vectors: Vec<Vector> = [vec![2.0, 0.0, 0.0], vec![1.0, 0.1, 0.0]]
+        .into_iter()
+        .map(|data| Vector::new(Some(data), Some(1), None))
+        .collect();
+    let query = vec![1.0, 0.0, 0.0];
+    assert_eq!(find_most_similar(&query, &vectors, 1, &LSHMetric::Cosine).unwrap()[0].0, 0);
+    assert_eq!(find_most_similar(&query, &vectors, 1, &LSHMetric::Euclidean).unwrap()[0].0, 1);
+    assert_eq!(find_most_similar(&query, &vectors, 1, &LSHMetric::Manhattan).unwrap()[0].0, 1);
+}
+
+#[test]
+fn test_lsh_projection_value_is_not_a_distance() {
+    let lsh = LSH::new(3, 2, 1.0, LSHMetric::Euclidean, Some(42));
+    let vector = [1.0, 2.0, 3.0];
+
+    // Для совпадающих векторов проекция равна квадрату нормы, а не нулю, как было бы у расстояния
+    assert_eq!(lsh.projection_value(&vector, &vector), 14.0);
+    assert_eq!(lsh.projection_value(&vector, &[0.0, 0.0, 0.0]), 0.0);
+    assert_eq!(lsh.projected(&vector, &lsh.projections[0]), lsh.projection_value(&vector, &lsh.projections[0]));
+}
+
+#[test]
+fn test_gzip_compression_round_trip_and_mixed_directory() {
+    let root = std::env::temp_dir().join(format!("vecdb_gzip_{}", std::process::id()));
+    let storage_for = |path: &std::path::Path, compression: &str| {
+        Arc::new(StorageController::new(HashMap::from([
+            ("path".to_string(), path.to_string_lossy().to_string()),
+            ("compression".to_string(), compression.to_string()),
+        ])).unwrap())
+    };
+    let fill = |collection_controller: &mut CollectionController, from: usize, to: usize| -> Vec<u64> {
+        (from..to)
+            .map(|i| {
+                let mut data = vec![0.0; 64];
+                data[i % 64] = 1.0;
+                collection_controller.add_vector("gzip_collection", data, metadata_with_category("repeated category")).unwrap()
+            })
+            .collect()
+    };
+
+    // Одинаковые данные без сжатия и со сжатием
+    let mut sizes = Vec::new();
+    for compression in ["none", "gzip"] {
+        let mut collection_controller = CollectionController::new(storage_for(&root.join(compression), compression));
+        collection_controller.add_collection("gzip_collection".to_string(), LSHMetric::Cosine, 64).unwrap();
+        fill(&mut collection_controller, 0, 20);
+        collection_controller.dump();
+        sizes.push(collection_controller.disk_usage().1["gzip_collection"].vectors);
+    }
+    assert!(sizes[1] < sizes[0], "gzip {} >= none {}", sizes[1], sizes[0]);
+
+    // Смешанная папка: часть файлов записана без сжатия, часть — со сжатием
+    let mixed = root.join("none");
+    let mut collection_controller = CollectionController::new(storage_for(&mixed, "gzip"));
+    collection_controller.load();
+    let new_ids = fill(&mut collection_controller, 20, 30);
+    collection_controller.dump();
+
+    let mut reloaded = CollectionController::new(storage_for(&mixed, "none"));
+    reloaded.load();
+    let collection = reloaded.get_collection("gzip_collection").expect("Коллекция должна загрузиться");
+    assert_eq!(collection.buckets_controller.total_vectors(), 30);
+    for id in new_ids {
+        let vector = reloaded.get_vector("gzip_collection", id).unwrap();
+        assert_eq!(vector.metadata, metadata_with_category("repeated category"));
+        assert_eq!(vector.data.iter().sum::<f32>(), 1.0);
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_per_request_normalization_is_stored_with_vector() {
+    use crate::core::handlers::add_vector;
+    use crate::core::openapi::AddVectorParams;
+
+    let state = app_state_for(collection_controller_with_metric("normalize_collection", 2, LSHMetric::Cosine), HashMap::new());
+    let params = |embedding: Vec<f32>, normalize: Option<bool>| -> AddVectorParams {
+        serde_json::from_value(serde_json::json!({
+            "collection": "normalize_collection",
+            "embedding": embedding,
+            "normalize": normalize
+        })).unwrap()
+    };
+
+    let response = add_vector(State(state.clone()), Json(params(vec![3.0, 4.0], Some(true)))).await;
+    let normalized_id = response.1.0.data.unwrap()["id"].as_u64().unwrap();
+    let response = add_vector(State(state.clone()), Json(params(vec![6.0, 8.0], None))).await;
+    let raw_id = response.1.0.data.unwrap()["id"].as_u64().unwrap();
+    let response = add_vector(State(state.clone()), Json(params(vec![0.0, 0.0], Some(true)))).await;
+    assert_eq!(response.1.0.status, "error");
+
+    let mut ctrl = state.controller.write().await;
+    let normalized = ctrl.get_vector("normalize_collection", normalized_id).unwrap();
+    assert!(normalized.is_normalized());
+    assert!((normalized.norm() - 1.0).abs() < 1e-6);
+    assert!((normalized.data[0] - 0.6).abs() < 1e-6);
+    let raw = ctrl.get_vector("normalize_collection", raw_id).unwrap();
+    assert!(!raw.is_normalized());
+    assert_eq!(raw.norm(), 10.0);
+
+    // Флаг переживает сохранение и загрузку, а косинус не зависит от нормы
+    ctrl.dump();
+    ctrl.load();
+    assert!(ctrl.get_vector("normalize_collection", normalized_id).unwrap().is_normalized());
+    assert!(!ctrl.get_vector("normalize_collection", raw_id).unwrap().is_normalized());
+    let results = ctrl.find_similar("normalize_collection".to_string(), &vec![3.0, 4.0], 2).unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|(_, _, score)| (score - 1.0).abs() < 1e-6));
+}
+
+#[tokio::test]
+async fn test_get_all_collections_includes_disk_only_collections() {
+    use crate::core::handlers::get_all_collections;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let mut writer = CollectionController::new(Arc::clone(&storage_controller));
+    writer.add_collection("disk_only".to_string(), LSHMetric::Cosine, 4).unwrap();
+    writer.add_vector("disk_only", vec![1.0, 0.0, 0.0, 0.0], HashMap::new()).unwrap();
+    writer.dump();
+
+    // Новый контроллер ничего не загружал, но видит коллекцию в storage
+    let mut reader = CollectionController::new(Arc::clone(&storage_controller));
+    reader.add_collection("in_memory".to_string(), LSHMetric::Euclidean, 2).unwrap();
+    let listed = reader.get_all_collections_with_storage();
+    assert_eq!(listed.len(), 2);
+    let disk_only = listed.iter().find(|c| c.name == "disk_only").expect("Коллекция с диска должна быть в списке");
+    assert!(!disk_only.is_loaded());
+    assert_eq!(disk_only.vector_dimension, 4);
+    assert_eq!(disk_only.lsh_metric, LSHMetric::Cosine);
+    assert!(listed.iter().any(|c| c.name == "in_memory" && c.is_loaded()));
+    drop(listed);
+    assert!(!reader.collection_exists("disk_only"));
+
+    let state = app_state_for(reader, HashMap::new());
+    let (_, response) = get_all_collections(State(state), Json(serde_json::json!({}))).await;
+    let data = response.0.data.unwrap();
+    assert_eq!(data["total"], 2);
+    let info = data["collections"].as_array().unwrap().iter().find(|c| c["name"] == "disk_only").unwrap().clone();
+    assert_eq!(info["loaded"], false);
+    assert_eq!(info["vector_dimension"], 4);
+}
+
+#[tokio::test]
+async fn test_update_collection_metric_rebuckets_and_persists() {
+    use crate::core::handlers::update_collection;
+    use crate::core::interfaces::Object;
+    use crate::core::openapi::UpdateCollectionParams;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("metric_collection".to_string(), LSHMetric::Euclidean, 2).unwrap();
+    let small_id = collection_controller.add_vector("metric_collection", vec![1.0, 0.1], metadata_with_category("small")).unwrap();
+    let far_id = collection_controller.add_vector("metric_collection", vec![5.0, 3.0], metadata_with_category("far")).unwrap();
+    collection_controller.dump();
+
+    // По евклидовой метрике ближе вектор [5, 3], по косинусу — совпадающий по направлению [1, 0.1]
+    let query = vec![10.0, 1.0];
+    let nearest = |ctrl: &CollectionController| {
+        let (bucket_id, index, _) = ctrl.find_similar("metric_collection".to_string(), &query, 2).unwrap()[0];
+        let collection = ctrl.get_collection("metric_collection").unwrap();
+        collection.buckets_controller.get_bucket(bucket_id).unwrap().vectors_controller.get_vector(index).unwrap().hash_id()
+    };
+    assert_eq!(nearest(&collection_controller), far_id);
+
+    let state = app_state_for(collection_controller, HashMap::new());
+    let params = |metric: &str| -> UpdateCollectionParams {
+        serde_json::from_value(serde_json::json!({"name": "metric_collection", "metric": metric})).unwrap()
+    };
+    let response = update_collection(State(state.clone()), Json(params("Hamming"))).await;
+    assert_eq!(response.1.0.status, "error");
+    let response = update_collection(State(state.clone()), Json(params("Cosine"))).await;
+    assert_eq!(response.1.0.data.unwrap()["reindexed_vectors"], 2);
+
+    let ctrl = state.controller.read().await;
+    assert_eq!(nearest(&ctrl), small_id);
+    assert_eq!(ctrl.get_vector("metric_collection", far_id).unwrap().metadata, metadata_with_category("far"));
+    ctrl.dump();
+
+    // После перезагрузки метрика сохранена, а старые бакеты не дают дубликатов
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load();
+    let collection = reloaded.get_collection("metric_collection").unwrap();
+    assert_eq!(collection.lsh_metric, LSHMetric::Cosine);
+    assert_eq!(collection.buckets_controller.total_vectors(), 2);
+    assert_eq!(nearest(&reloaded), small_id);
+
+    // Удалённый в памяти вектор удаляется и из storage при сохранении
+    reloaded.delete_vector("metric_collection", far_id).unwrap();
+    reloaded.dump();
+    let mut reloaded_again = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded_again.load();
+    assert_eq!(reloaded_again.get_collection("metric_collection").unwrap().buckets_controller.total_vectors(), 1);
+}
+
+#[tokio::test]
+async fn test_load_async_reads_collections_concurrently_in_name_order() {
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([
+        ("backend".to_string(), "memory".to_string()),
+        ("load_concurrency".to_string(), "2".to_string()),
+    ])).unwrap());
+    assert_eq!(storage_controller.load_concurrency, 2);
+
+    let names = ["delta", "alpha", "echo", "charlie", "bravo"];
+    let mut writer = CollectionController::new(Arc::clone(&storage_controller));
+    for (i, name) in names.iter().enumerate() {
+        writer.add_collection(name.to_string(), LSHMetric::Euclidean, 3).unwrap();
+        for j in 0..=i {
+            writer.add_vector(name, vec![j as f32, 1.0, i as f32], HashMap::new()).unwrap();
+        }
+    }
+    writer.dump();
+
+    let controller = Arc::new(tokio::sync::RwLock::new(CollectionController::new(Arc::clone(&storage_controller))));
+    assert_eq!(CollectionController::load_async(&controller).await.unwrap(), names.len());
+
+    let ctrl = controller.read().await;
+    let loaded: Vec<&str> = ctrl.get_all_collections().iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(loaded, vec!["alpha", "bravo", "charlie", "delta", "echo"]);
+    for (i, name) in names.iter().enumerate() {
+        assert_eq!(ctrl.get_collection(name).unwrap().buckets_controller.total_vectors(), i + 1);
+    }
+    drop(ctrl);
+
+    // Повреждённая коллекция пропускается, остальные загружаются (и асинхронно, и через load)
+    let bucket_id = writer.get_collection("charlie").unwrap().buckets_controller.buckets.as_ref().unwrap()[0].id;
+    storage_controller.save_bucket("charlie".to_string(), bucket_id.to_string(), vec![1, 2, 3]).unwrap();
+    let controller = Arc::new(tokio::sync::RwLock::new(CollectionController::new(Arc::clone(&storage_controller))));
+    assert_eq!(CollectionController::load_async(&controller).await.unwrap(), names.len() - 1);
+    assert!(!controller.read().await.collection_exists("charlie"));
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load();
+    let loaded: Vec<&str> = reloaded.get_all_collections().iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(loaded, vec!["alpha", "bravo", "delta", "echo"]);
+}
+
+#[tokio::test]
+async fn test_flush_persists_state_without_stopping() {
+    use crate::core::controllers::ConnectionController;
+    use crate::core::handlers::flush;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("flush_collection".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    let id = collection_controller.add_vector("flush_collection", vec![1.0, 2.0, 3.0], metadata_with_category("flushed")).unwrap();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let (status, response) = flush(State(state.clone())).await;
+    assert_eq!(status, axum::http::StatusCode::OK);
+    assert_eq!(response.0.data.unwrap()["collections"], 1);
+
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load();
+    assert_eq!(reloaded.get_vector("flush_collection", id).unwrap().metadata, metadata_with_category("flushed"));
+
+    // Фоновое сохранение подхватывает изменения, сделанные после ручного flush
+    let late_id = state.controller.write().await
+        .add_vector("flush_collection", vec![3.0, 2.0, 1.0], HashMap::new())
+        .unwrap();
+    let task = ConnectionController::spawn_auto_flush(Arc::clone(&state.controller), std::time::Duration::from_millis(20));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    task.abort();
+
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load();
+    assert!(reloaded.get_vector("flush_collection", late_id).is_ok());
+}
+
+#[tokio::test]
+async fn test_vector_raw_returns_stored_bytes() {
+    use axum::http::StatusCode;
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use crate::core::handlers::vector_raw;
+    use crate::core::interfaces::Object;
+    use crate::core::openapi::VectorRawParams;
+    use crate::core::utils::StorageFormat;
+
+    let mut collection_controller = collection_controller_with("raw_collection", 3);
+    let id = collection_controller.add_vector("raw_collection", vec![0.5, -1.0, 2.0], metadata_with_category("raw")).unwrap();
+    collection_controller.dump();
+    let (bucket_id, expected) = {
+        let collection = collection_controller.get_collection("raw_collection").unwrap();
+        let bucket_id = collection.buckets_controller.buckets.as_ref().unwrap()
+            .iter()
+            .find(|b| b.contains_vector(id))
+            .unwrap()
+            .id;
+        let vector = collection.buckets_controller.get_vector(id).unwrap();
+        (bucket_id, vector.dump(StorageFormat::Bincode).unwrap().0)
+    };
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params = |id: u64| -> VectorRawParams {
+        serde_json::from_value(serde_json::json!({"collection": "raw_collection", "bucket_id": bucket_id, "id": id})).unwrap()
+    };
+    let (status, response) = vector_raw(State(state.clone()), Json(params(id))).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+    let raw = BASE64_STANDARD.decode(data["raw_base64"].as_str().unwrap()).unwrap();
+    assert_eq!(raw, expected);
+    assert_eq!(data["length"], expected.len());
+
+    let (status, _) = vector_raw(State(state), Json(params(id.wrapping_add(1)))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_merge_small_buckets_reduces_fallback() {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    let k = 3;
+    let mut rng = StdRng::seed_from_u64(637);
+    let mut random_vector = || (0..4).map(|_| rng.gen_range(-20.0..20.0)).collect::<Vec<f32>>();
+    let vectors: Vec<Vec<f32>> = (0..200).map(|_| random_vector()).collect();
+    let queries: Vec<Vec<f32>> = (0..200).map(|_| random_vector()).collect();
+
+    // Количество запросов, для которых бакет запроса меньше k и поиск уходит в обход всех бакетов
+    let fallbacks = |threshold: Option<usize>| {
+        let mut collection_controller = collection_controller_with("merge_collection", 4);
+        collection_controller.set_merge_small_buckets(threshold);
+        for vector in &vectors {
+            collection_controller.add_vector("merge_collection", vector.clone(), Metadata::new()).unwrap();
+        }
+        let total = collection_controller.get_collection("merge_collection").unwrap().buckets_controller.total_vectors();
+        assert_eq!(total, vectors.len());
+        queries.iter()
+            .filter(|query| collection_controller.query_bucket("merge_collection", query).unwrap().2 < k)
+            .count()
+    };
+
+    let without_merging = fallbacks(None);
+    let with_merging = fallbacks(Some(k + 1));
+    assert!(with_merging < without_merging, "слияние: {}, без слияния: {}", with_merging, without_merging);
+    assert_eq!(fallbacks(Some(0)), without_merging);
+}
+
+#[tokio::test]
+async fn test_add_vectors_batch_reports_each_item() {
+    use crate::core::handlers::add_vectors_batch;
+    use crate::core::openapi::AddVectorsBatchParams;
+    use axum::http::StatusCode;
+
+    let state = app_state_for(collection_controller_with("batch_insert", 3), HashMap::new());
+    let params = |collection: &str| -> AddVectorsBatchParams {
+        serde_json::from_value(serde_json::json!({
+            "collection": collection,
+            "vectors": [
+                {"embedding": [1.0, 0.0, 0.0]},
+                {"embedding": [0.0, 1.0]},
+                {"embedding": [0.0, 0.0, 1.0], "id": 7, "metadata": {"category": "seven"}},
+                {"embedding": [0.0, 1.0, 0.0], "id": 7}
+            ]
+        })).unwrap()
+    };
+
+    let (status, response) = add_vectors_batch(State(state.clone()), Json(params("batch_insert"))).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+    assert_eq!(data["inserted"], 2);
+    assert_eq!(data["failed"], 2);
+    let results = data["results"].as_array().unwrap();
+    assert_eq!(results.len(), 4);
+    for (index, result) in results.iter().enumerate() {
+        assert_eq!(result["index"], index);
+    }
+    assert_eq!(results[0]["status"], "ok");
+    assert_eq!(results[1]["status"], "error");
+    assert!(results[1]["error"].as_str().unwrap().contains("Размерность"));
+    assert!(results[1].get("id").is_none());
+    assert_eq!(results[2]["id"], 7);
+    assert_eq!(results[3]["status"], "error");
+
+    let ctrl = state.controller.read().await;
+    let first_id = results[0]["id"].as_u64().unwrap();
+    assert!(ctrl.get_vector("batch_insert", first_id).is_ok());
+    assert_eq!(ctrl.get_vector("batch_insert", 7).unwrap().data, vec![0.0, 0.0, 1.0]);
+    assert_eq!(ctrl.get_collection("batch_insert").unwrap().buckets_controller.total_vectors(), 2);
+    drop(ctrl);
+
+    // normalize пакета применяется к каждому вектору, normalize вектора его переопределяет
+    let normalized: AddVectorsBatchParams = serde_json::from_value(serde_json::json!({
+        "collection": "batch_insert",
+        "normalize": true,
+        "vectors": [
+            {"embedding": [3.0, 4.0, 0.0], "id": 11},
+            {"embedding": [0.0, 3.0, 4.0], "id": 12, "normalize": false},
+            {"embedding": [0.0, 0.0, 0.0], "id": 13}
+        ]
+    })).unwrap();
+    let data = add_vectors_batch(State(state.clone()), Json(normalized)).await.1.0.data.unwrap();
+    assert_eq!(data["inserted"], 2);
+    assert!(data["results"][2]["error"].as_str().unwrap().contains("Нулевой вектор"));
+    let ctrl = state.controller.read().await;
+    let scaled = ctrl.get_vector("batch_insert", 11).unwrap();
+    assert_eq!((scaled.data.clone(), scaled.is_normalized()), (vec![0.6, 0.8, 0.0], true));
+    let raw = ctrl.get_vector("batch_insert", 12).unwrap();
+    assert_eq!((raw.data.clone(), raw.is_normalized()), (vec![0.0, 3.0, 4.0], false));
+    drop(ctrl);
+
+    let (status, _) = add_vectors_batch(State(state), Json(params("missing"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_storage_controller_reports_unwritable_path() {
+    // Вместо папки по пути лежит файл: storage нельзя создать, но процесс не должен паниковать
+    let root = std::env::temp_dir().join(format!("vecdb_unwritable_{}", std::process::id()));
+    std::fs::write(&root, b"not a directory").unwrap();
+    let configs = HashMap::from([("path".to_string(), root.to_string_lossy().to_string())]);
+
+    let result = std::panic::catch_unwind(|| StorageController::new(configs));
+    std::fs::remove_file(&root).unwrap();
+    let error = result.expect("StorageController::new не должен паниковать").err().expect("ожидалась ошибка");
+    assert!(error.to_string().contains("недоступна для записи"));
+}
+
+#[tokio::test]
+async fn test_add_collection_checks_embedding_model_dimension() {
+    use crate::core::handlers::add_collection;
+    use crate::core::openapi::AddCollectionParams;
+
+    let state = app_state_for(collection_controller_with("existing", 3), HashMap::new());
+    let params = |name: &str, dimension: usize, model: &str| -> AddCollectionParams {
+        serde_json::from_value(serde_json::json!({
+            "name": name, "metric": "Cosine", "dimension": dimension, "embedding_model": model
+        })).unwrap()
+    };
+
+    let response = add_collection(State(state.clone()), Json(params("mismatched", 128, "AllMiniLML6V2"))).await;
+    assert_eq!(response.1.0.status, "error");
+    let message = response.1.0.message.unwrap();
+    assert!(message.contains("128") && message.contains("AllMiniLML6V2") && message.contains("384"), "{}", message);
+
+    let response = add_collection(State(state.clone()), Json(params("unknown_model", 384, "NoSuchModel"))).await;
+    assert_eq!(response.1.0.status, "error");
+    assert!(response.1.0.message.unwrap().contains("NoSuchModel"));
+
+    let response = add_collection(State(state.clone()), Json(params("matching", 384, "AllMiniLML6V2"))).await;
+    assert_eq!(response.1.0.status, "ok");
+
+    let ctrl = state.controller.read().await;
+    assert!(ctrl.collection_exists("matching"));
+    assert!(!ctrl.collection_exists("mismatched"));
+    assert!(!ctrl.collection_exists("unknown_model"));
+}
+
+#[test]
+fn test_rerank_matches_brute_force_better_than_raw_lsh() {
+    use std::collections::HashSet;
+    use crate::core::controllers::{SearchOptions, DEFAULT_RERANK_CANDIDATE_FACTOR};
+    use crate::core::embeddings::{l2_norm, similarity_score};
+    use crate::core::interfaces::Object;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    let k = 5;
+    let mut rng = StdRng::seed_from_u64(643);
+    let mut collection_controller = collection_controller_with("rerank_collection", 4);
+    let mut dataset: Vec<(u64, Vec<f32>)> = Vec::new();
+    for _ in 0..400 {
+        let data: Vec<f32> = (0..4).map(|_| rng.gen_range(-15.0..15.0)).collect();
+        let id = collection_controller.add_vector("rerank_collection", data.clone(), Metadata::new()).unwrap();
+        dataset.push((id, data));
+    }
+    let queries: Vec<Vec<f32>> = (0..60).map(|_| (0..4).map(|_| rng.gen_range(-15.0..15.0)).collect()).collect();
+
+    let buckets_controller = &collection_controller.get_collection("rerank_collection").unwrap().buckets_controller;
+    let recall = |options: &SearchOptions| -> usize {
+        queries.iter().map(|query| {
+            let query_norm = l2_norm(query);
+            let mut exact: Vec<(u64, f32)> = dataset.iter()
+                .map(|(id, data)| (*id, similarity_score(&LSHMetric::Euclidean, query, query_norm, data, l2_norm(data))))
+                .collect();
+            exact.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            let expected: HashSet<u64> = exact.iter().take(k).map(|(id, _)| *id).collect();
+
+            let outcome = collection_controller.find_similar_with_options("rerank_collection".to_string(), query, k, options).unwrap();
+            assert_eq!(outcome.results.len(), k);
+            outcome.results.iter()
+                .filter(|(bucket_id, index, _)| {
+                    let vector = buckets_controller.get_bucket(*bucket_id).unwrap().vectors_controller.get_vector(*index).unwrap();
+                    expected.contains(&vector.hash_id())
+                })
+                .count()
+        }).sum()
+    };
+
+    let raw = recall(&SearchOptions::default());
+    let reranked = recall(&SearchOptions { rerank: Some(DEFAULT_RERANK_CANDIDATE_FACTOR), ..Default::default() });
+    assert!(reranked > raw, "с переранжированием: {}, без: {}", reranked, raw);
+}
+
+#[test]
+fn test_empty_bucket_writes_no_files_on_dump() {
+    use crate::core::objects::Bucket;
+
+    // None и Some(пустой список) ведут себя одинаково
+    let mut never_filled = VectorController::new();
+    let mut emptied = VectorController::new();
+    let id = emptied.add_vector(Some(vec![1.0, 2.0, 3.0]), Some(Metadata::new()), None, None).unwrap();
+    emptied.remove_vector(id).unwrap();
+    assert!(never_filled.is_empty() && emptied.is_empty());
+    assert_eq!(never_filled.remove_vector(id), emptied.remove_vector(id));
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("empty_buckets".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    let id = collection_controller.add_vector("empty_buckets", vec![1.0, 2.0, 3.0], Metadata::new()).unwrap();
+    let buckets_controller = &mut collection_controller.get_collection_mut("empty_buckets").unwrap().buckets_controller;
+    let live_bucket = buckets_controller.buckets.as_ref().unwrap()[0].id;
+    let empty_bucket = live_bucket.wrapping_add(1);
+    buckets_controller.buckets.as_mut().unwrap().push(Bucket::new(empty_bucket));
+
+    collection_controller.dump();
+    assert_eq!(storage_controller.get_all_buckets_names("empty_buckets".to_string()).unwrap(), vec![live_bucket.to_string()]);
+    assert!(storage_controller.read_bucket("empty_buckets".to_string(), empty_bucket.to_string()).unwrap().is_none());
+
+    let mut reloaded = CollectionController::new(storage_controller);
+    reloaded.load();
+    assert!(reloaded.get_vector("empty_buckets", id).is_ok());
+    assert_eq!(reloaded.get_collection("empty_buckets").unwrap().buckets_controller.count(), 1);
+}
+
+#[tokio::test]
+async fn test_collection_default_metadata_applies_to_inserts() {
+    use crate::core::handlers::add_collection;
+    use crate::core::interfaces::Object;
+    use crate::core::openapi::AddCollectionParams;
+    use crate::core::utils::StorageFormat;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let state = app_state_for(CollectionController::new(Arc::clone(&storage_controller)), HashMap::new());
+    let params: AddCollectionParams = serde_json::from_value(serde_json::json!({
+        "name": "tenant_collection", "metric": "Euclidean", "dimension": 3,
+        "default_metadata": {"tenant": "acme", "tier": "free", "quota": 100}
+    })).unwrap();
+    assert_eq!(add_collection(State(state.clone()), Json(params)).await.1.0.status, "ok");
+
+    let mut ctrl = state.controller.write().await;
+    let plain = ctrl.add_vector("tenant_collection", vec![1.0, 0.0, 0.0], metadata_with_category("doc")).unwrap();
+    let mut overriding = metadata_with_category("doc");
+    overriding.insert("tier".to_string(), MetadataValue::from("premium"));
+    let overridden = ctrl.add_vector("tenant_collection", vec![0.0, 1.0, 0.0], overriding).unwrap();
+
+    let metadata = &ctrl.get_vector("tenant_collection", plain).unwrap().metadata;
+    assert_eq!(metadata.get("tenant"), Some(&MetadataValue::from("acme")));
+    assert_eq!(metadata.get("tier"), Some(&MetadataValue::from("free")));
+    assert_eq!(metadata.get("category"), Some(&MetadataValue::from("doc")));
+    let metadata = &ctrl.get_vector("tenant_collection", overridden).unwrap().metadata;
+    assert_eq!(metadata.get("tenant"), Some(&MetadataValue::from("acme")));
+    assert_eq!(metadata.get("tier"), Some(&MetadataValue::from("premium")));
+
+    // Метаданные по умолчанию сохраняются вместе с коллекцией
+    ctrl.dump();
+    let mut reloaded = CollectionController::new(storage_controller);
+    reloaded.load();
+    let collection = reloaded.get_collection("tenant_collection").unwrap();
+    assert_eq!(collection.default_metadata.get("tenant"), Some(&MetadataValue::from("acme")));
+    assert_eq!(collection.default_metadata.get("quota"), Some(&MetadataValue::Int(100)));
+
+    // Коллекция, записанная до появления поля (name, id, lsh_metric, vector_dimension, num_tables, lsh_seed),
+    // читается с пустыми метаданными по умолчанию
+    let legacy = ("legacy".to_string(), 1u64, "Euclidean".to_string(), 3usize, 1usize, 7u64);
+    let mut loaded = Collection::new(None, LSHMetric::Euclidean, 3);
+    loaded.load(StorageFormat::Bincode.encode(&legacy).unwrap(), StorageFormat::Bincode).unwrap();
+    assert_eq!(loaded.lsh_seed, 7);
+    assert!(loaded.default_metadata.is_empty());
+}
+
+#[tokio::test]
+async fn test_search_reports_buckets_probed_and_candidates() {
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+    use axum::http::StatusCode;
+
+    let mut collection_controller = collection_controller_with("probe_stats", 3);
+    for offset in [0.0, 0.01, 0.02, 0.03] {
+        collection_controller.add_vector("probe_stats", vec![1.0 + offset, 1.0, 1.0], Metadata::new()).unwrap();
+    }
+    for far in [100.0, -100.0, 250.0] {
+        collection_controller.add_vector("probe_stats", vec![far, far, -far], Metadata::new()).unwrap();
+    }
+    let buckets_controller = &collection_controller.get_collection("probe_stats").unwrap().buckets_controller;
+    let (query_bucket, _, query_bucket_size) = collection_controller.query_bucket("probe_stats", &[1.0, 1.0, 1.0]).unwrap();
+    assert!(query_bucket_size >= 4, "кластер должен лежать в одном бакете {}", query_bucket);
+    let total_buckets = buckets_controller.count();
+    let total_vectors = buckets_controller.total_vectors();
+    assert!(total_buckets > 1);
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params = |k: usize| -> FindSimilarParams {
+        serde_json::from_value(serde_json::json!({"collection": "probe_stats", "query": [1.0, 1.0, 1.0], "k": k})).unwrap()
+    };
+
+    // Бакета запроса хватает: просмотрен только он
+    let (status, response) = find_similar(State(state.clone()), Json(params(2))).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+    assert_eq!(data["buckets_probed"], 1);
+    assert_eq!(data["candidates_examined"], query_bucket_size);
+
+    // k больше бакета запроса: просматриваются все бакеты
+    let (_, response) = find_similar(State(state), Json(params(total_vectors))).await;
+    let data = response.0.data.unwrap();
+    assert_eq!(data["buckets_probed"], total_buckets);
+    assert_eq!(data["candidates_examined"], total_vectors);
+}
+
+#[test]
+fn test_metadata_contains_and_starts_with_filters() {
+    use crate::core::utils::MetadataFilter;
+
+    let mut controller = VectorController::new();
+    let mut add = |title: Option<&str>| {
+        let mut metadata = metadata_with_category("book");
+        if let Some(title) = title {
+            metadata.insert("title".to_string(), MetadataValue::from(title));
+        }
+        controller.add_vector(Some(embedding_for(title.unwrap_or("untitled"))), Some(metadata), None, None).unwrap()
+    };
+    let rust_book = add(Some("The Rust Programming Language"));
+    let rustacean = add(Some("rustacean handbook"));
+    let go_book = add(Some("Learning Go"));
+    let _untitled = add(None);
+
+    let filter = |json: serde_json::Value| -> Vec<u64> {
+        let expression: MetadataFilter = serde_json::from_value(json).unwrap();
+        let mut ids = controller.filter_by_expression(&expression);
+        ids.sort();
+        ids
+    };
+    let sorted = |mut ids: Vec<u64>| { ids.sort(); ids };
+
+    // По умолчанию сравнение чувствительно к регистру; вектор без ключа не подходит
+    assert_eq!(filter(serde_json::json!({"contains": {"key": "title", "value": "Rust"}})), vec![rust_book]);
+    assert_eq!(
+        filter(serde_json::json!({"contains": {"key": "title", "value": "rust", "case_insensitive": true}})),
+        sorted(vec![rust_book, rustacean])
+    );
+    assert_eq!(filter(serde_json::json!({"starts_with": {"key": "title", "value": "rust"}})), vec![rustacean]);
+    assert_eq!(filter(serde_json::json!({"starts_with": {"key": "title", "value": "the", "case_insensitive": true}})), vec![rust_book]);
+    assert_eq!(filter(serde_json::json!({"starts_with": {"key": "title", "value": "Learning"}})), vec![go_book]);
+    assert!(filter(serde_json::json!({"contains": {"key": "subtitle", "value": ""}})).is_empty());
+
+    // Комбинируется с другими условиями
+    assert_eq!(
+        filter(serde_json::json!({"and": [
+            {"contains": {"key": "title", "value": "rust", "case_insensitive": true}},
+            {"starts_with": {"key": "title", "value": "The"}}
+        ]})),
+        vec![rust_book]
+    );
+}
+
+#[tokio::test]
+async fn test_concurrency_limit_rejects_excess_and_drains_in_flight() {
+    use crate::core::controllers::ConnectionController;
+    use axum::routing::post;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Отправляет POST и возвращает код ответа из строки статуса
+    async fn post_status(addr: std::net::SocketAddr, path: &str) -> u16 {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!("POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+
+    let (release_tx, release_rx) = tokio::sync::watch::channel(false);
+    let slow = move || {
+        let mut release_rx = release_rx.clone();
+        async move {
+            release_rx.wait_for(|released| *released).await.unwrap();
+            "done"
+        }
+    };
+    let app = ConnectionController::with_concurrency_limit(axum::Router::new().route("/slow", post(slow)), 2);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move { shutdown_rx.await.ok(); })
+            .await
+            .unwrap();
+    });
+
+    // Два запроса занимают лимит и ждут
+    let in_flight: Vec<_> = (0..2).map(|_| tokio::spawn(post_status(addr, "/slow"))).collect();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Лишний запрос сразу получает 503
+    assert_eq!(post_status(addr, "/slow").await, 503);
+
+    // Остановка сервера дожидается уже принятых запросов
+    shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    release_tx.send(true).unwrap();
+    for request in in_flight {
+        assert_eq!(request.await.unwrap(), 200);
+    }
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_reindex_rebuilds_deleted_bucket_files() {
+    use crate::core::handlers::reindex;
+    use crate::core::openapi::ReindexParams;
+    use axum::http::StatusCode;
+
+    let root = std::env::temp_dir().join(format!("vecdb_reindex_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let configs: HashMap<String, String> =
+        [("path".to_string(), root.to_string_lossy().to_string())].into_iter().collect();
+    let storage_controller = Arc::new(StorageController::new(configs).unwrap());
+
+    let collection_name = "reindex_collection";
+    let mut writer = CollectionController::new(Arc::clone(&storage_controller));
+    writer.add_collection(collection_name.to_string(), LSHMetric::Euclidean, 4).unwrap();
+    for i in 0..20 {
+        let x = i as f32;
+        writer.add_vector(collection_name, vec![x, x * 0.5, -x, 1.0], HashMap::new()).unwrap();
+    }
+    writer.dump();
+
+    // Удаляем файлы бакетов: без них коллекция загружается пустой
+    for bucket_name in storage_controller.get_all_buckets_names(collection_name.to_string()).unwrap() {
+        std::fs::remove_file(root.join("storage").join(collection_name).join(&bucket_name).join("0.bin")).unwrap();
+    }
+    let mut broken = CollectionController::new(Arc::clone(&storage_controller));
+    broken.load_one(collection_name.to_string());
+    assert_eq!(broken.get_collection(collection_name).unwrap().buckets_controller.total_vectors(), 0);
+
+    let state = app_state_for(broken, HashMap::new());
+    let params: ReindexParams = serde_json::from_value(serde_json::json!({"name": collection_name})).unwrap();
+    let (status, response) = reindex(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.0.data.unwrap()["vectors"], 20);
+
+    // Поиск снова находит векторы, а бакеты восстановлены и на диске
+    let query = vec![3.0, 1.5, -3.0, 1.0];
+    let ctrl = state.controller.read().await;
+    let results = ctrl.find_similar(collection_name.to_string(), &query, 1).unwrap();
+    assert_eq!(results.len(), 1);
+    let found = ctrl.get_bucket(collection_name, results[0].0).unwrap().vectors_controller.get_vector(results[0].1).unwrap();
+    assert_eq!(found.data, query);
+    drop(ctrl);
+
+    let mut reloaded = CollectionController::new(Arc::clone(&storage_controller));
+    reloaded.load_one(collection_name.to_string());
+    assert_eq!(reloaded.get_collection(collection_name).unwrap().buckets_controller.total_vectors(), 20);
+
+    let params: ReindexParams = serde_json::from_value(serde_json::json!({"name": "missing"})).unwrap();
+    let (status, _) = reindex(State(state), Json(params)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_auto_tune_bucket_width_spreads_clustered_data() {
+    use crate::core::handlers::{add_collection, get_collection};
+    use crate::core::openapi::{AddCollectionParams, GetCollectionParams};
+    use crate::core::lsh::DEFAULT_BUCKET_WIDTH;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let state = app_state_for(CollectionController::new(Arc::clone(&storage_controller)), HashMap::new());
+    let params: AddCollectionParams = serde_json::from_value(serde_json::json!({
+        "name": "tuned", "metric": "Euclidean", "dimension": 4, "auto_tune": true, "auto_tune_sample": 100
+    })).unwrap();
+    assert_eq!(add_collection(State(state.clone()), Json(params)).await.1.0.status, "ok");
+
+    // Четыре плотных кластера в масштабе, много меньшем ширины бакета по умолчанию
+    let point = |i: usize| {
+        let mut point = vec![0.0f32; 4];
+        point[i % 4] = 0.3;
+        for (d, x) in point.iter_mut().enumerate() {
+            *x += ((i * 7 + d * 13) % 11) as f32 * 0.002;
+        }
+        point
+    };
+    let mut ctrl = state.controller.write().await;
+    for i in 0..99 {
+        ctrl.add_vector("tuned", point(i), Metadata::new()).unwrap();
+    }
+    let collection = ctrl.get_collection("tuned").unwrap();
+    assert_eq!(collection.bucket_width, DEFAULT_BUCKET_WIDTH);
+    assert_eq!(collection.buckets_controller.count(), 1);
+
+    // Сотый вектор запускает автонастройку: ширина подобрана по данным, векторы разложены заново
+    ctrl.add_vector("tuned", point(99), Metadata::new()).unwrap();
+    let collection = ctrl.get_collection("tuned").unwrap();
+    assert!(collection.bucket_width < DEFAULT_BUCKET_WIDTH / 10.0);
+    assert_eq!(collection.auto_tune_after, None);
+    assert_eq!(collection.buckets_controller.total_vectors(), 100);
+    let sizes: Vec<usize> = collection.buckets_controller.get_all_buckets().iter().map(|bucket| bucket.size()).collect();
+    assert!(sizes.len() >= 4);
+    assert!(sizes.iter().all(|size| *size < 50));
+    assert!(sizes.iter().filter(|size| **size >= 5).sum::<usize>() >= 50, "бакеты слишком мелкие: {:?}", sizes);
+    let tuned_width = collection.bucket_width;
+
+    // Подобранная ширина сохраняется вместе с коллекцией
+    ctrl.dump();
+    drop(ctrl);
+    let mut reloaded = CollectionController::new(storage_controller);
+    reloaded.load();
+    let collection = reloaded.get_collection("tuned").unwrap();
+    assert_eq!(collection.bucket_width, tuned_width);
+    assert_eq!(collection.auto_tune_after, None);
+    assert_eq!(collection.buckets_controller.count(), sizes.len());
+
+    let params: GetCollectionParams = serde_json::from_value(serde_json::json!({"name": "tuned"})).unwrap();
+    let info = get_collection(State(state), Json(params)).await.0.data.unwrap();
+    assert_eq!(info["bucket_width"], serde_json::json!(tuned_width));
+}
+
+#[tokio::test]
+async fn test_similar_scores_are_sorted_top_k() {
+    use crate::core::handlers::{find_similar, similar_scores};
+    use crate::core::openapi::{FindSimilarParams, SimilarScoresParams};
+    use axum::http::StatusCode;
+
+    let mut collection_controller = collection_controller_with("scores_collection", 3);
+    for offset in [0.3, 0.0, 0.2, 0.05, 0.1] {
+        collection_controller.add_vector("scores_collection", vec![1.0 + offset, 1.0, 1.0], Metadata::new()).unwrap();
+    }
+    let state = app_state_for(collection_controller, HashMap::new());
+    let request = serde_json::json!({"collection": "scores_collection", "query": [1.0, 1.0, 1.0], "k": 3});
+
+    let params: SimilarScoresParams = serde_json::from_value(request.clone()).unwrap();
+    let (status, response) = similar_scores(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+    let scores: Vec<f64> = data["scores"].as_array().unwrap().iter().map(|s| s.as_f64().unwrap()).collect();
+    assert_eq!(scores.len(), 3);
+    assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]), "score должны не возрастать: {:?}", scores);
+    assert_eq!(data["max"].as_f64(), Some(scores[0]));
+    assert_eq!(data["min"].as_f64(), Some(scores[2]));
+    let mean = data["mean"].as_f64().unwrap();
+    assert!((mean - scores.iter().sum::<f64>() / 3.0).abs() < 1e-5);
+
+    // Score совпадают с top-k обычного поиска
+    let params: FindSimilarParams = serde_json::from_value(request).unwrap();
+    let (_, response) = find_similar(State(state.clone()), Json(params)).await;
+    let top_k: Vec<f64> = response.0.data.unwrap()["results"].as_array().unwrap()
+        .iter()
+        .map(|result| result["score"].as_f64().unwrap())
+        .collect();
+    assert_eq!(scores, top_k);
+
+    let params: SimilarScoresParams = serde_json::from_value(serde_json::json!({"collection": "missing", "query": [1.0, 1.0, 1.0]})).unwrap();
+    let (status, _) = similar_scores(State(state), Json(params)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_prune_removes_empty_buckets() {
+    use crate::core::handlers::prune;
+
+    let mut collection_controller = collection_controller_with("prune_collection", 3);
+    let moved = collection_controller.add_vector("prune_collection", vec![1.0, 1.0, 1.0], Metadata::new()).unwrap();
+    let kept = collection_controller.add_vector("prune_collection", vec![-200.0, 50.0, 300.0], Metadata::new()).unwrap();
+
+    // Обновление, переносящее вектор в другой бакет, сразу удаляет опустевший исходный бакет
+    collection_controller.update_vector("prune_collection", moved, Some(vec![500.0, -500.0, 500.0]), None).unwrap();
+    let buckets_controller = &collection_controller.get_collection("prune_collection").unwrap().buckets_controller;
+    assert_eq!(buckets_controller.count(), 2);
+    assert!(buckets_controller.get_all_buckets().iter().all(|bucket| bucket.size() > 0));
+
+    // Бакет, опустошённый в обход BucketController, остаётся до прохода очистки
+    let buckets_controller = &mut collection_controller.get_collection_mut("prune_collection").unwrap().buckets_controller;
+    let kept_bucket = buckets_controller.get_all_buckets().iter().find(|bucket| bucket.contains_vector(kept)).unwrap().id;
+    buckets_controller.get_bucket_mut(kept_bucket).unwrap().remove_vector(kept).unwrap();
+    assert_eq!(buckets_controller.count(), 2);
+
+    let state = app_state_for(collection_controller, HashMap::new());
+    let data = prune(State(state.clone())).await.0.data.unwrap();
+    assert_eq!(data["pruned"], 1);
+    assert_eq!(data["collections"]["prune_collection"], 1);
+    let ctrl = state.controller.read().await;
+    let buckets_controller = &ctrl.get_collection("prune_collection").unwrap().buckets_controller;
+    assert_eq!(buckets_controller.count(), 1);
+    assert!(buckets_controller.contains_vector(moved));
+    drop(ctrl);
+
+    // Повторный проход ничего не находит
+    assert_eq!(prune(State(state)).await.0.data.unwrap()["pruned"], 0);
+}
+
+#[tokio::test]
+async fn test_stop_signal_fires_once() {
+    use crate::core::config::ConfigLoader;
+    use crate::core::controllers::ConnectionController;
+    use crate::core::handlers::stop;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Повторный /stop не паникует и не подаёт сигнал второй раз
+    let state = app_state_for(collection_controller_with("stop_collection", 3), HashMap::new());
+    assert_eq!(stop(State(state.clone())).await.0.data.unwrap(), "Server stopping...");
+    assert_eq!(stop(State(state.clone())).await.0.data.unwrap(), "Server is already stopping");
+    assert!(state.shutdown.is_triggered());
+
+    // Сигнал, поданный до начала ожидания, не теряется, но срабатывает только один раз
+    tokio::time::timeout(Duration::from_secs(1), state.shutdown.wait()).await.expect("сигнал должен сохраниться");
+    assert!(tokio::time::timeout(Duration::from_millis(50), state.shutdown.wait()).await.is_err());
+
+    // Сервер останавливается по первому /stop; второй запрос не мешает остановке
+    let controller = Arc::new(tokio::sync::RwLock::new(collection_controller_with("stop_collection", 3)));
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        ConnectionController::new(ConfigLoader::new()).connection_handler(controller, addr).await
+    });
+
+    let send_stop = || async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        stream.write_all(b"POST /stop HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok::<String, std::io::Error>(response)
+    };
+    let first = loop {
+        match send_stop().await {
+            Ok(response) => break response,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    assert!(first.starts_with("HTTP/1.1 200"));
+    let _ = send_stop().await;
+
+    let stopped = tokio::time::timeout(Duration::from_secs(5), server).await.expect("сервер должен остановиться");
+    assert!(stopped.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_migrate_metadata_renames_key_without_rebucketing() {
+    use crate::core::handlers::migrate_metadata;
+    use crate::core::interfaces::Object;
+    use crate::core::objects::Vector;
+    use crate::core::openapi::MigrateMetadataParams;
+    use axum::http::StatusCode;
+
+    let configs = HashMap::from([("backend".to_string(), "memory".to_string())]);
+    let storage_controller = Arc::new(StorageController::new(configs).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("migrate_collection".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    let mut ids = Vec::new();
+    for (i, tag) in ["draft", "final", "draft"].iter().enumerate() {
+        let mut metadata = Metadata::new();
+        metadata.insert("tag".to_string(), MetadataValue::from(*tag));
+        metadata.insert("author".to_string(), MetadataValue::from("ann"));
+        let x = i as f32 * 40.0;
+        ids.push(collection_controller.add_vector("migrate_collection", vec![x, -x, 1.0], metadata).unwrap());
+    }
+    collection_controller.dump();
+    let buckets_before = collection_controller.bucket_summaries("migrate_collection", None).unwrap();
+
+    let state = app_state_for(collection_controller, HashMap::new());
+    let params: MigrateMetadataParams = serde_json::from_value(serde_json::json!({
+        "collection": "migrate_collection",
+        "rename": {"tag": "status"},
+        "remap": {"status": {"draft": "in_progress"}}
+    })).unwrap();
+    let (status, response) = migrate_metadata(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.0.data.unwrap()["updated"], 3);
+
+    // Бакеты не перестраивались, ID векторов прежние, новый ключ доступен для фильтрации
+    let ctrl = state.controller.read().await;
+    assert_eq!(ctrl.bucket_summaries("migrate_collection", None).unwrap(), buckets_before);
+    let in_progress = Metadata::from([("status".to_string(), MetadataValue::from("in_progress"))]);
+    let mut found = ctrl.filter_by_metadata("migrate_collection", &in_progress).unwrap();
+    found.sort();
+    let mut expected = vec![ids[0], ids[2]];
+    expected.sort();
+    assert_eq!(found, expected);
+    assert!(ctrl.filter_by_metadata("migrate_collection", &Metadata::from([("tag".to_string(), MetadataValue::from("final"))])).unwrap().is_empty());
+
+    // Файл вектора на диске уже содержит новые метаданные
+    let bucket_id = ctrl.get_all_buckets("migrate_collection").unwrap()
+        .into_iter()
+        .find(|bucket| bucket.contains_vector(ids[1]))
+        .unwrap()
+        .id;
+    let raw = storage_controller.read_vector_from_bucket("migrate_collection".to_string(), bucket_id.to_string(), ids[1]).unwrap().unwrap();
+    let mut stored = Vector::new(None, None, None);
+    stored.load(raw, storage_controller.format).unwrap();
+    assert_eq!(stored.metadata.get("status"), Some(&MetadataValue::from("final")));
+    assert_eq!(stored.metadata.get("author"), Some(&MetadataValue::from("ann")));
+    assert!(!stored.metadata.contains_key("tag"));
+    drop(ctrl);
+
+    let params: MigrateMetadataParams = serde_json::from_value(serde_json::json!({"collection": "migrate_collection"})).unwrap();
+    assert_eq!(migrate_metadata(State(state), Json(params)).await.0, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_search_facets_count_candidates_before_truncation() {
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+    use axum::http::StatusCode;
+
+    let mut collection_controller = collection_controller_with("facet_collection", 3);
+    let mut ids = Vec::new();
+    for (i, category) in ["a", "b", "a", "c", "a", "b"].iter().enumerate() {
+        let offset = i as f32 * 0.01;
+        ids.push(collection_controller.add_vector("facet_collection", vec![1.0 + offset, 1.0, 1.0], metadata_with_category(category)).unwrap());
+    }
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    let params: FindSimilarParams = serde_json::from_value(serde_json::json!({
+        "collection": "facet_collection",
+        "query": [1.0, 1.0, 1.0],
+        "k": 2,
+        "exclude_ids": [ids[4]],
+        "facets": ["category", "missing"]
+    })).unwrap();
+    let (status, response) = find_similar(State(state), Json(params)).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+
+    // Фасеты считаются по всем кандидатам, а не только по top-k; исключённый вектор не учитывается
+    assert_eq!(data["results"].as_array().unwrap().len(), 2);
+    assert_eq!(data["candidates_examined"], 6);
+    assert_eq!(data["facets"]["category"], serde_json::json!({"a": 2, "b": 2, "c": 1}));
+    assert_eq!(data["facets"]["missing"], serde_json::json!({}));
+}
+
+#[tokio::test]
+async fn test_approximate_dedup_returns_existing_id() {
+    use crate::core::handlers::add_vector;
+    use crate::core::openapi::AddVectorParams;
+
+    let state = app_state_for(
+        collection_controller_with("dedup_collection", 3),
+        HashMap::from([("dedup_tolerance".to_string(), "0.0001".to_string())]),
+    );
+    let insert = |embedding: Vec<f32>, dedup: bool| {
+        let state = state.clone();
+        async move {
+            let params: AddVectorParams = serde_json::from_value(serde_json::json!({
+                "collection": "dedup_collection", "embedding": embedding, "dedup": dedup
+            })).unwrap();
+            add_vector(State(state), Json(params)).await.1.0.data.unwrap()
+        }
+    };
+
+    let original = insert(vec![0.3, 0.5, 0.7], true).await;
+    assert_eq!(original["deduplicated"], false);
+
+    // Отличие в последнем бите одной координаты: с dedup возвращается существующий ID
+    let nudged = f32::from_bits(0.7f32.to_bits() + 1);
+    let duplicate = insert(vec![0.3, 0.5, nudged], true).await;
+    assert_eq!(duplicate["deduplicated"], true);
+    assert_eq!(duplicate["id"], original["id"]);
+
+    // Непохожий вектор вставляется, а без dedup вставляется и почти совпадающий
+    let dissimilar = insert(vec![0.7, -0.5, 0.3], true).await;
+    assert_eq!(dissimilar["deduplicated"], false);
+    assert_ne!(dissimilar["id"], original["id"]);
+    assert_eq!(insert(vec![0.3, 0.5, nudged], false).await["deduplicated"], false);
+
+    let ctrl = state.controller.read().await;
+    assert_eq!(ctrl.get_collection("dedup_collection").unwrap().buckets_controller.total_vectors(), 3);
+}
+
+#[test]
+fn test_openapi_spec_lists_all_routes() {
+    use crate::core::openapi::load_openapi_spec;
+
+    let spec = serde_json::to_value(load_openapi_spec()).unwrap();
+    let paths = spec["paths"].as_object().unwrap();
+
+    // Все маршруты connection_handler (кроме Swagger UI) должны быть описаны
+    let routes = [
+        ("/collection", "post"), ("/collection/delete", "post"), ("/collection/update", "post"),
+        ("/collection/all", "post"), ("/collection/get", "post"), ("/collection/exists", "post"),
+        ("/collection/buckets", "post"), ("/collection/query-bucket", "post"), ("/collection/bucket", "post"),
+        ("/collection/preload", "post"), ("/vector", "post"), ("/vector/batch", "post"),
+        ("/vector/text-chunks", "post"), ("/vector/update", "post"), ("/vector/upsert", "post"),
+        ("/vector/get", "post"), ("/vector/get-many", "post"), ("/vector/exists", "post"), ("/vector/delete", "post"),
+        ("/vector/filter", "post"), ("/vector/similar", "post"), ("/vector/similar-batch", "post"),
+        ("/vector/similar-scores", "post"), ("/vector/similar-to", "post"), ("/vector/compare", "post"), ("/info", "get"), ("/health", "get"), ("/admin/disk-usage", "get"),
+        ("/admin/flush", "post"), ("/admin/vector-raw", "post"), ("/admin/reindex", "post"),
+        ("/admin/prune", "post"), ("/admin/migrate-metadata", "post"), ("/stop", "post"),
+    ];
+    for (path, method) in routes {
+        assert!(paths.get(path).and_then(|item| item.get(method)).is_some(), "в спецификации нет {} {}", method, path);
+    }
+    assert_eq!(paths.len(), routes.len());
+
+    // Схемы параметров, на которые ссылаются пути, тоже зарегистрированы
+    let schemas = spec["components"]["schemas"].as_object().unwrap();
+    for schema in ["FilterByMetadataParams", "MetadataFilter", "GetCollectionParams", "AddVectorsBatchParams", "RpcResponse"] {
+        assert!(schemas.contains_key(schema), "в спецификации нет схемы {}", schema);
+    }
+}
+
+#[tokio::test]
+async fn test_query_cache_hit_and_invalidation_on_mutation() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+
+    let mut collection_controller = collection_controller_with("cached_search", 3);
+    for i in 0..5 {
+        collection_controller.add_vector("cached_search", vec![i as f32, 1.0, 0.0], Metadata::new()).unwrap();
+    }
+    let state = app_state_for(
+        collection_controller,
+        HashMap::from([("query_cache_size".to_string(), "8".to_string())]),
+    );
+    let params = || -> FindSimilarParams {
+        serde_json::from_value(serde_json::json!({"collection": "cached_search", "query": [0.0, 1.0, 0.0], "k": 3})).unwrap()
+    };
+
+    let (status, response) = find_similar(State(state.clone()), Json(params())).await;
+    assert_eq!(status, StatusCode::OK);
+    let first = response.0.data.unwrap();
+    assert_eq!(first["cached"], false);
+
+    // Повторный запрос обслуживается из кэша с теми же результатами
+    let second = find_similar(State(state.clone()), Json(params())).await.1.0.data.unwrap();
+    assert_eq!(second["cached"], true);
+    assert_eq!(second["results"], first["results"]);
+    assert_eq!(state.query_cache.len(), 1);
+
+    // Изменение коллекции делает старую запись недействительной
+    let new_id = state.controller.write().await
+        .add_vector("cached_search", vec![0.0, 1.0, 0.0], Metadata::new()).unwrap();
+    let third = find_similar(State(state.clone()), Json(params())).await.1.0.data.unwrap();
+    assert_eq!(third["cached"], false);
+    assert_eq!(third["results"][0]["vector_id"], new_id);
+
+    // Без query_cache_size кэш выключен
+    let state = app_state_for(collection_controller_with("cached_search", 3), HashMap::new());
+    let (status, _) = find_similar(State(state.clone()), Json(params())).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = find_similar(State(state.clone()), Json(params())).await.1.0.data.unwrap();
+    assert_eq!(data["cached"], false);
+    assert!(state.query_cache.is_empty());
+}
+
+#[test]
+fn test_query_cache_evicts_least_recently_used() {
+    use crate::core::controllers::{QueryCacheKey, SearchOptions, SearchOutcome};
+
+    let collection = Collection::new(Some("lru".to_string()), LSHMetric::Euclidean, 2);
+    let options = SearchOptions::default();
+    let key = |x: f32| QueryCacheKey::new(&collection, &[x, 0.0], 1, &options);
+    let outcome = |bucket_id: u64| SearchOutcome { results: vec![(bucket_id, 0, 1.0)], ..Default::default() };
+
+    let cache = QueryCache::new(2);
+    cache.insert(key(1.0), &outcome(1));
+    cache.insert(key(2.0), &outcome(2));
+    // Обращение к первой записи делает её свежее второй
+    assert_eq!(cache.get(&key(1.0)).unwrap().results[0].0, 1);
+    cache.insert(key(3.0), &outcome(3));
+
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&key(2.0)).is_none());
+    assert!(cache.get(&key(1.0)).is_some());
+    assert!(cache.get(&key(3.0)).is_some());
+
+    // Запросы, совпадающие с точностью до шага квантования, используют одну запись
+    assert!(cache.get(&key(1.0 + 2e-7)).is_some());
+
+    // Неполные результаты не кэшируются
+    cache.insert(key(4.0), &SearchOutcome { timed_out: true, ..Default::default() });
+    assert!(cache.get(&key(4.0)).is_none());
+}
+
+#[tokio::test]
+async fn test_get_vectors_binary_matches_json() {
+    use axum::http::{header, HeaderMap, StatusCode};
+    use crate::core::handlers::{get_many_vectors, get_vector};
+    use crate::core::openapi::{GetManyVectorsParams, GetVectorParams};
+    use crate::core::utils::decode_embeddings;
+
+    let mut collection_controller = collection_controller_with("binary_get", 4);
+    let ids: Vec<u64> = (0..3)
+        .map(|i| collection_controller.add_vector("binary_get", vec![i as f32, -1.5, 0.25, 1e-3], Metadata::new()).unwrap())
+        .collect();
+    let state = app_state_for(collection_controller, HashMap::new());
+    let params = |vector_ids: &[u64]| -> GetManyVectorsParams {
+        serde_json::from_value(serde_json::json!({"collection": "binary_get", "vector_ids": vector_ids})).unwrap()
+    };
+    let mut binary = HeaderMap::new();
+    binary.insert(header::ACCEPT, "application/octet-stream".parse().unwrap());
+
+    // JSON остаётся форматом по умолчанию
+    let json = response_json(get_many_vectors(State(state.clone()), HeaderMap::new(), Json(params(&ids))).await).await;
+    let json_embeddings: Vec<Vec<f32>> = json["data"]["vectors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|vector| serde_json::from_value(vector["embedding"].clone()).unwrap())
+        .collect();
+    assert_eq!(json_embeddings.len(), 3);
+
+    let response = get_many_vectors(State(state.clone()), binary.clone(), Json(params(&ids))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/octet-stream");
+    let body = response_body(response).await;
+    assert_eq!(body.len(), 8 + 3 * 4 * 4);
+    assert_eq!(decode_embeddings(&body).unwrap(), json_embeddings);
+
+    // Одиночный вектор в бинарном виде
+    let single: GetVectorParams = serde_json::from_value(serde_json::json!({"collection": "binary_get", "vector_id": ids[1]})).unwrap();
+    let body = response_body(get_vector(State(state.clone()), binary.clone(), Json(single)).await).await;
+    assert_eq!(decode_embeddings(&body).unwrap(), vec![json_embeddings[1].clone()]);
+
+    // В JSON ненайденные ID перечисляются, в бинарном виде это ошибка
+    let json = response_json(get_many_vectors(State(state.clone()), HeaderMap::new(), Json(params(&[ids[0], 12345]))).await).await;
+    assert_eq!(json["data"]["vectors"].as_array().unwrap().len(), 1);
+    assert_eq!(json["data"]["missing"], serde_json::json!([12345]));
+    let response = get_many_vectors(State(state), binary, Json(params(&[ids[0], 12345]))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_vecdb_error_maps_to_status_code() {
+    use axum::http::StatusCode;
+    use crate::core::errors::VecDbError;
+
+    let cases = [
+        (VecDbError::collection_not_found("missing"), StatusCode::NOT_FOUND),
+        (VecDbError::vector_not_found(7), StatusCode::NOT_FOUND),
+        (VecDbError::DimensionMismatch { expected: 4, actual: 3 }, StatusCode::BAD_REQUEST),
+        (VecDbError::AlreadyExists("Коллекция 'docs' уже существует".to_string()), StatusCode::CONFLICT),
+        (VecDbError::from(std::io::Error::other("disk full")), StatusCode::INTERNAL_SERVER_ERROR),
+        (VecDbError::from(serde_json::from_str::<u64>("not a number").unwrap_err()), StatusCode::INTERNAL_SERVER_ERROR),
+        (VecDbError::Validation("Эмбеддинг не может быть пустым".to_string()), StatusCode::BAD_REQUEST),
+    ];
+    for (error, status) in cases {
+        assert_eq!(error.status_code(), status, "неверный статус для {:?}", error);
+    }
+    assert_eq!(
+        VecDbError::DimensionMismatch { expected: 4, actual: 3 }.to_string(),
+        "Размерность вектора 3 не соответствует размерности коллекции 4"
+    );
+}
+
+#[tokio::test]
+async fn test_handlers_return_status_by_error_category() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::{add_collection, add_vector, delete_collection, delete_vector};
+    use crate::core::openapi::{AddCollectionParams, AddVectorParams, DeleteCollectionParams, DeleteVectorParams};
+
+    let state = app_state_for(collection_controller_with("status_codes", 3), HashMap::new());
+
+    let params: AddCollectionParams = serde_json::from_value(serde_json::json!({"name": "status_codes", "metric": "Euclidean", "dimension": 3})).unwrap();
+    let (status, response) = add_collection(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(response.0.status, "error");
+
+    let params: AddVectorParams = serde_json::from_value(serde_json::json!({"collection": "status_codes", "embedding": [1.0, 2.0]})).unwrap();
+    let (status, response) = add_vector(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(response.0.message.unwrap().contains("Размерность вектора 2"));
+
+    let params: DeleteVectorParams = serde_json::from_value(serde_json::json!({"collection": "status_codes", "vector_id": 42})).unwrap();
+    assert_eq!(delete_vector(State(state.clone()), Json(params)).await.0, StatusCode::NOT_FOUND);
+
+    let params: DeleteCollectionParams = serde_json::from_value(serde_json::json!({"name": "missing"})).unwrap();
+    assert_eq!(delete_collection(State(state.clone()), Json(params)).await.0, StatusCode::NOT_FOUND);
+
+    let params: AddVectorParams = serde_json::from_value(serde_json::json!({"collection": "status_codes", "embedding": [1.0, 2.0, 3.0]})).unwrap();
+    assert_eq!(add_vector(State(state), Json(params)).await.0, StatusCode::OK);
+}
+
+#[test]
+fn test_fifo_eviction_removes_oldest_vector() {
+    use crate::core::objects::EvictionPolicy;
+
+    let mut ctrl = collection_controller_with("fifo", 3);
+    ctrl.set_max_vectors("fifo", Some(3), EvictionPolicy::Fifo).unwrap();
+    let ids: Vec<u64> = (0..3)
+        .map(|i| ctrl.add_vector("fifo", vec![i as f32, 1.0, 0.0], Metadata::new()).unwrap())
+        .collect();
+
+    // Обращения к вектору не спасают его от вытеснения по fifo
+    ctrl.get_vector("fifo", ids[0]).unwrap();
+    let newest = ctrl.add_vector("fifo", vec![5.0, 1.0, 0.0], Metadata::new()).unwrap();
+
+    let collection = ctrl.get_collection("fifo").unwrap();
+    assert_eq!(collection.buckets_controller.total_vectors(), 3);
+    assert!(!collection.buckets_controller.contains_vector(ids[0]));
+    for id in [ids[1], ids[2], newest] {
+        assert!(collection.buckets_controller.contains_vector(id));
+    }
+
+    // Вставка с заданным ID тоже вытесняет следующий по старшинству вектор
+    assert!(ctrl.upsert_vector("fifo", 7, vec![9.0, 1.0, 0.0], None).unwrap());
+    let collection = ctrl.get_collection("fifo").unwrap();
+    assert!(!collection.buckets_controller.contains_vector(ids[1]));
+    assert!(collection.buckets_controller.contains_vector(7));
+}
+
+#[test]
+fn test_lru_eviction_removes_least_recently_accessed_vector() {
+    use crate::core::interfaces::Object;
+    use crate::core::objects::EvictionPolicy;
+
+    let mut ctrl = collection_controller_with("lru", 3);
+    ctrl.set_max_vectors("lru", Some(3), EvictionPolicy::Lru).unwrap();
+    let embeddings = [vec![1.0, 0.0, 0.0], vec![0.0, 50.0, 0.0], vec![0.0, 0.0, 100.0]];
+    let ids: Vec<u64> = embeddings.iter()
+        .map(|embedding| ctrl.add_vector("lru", embedding.clone(), Metadata::new()).unwrap())
+        .collect();
+
+    // Первый вектор прочитан по ID, второй попал в выдачу поиска; третий давно не использовался
+    ctrl.get_vector("lru", ids[0]).unwrap();
+    let results = ctrl.find_similar("lru".to_string(), &embeddings[1], 1).unwrap();
+    let (bucket_id, index, _) = results[0];
+    let collection = ctrl.get_collection("lru").unwrap();
+    assert_eq!(collection.buckets_controller.get_vector_at(bucket_id, index).unwrap().hash_id(), ids[1]);
+
+    let newest = ctrl.add_vector("lru", vec![3.0, 3.0, 3.0], Metadata::new()).unwrap();
+    let collection = ctrl.get_collection("lru").unwrap();
+    assert_eq!(collection.buckets_controller.total_vectors(), 3);
+    assert!(!collection.buckets_controller.contains_vector(ids[2]));
+    for id in [ids[0], ids[1], newest] {
+        assert!(collection.buckets_controller.contains_vector(id));
+    }
+
+    // Следующим вытесняется первый: к нему обращались раньше, чем ко второму
+    ctrl.add_vector("lru", vec![4.0, 4.0, 4.0], Metadata::new()).unwrap();
+    assert!(!ctrl.get_collection("lru").unwrap().buckets_controller.contains_vector(ids[0]));
+}
+
+#[tokio::test]
+async fn test_max_vectors_rejects_by_default_and_is_persisted() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::{add_collection, add_vector, get_collection};
+    use crate::core::openapi::{AddCollectionParams, AddVectorParams, GetCollectionParams};
+    use crate::core::objects::EvictionPolicy;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let state = app_state_for(CollectionController::new(Arc::clone(&storage_controller)), HashMap::new());
+
+    let params: AddCollectionParams = serde_json::from_value(serde_json::json!({
+        "name": "bounded", "metric": "Euclidean", "dimension": 2, "max_vectors": 2, "eviction": "random"
+    })).unwrap();
+    assert_eq!(add_collection(State(state.clone()), Json(params)).await.0, StatusCode::BAD_REQUEST);
+
+    let params: AddCollectionParams = serde_json::from_value(serde_json::json!({
+        "name": "bounded", "metric": "Euclidean", "dimension": 2, "max_vectors": 2
+    })).unwrap();
+    assert_eq!(add_collection(State(state.clone()), Json(params)).await.0, StatusCode::OK);
+
+    for (i, expected) in [StatusCode::OK, StatusCode::OK, StatusCode::BAD_REQUEST].into_iter().enumerate() {
+        let params: AddVectorParams = serde_json::from_value(serde_json::json!({"collection": "bounded", "embedding": [i as f32, 1.0]})).unwrap();
+        let (status, response) = add_vector(State(state.clone()), Json(params)).await;
+        assert_eq!(status, expected);
+        if status != StatusCode::OK {
+            assert!(response.0.message.unwrap().contains("заполнена"));
+        }
+    }
+
+    let params: GetCollectionParams = serde_json::from_value(serde_json::json!({"name": "bounded"})).unwrap();
+    let info = get_collection(State(state.clone()), Json(params)).await.0.data.unwrap();
+    assert_eq!(info["max_vectors"], 2);
+    assert_eq!(info["eviction"], "reject");
+
+    let mut ctrl = state.controller.write().await;
+    ctrl.set_max_vectors("bounded", Some(5), EvictionPolicy::Lru).unwrap();
+    ctrl.dump();
+    drop(ctrl);
+    let mut reloaded = CollectionController::new(storage_controller);
+    reloaded.load();
+    let collection = reloaded.get_collection("bounded").unwrap();
+    assert_eq!(collection.max_vectors, Some(5));
+    assert_eq!(collection.eviction, EvictionPolicy::Lru);
+}
+
+#[test]
+fn test_append_mode_writes_to_storage_and_finalize_enables_search() {
+    use crate::core::errors::VecDbError;
+    use crate::core::interfaces::Object;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let mut ctrl = CollectionController::new(Arc::clone(&storage_controller));
+    ctrl.add_collection("bulk".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    let mut defaults = Metadata::new();
+    defaults.insert("source".to_string(), MetadataValue::from("bulk"));
+    ctrl.set_default_metadata("bulk", defaults).unwrap();
+    let existing = ctrl.add_vector("bulk", vec![0.5, 0.5, 0.5], Metadata::new()).unwrap();
+
+    // Во время добавления коллекция выгружена: векторы не копятся в памяти
+    let mut writer = ctrl.begin_append("bulk").unwrap();
+    assert!(!ctrl.collection_exists("bulk"));
+    let point = |i: usize| vec![i as f32 * 0.7, (i % 13) as f32, (i % 5) as f32 * 3.0];
+    let ids: Vec<u64> = (0..200).map(|i| writer.add_vector(point(i), Metadata::new()).unwrap()).collect();
+    assert_eq!(writer.written(), 200);
+    assert!(matches!(writer.add_vector(vec![1.0, 2.0], Metadata::new()), Err(VecDbError::DimensionMismatch { expected: 3, actual: 2 })));
+    assert!(!ctrl.collection_exists("bulk"));
+
+    let (buckets, vectors) = ctrl.finalize_append(writer).unwrap();
+    assert_eq!(vectors, 201);
+    assert!(buckets > 1);
+
+    let collection = ctrl.get_collection("bulk").unwrap();
+    assert!(collection.buckets_controller.contains_vector(existing));
+    let appended = collection.buckets_controller.get_vector(ids[42]).unwrap();
+    assert_eq!(appended.data, point(42));
+    assert_eq!(appended.metadata["source"].to_string(), "bulk");
+
+    let results = ctrl.find_similar("bulk".to_string(), &point(42), 1).unwrap();
+    let (bucket_id, index, _) = results[0];
+    assert_eq!(collection.buckets_controller.get_vector_at(bucket_id, index).unwrap().hash_id(), ids[42]);
+
+    assert!(matches!(ctrl.begin_append("missing"), Err(VecDbError::NotFound(_))));
+}
+
+#[test]
+fn test_metadata_index_matches_scan_and_is_consulted() {
+    use crate::core::utils::MetadataFilter;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let mut ctrl = CollectionController::new(Arc::clone(&storage_controller));
+    ctrl.add_collection("indexed".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    ctrl.set_indexed_metadata_keys("indexed", vec!["genre".to_string(), "year".to_string()]).unwrap();
+
+    let genres = ["rock", "jazz", "pop"];
+    let mut ids = Vec::new();
+    for i in 0..60 {
+        let mut metadata = Metadata::new();
+        metadata.insert("genre".to_string(), MetadataValue::from(genres[i % 3]));
+        metadata.insert("year".to_string(), MetadataValue::Int(2000 + (i % 4) as i64));
+        metadata.insert("title".to_string(), MetadataValue::from(format!("song {}", i).as_str()));
+        ids.push(ctrl.add_vector("indexed", vec![i as f32, (i % 7) as f32 * 5.0, 1.0], metadata).unwrap());
+    }
+    // Изменение метаданных, перенос в другой бакет и удаление поддерживают индекс в актуальном виде
+    let mut changed = Metadata::new();
+    changed.insert("genre".to_string(), MetadataValue::from("jazz"));
+    changed.insert("year".to_string(), MetadataValue::Int(2001));
+    ctrl.update_vector("indexed", ids[0], None, Some(changed.clone())).unwrap();
+    ctrl.update_vector("indexed", ids[3], Some(vec![500.0, -300.0, 9.0]), Some(changed)).unwrap();
+    ctrl.delete_vector("indexed", ids[1]).unwrap();
+
+    let filters = [
+        HashMap::from([("genre".to_string(), MetadataValue::from("jazz"))]),
+        HashMap::from([("genre".to_string(), MetadataValue::from("jazz")), ("year".to_string(), MetadataValue::from("2001"))]),
+        HashMap::from([("year".to_string(), MetadataValue::Float(2003.0))]),
+        HashMap::from([("genre".to_string(), MetadataValue::from("metal"))]),
+    ];
+    let check = |ctrl: &CollectionController| {
+        let controller = &ctrl.get_collection("indexed").unwrap().buckets_controller;
+        for filter in &filters {
+            let before = controller.metadata_index.as_ref().unwrap().lookups();
+            let mut scanned = controller.filter_by_expression(&MetadataFilter::from_map(filter));
+            scanned.sort_unstable();
+            assert_eq!(ctrl.filter_by_metadata("indexed", filter).unwrap(), scanned, "фильтр {:?}", filter);
+            assert_eq!(controller.metadata_index.as_ref().unwrap().lookups(), before + 1);
+        }
+    };
+    check(&ctrl);
+    let jazz_2001 = ctrl.filter_by_metadata("indexed", &filters[1]).unwrap();
+    assert!(jazz_2001.contains(&ids[0]) && jazz_2001.contains(&ids[3]));
+    assert!(!ctrl.filter_by_metadata("indexed", &filters[0]).unwrap().contains(&ids[1]));
+
+    // Фильтр по непроиндексированному ключу выполняется полным просмотром
+    let title = HashMap::from([("title".to_string(), MetadataValue::from("song 5"))]);
+    let lookups = ctrl.get_collection("indexed").unwrap().buckets_controller.metadata_index.as_ref().unwrap().lookups();
+    assert_eq!(ctrl.filter_by_metadata("indexed", &title).unwrap(), vec![ids[5]]);
+    assert_eq!(ctrl.get_collection("indexed").unwrap().buckets_controller.metadata_index.as_ref().unwrap().lookups(), lookups);
+
+    // Набор ключей сохраняется, а индекс строится заново при загрузке
+    ctrl.dump();
+    let mut reloaded = CollectionController::new(storage_controller);
+    reloaded.load();
+    assert_eq!(reloaded.get_collection("indexed").unwrap().indexed_metadata_keys, vec!["genre", "year"]);
+    check(&reloaded);
+}
+
+#[tokio::test]
+async fn test_add_collection_echoes_resolved_parameters() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::add_collection;
+    use crate::core::lsh::{DEFAULT_BUCKET_WIDTH, DEFAULT_LSH_SEED};
+    use crate::core::openapi::AddCollectionParams;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let state = app_state_for(CollectionController::new(storage_controller), HashMap::new());
+
+    // Неизвестная метрика заменяется на Euclidean, число таблиц — на 1
+    let params: AddCollectionParams = serde_json::from_value(serde_json::json!({"name": "resolved", "metric": "Unknown", "dimension": 8})).unwrap();
+    let (status, response) = add_collection(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+    assert_eq!(data["added"], true);
+    assert_eq!(data["name"], "resolved");
+    assert_eq!(data["dimension"], 8);
+    assert_eq!(data["metric"], "Euclidean");
+    assert_eq!(data["num_hashes"], 3);
+    assert_eq!(data["bucket_width"], serde_json::json!(DEFAULT_BUCKET_WIDTH));
+    assert_eq!(data["seed"], DEFAULT_LSH_SEED);
+    assert_eq!(data["num_tables"], 1);
+
+    let params: AddCollectionParams = serde_json::from_value(serde_json::json!({"name": "cosine", "metric": "Cosine", "dimension": 4, "num_tables": 3})).unwrap();
+    let data = add_collection(State(state), Json(params)).await.1.0.data.unwrap();
+    assert_eq!(data["metric"], "Cosine");
+    assert_eq!(data["num_tables"], 3);
+}
+
+#[tokio::test]
+async fn test_similar_to_uses_stored_embedding_and_excludes_self() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::similar_to;
+    use crate::core::openapi::SimilarToParams;
+
+    let mut ctrl = collection_controller_with("mlt", 3);
+    let query = ctrl.add_vector("mlt", vec![1.0, 1.0, 1.0], Metadata::new()).unwrap();
+    let near = ctrl.add_vector("mlt", vec![1.1, 1.0, 0.9], Metadata::new()).unwrap();
+    let far = ctrl.add_vector("mlt", vec![-40.0, 25.0, 60.0], Metadata::new()).unwrap();
+    let state = app_state_for(ctrl, HashMap::new());
+
+    let params = |exclude_self: bool, id: u64| -> SimilarToParams {
+        serde_json::from_value(serde_json::json!({"collection": "mlt", "id": id, "k": 2, "exclude_self": exclude_self})).unwrap()
+    };
+    let ids = |data: &serde_json::Value| -> Vec<u64> {
+        data["results"].as_array().unwrap().iter().map(|result| result["vector_id"].as_u64().unwrap()).collect()
+    };
+
+    // Без исключения ближайший сосед — сам вектор
+    let (status, response) = similar_to(State(state.clone()), Json(params(false, query))).await;
+    assert_eq!(status, StatusCode::OK);
+    let data = response.0.data.unwrap();
+    assert_eq!(ids(&data)[..2], [query, near]);
+
+    let data = similar_to(State(state.clone()), Json(params(true, query))).await.1.0.data.unwrap();
+    let found = ids(&data);
+    assert_eq!(found[0], near);
+    assert!(!found.contains(&query));
+    assert!(found.len() < 2 || found[1] == far);
+
+    let (status, _) = similar_to(State(state), Json(params(true, 12345))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_runtime_threads_come_from_server_config() {
+    use crate::core::controllers::{ConnectionController, DEFAULT_MAX_BLOCKING_THREADS};
+
+    let configs = HashMap::from([
+        ("worker_threads".to_string(), "3".to_string()),
+        ("max_blocking_threads".to_string(), "16".to_string()),
+    ]);
+    assert_eq!(ConnectionController::runtime_threads(&configs), (3, 16));
+    let runtime = ConnectionController::build_runtime(&configs).unwrap();
+    assert_eq!(runtime.metrics().num_workers(), 3);
+
+    // Без настроек (или с некорректными) — по числу ядер и размер пула tokio по умолчанию
+    let cores = std::thread::available_parallelism().unwrap().get();
+    assert_eq!(ConnectionController::runtime_threads(&HashMap::new()), (cores, DEFAULT_MAX_BLOCKING_THREADS));
+    let invalid = HashMap::from([
+        ("worker_threads".to_string(), "0".to_string()),
+        ("max_blocking_threads".to_string(), "many".to_string()),
+    ]);
+    assert_eq!(ConnectionController::runtime_threads(&invalid), (cores, DEFAULT_MAX_BLOCKING_THREADS));
+}
+
+#[tokio::test]
+async fn test_dimension_mismatch_returns_structured_error() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::{add_vector, find_similar, update_vector};
+    use crate::core::openapi::{AddVectorParams, FindSimilarParams, RpcResponse, UpdateVectorParams};
+
+    let mut ctrl = collection_controller_with("structured", 3);
+    let id = ctrl.add_vector("structured", vec![1.0, 2.0, 3.0], Metadata::new()).unwrap();
+    let state = app_state_for(ctrl, HashMap::new());
+    let assert_mismatch = |status: StatusCode, response: Json<RpcResponse>| {
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let data = response.0.data.unwrap();
+        assert_eq!(data["error_code"], "dimension_mismatch");
+        assert_eq!(data["expected"], 3);
+        assert_eq!(data["actual"], 2);
+        assert_eq!(data["collection"], "structured");
+        assert!(response.0.message.unwrap().contains("Размерность вектора 2"));
+    };
+
+    let params: AddVectorParams = serde_json::from_value(serde_json::json!({"collection": "structured", "embedding": [1.0, 2.0]})).unwrap();
+    let (status, response) = add_vector(State(state.clone()), Json(params)).await;
+    assert_mismatch(status, response);
+
+    let params: UpdateVectorParams = serde_json::from_value(serde_json::json!({"collection": "structured", "vector_id": id, "embedding": [1.0, 2.0]})).unwrap();
+    let (status, response) = update_vector(State(state.clone()), Json(params)).await;
+    assert_mismatch(status, response);
+
+    let params: FindSimilarParams = serde_json::from_value(serde_json::json!({"collection": "structured", "query": [1.0, 2.0]})).unwrap();
+    let (status, response) = find_similar(State(state.clone()), Json(params)).await;
+    assert_mismatch(status, response);
+
+    // Остальные ошибки тоже несут код категории
+    let params: FindSimilarParams = serde_json::from_value(serde_json::json!({"collection": "missing", "query": [1.0, 2.0, 3.0]})).unwrap();
+    let (status, response) = find_similar(State(state), Json(params)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let data = response.0.data.unwrap();
+    assert_eq!(data["error_code"], "not_found");
+    assert_eq!(data["collection"], "missing");
+    assert!(data.get("expected").is_none());
+}
+
+#[tokio::test]
+async fn test_compare_vectors_raw_and_stored() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::compare_vectors;
+    use crate::core::openapi::CompareVectorsParams;
+
+    let mut ctrl = collection_controller_with("compare", 2);
+    let id_a = ctrl.add_vector("compare", vec![3.0, 0.0], Metadata::new()).unwrap();
+    let id_b = ctrl.add_vector("compare", vec![0.0, 4.0], Metadata::new()).unwrap();
+    let state = app_state_for(ctrl, HashMap::new());
+
+    let params: CompareVectorsParams = serde_json::from_value(serde_json::json!({"a": [3.0, 0.0], "b": [0.0, 4.0]})).unwrap();
+    let (status, raw) = compare_vectors(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::OK);
+    let raw = raw.0.data.unwrap();
+    assert_eq!(raw["cosine"], 0.0);
+    assert_eq!(raw["euclidean"], 5.0);
+    assert_eq!(raw["manhattan"], 7.0);
+    assert_eq!(raw["dot_product"], 0.0);
+    assert!((raw["scores"]["Euclidean"].as_f64().unwrap() - 1.0 / 6.0).abs() < 1e-6);
+
+    // Сохранённые векторы дают тот же результат
+    let params: CompareVectorsParams = serde_json::from_value(serde_json::json!({"collection": "compare", "id_a": id_a, "id_b": id_b})).unwrap();
+    let (status, stored) = compare_vectors(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stored.0.data.unwrap(), raw);
+
+    let params: CompareVectorsParams = serde_json::from_value(serde_json::json!({"collection": "compare", "id_a": id_a, "id_b": 999})).unwrap();
+    let (status, response) = compare_vectors(State(state.clone()), Json(params)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(response.0.data.unwrap()["collection"], "compare");
+
+    // Смешанный или неполный запрос отклоняется
+    let params: CompareVectorsParams = serde_json::from_value(serde_json::json!({"a": [1.0, 0.0], "collection": "compare", "id_b": id_b})).unwrap();
+    let (status, response) = compare_vectors(State(state), Json(params)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.0.data.unwrap()["error_code"], "validation_error");
+}
+
+#[tokio::test]
+async fn test_compare_vectors_rejects_dimension_mismatch() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::compare_vectors;
+    use crate::core::openapi::CompareVectorsParams;
+
+    let state = app_state_for(collection_controller_with("compare", 2), HashMap::new());
+    let params: CompareVectorsParams = serde_json::from_value(serde_json::json!({"a": [1.0, 2.0, 3.0], "b": [1.0, 2.0]})).unwrap();
+    let (status, response) = compare_vectors(State(state), Json(params)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let data = response.0.data.unwrap();
+    assert_eq!(data["error_code"], "dimension_mismatch");
+    assert_eq!(data["expected"], 3);
+    assert_eq!(data["actual"], 2);
+}
+
+/// Бэкенд в памяти, у которого следующие failures операций чтения, записи и листинга
+/// завершаются ошибкой kind
+struct FlakyBackend {
+    inner: crate::core::backends::InMemoryBackend,
+    failures: Arc<std::sync::atomic::AtomicUsize>,
+    kind: std::io::ErrorKind,
+}
+
+impl FlakyBackend {
+    fn new(kind: std::io::ErrorKind) -> (Self, Arc<std::sync::atomic::AtomicUsize>) {
+        let failures = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        (FlakyBackend { inner: crate::core::backends::InMemoryBackend::new(), failures: Arc::clone(&failures), kind }, failures)
+    }
+
+    fn fail(&self) -> std::io::Result<()> {
+        use std::sync::atomic::Ordering;
+        match self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)) {
+            Ok(_) => Err(std::io::Error::from(self.kind)),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+impl crate::core::interfaces::StorageBackend for FlakyBackend {
+    fn write(&self, path: &str, data: &[u8]) -> std::io::Result<()> {
+        self.fail()?;
+        self.inner.write(path, data)
+    }
+    fn read(&self, path: &str) -> std::io::Result<Vec<u8>> {
+        self.fail()?;
+        self.inner.read(path)
+    }
+    fn list_files(&self, dir: &str) -> std::io::Result<Vec<String>> {
+        self.fail()?;
+        self.inner.list_files(dir)
+    }
+    fn list_dirs(&self, dir: &str) -> std::io::Result<Vec<String>> {
+        self.fail()?;
+        self.inner.list_dirs(dir)
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.inner.exists(path)
+    }
+    fn file_size(&self, path: &str) -> std::io::Result<u64> {
+        self.inner.file_size(path)
+    }
+    fn remove(&self, path: &str) -> std::io::Result<()> {
+        self.inner.remove(path)
+    }
+}
+
+#[test]
+fn test_storage_retries_transient_io_errors() {
+    use std::sync::atomic::Ordering;
+    use crate::core::errors::VecDbError;
+
+    let (backend, failures) = FlakyBackend::new(std::io::ErrorKind::Interrupted);
+    let configs = HashMap::from([("io_retry_backoff_ms".to_string(), "1".to_string())]);
+    let storage_controller = StorageController::with_backend(Box::new(backend), &configs).unwrap();
+    assert_eq!(storage_controller.io_attempts, 3);
+
+    // Первая попытка записи и чтения прерывается, повтор проходит
+    failures.store(1, Ordering::SeqCst);
+    storage_controller.save_vector_to_bucket("flaky".to_string(), "1".to_string(), 7, vec![1, 2, 3]).unwrap();
+    assert_eq!(failures.load(Ordering::SeqCst), 0);
+    failures.store(1, Ordering::SeqCst);
+    assert_eq!(storage_controller.read_vector_from_bucket("flaky".to_string(), "1".to_string(), 7).unwrap(), Some(vec![1, 2, 3]));
+    failures.store(2, Ordering::SeqCst);
+    assert_eq!(storage_controller.read_all_bucket_vectors("flaky".to_string()).unwrap().len(), 1);
+
+    // Попытки кончились: ошибка storage вместо паники
+    failures.store(3, Ordering::SeqCst);
+    let error = storage_controller.read_vector_from_bucket("flaky".to_string(), "1".to_string(), 7).unwrap_err();
+    assert!(matches!(error, VecDbError::Storage(_)));
+}
+
+#[test]
+fn test_storage_does_not_retry_permanent_io_errors() {
+    use std::sync::atomic::Ordering;
+    use crate::core::errors::VecDbError;
+
+    let (backend, failures) = FlakyBackend::new(std::io::ErrorKind::PermissionDenied);
+    let storage_controller = StorageController::with_backend(Box::new(backend), &HashMap::new()).unwrap();
+    storage_controller.save_bucket("denied".to_string(), "1".to_string(), vec![1]).unwrap();
+
+    // Одна ошибка доступа сразу возвращается: повтор прошёл бы успешно
+    failures.store(1, Ordering::SeqCst);
+    let error = storage_controller.read_bucket("denied".to_string(), "1".to_string()).unwrap_err();
+    assert!(matches!(error, VecDbError::Storage(_)));
+    failures.store(1, Ordering::SeqCst);
+    assert!(storage_controller.read_all_buckets("denied".to_string()).is_err());
+    assert_eq!(storage_controller.read_all_buckets("denied".to_string()).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_read_only_mode_rejects_mutations() {
+    use axum::routing::post;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use crate::core::controllers::ConnectionController;
+    use crate::core::handlers::{add_vector, delete_collection, find_similar};
+
+    async fn post_json(addr: std::net::SocketAddr, path: &str, body: serde_json::Value) -> (u16, serde_json::Value) {
+        let body = body.to_string();
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            path, body.len(), body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    let mut ctrl = collection_controller_with("replica", 2);
+    ctrl.add_vector("replica", vec![1.0, 0.0], Metadata::new()).unwrap();
+    let state = app_state_for(ctrl, HashMap::new());
+    let app = ConnectionController::with_read_only(
+        axum::Router::new()
+            .route("/vector", post(add_vector))
+            .route("/vector/similar", post(find_similar))
+            .route("/collection/delete", post(delete_collection)),
+    ).with_state(state.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let (status, body) = post_json(addr, "/vector", serde_json::json!({"collection": "replica", "embedding": [0.0, 1.0]})).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["data"]["error_code"], "read_only");
+    let (status, _) = post_json(addr, "/collection/delete", serde_json::json!({"name": "replica"})).await;
+    assert_eq!(status, 403);
+
+    // Поиск работает, а коллекция не изменилась
+    let (status, body) = post_json(addr, "/vector/similar", serde_json::json!({"collection": "replica", "query": [1.0, 0.0], "k": 5})).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ok");
+    assert_eq!(state.controller.read().await.get_collection("replica").unwrap().buckets_controller.total_vectors(), 1);
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn test_get_all_collections_is_sorted_by_name() {
+    use crate::core::handlers::get_all_collections;
+
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("backend".to_string(), "memory".to_string())])).unwrap());
+    let mut writer = CollectionController::new(Arc::clone(&storage_controller));
+    writer.add_collection("bravo".to_string(), LSHMetric::Euclidean, 2).unwrap();
+    writer.dump();
+
+    // Коллекции добавляются не по алфавиту, одна из них есть только в storage
+    let mut ctrl = CollectionController::new(Arc::clone(&storage_controller));
+    for name in ["zulu", "alpha", "mike"] {
+        ctrl.add_collection(name.to_string(), LSHMetric::Euclidean, 2).unwrap();
+    }
+    let loaded: Vec<&str> = ctrl.get_all_collections().iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(loaded, vec!["alpha", "mike", "zulu"]);
+    let listed: Vec<String> = ctrl.get_all_collections_with_storage().iter().map(|c| c.name.clone()).collect();
+    assert_eq!(listed, vec!["alpha", "bravo", "mike", "zulu"]);
+
+    let (_, response) = get_all_collections(State(app_state_for(ctrl, HashMap::new())), Json(serde_json::json!({}))).await;
+    let names: Vec<String> = response.0.data.unwrap()["collections"].as_array().unwrap()
+        .iter()
+        .map(|info| info["name"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(names, listed);
+}
+
+#[test]
+fn test_lsh_params_come_from_config() {
+    use crate::core::interfaces::Object;
+    use crate::core::lsh::{DEFAULT_BUCKET_WIDTH, DEFAULT_NUM_HASHES};
+    use crate::core::utils::{StorageFormat, StorageMetadataValue};
+    use crate::core::vector_db::VectorDB;
+
+    let dir = std::env::temp_dir().join(format!("vecdb_lsh_params_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.json");
+    std::fs::write(&path, r#"{"storage": {"backend": "memory"}, "sharding": {"lsh_seed": 9}, "lsh": {"num_hashes": 5, "bucket_width": 2.5}}"#).unwrap();
+    let mut db = VectorDB::new(path.to_string_lossy().to_string()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Коллекция создаётся с параметрами из конфига, а не с значениями по умолчанию
+    db.add_collection("configured".to_string(), LSHMetric::Euclidean, 4).unwrap();
+    let collection = db.get_collection("configured").unwrap();
+    let lsh = collection.buckets_controller.lsh.as_ref().unwrap();
+    assert_ne!(DEFAULT_NUM_HASHES, 5);
+    assert_ne!(DEFAULT_BUCKET_WIDTH, 2.5);
+    assert_eq!((lsh.num_hashes, lsh.bucket_width, lsh.seed), (5, 2.5, Some(9)));
+    assert_eq!((collection.num_hashes, collection.bucket_width), (5, 2.5));
+
+    // Параметры сохраняются вместе с коллекцией
+    let (raw, _) = collection.dump(StorageFormat::Bincode).unwrap();
+    let mut reloaded = Collection::new(None, LSHMetric::Euclidean, 4);
+    reloaded.load(raw, StorageFormat::Bincode).unwrap();
+    let lsh = reloaded.buckets_controller.lsh.as_ref().unwrap();
+    assert_eq!((lsh.num_hashes, lsh.bucket_width, lsh.seed), (5, 2.5, Some(9)));
+
+    // Коллекции, сохранённые до появления настройки (все поля до indexed_metadata_keys включительно),
+    // загружаются с тремя хэш-функциями
+    let legacy = (
+        "legacy".to_string(), 1u64, "Euclidean".to_string(), 4usize, 1usize, 9u64,
+        HashMap::<String, String>::new(), HashMap::<String, StorageMetadataValue>::new(), 2.5f32, None::<usize>,
+        None::<usize>, "reject".to_string(), Vec::<String>::new(),
+    );
+    reloaded.load(StorageFormat::Bincode.encode(&legacy).unwrap(), StorageFormat::Bincode).unwrap();
+    assert_eq!(reloaded.buckets_controller.lsh.as_ref().unwrap().num_hashes, DEFAULT_NUM_HASHES);
+}
+
+#[tokio::test]
+async fn test_deep_health_check_detects_unwritable_storage() {
+    use axum::extract::Query;
+    use axum::http::StatusCode;
+    use crate::core::handlers::health;
+    use crate::core::openapi::HealthParams;
+
+    let root = std::env::temp_dir().join(format!("vecdb_health_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let storage_controller = Arc::new(StorageController::new(HashMap::from([("path".to_string(), root.to_string_lossy().to_string())])).unwrap());
+    let state = app_state_for(CollectionController::new(Arc::clone(&storage_controller)), HashMap::new());
+
+    let (status, response) = health(State(state.clone()), Query(HealthParams { deep: true })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.0.data.unwrap()["storage"], "writable");
+    assert!(!root.join("storage").join(".health_check").exists());
+
+    // Папку storage подменяем файлом: права доступа не помешали бы записи под root
+    std::fs::remove_dir_all(root.join("storage")).unwrap();
+    std::fs::write(root.join("storage"), b"").unwrap();
+
+    let (status, response) = health(State(state.clone()), Query(HealthParams { deep: true })).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let data = response.0.data.unwrap();
+    assert_eq!(data["healthy"], false);
+    assert_eq!(data["error_code"], "storage_error");
+
+    // Дешёвая проверка по умолчанию storage не трогает
+    let (status, response) = health(State(state), Query(HealthParams { deep: false })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.0.data.unwrap()["healthy"], true);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_find_similar_projects_requested_fields() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::find_similar;
+    use crate::core::openapi::FindSimilarParams;
+
+    let mut ctrl = collection_controller_with("projection", 2);
+    let mut metadata = Metadata::new();
+    metadata.insert("kind".to_string(), MetadataValue::from("doc"));
+    let id = ctrl.add_vector("projection", vec![1.0, 0.0], metadata).unwrap();
+    let state = app_state_for(ctrl, HashMap::new());
+    let search = |body: serde_json::Value| {
+        let state = state.clone();
+        async move {
+            let params: FindSimilarParams = serde_json::from_value(body).unwrap();
+            let (status, response) = find_similar(State(state), Json(params)).await;
+            (status, response.0)
+        }
+    };
+
+    // Только ID: ни score, ни метаданных; ключ тот же, что и в полном результате
+    let (status, response) = search(serde_json::json!({"collection": "projection", "query": [1.0, 0.0], "fields": ["vector_id"], "include_metadata": true})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.data.unwrap()["results"], serde_json::json!([{"vector_id": id}]));
+
+    let (_, response) = search(serde_json::json!({"collection": "projection", "query": [1.0, 0.0], "fields": ["vector_id", "metadata"]})).await;
+    let result = &response.data.unwrap()["results"][0];
+    assert_eq!(result["metadata"]["kind"], "doc");
+    assert!(result.get("score").is_none());
+
+    // Без fields формат прежний
+    let (_, response) = search(serde_json::json!({"collection": "projection", "query": [1.0, 0.0]})).await;
+    let result = &response.data.unwrap()["results"][0];
+    assert_eq!(result["vector_id"], id);
+    assert!(result.get("score").is_some() && result.get("bucket_id").is_some());
+
+    let (status, response) = search(serde_json::json!({"collection": "projection", "query": [1.0, 0.0], "fields": ["vector_id", "rank"]})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(response.message.unwrap().contains("rank"));
+}
+
+#[tokio::test]
+async fn test_shutdown_grace_drops_hung_requests() {
+    use axum::routing::post;
+    use std::time::{Duration, Instant};
+    use tokio::io::AsyncWriteExt;
+    use crate::core::controllers::ConnectionController;
+
+    // Зависший поиск: держит контроллер на чтение и никогда не отвечает
+    async fn hung(State(state): State<AppState>) -> &'static str {
+        let _ctrl = state.controller.read().await;
+        std::future::pending().await
+    }
+    let state = app_state_for(collection_controller_with("grace", 2), HashMap::new());
+    let controller = Arc::clone(&state.controller);
+    let app = axum::Router::new().route("/hung", post(hung)).with_state(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = Arc::new(ShutdownSignal::new());
+    let grace = Duration::from_millis(200);
+    let server = tokio::spawn(ConnectionController::serve_with_grace(listener, app, Arc::clone(&shutdown), Some(grace)));
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"POST /hung HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(controller.try_write().is_err(), "зависший запрос должен удерживать контроллер");
+
+    // Без ограничения сервер ждал бы зависший запрос вечно
+    let stopping = Instant::now();
+    shutdown.trigger();
+    let served = tokio::time::timeout(Duration::from_secs(5), server).await.expect("сервер должен остановиться после grace");
+    assert!(served.unwrap().is_ok());
+    assert!(stopping.elapsed() >= grace);
+
+    // Финальный dump тоже не ждёт зависший запрос дольше grace
+    let dumped = tokio::time::timeout(Duration::from_secs(5), CollectionController::dump_on_shutdown(&controller, Some(grace)))
+        .await
+        .expect("финальный dump не должен зависать");
+    assert!(dumped);
+    assert!(stopping.elapsed() < Duration::from_secs(2));
+
+    // Зависшее изменение держит блокировку на запись: dump пропускается, но остановка не зависает
+    let writer = Arc::clone(&controller);
+    let held = tokio::spawn(async move {
+        let _ctrl = writer.write_owned().await;
+        std::future::pending::<()>().await
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!CollectionController::dump_on_shutdown(&controller, Some(grace)).await);
+    held.abort();
+}
+
+#[test]
+fn test_storage_records_carry_schema_version() {
+    use crate::core::interfaces::Object;
+    use crate::core::objects::Vector;
+    use crate::core::utils::{StorageCollection, StorageFormat, VersionedRecord};
+
+    let collection = Collection::new(Some("versioned".to_string()), LSHMetric::Cosine, 3);
+    for format in [StorageFormat::Bincode, StorageFormat::Json] {
+        // Запись начинается с заголовка текущей версии схемы и читается обратно
+        let (raw, _) = collection.dump(format).unwrap();
+        assert!(raw.starts_with(format!("vdbs:{}\n", StorageCollection::SCHEMA_VERSION).as_bytes()));
+        let mut loaded = Collection::new(None, LSHMetric::Euclidean, 1);
+        loaded.load(raw.clone(), format).unwrap();
+        assert_eq!((loaded.name.as_str(), loaded.vector_dimension, loaded.lsh_metric.clone()), ("versioned", 3, LSHMetric::Cosine));
+
+        // Запись более новой версии схемы отклоняется ошибкой, а не паникой
+        let newer = [format!("vdbs:{}\n", StorageCollection::SCHEMA_VERSION + 1).into_bytes(), raw[raw.iter().position(|&b| b == b'\n').unwrap() + 1..].to_vec()].concat();
+        let error = Collection::new(None, LSHMetric::Euclidean, 1).load(newer, format).unwrap_err();
+        assert_eq!(error.error_code(), "serialization_error");
+    }
+
+    // Файлы без заголовка (записанные до версионирования) читаются как версия 0
+    let vector = Vector::new(Some(vec![1.0, 2.0]), Some(5), Some(metadata_with_category("old")));
+    let (raw, _) = vector.dump(StorageFormat::Bincode).unwrap();
+    let unversioned = raw[raw.iter().position(|&b| b == b'\n').unwrap() + 1..].to_vec();
+    let mut loaded = Vector::new(None, None, None);
+    loaded.load(unversioned, StorageFormat::Bincode).unwrap();
+    assert_eq!((loaded.data.clone(), loaded.metadata.clone()), (vec![1.0, 2.0], metadata_with_category("old")));
+
+    // Повреждённые данные — тоже ошибка
+    assert!(Vector::new(None, None, None).load(vec![1, 2, 3], StorageFormat::Bincode).is_err());
+}
+
+#[tokio::test]
+async fn test_flush_reports_failed_collections() {
+    use axum::http::StatusCode;
+    use crate::core::handlers::flush;
+
+    // Ошибка доступа не считается временной: запись падает без повторов
+    let (backend, failures) = FlakyBackend::new(std::io::ErrorKind::PermissionDenied);
+    let storage_controller = Arc::new(StorageController::with_backend(Box::new(backend), &HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("unsaved".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    collection_controller.add_vector("unsaved", vec![1.0, 2.0, 3.0], Metadata::new()).unwrap();
+    let state = app_state_for(collection_controller, HashMap::new());
+
+    failures.store(usize::MAX, std::sync::atomic::Ordering::SeqCst);
+    let (status, response) = flush(State(state.clone())).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.0.status, "error");
+    let data = response.0.data.unwrap();
+    assert_eq!(data["flushed"], false);
+    assert!(data["failed"]["unsaved"].is_string());
+    assert!(response.0.message.unwrap().contains("unsaved"));
+
+    // Когда storage снова доступно, flush проходит
+    failures.store(0, std::sync::atomic::Ordering::SeqCst);
+    let (status, response) = flush(State(state)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.0.data.unwrap()["flushed"], true);
+}
+
+#[test]
+fn test_preload_skips_storage_for_loaded_collection() {
+    use std::sync::atomic::Ordering;
+
+    let (backend, failures) = FlakyBackend::new(std::io::ErrorKind::PermissionDenied);
+    let storage_controller = Arc::new(StorageController::with_backend(Box::new(backend), &HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("warm".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    collection_controller.add_vector("warm", vec![1.0, 0.0, 0.0], Metadata::new()).unwrap();
+
+    // Коллекция уже в памяти: storage не читается, поэтому сбой чтения не тратится и не мешает
+    failures.store(1, Ordering::SeqCst);
+    let (loaded, _, vectors) = collection_controller.preload_collection("warm").unwrap();
+    assert!(!loaded);
+    assert_eq!(vectors, 1);
+    assert_eq!(failures.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_migrate_metadata_reports_storage_errors() {
+    use std::sync::atomic::Ordering;
+    use crate::core::errors::VecDbError;
+    use crate::core::utils::MetadataMigration;
+
+    let (backend, failures) = FlakyBackend::new(std::io::ErrorKind::PermissionDenied);
+    let storage_controller = Arc::new(StorageController::with_backend(Box::new(backend), &HashMap::new()).unwrap());
+    let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+    collection_controller.add_collection("migrate_flaky".to_string(), LSHMetric::Euclidean, 3).unwrap();
+    let metadata = Metadata::from([("tag".to_string(), MetadataValue::from("draft"))]);
+    collection_controller.add_vector("migrate_flaky", vec![1.0, 2.0, 3.0], metadata).unwrap();
+    let migration = MetadataMigration::new(HashMap::from([("tag".to_string(), "status".to_string())]), HashMap::new()).unwrap();
+
+    // Запись вектора не удалась: миграция возвращает ошибку storage, а не количество изменённых
+    failures.store(usize::MAX, Ordering::SeqCst);
+    let error = collection_controller.migrate_metadata("migrate_flaky", &migration).unwrap_err();
+    assert!(matches!(error, VecDbError::Storage(_)));
+
+    failures.store(0, Ordering::SeqCst);
+    let rename_back = MetadataMigration::new(HashMap::from([("status".to_string(), "tag".to_string())]), HashMap::new()).unwrap();
+    assert_eq!(collection_controller.migrate_metadata("migrate_flaky", &rename_back).unwrap(), 1);
+}
+
+#[test]
+fn test_non_finite_embeddings_rejected_on_every_write_path() {
+    use crate::core::errors::VecDbError;
+
+    let mut ctrl = collection_controller_with("finite_collection", 3);
+    let id = ctrl.add_vector("finite_collection", vec![1.0, 2.0, 3.0], Metadata::new()).unwrap();
+    let nan = vec![1.0, f32::NAN, 3.0];
+    let inf = vec![f32::INFINITY, 2.0, 3.0];
+
+    assert!(matches!(ctrl.add_vector("finite_collection", nan.clone(), Metadata::new()), Err(VecDbError::Validation(_))));
+    assert!(matches!(ctrl.update_vector("finite_collection", id, Some(inf.clone()), None), Err(VecDbError::Validation(_))));
+    assert!(matches!(ctrl.upsert_vector("finite_collection", id, nan.clone(), None), Err(VecDbError::Validation(_))));
+    assert!(matches!(ctrl.upsert_vector("finite_collection", id + 1, inf.clone(), None), Err(VecDbError::Validation(_))));
+    let outcomes = ctrl.add_vectors_batch("finite_collection", vec![(None, nan, Metadata::new(), None)]).unwrap();
+    assert!(outcomes[0].is_err());
+
+    // Отклонённые записи не изменили коллекцию
+    assert_eq!(ctrl.get_vector("finite_collection", id).unwrap().data, vec![1.0, 2.0, 3.0]);
+    assert_eq!(ctrl.get_collection("finite_collection").unwrap().buckets_controller.total_vectors(), 1);
+
+    // Неверная размерность по-прежнему сообщается как DimensionMismatch
+    assert!(matches!(
+        ctrl.update_vector("finite_collection", id, Some(vec![1.0]), None),
+        Err(VecDbError::DimensionMismatch { expected: 3, actual: 1 })
+    ));
+}
@@ -1,8 +1,12 @@
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
 use crate::core::objects::{Vector};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 use crate::core::lsh::LSHMetric;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use utoipa::ToSchema;
 
 // util types
 
@@ -11,12 +15,148 @@ pub struct VectorResponse {
     score: Vec<i8>,
 }
 
+/// Значение метаданных вектора. В JSON записывается как есть: bool, целое, дробное число или строка
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+/// Метаданные вектора
+pub type Metadata = HashMap<String, MetadataValue>;
+
+impl MetadataValue {
+    /// Числовое значение для диапазонных фильтров. Строки разбираются как числа,
+    /// чтобы старые данные, где числа хранились строками, тоже участвовали в фильтрах
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MetadataValue::Int(value) => Some(*value as f64),
+            MetadataValue::Float(value) => Some(*value),
+            MetadataValue::Str(value) => value.parse().ok(),
+            MetadataValue::Bool(_) => None,
+        }
+    }
+
+    /// Сравнение с приведением типов: числа сравниваются численно, остальное — по строковому
+    /// представлению, поэтому фильтр {"year": "2020"} находит и строку "2020", и число 2020
+    pub fn loosely_equals(&self, other: &MetadataValue) -> bool {
+        match (self, other) {
+            (MetadataValue::Int(_) | MetadataValue::Float(_), MetadataValue::Int(_) | MetadataValue::Float(_)) => {
+                self.as_f64() == other.as_f64()
+            }
+            _ => self.to_string() == other.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetadataValue::Bool(value) => write!(f, "{}", value),
+            MetadataValue::Int(value) => write!(f, "{}", value),
+            MetadataValue::Float(value) => write!(f, "{}", value),
+            MetadataValue::Str(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        MetadataValue::Str(value)
+    }
+}
+
+impl From<i64> for MetadataValue {
+    fn from(value: i64) -> Self {
+        MetadataValue::Int(value)
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        MetadataValue::Float(value)
+    }
+}
+
+impl From<bool> for MetadataValue {
+    fn from(value: bool) -> Self {
+        MetadataValue::Bool(value)
+    }
+}
+
+/// Нестроковое значение метаданных на диске. Bincode не поддерживает untagged-перечисления,
+/// поэтому тип сохраняется явно
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum StorageMetadataValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct StorageVector {
     pub data: Vec<f32>,
     pub timestamp: i64,
+    /// Строковые значения метаданных
     pub metadata: HashMap<String, String>,
     pub hash_id: u64,
+    /// Предвычисленная L2-норма (None — пересчитать при загрузке)
+    #[serde(default)]
+    pub norm: Option<f32>,
+    /// Числовые и логические значения метаданных
+    #[serde(default)]
+    pub typed_metadata: HashMap<String, StorageMetadataValue>,
+    /// Эмбеддинг нормирован сервером при вставке
+    #[serde(default)]
+    pub normalized: bool,
+}
+
+impl StorageVector {
+    /// Раскладывает метаданные на строковые и типизированные для сохранения
+    pub fn split_metadata(metadata: &Metadata) -> (HashMap<String, String>, HashMap<String, StorageMetadataValue>) {
+        let mut strings = HashMap::new();
+        let mut typed = HashMap::new();
+        for (key, value) in metadata {
+            match value {
+                MetadataValue::Str(value) => { strings.insert(key.clone(), value.clone()); }
+                MetadataValue::Int(value) => { typed.insert(key.clone(), StorageMetadataValue::Int(*value)); }
+                MetadataValue::Float(value) => { typed.insert(key.clone(), StorageMetadataValue::Float(*value)); }
+                MetadataValue::Bool(value) => { typed.insert(key.clone(), StorageMetadataValue::Bool(*value)); }
+            }
+        }
+        (strings, typed)
+    }
+
+    /// Собирает метаданные обратно. Старые файлы содержат только строки — они становятся MetadataValue::Str
+    pub fn merged_metadata(&self) -> Metadata {
+        Self::join_metadata(&self.metadata, &self.typed_metadata)
+    }
+
+    /// Обратная операция к split_metadata
+    pub fn join_metadata(strings: &HashMap<String, String>, typed: &HashMap<String, StorageMetadataValue>) -> Metadata {
+        let mut metadata: Metadata = strings
+            .iter()
+            .map(|(key, value)| (key.clone(), MetadataValue::Str(value.clone())))
+            .collect();
+        for (key, value) in typed {
+            let value = match value {
+                StorageMetadataValue::Int(value) => MetadataValue::Int(*value),
+                StorageMetadataValue::Float(value) => MetadataValue::Float(*value),
+                StorageMetadataValue::Bool(value) => MetadataValue::Bool(*value),
+            };
+            metadata.insert(key.clone(), value);
+        }
+        metadata
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -25,6 +165,55 @@ pub struct StorageCollection {
     pub id: u64,
     pub lsh_metric: String, // Сохраняем как строку для сериализации
     pub vector_dimension: usize,
+    #[serde(default = "default_num_tables")]
+    pub num_tables: usize,
+    #[serde(default = "default_lsh_seed")]
+    pub lsh_seed: u64,
+    /// Строковые значения метаданных по умолчанию
+    #[serde(default)]
+    pub default_metadata: HashMap<String, String>,
+    /// Числовые и логические значения метаданных по умолчанию
+    #[serde(default)]
+    pub default_typed_metadata: HashMap<String, StorageMetadataValue>,
+    /// Ширина бакетов LSH (подобранная автонастройкой или по умолчанию)
+    #[serde(default = "default_bucket_width")]
+    pub bucket_width: f32,
+    /// После скольких векторов подобрать ширину бакетов; None — автонастройка не ожидается
+    #[serde(default)]
+    pub auto_tune_after: Option<usize>,
+    /// Максимальное количество векторов; None — без ограничения
+    #[serde(default)]
+    pub max_vectors: Option<usize>,
+    /// Политика вытеснения при достижении max_vectors (reject, fifo или lru)
+    #[serde(default = "default_eviction")]
+    pub eviction: String,
+    /// Ключи метаданных с инвертированным индексом
+    #[serde(default)]
+    pub indexed_metadata_keys: Vec<String>,
+    /// Количество хэш-функций LSH
+    #[serde(default = "default_num_hashes")]
+    pub num_hashes: usize,
+}
+
+fn default_num_tables() -> usize {
+    1
+}
+
+// Старые коллекции строились с фиксированным seed
+fn default_lsh_seed() -> u64 {
+    crate::core::lsh::DEFAULT_LSH_SEED
+}
+
+fn default_bucket_width() -> f32 {
+    crate::core::lsh::DEFAULT_BUCKET_WIDTH
+}
+
+fn default_num_hashes() -> usize {
+    crate::core::lsh::DEFAULT_NUM_HASHES
+}
+
+fn default_eviction() -> String {
+    "reject".to_string()
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -34,8 +223,453 @@ pub struct StorageBucket {
     pub updated_at: i64,
 }
 
+impl VersionedRecord for StorageVector {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn read_fields(fields: &mut BincodeFields) -> Result<Self, String> {
+        Ok(StorageVector {
+            data: fields.required()?,
+            timestamp: fields.required()?,
+            metadata: fields.required()?,
+            hash_id: fields.required()?,
+            norm: fields.optional(|| None)?,
+            typed_metadata: fields.optional(HashMap::new)?,
+            normalized: fields.optional(|| false)?,
+        })
+    }
+}
+
+impl VersionedRecord for StorageCollection {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn read_fields(fields: &mut BincodeFields) -> Result<Self, String> {
+        Ok(StorageCollection {
+            name: fields.required()?,
+            id: fields.required()?,
+            lsh_metric: fields.required()?,
+            vector_dimension: fields.required()?,
+            num_tables: fields.optional(default_num_tables)?,
+            lsh_seed: fields.optional(default_lsh_seed)?,
+            default_metadata: fields.optional(HashMap::new)?,
+            default_typed_metadata: fields.optional(HashMap::new)?,
+            bucket_width: fields.optional(default_bucket_width)?,
+            auto_tune_after: fields.optional(|| None)?,
+            max_vectors: fields.optional(|| None)?,
+            eviction: fields.optional(default_eviction)?,
+            indexed_metadata_keys: fields.optional(Vec::new)?,
+            num_hashes: fields.optional(default_num_hashes)?,
+        })
+    }
+}
+
+impl VersionedRecord for StorageBucket {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn read_fields(fields: &mut BincodeFields) -> Result<Self, String> {
+        Ok(StorageBucket {
+            id: fields.required()?,
+            created_at: fields.required()?,
+            updated_at: fields.required()?,
+        })
+    }
+}
+
+/// Запись хранилища с явной версией схемы. Поля в схему только дописываются в конец
+/// (со значением по умолчанию для старых файлов), поэтому любая поддерживаемая версия
+/// читается одним последовательным проходом по полям
+pub trait VersionedRecord: Serialize + DeserializeOwned {
+    /// Текущая версия схемы; файлы без заголовка версии считаются версией 0
+    const SCHEMA_VERSION: u32;
+
+    /// Читает поля bincode-записи по порядку, подставляя значения по умолчанию для отсутствующих
+    fn read_fields(fields: &mut BincodeFields) -> Result<Self, String>;
+}
+
+/// Последовательное чтение полей bincode-записи. Поля, дописанные в схему позже,
+/// отсутствуют в старых файлах: optional возвращает для них значение по умолчанию
+pub struct BincodeFields<'a> {
+    cursor: std::io::Cursor<&'a [u8]>,
+}
+
+impl<'a> BincodeFields<'a> {
+    pub fn new(raw_data: &'a [u8]) -> Self {
+        BincodeFields { cursor: std::io::Cursor::new(raw_data) }
+    }
+
+    /// Читает обязательное поле
+    pub fn required<T: DeserializeOwned>(&mut self) -> Result<T, String> {
+        bincode::deserialize_from(&mut self.cursor).map_err(|e| e.to_string())
+    }
+
+    /// Читает поле, которого может не быть в старых файлах
+    pub fn optional<T: DeserializeOwned>(&mut self, default: impl FnOnce() -> T) -> Result<T, String> {
+        if self.cursor.position() >= self.cursor.get_ref().len() as u64 {
+            return Ok(default());
+        }
+        self.required()
+    }
+}
+
+/// Заголовок версии схемы записи: "vdbs:<версия>\n". Текстовый, как и заголовок контрольной суммы
+const SCHEMA_HEADER_PREFIX: &[u8] = b"vdbs:";
+
+/// Отделяет заголовок версии схемы; данные без заголовка (записанные до его появления) — версия 0
+fn split_schema_header(raw_data: &[u8]) -> Result<(u32, &[u8]), String> {
+    let Some(rest) = raw_data.strip_prefix(SCHEMA_HEADER_PREFIX) else {
+        return Ok((0, raw_data));
+    };
+    rest.iter()
+        .take(11)
+        .position(|&byte| byte == b'\n')
+        .and_then(|end| Some((std::str::from_utf8(&rest[..end]).ok()?.parse().ok()?, &rest[end + 1..])))
+        .ok_or_else(|| "Повреждён заголовок версии схемы".to_string())
+}
+
+/// Формат файлов на диске (ключ конфига `storage.format`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageFormat {
+    /// Компактный бинарный формат (по умолчанию)
+    #[default]
+    Bincode,
+    /// Читаемый JSON для отладки, больше по размеру и медленнее
+    Json,
+}
+
+impl fmt::Display for StorageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageFormat::Bincode => write!(f, "bincode"),
+            StorageFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl StorageFormat {
+    pub fn from_string(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "bincode" => Ok(StorageFormat::Bincode),
+            "json" => Ok(StorageFormat::Json),
+            other => Err(format!("Неизвестный формат хранения: '{}'", other)),
+        }
+    }
+
+    /// Сериализует структуру хранения в байты выбранного формата
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            StorageFormat::Bincode => bincode::serialize(value).map_err(|e| e.to_string()),
+            StorageFormat::Json => serde_json::to_vec_pretty(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Десериализует структуру хранения из байт выбранного формата
+    pub fn decode<T: DeserializeOwned>(&self, raw_data: &[u8]) -> Result<T, String> {
+        match self {
+            StorageFormat::Bincode => bincode::deserialize(raw_data).map_err(|e| e.to_string()),
+            StorageFormat::Json => serde_json::from_slice(raw_data).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Сериализует запись хранилища с заголовком текущей версии схемы
+    pub fn encode_versioned<T: VersionedRecord>(&self, value: &T) -> Result<Vec<u8>, String> {
+        let mut result = format!("vdbs:{}\n", T::SCHEMA_VERSION).into_bytes();
+        result.extend(self.encode(value)?);
+        Ok(result)
+    }
+
+    /// Десериализует запись хранилища любой поддерживаемой версии схемы в текущую.
+    /// Записи более новой версии отклоняются, чтобы не потерять незнакомые поля при пересохранении
+    pub fn decode_versioned<T: VersionedRecord>(&self, raw_data: &[u8]) -> Result<T, String> {
+        let (version, payload) = split_schema_header(raw_data)?;
+        if version > T::SCHEMA_VERSION {
+            return Err(format!("Версия схемы {} новее поддерживаемой ({})", version, T::SCHEMA_VERSION));
+        }
+        match self {
+            StorageFormat::Bincode => T::read_fields(&mut BincodeFields::new(payload)),
+            // В JSON поля именованы: отсутствующие заполняет serde(default)
+            StorageFormat::Json => self.decode(payload),
+        }
+    }
+}
+
+/// Сжатие файлов на диске (ключ конфига `storage.compression`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageCompression {
+    /// Без сжатия (по умолчанию)
+    #[default]
+    None,
+    /// gzip; такие файлы начинаются с заголовка GZIP_HEADER
+    Gzip,
+}
+
+impl fmt::Display for StorageCompression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageCompression::None => write!(f, "none"),
+            StorageCompression::Gzip => write!(f, "gzip"),
+        }
+    }
+}
+
+/// Заголовок сжатого файла: магия и версия, по нему сжатые файлы отличаются от несжатых
+const GZIP_HEADER: &[u8] = b"vdbz1\n";
+
+impl StorageCompression {
+    pub fn from_string(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "none" => Ok(StorageCompression::None),
+            "gzip" => Ok(StorageCompression::Gzip),
+            other => Err(format!("Неизвестный тип сжатия: '{}'", other)),
+        }
+    }
+
+    /// Сжимает данные перед записью; без сжатия возвращает их как есть
+    pub fn compress(&self, payload: Vec<u8>) -> Result<Vec<u8>, String> {
+        match self {
+            StorageCompression::None => Ok(payload),
+            StorageCompression::Gzip => {
+                let mut encoder = GzEncoder::new(GZIP_HEADER.to_vec(), Compression::default());
+                encoder.write_all(&payload).map_err(|e| e.to_string())?;
+                encoder.finish().map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Распаковывает прочитанные данные. Тип сжатия определяется по заголовку, а не по настройке,
+    /// поэтому в одной папке могут лежать и сжатые, и несжатые файлы
+    pub fn decompress(raw_data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let Some(compressed) = raw_data.strip_prefix(GZIP_HEADER) else {
+            return Ok(raw_data);
+        };
+        let mut payload = Vec::new();
+        GzDecoder::new(compressed)
+            .read_to_end(&mut payload)
+            .map_err(|e| format!("ошибка распаковки gzip: {}", e))?;
+        Ok(payload)
+    }
+}
+
+/// Выражение фильтрации по метаданным
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataFilter {
+    /// Все вложенные условия должны выполняться (пустой список — подходит любой вектор)
+    And(Vec<MetadataFilter>),
+    /// Хотя бы одно вложенное условие должно выполняться (пустой список — подходит любой вектор)
+    Or(Vec<MetadataFilter>),
+    /// Значение ключа метаданных равно заданному (с приведением типов, см. MetadataValue::loosely_equals)
+    Eq { key: String, value: MetadataValue },
+    /// Числовое значение ключа лежит в диапазоне [min, max]; любая из границ может отсутствовать
+    Range {
+        key: String,
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    /// Значение ключа (в строковом виде) содержит подстроку value
+    Contains {
+        key: String,
+        value: String,
+        #[serde(default)]
+        case_insensitive: bool,
+    },
+    /// Значение ключа (в строковом виде) начинается с value
+    StartsWith {
+        key: String,
+        value: String,
+        #[serde(default)]
+        case_insensitive: bool,
+    },
+}
+
+impl MetadataFilter {
+    /// Строит выражение из плоского набора пар ключ/значение (неявное AND)
+    pub fn from_map(filters: &Metadata) -> MetadataFilter {
+        MetadataFilter::And(
+            filters
+                .iter()
+                .map(|(key, value)| MetadataFilter::Eq { key: key.clone(), value: value.clone() })
+                .collect(),
+        )
+    }
+
+    /// Проверяет, удовлетворяют ли метаданные выражению
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        match self {
+            MetadataFilter::And(filters) => filters.iter().all(|f| f.matches(metadata)),
+            MetadataFilter::Or(filters) => filters.is_empty() || filters.iter().any(|f| f.matches(metadata)),
+            MetadataFilter::Eq { key, value } => metadata.get(key).is_some_and(|v| v.loosely_equals(value)),
+            MetadataFilter::Range { key, min, max } => metadata
+                .get(key)
+                .and_then(MetadataValue::as_f64)
+                .is_some_and(|v| min.is_none_or(|min| v >= min) && max.is_none_or(|max| v <= max)),
+            MetadataFilter::Contains { key, value, case_insensitive } =>
+                Self::text_matches(metadata, key, value, *case_insensitive, |text, pattern| text.contains(pattern)),
+            MetadataFilter::StartsWith { key, value, case_insensitive } =>
+                Self::text_matches(metadata, key, value, *case_insensitive, |text, pattern| text.starts_with(pattern)),
+        }
+    }
+
+    /// Применяет строковую проверку к значению ключа; без ключа условие не выполняется
+    fn text_matches(metadata: &Metadata, key: &str, pattern: &str, case_insensitive: bool, check: fn(&str, &str) -> bool) -> bool {
+        metadata.get(key).is_some_and(|value| {
+            let text = value.to_string();
+            if case_insensitive {
+                check(&text.to_lowercase(), &pattern.to_lowercase())
+            } else {
+                check(&text, pattern)
+            }
+        })
+    }
+}
+
+/// Бусты score по метаданным: к оценке результата прибавляется boost каждого
+/// совпавшего условия ключ=значение
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataBoosts(Vec<(String, String, f32)>);
+
+impl MetadataBoosts {
+    /// Разбирает бусты из словаря вида {"premium=true": 0.1}
+    pub fn from_map(boosts: &HashMap<String, f32>) -> Result<MetadataBoosts, String> {
+        let mut parsed = boosts
+            .iter()
+            .map(|(condition, boost)| match condition.split_once('=') {
+                Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string(), *boost)),
+                _ => Err(format!("Некорректный буст '{}': ожидается формат ключ=значение", condition)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        parsed.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        Ok(MetadataBoosts(parsed))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Суммарный буст для метаданных вектора
+    pub fn boost_for(&self, metadata: &Metadata) -> f32 {
+        self.0
+            .iter()
+            .filter(|(key, value, _)| metadata.get(key).is_some_and(|v| v.to_string() == *value))
+            .map(|(_, _, boost)| boost)
+            .sum()
+    }
+}
+
+/// Массовая миграция метаданных: переименование ключей и замена значений.
+/// Эмбеддинги не затрагиваются, поэтому бакеты векторов не меняются
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataMigration {
+    /// Старый ключ -> новый ключ
+    pub rename: HashMap<String, String>,
+    /// Ключ (после переименования) -> {старое значение в строковом виде -> новое значение}
+    pub remap: HashMap<String, HashMap<String, MetadataValue>>,
+}
+
+impl MetadataMigration {
+    /// Проверяет миграцию: должно быть задано хотя бы одно правило, ключи не пустые
+    pub fn new(rename: HashMap<String, String>, remap: HashMap<String, HashMap<String, MetadataValue>>) -> Result<Self, String> {
+        if rename.is_empty() && remap.is_empty() {
+            return Err("Не задано ни одного переименования или замены значений".to_string());
+        }
+        if rename.iter().any(|(old, new)| old.is_empty() || new.is_empty()) || remap.keys().any(String::is_empty) {
+            return Err("Ключ метаданных не может быть пустым".to_string());
+        }
+        Ok(MetadataMigration { rename, remap })
+    }
+
+    /// Применяет миграцию к метаданным вектора. Переименования выполняются одновременно
+    /// (a -> b и b -> a меняют ключи местами), затем заменяются значения.
+    /// Возвращает true, если метаданные изменились
+    pub fn apply(&self, metadata: &mut Metadata) -> bool {
+        let moved: Vec<(String, MetadataValue)> = self.rename
+            .iter()
+            .filter(|(old, new)| old != new)
+            .filter_map(|(old, new)| metadata.remove(old).map(|value| (new.clone(), value)))
+            .collect();
+        let mut changed = !moved.is_empty();
+        metadata.extend(moved);
+
+        for (key, values) in &self.remap {
+            if let Some(value) = metadata.get_mut(key)
+                && let Some(new_value) = values.get(&value.to_string())
+                && value != new_value
+            {
+                *value = new_value.clone();
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
 // utils func
 
+/// Заголовок файла с контрольной суммой: "vdb1:<crc32 в hex>\n". Текстовый, чтобы JSON-файлы оставались читаемыми
+const CHECKSUM_HEADER_PREFIX: &[u8] = b"vdb1:";
+const CHECKSUM_HEADER_LEN: usize = 14;
+
+/// Добавляет к данным версионированный заголовок с CRC32
+pub fn with_checksum(payload: Vec<u8>) -> Vec<u8> {
+    let mut result = format!("vdb1:{:08x}\n", crc32fast::hash(&payload)).into_bytes();
+    result.extend(payload);
+    result
+}
+
+/// Проверяет контрольную сумму и возвращает данные без заголовка.
+/// Файлы без заголовка (записанные до появления контрольных сумм) считаются корректными
+pub fn verify_checksum(raw_data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !raw_data.starts_with(CHECKSUM_HEADER_PREFIX) {
+        return Ok(raw_data);
+    }
+    if raw_data.len() < CHECKSUM_HEADER_LEN || raw_data[CHECKSUM_HEADER_LEN - 1] != b'\n' {
+        return Err("checksum mismatch: повреждён заголовок".to_string());
+    }
+    let expected = std::str::from_utf8(&raw_data[CHECKSUM_HEADER_PREFIX.len()..CHECKSUM_HEADER_LEN - 1])
+        .ok()
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .ok_or("checksum mismatch: повреждён заголовок")?;
+    let payload = raw_data[CHECKSUM_HEADER_LEN..].to_vec();
+    let actual = crc32fast::hash(&payload);
+    if actual != expected {
+        return Err(format!("checksum mismatch: ожидалось {:08x}, получено {:08x}", expected, actual));
+    }
+    Ok(payload)
+}
+
+/// Content-Type компактного бинарного ответа с эмбеддингами
+pub const EMBEDDINGS_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Размер заголовка бинарного ответа с эмбеддингами: количество и размерность (u32 LE)
+const EMBEDDINGS_HEADER_LEN: usize = 8;
+
+/// Упаковывает эмбеддинги одной размерности: заголовок (количество, размерность) в u32 LE,
+/// затем все значения подряд как f32 LE
+pub fn encode_embeddings(dimension: usize, embeddings: &[&[f32]]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(EMBEDDINGS_HEADER_LEN + embeddings.len() * dimension * 4);
+    result.extend((embeddings.len() as u32).to_le_bytes());
+    result.extend((dimension as u32).to_le_bytes());
+    for value in embeddings.iter().flat_map(|embedding| embedding.iter()) {
+        result.extend(value.to_le_bytes());
+    }
+    result
+}
+
+/// Разбирает данные encode_embeddings обратно в список эмбеддингов
+pub fn decode_embeddings(data: &[u8]) -> Result<Vec<Vec<f32>>, String> {
+    if data.len() < EMBEDDINGS_HEADER_LEN {
+        return Err("Бинарный ответ короче заголовка".to_string());
+    }
+    let count = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    let dimension = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    let values = &data[EMBEDDINGS_HEADER_LEN..];
+    if values.len() != count * dimension * 4 {
+        return Err(format!("Ожидалось {} значений f32, получено {} байт", count * dimension, values.len()));
+    }
+    let floats: Vec<f32> = values.chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect();
+    Ok(floats.chunks(dimension.max(1)).take(count).map(|chunk| chunk.to_vec()).collect())
+}
+
 pub fn calculate_hash<T: Hash>(t: &T) -> u64 {
     let mut s = DefaultHasher::new();
     t.hash(&mut s);
@@ -1,9 +1,10 @@
-use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::core::config::ConfigLoader;
-use crate::core::controllers::{CollectionController, ConnectionController, StorageController};
+use crate::core::controllers::{BucketSummaryRow, CollectionController, ConnectionController, ListedCollection, SimilarResults, StorageController};
+use crate::core::errors::VecDbError;
 use crate::core::lsh::LSHMetric;
+use crate::core::utils::{Metadata, MetadataFilter};
 
 pub struct VectorDB {
     storage_controller: Arc<StorageController>,
@@ -12,25 +13,35 @@ pub struct VectorDB {
 }
 
 impl VectorDB {
-    pub fn new(path: String) -> Self {
+    /// Создаёт VectorDB по конфигу. Возвращает ошибку, если storage недоступен для записи
+    pub fn new(path: String) -> Result<Self, std::io::Error> {
         let mut config_loader = ConfigLoader::new();
         config_loader.load(path);
-        let storage_controller = Arc::new(StorageController::new(config_loader.get("path")));
+        let storage_controller = Arc::new(StorageController::new(config_loader.storage_configs())?);
 
         // Передаем Arc на storage_controller в CollectionController и ConnectionController
-        let collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+        let mut collection_controller = CollectionController::new(Arc::clone(&storage_controller));
+        collection_controller.set_lsh_seed(config_loader.lsh_seed());
+        let (num_hashes, bucket_width) = config_loader.lsh_params();
+        collection_controller.set_lsh_params(num_hashes, bucket_width);
+        collection_controller.set_merge_small_buckets(config_loader.merge_small_buckets());
         let connection_controller = ConnectionController::new(config_loader);
 
-        VectorDB { storage_controller, collection_controller, connection_controller }
+        Ok(VectorDB { storage_controller, collection_controller, connection_controller })
     }
 
     /// Добавляет новую коллекцию
-    pub fn add_collection(&mut self, name: String, lsh_metric: LSHMetric, vector_dimension: usize) -> Result<(), &'static str> {
+    pub fn add_collection(&mut self, name: String, lsh_metric: LSHMetric, vector_dimension: usize) -> Result<(), VecDbError> {
         self.collection_controller.add_collection(name, lsh_metric, vector_dimension)
     }
 
+    /// Добавляет новую коллекцию с заданным количеством хэш-таблиц LSH
+    pub fn add_collection_with_tables(&mut self, name: String, lsh_metric: LSHMetric, vector_dimension: usize, num_tables: usize) -> Result<(), VecDbError> {
+        self.collection_controller.add_collection_with_tables(name, lsh_metric, vector_dimension, num_tables)
+    }
+
     /// Удаляет коллекцию
-    pub fn delete_collection(&mut self, name: String) -> Result<(), &'static str> {
+    pub fn delete_collection(&mut self, name: String) -> Result<(), VecDbError> {
         self.collection_controller.delete_collection(name)
     }
 
@@ -39,28 +50,59 @@ impl VectorDB {
         self.collection_controller.get_collection(name)
     }
 
-    /// Получает список всех коллекций
-    pub fn get_all_collections(&self) -> Vec<&crate::core::objects::Collection> {
-        self.collection_controller.get_all_collections()
+    /// Проверяет существование коллекции
+    pub fn collection_exists(&self, name: &str) -> bool {
+        self.collection_controller.collection_exists(name)
+    }
+
+    /// Загружает коллекцию из storage, если она ещё не в памяти
+    pub fn preload_collection(&mut self, name: &str) -> Result<(bool, usize, usize), VecDbError> {
+        self.collection_controller.preload_collection(name)
+    }
+
+    /// Получает список всех коллекций, включая те, что есть только в storage
+    /// (у таких коллекций бакеты и векторы не загружены)
+    pub fn get_all_collections(&self) -> Vec<ListedCollection<'_>> {
+        self.collection_controller.get_all_collections_with_storage()
     }
 
     /// Добавляет вектор в коллекцию
-    pub fn add_vector(&mut self, collection_name: &str, embedding: Vec<f32>, metadata: HashMap<String, String>) -> Result<u64, &'static str> {
+    pub fn add_vector(&mut self, collection_name: &str, embedding: Vec<f32>, metadata: Metadata) -> Result<u64, VecDbError> {
         self.collection_controller.add_vector(collection_name, embedding, metadata)
     }
 
+    /// Добавляет вектор в коллекцию, при normalize = Some(true) нормируя его по L2
+    pub fn add_vector_with_options(&mut self, collection_name: &str, embedding: Vec<f32>, metadata: Metadata, normalize: Option<bool>) -> Result<u64, VecDbError> {
+        self.collection_controller.add_vector_with_options(collection_name, embedding, metadata, normalize)
+    }
+
+    /// Сводка по бакетам коллекции (самые заполненные первыми)
+    pub fn bucket_summaries(&self, collection_name: &str, top: Option<usize>) -> Result<Vec<BucketSummaryRow>, VecDbError> {
+        self.collection_controller.bucket_summaries(collection_name, top)
+    }
+
+    /// Определяет бакет, в который хэшируется запрос (для отладки)
+    pub fn query_bucket(&self, collection_name: &str, query: &[f32]) -> Result<(u64, bool, usize), VecDbError> {
+        self.collection_controller.query_bucket(collection_name, query)
+    }
+
     /// Получает вектор по ID из коллекции
-    pub fn get_vector(&self, collection_name: &str, vector_id: u64) -> Result<&crate::core::objects::Vector, Box<dyn std::error::Error>> {
+    pub fn get_vector(&self, collection_name: &str, vector_id: u64) -> Result<&crate::core::objects::Vector, VecDbError> {
         self.collection_controller.get_vector(collection_name, vector_id)
     }
 
     /// Обновляет вектор в коллекции
-    pub fn update_vector(&mut self, collection_name: &str, vector_id: u64, new_embedding: Option<Vec<f32>>, new_metadata: Option<HashMap<String, String>>) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn update_vector(&mut self, collection_name: &str, vector_id: u64, new_embedding: Option<Vec<f32>>, new_metadata: Option<Metadata>) -> Result<(), VecDbError> {
         self.collection_controller.update_vector(collection_name, vector_id, new_embedding, new_metadata)
     }
 
+    /// Вставляет или заменяет вектор с заданным ID, возвращает true при вставке
+    pub fn upsert_vector(&mut self, collection_name: &str, vector_id: u64, embedding: Vec<f32>, metadata: Option<Metadata>) -> Result<bool, VecDbError> {
+        self.collection_controller.upsert_vector(collection_name, vector_id, embedding, metadata)
+    }
+
     /// Удаляет вектор по ID из коллекции
-    pub fn delete_vector(&mut self, collection_name: &str, vector_id: u64) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn delete_vector(&mut self, collection_name: &str, vector_id: u64) -> Result<(), VecDbError> {
         self.collection_controller.delete_vector(collection_name, vector_id)
     }
 
@@ -88,18 +130,37 @@ impl VectorDB {
     pub fn filter_by_metadata(
         &self,
         collection_name: &str,
-        filters: &HashMap<String, String>,
-    ) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+        filters: &Metadata,
+    ) -> Result<Vec<u64>, VecDbError> {
         self.collection_controller.filter_by_metadata(collection_name, filters)
     }
 
+    /// Фильтрует векторы по выражению над метаданными в указанной коллекции
+    pub fn filter_by_expression(
+        &self,
+        collection_name: &str,
+        expression: &MetadataFilter,
+    ) -> Result<Vec<u64>, VecDbError> {
+        self.collection_controller.filter_by_expression(collection_name, expression)
+    }
+
     /// Ищет похожие векторы в указанной коллекции
     pub fn find_similar(
         &self,
         collection_name: String,
         query: &Vec<f32>,
         k: usize,
-    ) -> Result<Vec<(u64, usize, f32)>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<(u64, usize, f32)>, VecDbError> {
         self.collection_controller.find_similar(collection_name, query, k)
     }
+
+    /// Ищет похожие векторы сразу для нескольких запросов
+    pub fn find_similar_batch(
+        &self,
+        collection_name: &str,
+        queries: &[Vec<f32>],
+        k: usize,
+    ) -> Result<Vec<SimilarResults>, VecDbError> {
+        self.collection_controller.find_similar_batch(collection_name, queries, k)
+    }
 }
\ No newline at end of file
@@ -8,13 +8,14 @@ use crate::core::vector_db::VectorDB;
 use crate::core::lsh::LSHMetric;
 use crate::core::controllers::{CollectionController, ConnectionController, StorageController};
 use crate::core::config::ConfigLoader;
+use crate::core::utils::{Metadata, MetadataValue};
 
 pub mod core;
 
 // Функция для создания метаданных
-fn create_metadata(category: &str, additional: Option<HashMap<String, String>>) -> HashMap<String, String> {
+fn create_metadata(category: &str, additional: Option<Metadata>) -> Metadata {
     let mut meta = HashMap::new();
-    meta.insert("category".to_string(), category.to_string());
+    meta.insert("category".to_string(), MetadataValue::from(category));
 
     if let Some(additional_meta) = additional {
         meta.extend(additional_meta);
@@ -23,8 +24,7 @@ fn create_metadata(category: &str, additional: Option<HashMap<String, String>>)
     meta
 }
 
-#[tokio::main]
-async fn main() {
+fn main() {
     println!("=== Демонстрация работы с VectorDB ===\n");
 
     // Извлекаем путь до конфига из аргументов командной строки
@@ -37,8 +37,29 @@ async fn main() {
         std::process::exit(1);
     };
 
+    // Конфиг читается до запуска рантайма: от него зависит число потоков tokio
+    let mut config_loader = ConfigLoader::new();
+    config_loader.load(config_path.clone());
+
+    let runtime = match ConnectionController::build_runtime(&config_loader.connection_configs()) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("❌ Не удалось запустить рантайм tokio: {}", e);
+            std::process::exit(1);
+        }
+    };
+    runtime.block_on(run(config_path, config_loader));
+}
+
+async fn run(config_path: String, config_loader: ConfigLoader) {
     // Создаем VectorDB, передав путь до конфиг файла
-    let mut db = VectorDB::new(config_path.clone());
+    let mut db = match VectorDB::new(config_path) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("❌ Не удалось инициализировать хранилище: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     // // Пробуем загрузить существующие коллекции
     // println!("📂 Попытка загрузить существующие коллекции...");
@@ -85,12 +106,13 @@ async fn main() {
     println!("🚀 Подготовка к запуску HTTP сервера...");
     
     // Подготовка контроллеров для HTTP сервера
-    let mut config_loader = ConfigLoader::new();
-    config_loader.load(config_path);
-    
-    let storage_controller = Arc::new(
-        StorageController::new(config_loader.get("path"))
-    );
+    let storage_controller = match StorageController::new(config_loader.storage_configs()) {
+        Ok(storage_controller) => Arc::new(storage_controller),
+        Err(e) => {
+            eprintln!("❌ Не удалось инициализировать хранилище: {}", e);
+            std::process::exit(1);
+        }
+    };
     
     // Извлекаем collection_controller из db и оборачиваем в Arc<RwLock<>>
     let collection_controller = Arc::new(RwLock::new(
@@ -113,6 +135,7 @@ async fn main() {
     let mut connection_controller = ConnectionController::new(
         config_loader
     );
+    let shutdown_grace = connection_controller.shutdown_grace();
     
     let addr_str = format!("{}:{}", host, port);
     let addr = addr_str.parse().expect("Неверный адрес сервера из конфига");
@@ -121,6 +144,7 @@ async fn main() {
     println!("🌐 Адрес сервера: http://{}", addr);
     println!("📖 Swagger UI: http://{}/swagger-ui", addr);
     println!("📄 OpenAPI спецификация: http://{}/api-docs/openapi.json", addr);
+    println!("ℹ️  Информация о сервере: http://{}/info", addr);
     println!("\n🛑 Для остановки сервера отправьте POST запрос на /stop");
     println!("═══════════════════════════════════════════════════════\n");
     
@@ -133,9 +157,13 @@ async fn main() {
             println!("\n🛑 Получен сигнал остановки сервера");
             println!("💾 Сохранение всех коллекций на диск...");
             
-            // Получаем контроллер обратно и выполняем dump
-            let ctrl = returned_controller.read().await;
-            ctrl.dump();
+            // Получаем контроллер обратно и выполняем dump под блокировкой на запись,
+            // чтобы дождаться завершения всех операций, ещё удерживающих контроллер.
+            // С server.shutdown_grace_secs зависшая операция не задерживает остановку дольше grace
+            if !CollectionController::dump_on_shutdown(&returned_controller, shutdown_grace).await {
+                eprintln!("⚠️  Не все коллекции сохранены, подробности в логе выше");
+                std::process::exit(1);
+            }
             
             println!("✅ Все коллекции успешно сохранены!");
             println!("👋 Завершение работы...");